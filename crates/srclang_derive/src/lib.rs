@@ -14,6 +14,21 @@ mod node;
 ///   field: Spanned<Type>,
 /// }
 /// ```
+/// along with a `MyNodeVisitor` trait with a `visit_field` method, `MyNode::new`,
+/// `field_node`/`field_span` getters, `MyNode::accept` and a `span::HasChildSpans` impl.
+///
+/// `Option<T>`, `Vec<T>` and `Box<T>` fields wrap their inner type instead, and
+/// `PhantomData` fields are left alone. Tuple struct fields are named `field_0`,
+/// `field_1`, ..., and enum variant fields are prefixed with the snake cased variant,
+/// or named after it when the variant holds a single tuple field:
+/// ```rust,ignore
+/// #[node]
+/// enum Literal {
+///     Bool(bool),         // visit_bool, bool_node, bool_span
+///     Pair(i64, i64),     // visit_pair_0, visit_pair_1, ...
+/// }
+/// ```
+/// The expansion expects `Spanned`, `Location`, `Range`, `ops` and `span` in scope.
 #[proc_macro_attribute]
 pub fn node(_attr: TokenStream, item: TokenStream) -> TokenStream {
     node::define_nodes(_attr, item)
@@ -1,329 +1,690 @@
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, ToTokens};
-use syn::{parse_macro_input, Data, DeriveInput, GenericArgument, PathArguments, Type, TypePath};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, Meta,
+    NestedMeta, PathArguments, Type, TypePath,
+};
 
 pub fn define_nodes(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as DeriveInput);
+    expand(input).into()
+}
+
+/// How a field's declared type is wrapped in `Spanned`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    /// `T` becomes `Spanned<T>`.
+    Plain,
+    /// `Option<T>` becomes `Option<Spanned<T>>`.
+    Option,
+    /// `Vec<T>` becomes `Vec<Spanned<T>>`.
+    Vec,
+    /// `Box<T>` becomes `Box<Spanned<T>>`.
+    Box,
+    /// `PhantomData<T>` is left alone and never visited.
+    Phantom,
+}
 
-    let struct_name = &input.ident;
-    let fields = match &input.data {
-        Data::Struct(data) => &data.fields,
-        _ => {
-            return quote! {
-                compile_error!("define_nodes can only be used on structs");
+/// A field of a node, after its type has been classified.
+struct NodeField {
+    /// The name used to derive `visit_*`, `*_node` and `*_span`.
+    base: Ident,
+    /// The field's own name, or its index for tuple fields.
+    member: syn::Member,
+    /// The parameter naming the field in `new`.
+    param: Ident,
+    /// The binding used when the field is destructured in a `match`.
+    binding: Ident,
+    /// The attributes on the field, passed through untouched.
+    attrs: Vec<Attribute>,
+    vis: syn::Visibility,
+    shape: Shape,
+    /// The type the field was declared with.
+    declared: TypePath,
+    /// The type handed to the visitor, `T` for all of the shapes above.
+    inner: Type,
+}
+
+impl NodeField {
+    fn new(field: &syn::Field, index: usize, prefix: Option<&str>) -> syn::Result<Self> {
+        let declared = match &field.ty {
+            Type::Path(path) if path.qself.is_none() => path.clone(),
+            ty => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "#[node] fields must be plain type paths, which {} is not",
+                        ty.into_token_stream()
+                    ),
+                ))
             }
-            .into()
-        }
-    };
+        };
+        let (member, base) = match (&field.ident, prefix) {
+            (Some(ident), None) => (syn::Member::Named(ident.clone()), ident.clone()),
+            (Some(ident), Some(prefix)) => (
+                syn::Member::Named(ident.clone()),
+                format_ident!("{}_{}", prefix, ident),
+            ),
+            (None, None) => (syn::Member::Unnamed(index.into()), format_ident!("field_{}", index)),
+            (None, Some(prefix)) => (
+                syn::Member::Unnamed(index.into()),
+                format_ident!("{}_{}", prefix, index),
+            ),
+        };
+        let (shape, inner) = classify(&declared);
+        let param = match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("field_{}", index),
+        };
+        Ok(Self {
+            base,
+            member,
+            param,
+            binding: format_ident!("__field_{}", index),
+            attrs: field.attrs.clone(),
+            vis: field.vis.clone(),
+            shape,
+            declared,
+            inner,
+        })
+    }
 
-    let spanned_fields = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        let field_type = &field.ty;
-        match field_type {
-            Type::Path(path) => {
-                let expanded_type = wrap_path_in_spanned(path);
-                quote! {
-                    #field_name: #expanded_type,
-                }
+    /// Whether the visitor gets a callback for this field.
+    fn is_visited(&self) -> bool {
+        self.shape != Shape::Phantom && !self.base.to_string().starts_with('_')
+    }
+
+    fn visit_fn(&self) -> Ident {
+        format_ident!("visit_{}", self.base)
+    }
+
+    fn node_getter(&self) -> Ident {
+        format_ident!("{}_node", self.base)
+    }
+
+    fn span_getter(&self) -> Ident {
+        format_ident!("{}_span", self.base)
+    }
+
+    /// The type the field has in the generated node.
+    fn spanned_type(&self) -> TokenStream2 {
+        let inner = &self.inner;
+        match self.shape {
+            Shape::Plain => {
+                let declared = &self.declared;
+                quote! { Spanned<#declared> }
             }
-            _ => {
-                panic!(
-                    "Only named fields are supported which {} is not",
-                    field_type.into_token_stream()
-                );
+            Shape::Option => quote! { Option<Spanned<#inner>> },
+            Shape::Vec => quote! { Vec<Spanned<#inner>> },
+            Shape::Box => quote! { Box<Spanned<#inner>> },
+            Shape::Phantom => {
+                let declared = &self.declared;
+                quote! { #declared }
             }
         }
-    });
+    }
 
-    let _expanded_impl = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        let field_span_getter = format_ident!("{}_span", field_name.as_ref().unwrap());
-        let field_node_getter = format_ident!("{}_node", field_name.as_ref().unwrap());
-        let field_type = &field.ty;
-        match field_type {
-            Type::Path(path) => {
-                let expanded_type = wrap_path_in_spanned(path);
-                let expanded_range_type = wrap_range_location(path);
+    /// Calls the visitor for `access`, an expression of type `&FieldType`,
+    /// returning early if the visitor asks to stop.
+    fn accept(&self, access: &TokenStream2) -> TokenStream2 {
+        if !self.is_visited() {
+            return quote! {};
+        }
+        let visit_fn = self.visit_fn();
+        let call = |spanned: TokenStream2| {
+            quote! {
+                if visitor.#visit_fn(&#spanned.1, &(#spanned.0..#spanned.2))
+                    == ops::traversal::Control::Break
+                {
+                    return ops::traversal::Control::Break;
+                }
+            }
+        };
+        match self.shape {
+            Shape::Plain | Shape::Box => call(access.clone()),
+            Shape::Option => {
+                let call = call(quote! { inner });
                 quote! {
-                    fn #field_node_getter(&self) -> &#expanded_type {
-                        todo!()
-                    }
-                    fn #field_span_getter(&self) -> &#expanded_range_type {
-                                            todo!()
+                    if let Some(inner) = #access {
+                        #call
                     }
                 }
             }
-            _ => {
-                panic!(
-                    "Only named fields are supported which {} is not",
-                    field_type.into_token_stream()
-                );
+            Shape::Vec => {
+                let call = call(quote! { inner });
+                quote! {
+                    for inner in #access.iter() {
+                        #call
+                    }
+                }
             }
+            Shape::Phantom => quote! {},
         }
-    });
+    }
 
-    let visitor_name = format_ident!("{}Visitor", struct_name);
-    let visitor_trait_stub = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        if let Some(field_name) = field_name {
-            if field_name.to_string().starts_with('_') {
-                return quote! {};
-            }
-            let field_visit = format_ident!("visit_{}", field_name);
-                    let field_type = &field.ty;
-            if let Type::Path(path) = field_type {
-                let unwrapped_type = unwrap_path(path);
-                return quote! {
-                    fn #field_visit(&self, node: &#unwrapped_type, span: &Range<Location>) -> ops::traversal::Control;
-                };
-            }
+    /// Pushes the spans of `access`, an expression of type `&FieldType`, onto `children`.
+    fn children(&self, access: &TokenStream2) -> TokenStream2 {
+        match self.shape {
+            Shape::Plain | Shape::Box => quote! {
+                children.push(#access.0..#access.2);
+            },
+            Shape::Option => quote! {
+                if let Some(inner) = #access {
+                    children.push(inner.0..inner.2);
+                }
+            },
+            Shape::Vec => quote! {
+                children.extend(#access.iter().map(|inner| inner.0..inner.2));
+            },
+            Shape::Phantom => quote! {},
         }
-        panic!("All fields must be named")
-    });
+    }
+}
 
-    let accept_impl = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        let field_type = &field.ty;
-        match field_type {
-            Type::Path(path) => {
-                let visit_fn = format_ident!("visit_{}", field_name.as_ref().unwrap());
-                match path.path.segments.last() {
-                    Some(syn::PathSegment { ident, arguments }) => match arguments {
-                        PathArguments::None => {
-                            quote! {
-                                if let cont = visitor.#visit_fn(
-                                    &self.#field_name.1,
-                                    &(self.#field_name.0..self.#field_name.2)
-                                )  {
-                                    if cont == ops::traversal::Control::Break {
-                                        return;
-                                    }
-                                }
-                            }
-                        }
-                        PathArguments::AngleBracketed(args) => match args.args.first() {
-                            Some(GenericArgument::Type(_)) => match ident.to_string().as_str() {
-                                "PhantomData" => {
-                                    quote! {
-                                        // Do nothing
-                                    }
-                                }
-                                "Option" => {
-                                    quote! {
-                                        if let Some(inner) = &self.#field_name {
-                                            if let cont = visitor.#visit_fn(
-                                                &inner.1,
-                                                &(inner.0..inner.2)
-                                            ) {
-                                                return;
-                                            }
-                                        }
-                                    }
-                                }
-                                "Vec" => {
-                                    quote! {
-                                        for inner in self.#field_name.iter() {
-                                            if let cont = visitor.#visit_fn(
-                                                &inner.1,
-                                                &(inner.0..inner.2)
-                                            ) {
-                                                return;
-                                            }
-                                        }
-                                    }
-                                }
-                                _ => {
-                                    quote! {
-                                        if let cont =visitor.#visit_fn(
-                                            &self.#field_name.1,
-                                            &(self.#field_name.0..self.#field_name.2)
-                                        ) {
-                                            return;
-                                        }
-                                    }
-                                }
-                            },
-                            _ => {
-                                quote! {
-                                    if let cont = visitor.#visit_fn(
-                                        self.1,
-                                        self.0..self.2
-                                    ) {
-                                        return;
-                                    }
-                                }
-                            }
-                        },
-                        PathArguments::Parenthesized(_) => todo!(),
-                    },
-                    None => {
-                        quote! {
-                            compile_error!("No path segments found");
-                        }
-                    }
-                }
-            }
-            _ => {
-                panic!(
-                    "Only named fields are supported which {} is not",
-                    field_type.into_token_stream()
-                );
+fn classify(path: &TypePath) -> (Shape, Type) {
+    let whole = Type::Path(path.clone());
+    let Some(segment) = path.path.segments.last() else {
+        return (Shape::Plain, whole);
+    };
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return (Shape::Plain, whole);
+    };
+    let Some(GenericArgument::Type(inner)) = args.args.first() else {
+        return (Shape::Plain, whole);
+    };
+    let shape = match segment.ident.to_string().as_str() {
+        "Option" => Shape::Option,
+        "Vec" => Shape::Vec,
+        "Box" => Shape::Box,
+        "PhantomData" => Shape::Phantom,
+        _ => return (Shape::Plain, whole),
+    };
+    (shape, inner.clone())
+}
+
+fn collect_fields(fields: &Fields, prefix: Option<&str>) -> syn::Result<Vec<NodeField>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| NodeField::new(field, index, prefix))
+        .collect()
+}
+
+/// Renders `FooBar` as `foo_bar`.
+fn snake_case(ident: &Ident) -> String {
+    let mut snake = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                snake.push('_');
             }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn derives_debug(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("derive"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|nested| {
+                matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("Debug"))
+            }),
+            _ => false,
+        })
+}
+
+/// Renders the field list of a struct or variant with every field wrapped in `Spanned`.
+fn spanned_fields(fields: &Fields, node_fields: &[NodeField]) -> TokenStream2 {
+    let rendered = node_fields.iter().map(|field| {
+        let attrs = &field.attrs;
+        let vis = &field.vis;
+        let ty = field.spanned_type();
+        match &field.member {
+            syn::Member::Named(name) => quote! { #(#attrs)* #vis #name: #ty },
+            syn::Member::Unnamed(_) => quote! { #(#attrs)* #vis #ty },
         }
     });
+    match fields {
+        Fields::Named(_) => quote! { { #(#rendered),* } },
+        Fields::Unnamed(_) => quote! { ( #(#rendered),* ) },
+        Fields::Unit => quote! {},
+    }
+}
 
-    let field_names = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        quote! {
-            #field_name
+/// The pattern destructuring a variant into its `binding`s.
+fn variant_pattern(variant: &Ident, fields: &Fields, node_fields: &[NodeField]) -> TokenStream2 {
+    let bindings = node_fields.iter().map(|field| {
+        let binding = &field.binding;
+        match &field.member {
+            syn::Member::Named(name) => quote! { #name: #binding },
+            syn::Member::Unnamed(_) => quote! { #binding },
         }
     });
-    let not_spanned_fileds = spanned_fields.clone();
-    let field_types = fields.iter().map(|field| {
-        let field_type = &field.ty;
-        match field_type {
-            Type::Path(path) => {
-                let expanded_type = wrap_path_in_spanned(path);
-                quote! {
-                    #expanded_type
+    match fields {
+        Fields::Named(_) => quote! { Self::#variant { #(#bindings),* } },
+        Fields::Unnamed(_) => quote! { Self::#variant ( #(#bindings),* ) },
+        Fields::Unit => quote! { Self::#variant },
+    }
+}
+
+fn visitor_stub(field: &NodeField) -> TokenStream2 {
+    if !field.is_visited() {
+        return quote! {};
+    }
+    let visit_fn = field.visit_fn();
+    let inner = &field.inner;
+    quote! {
+        fn #visit_fn(&self, node: &#inner, span: &Range<Location>) -> ops::traversal::Control {
+            let _ = (node, span);
+            ops::traversal::Control::Continue
+        }
+    }
+}
+
+/// `*_node` and `*_span` getters for a struct field.
+fn struct_getters(field: &NodeField) -> TokenStream2 {
+    let member = &field.member;
+    let node_getter = field.node_getter();
+    let span_getter = field.span_getter();
+    let inner = &field.inner;
+    let ty = field.spanned_type();
+    match field.shape {
+        Shape::Plain | Shape::Box => quote! {
+            pub fn #node_getter(&self) -> &#ty {
+                &self.#member
+            }
+            pub fn #span_getter(&self) -> Range<Location> {
+                self.#member.0..self.#member.2
+            }
+        },
+        Shape::Option => quote! {
+            pub fn #node_getter(&self) -> Option<&Spanned<#inner>> {
+                self.#member.as_ref()
+            }
+            pub fn #span_getter(&self) -> Option<Range<Location>> {
+                self.#member.as_ref().map(|inner| inner.0..inner.2)
+            }
+        },
+        Shape::Vec => quote! {
+            pub fn #node_getter(&self) -> &[Spanned<#inner>] {
+                &self.#member
+            }
+            pub fn #span_getter(&self) -> Vec<Range<Location>> {
+                self.#member.iter().map(|inner| inner.0..inner.2).collect()
+            }
+        },
+        Shape::Phantom => quote! {},
+    }
+}
+
+/// `*_node` and `*_span` getters for a variant field, empty when `self` is another variant.
+fn variant_getters(pattern: &TokenStream2, field: &NodeField) -> TokenStream2 {
+    let binding = &field.binding;
+    let node_getter = field.node_getter();
+    let span_getter = field.span_getter();
+    let inner = &field.inner;
+    match field.shape {
+        Shape::Plain | Shape::Box => quote! {
+            #[allow(unused_variables)]
+            pub fn #node_getter(&self) -> Option<&Spanned<#inner>> {
+                match self {
+                    #pattern => Some(#binding),
+                    _ => None,
+                }
+            }
+            #[allow(unused_variables)]
+            pub fn #span_getter(&self) -> Option<Range<Location>> {
+                match self {
+                    #pattern => Some(#binding.0..#binding.2),
+                    _ => None,
+                }
+            }
+        },
+        Shape::Option => quote! {
+            #[allow(unused_variables)]
+            pub fn #node_getter(&self) -> Option<&Spanned<#inner>> {
+                match self {
+                    #pattern => #binding.as_ref(),
+                    _ => None,
+                }
+            }
+            #[allow(unused_variables)]
+            pub fn #span_getter(&self) -> Option<Range<Location>> {
+                match self {
+                    #pattern => #binding.as_ref().map(|inner| inner.0..inner.2),
+                    _ => None,
+                }
+            }
+        },
+        Shape::Vec => quote! {
+            #[allow(unused_variables)]
+            pub fn #node_getter(&self) -> &[Spanned<#inner>] {
+                match self {
+                    #pattern => #binding,
+                    _ => &[],
                 }
             }
-            _ => {
-                panic!(
-                    "Only named fields are supported which {} is not",
-                    field_type.into_token_stream()
-                );
+            #[allow(unused_variables)]
+            pub fn #span_getter(&self) -> Vec<Range<Location>> {
+                match self {
+                    #pattern => #binding.iter().map(|inner| inner.0..inner.2).collect(),
+                    _ => vec![],
+                }
+            }
+        },
+        Shape::Phantom => quote! {},
+    }
+}
+
+pub(crate) fn expand(input: DeriveInput) -> TokenStream2 {
+    match &input.data {
+        Data::Struct(data) => match collect_fields(&data.fields, None) {
+            Ok(fields) => expand_struct(&input, &data.fields, &fields),
+            Err(err) => err.to_compile_error(),
+        },
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let prefix = snake_case(&variant.ident);
+                    collect_fields(&variant.fields, Some(&prefix)).map(|mut fields| {
+                        // a lone tuple field is named after its variant
+                        if let (Fields::Unnamed(_), [field]) =
+                            (&variant.fields, fields.as_mut_slice())
+                        {
+                            field.base = format_ident!("{}", prefix);
+                        }
+                        (variant, fields)
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>();
+            match variants {
+                Ok(variants) => expand_enum(&input, &variants),
+                Err(err) => err.to_compile_error(),
             }
         }
+        Data::Union(_) => syn::Error::new_spanned(
+            &input.ident,
+            "#[node] can only be used on structs and enums",
+        )
+        .to_compile_error(),
+    }
+}
+
+fn expand_struct(input: &DeriveInput, fields: &Fields, node_fields: &[NodeField]) -> TokenStream2 {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let attrs = &input.attrs;
+    let debug = (!derives_debug(attrs)).then(|| quote! { #[derive(Debug)] });
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generics = &input.generics;
+    let visitor_name = format_ident!("{}Visitor", name);
+
+    let body = spanned_fields(fields, node_fields);
+    let definition = match fields {
+        Fields::Named(_) => quote! { #vis struct #name #generics #where_clause #body },
+        Fields::Unnamed(_) => quote! { #vis struct #name #generics #body #where_clause; },
+        Fields::Unit => quote! { #vis struct #name #generics #where_clause; },
+    };
+
+    let visitor_stubs = node_fields.iter().map(visitor_stub);
+    let getters = node_fields.iter().map(struct_getters);
+    let accept_impl = node_fields.iter().map(|field| {
+        let member = &field.member;
+        field.accept(&quote! { (&self.#member) })
     });
-    let field_types_clone = field_types.clone();
-    let struct_name_lower = format_ident!("{}", struct_name.to_string().to_lowercase());
-    let field_ids = fields.iter().enumerate().map(|field| {
-        if fields.len() == 1 {
-            return quote! {
-                #struct_name_lower
-            };
-        }
-        let field_name = syn::Index::from(field.0);
-        quote! {
-            #struct_name_lower.#field_name
-        }
+    let children_impl = node_fields.iter().map(|field| {
+        let member = &field.member;
+        field.children(&quote! { (&self.#member) })
     });
 
-    let vis = &input.vis;
-    let expanded = quote! {
-        #[derive(Debug)]
-        #vis struct #struct_name {
-            #(#spanned_fields)*
-        }
-        #vis trait #visitor_name {
-            #(#visitor_trait_stub)*
+    // phantom fields are filled in rather than asked for
+    let args: Vec<_> = node_fields
+        .iter()
+        .filter(|field| field.shape != Shape::Phantom)
+        .collect();
+    let params = args.iter().map(|field| {
+        let param = &field.param;
+        let ty = field.spanned_type();
+        quote! { #param: #ty }
+    });
+    let inits = node_fields.iter().map(|field| {
+        let member = &field.member;
+        let param = &field.param;
+        if field.shape == Shape::Phantom {
+            quote! { #member: std::marker::PhantomData }
+        } else {
+            quote! { #member: #param }
         }
-        impl From<
-            (#(#field_types),*)
-        > for #struct_name {
-            fn from(
-                #struct_name_lower: (
-                    #(#field_types_clone),*
-                )
-            ) -> Self {
-                Self::new(
-                    #(#field_ids),*
-                )
+    });
+    let from_impl = if args.is_empty() {
+        quote! {}
+    } else {
+        let types = args.iter().map(|field| field.spanned_type());
+        let params = args.iter().map(|field| &field.param);
+        let pattern = if args.len() == 1 {
+            quote! { #(#params)* }
+        } else {
+            quote! { (#(#params),*) }
+        };
+        let types = if args.len() == 1 {
+            quote! { #(#types)* }
+        } else {
+            quote! { (#(#types),*) }
+        };
+        let params = args.iter().map(|field| &field.param);
+        quote! {
+            impl #impl_generics From<#types> for #name #ty_generics #where_clause {
+                fn from(#pattern: #types) -> Self {
+                    Self::new(#(#params),*)
+                }
             }
         }
+    };
+
+    quote! {
+        #debug
+        #(#attrs)*
+        #definition
+
+        #vis trait #visitor_name #generics #where_clause {
+            #(#visitor_stubs)*
+        }
+
+        #from_impl
 
-        impl #struct_name {
-            fn new(#(#not_spanned_fileds)*) -> Self {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[allow(clippy::too_many_arguments)]
+            pub fn new(#(#params),*) -> Self {
                 Self {
-                    #(#field_names,)*
+                    #(#inits,)*
                 }
             }
 
-        }
-        impl #struct_name {
-            fn accept(&self, visitor: &impl #visitor_name) {
+            #(#getters)*
+
+            pub fn accept<V: #visitor_name #ty_generics + ?Sized>(
+                &self,
+                visitor: &V,
+            ) -> ops::traversal::Control {
                 #(#accept_impl)*
+                ops::traversal::Control::Continue
             }
         }
-    };
 
-    expanded.into()
+        impl #impl_generics span::HasChildSpans for #name #ty_generics #where_clause {
+            fn children(&self) -> impl IntoIterator<Item = Range<Location>> {
+                #[allow(unused_mut)]
+                let mut children = Vec::new();
+                #(#children_impl)*
+                children
+            }
+        }
+    }
 }
-fn wrap_range_location(path: &TypePath) -> impl ToTokens {
-    match path.path.segments.last() {
-        Some(syn::PathSegment {
-            ident,
-            arguments: syn::PathArguments::AngleBracketed(args),
-        }) => {
-            if let Some(GenericArgument::Type(_inner_ty)) = args.args.first() {
-                quote! {
-                    #ident<Range<Location>>
-                }
-            } else {
-                quote! {
-                    Range<Location>
-                }
+
+fn expand_enum(input: &DeriveInput, variants: &[(&syn::Variant, Vec<NodeField>)]) -> TokenStream2 {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let attrs = &input.attrs;
+    let debug = (!derives_debug(attrs)).then(|| quote! { #[derive(Debug)] });
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generics = &input.generics;
+    let visitor_name = format_ident!("{}Visitor", name);
+
+    let definitions = variants.iter().map(|(variant, fields)| {
+        let attrs = &variant.attrs;
+        let ident = &variant.ident;
+        let body = spanned_fields(&variant.fields, fields);
+        let discriminant = variant
+            .discriminant
+            .as_ref()
+            .map(|(eq, expr)| quote! { #eq #expr });
+        quote! { #(#attrs)* #ident #body #discriminant }
+    });
+    let visitor_stubs = variants
+        .iter()
+        .flat_map(|(_, fields)| fields.iter().map(visitor_stub));
+    let getters = variants.iter().flat_map(|(variant, fields)| {
+        let pattern = variant_pattern(&variant.ident, &variant.fields, fields);
+        fields
+            .iter()
+            .map(move |field| variant_getters(&pattern, field))
+    });
+    let accept_arms = variants.iter().map(|(variant, fields)| {
+        let pattern = variant_pattern(&variant.ident, &variant.fields, fields);
+        let body = fields.iter().map(|field| field.accept(&field.binding.to_token_stream()));
+        quote! {
+            #[allow(unused_variables)]
+            #pattern => {
+                #(#body)*
             }
         }
-        _ => {
-            quote! {
-                Range<Location>
+    });
+    let children_arms = variants.iter().map(|(variant, fields)| {
+        let pattern = variant_pattern(&variant.ident, &variant.fields, fields);
+        let body = fields
+            .iter()
+            .map(|field| field.children(&field.binding.to_token_stream()));
+        quote! {
+            #[allow(unused_variables)]
+            #pattern => {
+                #(#body)*
             }
         }
-    }
-}
+    });
 
-fn wrap_path_in_spanned(path: &TypePath) -> impl ToTokens {
-    match path.path.segments.last() {
-        Some(syn::PathSegment { ident, arguments }) => {
-            if let syn::PathArguments::AngleBracketed(args) = arguments {
-                if let Some(GenericArgument::Type(inner_ty)) = args.args.first() {
-                    quote! {
-                        #ident<Spanned<#inner_ty>>
-                    }
-                } else {
-                    quote! {
-                        Spanned<#ident>
-                    }
-                }
-            } else {
-                quote! {
-                    Spanned<#ident>
+    quote! {
+        #debug
+        #(#attrs)*
+        #vis enum #name #generics #where_clause {
+            #(#definitions),*
+        }
+
+        #vis trait #visitor_name #generics #where_clause {
+            #(#visitor_stubs)*
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#getters)*
+
+            pub fn accept<V: #visitor_name #ty_generics + ?Sized>(
+                &self,
+                visitor: &V,
+            ) -> ops::traversal::Control {
+                match self {
+                    #(#accept_arms)*
                 }
+                ops::traversal::Control::Continue
             }
         }
-        _ => {
-            quote! {
-                Spanned<#path>
+
+        impl #impl_generics span::HasChildSpans for #name #ty_generics #where_clause {
+            fn children(&self) -> impl IntoIterator<Item = Range<Location>> {
+                #[allow(unused_mut)]
+                let mut children = Vec::new();
+                match self {
+                    #(#children_arms)*
+                }
+                children
             }
         }
     }
 }
 
-fn unwrap_path(path: &TypePath) -> impl ToTokens {
-    match path.path.segments.last() {
-        Some(syn::PathSegment { ident, arguments }) => {
-            if let syn::PathArguments::AngleBracketed(args) = arguments {
-                if let Some(GenericArgument::Type(inner_ty)) = args.args.first() {
-                    quote! {
-                        #inner_ty
-                    }
-                } else {
-                    quote! {
-                        #ident
-                    }
-                }
-            } else {
-                quote! {
-                    #ident
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(input: TokenStream2) -> String {
+        expand(syn::parse2(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_rejects_non_path_fields() {
+        let expanded = expand_str(quote! {
+            struct Pair {
+                both: (Ident, Ident),
             }
-        }
-        _ => {
-            quote! {
-                #path
+        });
+        assert!(expanded.contains("compile_error"));
+    }
+
+    #[test]
+    fn test_rejects_unions() {
+        let expanded = expand_str(quote! {
+            union Either {
+                a: u32,
             }
-        }
+        });
+        assert!(expanded.contains("compile_error"));
+    }
+
+    #[test]
+    fn test_keeps_user_derives() {
+        let expanded = expand_str(quote! {
+            #[derive(Clone, PartialEq, Debug)]
+            struct Ident {
+                name: String,
+            }
+        });
+        assert_eq!(expanded.matches("Debug").count(), 1);
+        assert!(expanded.contains("Clone , PartialEq"));
+    }
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!(snake_case(&format_ident!("FnCall")), "fn_call");
+        assert_eq!(snake_case(&format_ident!("Bool")), "bool");
+    }
+
+    #[test]
+    fn test_tuple_and_enum_names() {
+        let expanded = expand_str(quote! {
+            struct Binding(Ident, Box<Node>);
+        });
+        assert!(expanded.contains("fn visit_field_0"));
+        assert!(expanded.contains("fn field_1_span"));
+
+        let expanded = expand_str(quote! {
+            enum Literal {
+                Bool(bool),
+                Pair(i64, i64),
+                Named { value: String },
+                Unit,
+            }
+        });
+        assert!(expanded.contains("fn visit_bool"));
+        assert!(expanded.contains("fn visit_pair_1"));
+        assert!(expanded.contains("fn visit_named_value"));
+        assert!(expanded.contains("fn named_value_node"));
     }
 }
@@ -1,7 +1,7 @@
 use srclang_derive::node;
 use srclang::lexer::Location;
 use srclang::ops;
-use srclang::parser::span::Spanned;
+use srclang::parser::span::{self, Spanned};
 use srclang::span;
 use std::fmt::Display;
 use std::ops::Range;
@@ -72,8 +72,7 @@ impl FieldVisitor for PrettyPrinter {
     }
 
     fn visit_ty(&self, ty: &Ident, range: &Range<Location>) -> ops::traversal::Control {
-        ty.accept(self);
-        ops::traversal::Control::Continue
+        ty.accept(self)
     }
 }
 
@@ -85,9 +84,9 @@ impl IdentVisitor for PrettyPrinter {
 
     fn visit_generics(&self, generic: &Ident, range: &Range<Location>) -> ops::traversal::Control {
         print!("<");
-        generic.accept(self);
+        let control = generic.accept(self);
         print!(">");
-        ops::traversal::Control::Continue
+        control
     }
 }
 
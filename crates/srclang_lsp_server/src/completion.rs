@@ -0,0 +1,76 @@
+//! Completion items, what the analyzer completes described the way the protocol sends it.
+//!
+//! Describing every item of a list is wasted work, an item only carries where its
+//! definition is in its `data` and the signature and doc comment are added when the editor
+//! resolves the item it shows.
+
+use serde_json::{json, Value};
+use srclang::{
+    analyzer::{completion::Completion, resolve::DefId, semantic::TokenKind},
+    compiler::text::SourceProgram,
+};
+
+/// Where the definition of a completion item is.
+pub struct Data {
+    /// The url of the file defining it.
+    pub url: String,
+    pub def: DefId,
+    /// The offset completed at, when the definition is in the document completed in, whose
+    /// definitions are those of [`completed`](srclang::analyzer::completion::completed).
+    pub offset: Option<usize>,
+}
+
+impl Data {
+    fn to_json(&self) -> Value {
+        json!({ "url": self.url, "def": self.def.0, "offset": self.offset })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Data> {
+        Some(Data {
+            url: value["url"].as_str()?.to_string(),
+            def: DefId(value["def"].as_u64()? as u32),
+            offset: value["offset"].as_u64().map(|offset| offset as usize),
+        })
+    }
+}
+
+fn kind(kind: TokenKind) -> lsp::CompletionItemKind {
+    match kind {
+        TokenKind::Namespace => lsp::CompletionItemKind::MODULE,
+        TokenKind::Type | TokenKind::TypeParameter => lsp::CompletionItemKind::TYPE_PARAMETER,
+        TokenKind::Struct => lsp::CompletionItemKind::STRUCT,
+        TokenKind::Effect => lsp::CompletionItemKind::INTERFACE,
+        TokenKind::Function => lsp::CompletionItemKind::FUNCTION,
+        TokenKind::Method => lsp::CompletionItemKind::METHOD,
+        TokenKind::Field => lsp::CompletionItemKind::FIELD,
+        TokenKind::Parameter | TokenKind::Variable => lsp::CompletionItemKind::VARIABLE,
+        TokenKind::Keyword => lsp::CompletionItemKind::KEYWORD,
+        TokenKind::String | TokenKind::Number | TokenKind::Comment => lsp::CompletionItemKind::TEXT,
+    }
+}
+
+/// The item of `completion` at `offset` of the document `document`.
+pub fn item(
+    db: &dyn srclang::Db,
+    completion: Completion,
+    document: SourceProgram,
+    offset: usize,
+) -> lsp::CompletionItem {
+    let data = completion.def.map(|(src, def)| {
+        let url = src.url(db);
+        let offset = (url == document.url(db)).then_some(offset);
+        Data { url, def, offset }.to_json()
+    });
+    let insert_text_format = completion
+        .snippet
+        .as_ref()
+        .map(|_| lsp::InsertTextFormat::SNIPPET);
+    lsp::CompletionItem {
+        label: completion.label,
+        kind: Some(kind(completion.kind)),
+        insert_text: completion.snippet,
+        insert_text_format,
+        data,
+        ..Default::default()
+    }
+}
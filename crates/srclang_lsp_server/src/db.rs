@@ -1,158 +1,632 @@
+use futures::future::AbortHandle;
 use lsp::{InitializeParams, InitializeResult, Url};
 
-use lsp_text::RopeExt;
-use salsa::function::DynDb;
-use srclang_collections::Map;
+use ropey::Rope;
 use srclang::{
-    analyzer::{self, span_text},
-    compiler::text::{self, Document, SourceProgram},
-    parser::{
-        self,
-        span::{ByteOrLineColOrCoord, ByteOrLineColOrCoordInterned},
+    analyzer::{
+        completion::{completed, completions},
+        diagnostics::check,
+        docs::{docs, signature},
+        hover::hover,
+        modules::builtin_file,
+        navigation::{self, Target, Workspace},
+        references::{self, Occurrence},
+        resolve::resolve,
+        semantic::semantic_tokens,
+        signature_help::signature_help,
+        symbols::{document_symbols, workspace_symbols},
     },
-    Db,
+    compiler::text::{Document, SourceProgram},
+};
+use srclang_collections::Map;
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
 };
-use std::{borrow::BorrowMut, sync::Mutex};
 use tower_lsp::{jsonrpc, LanguageServer};
 
-use crate::update_channel;
+use crate::{
+    completion::{self, Data},
+    diagnostics,
+    position::{apply, to_offset, to_range},
+    semantic_tokens::{diff, encode},
+    symbols::{self, document_symbol},
+    trace, update_channel, workspace,
+};
+
+/// Finds where to jump to from an offset of a file, see [`navigation`].
+type Navigate = fn(&dyn srclang::Db, Workspace, SourceProgram, usize) -> Option<Target>;
 
 pub struct LspServerDatabase {
-    db: Mutex<src_lang::analyzer::db::Database>,
+    db: Mutex<srclang::analyzer::db::Database>,
+    /// The open documents, edited in place as changes come in. Their program in `programs`
+    /// holds the same text.
+    files: Mutex<Map<Url, Document>>,
+    /// The program of every file of the workspace, open or not. Each keeps its identity as
+    /// its text changes, so salsa recomputes only what the change affects.
+    programs: Mutex<Map<Url, SourceProgram>>,
+    /// A program for each document standing in for it with a name or a parenthesis typed,
+    /// see [`completed`] and [`signature_help`]. Salsa never frees an input, so these are
+    /// kept and their text set again rather than made anew for each request.
+    scratches: Mutex<Map<Url, SourceProgram>>,
+    workspace: Workspace,
+    /// The publication of diagnostics each document has waiting for its text to settle,
+    /// aborted when the document changes again.
+    publications: Mutex<Map<Url, AbortHandle>>,
+    /// The last full semantic tokens sent for each document, deltas are computed against them.
+    tokens: Mutex<Map<Url, lsp::SemanticTokens>>,
+    next_result_id: AtomicU64,
 }
 
 impl LspServerDatabase {
     pub fn new() -> Self {
+        let db = srclang::analyzer::db::Database::default();
+        let workspace = Workspace::new(&db, vec![]);
         Self {
-            db: Mutex::new(src_lang::analyzer::db::Database::default()),
+            db: Mutex::new(db),
+            files: Mutex::new(Map::default()),
+            programs: Mutex::new(Map::default()),
+            scratches: Mutex::new(Map::default()),
+            workspace,
+            publications: Mutex::new(Map::default()),
+            tokens: Mutex::new(Map::default()),
+            next_result_id: AtomicU64::new(0),
         }
     }
-}
 
-// create a global singleton for input files input_files: Map<Url, SourceProgram>,
-// so that we can access it from the analyzer it should be a mutable map so that we can add new files to it
-// we can use a Mutex to make it thread safe
-lazy_static::lazy_static! {
-    static ref FILES: Mutex<Map<Url, Document>> = Mutex::new(Map::default());
+    /// The program and text of the document at `url`, `None` when it is not open.
+    fn source(&self, db: &dyn srclang::Db, url: &Url) -> Option<(SourceProgram, Rope)> {
+        let document = *self.files.lock().unwrap().get(url)?;
+        let src = *self.programs.lock().unwrap().get(url)?;
+        Some((src, document.text(db)))
+    }
+
+    /// The scratch program of the document at `url`, see `scratches`.
+    fn scratch(&self, db: &mut srclang::analyzer::db::Database, url: &Url) -> SourceProgram {
+        *self
+            .scratches
+            .lock()
+            .unwrap()
+            .entry(url.clone())
+            .or_insert_with(|| SourceProgram::new(db, url.to_string(), String::new()))
+    }
+
+    /// Sets the text of the program at `url`, adding it to the workspace when it is new.
+    fn set_program(&self, db: &mut srclang::analyzer::db::Database, url: &Url, text: String) {
+        let mut programs = self.programs.lock().unwrap();
+        match programs.get(url) {
+            Some(src) => {
+                src.set_text(db).to(text);
+            }
+            None => {
+                programs.insert(url.clone(), SourceProgram::new(db, url.to_string(), text));
+                self.update_workspace(db, &programs);
+            }
+        }
+    }
+
+    /// Drops the program at `url` from the workspace.
+    fn remove_program(&self, db: &mut srclang::analyzer::db::Database, url: &Url) {
+        let mut programs = self.programs.lock().unwrap();
+        if programs.remove(url).is_some() {
+            self.update_workspace(db, &programs);
+        }
+    }
+
+    fn update_workspace(
+        &self,
+        db: &mut srclang::analyzer::db::Database,
+        programs: &Map<Url, SourceProgram>,
+    ) {
+        let mut files: Vec<_> = programs.iter().collect();
+        files.sort_by_key(|(url, _)| *url);
+        let files = files.into_iter().map(|(_, &src)| src).collect();
+        self.workspace.set_files(db).to(files);
+    }
+
+    /// Reads the `.src` files of `folders` into the workspace.
+    pub fn load_workspace(&self, folders: &[Url]) {
+        let db = &mut *self.db.lock().unwrap();
+        for url in folders.iter().flat_map(workspace::scan) {
+            if let Some(text) = workspace::read(&url) {
+                self.set_program(db, &url, text);
+            }
+        }
+    }
+
+    /// The program, text and offset of `position`, `None` when its document is not open.
+    fn at(
+        &self,
+        db: &dyn srclang::Db,
+        position: &lsp::TextDocumentPositionParams,
+    ) -> Option<(SourceProgram, Rope, usize)> {
+        let (src, text) = self.source(db, &position.text_document.uri)?;
+        let offset = to_offset(&text, position.position);
+        Some((src, text, offset))
+    }
+
+    /// The location `navigate` finds from `position` in the document at `url`.
+    pub fn navigate(
+        &self,
+        position: lsp::TextDocumentPositionParams,
+        navigate: Navigate,
+    ) -> Option<lsp::Location> {
+        let db = &*self.db.lock().unwrap();
+        let (src, text, offset) = self.at(db, &position)?;
+        let target = navigate(db, self.workspace, src, offset)?;
+        let uri = Url::parse(&target.src.url(db)).ok()?;
+        let text = match target.src == src {
+            true => text,
+            false => Rope::from_str(target.src.text(db)),
+        };
+        let span = target.span.start.offset..target.span.end.offset;
+        Some(lsp::Location::new(uri, to_range(&text, span)))
+    }
+
+    /// The file a completion item was completed from, see [`Data`].
+    fn completed_file(
+        &self,
+        db: &mut srclang::analyzer::db::Database,
+        data: &Data,
+    ) -> Option<SourceProgram> {
+        let url = Url::parse(&data.url).ok()?;
+        if let Some(offset) = data.offset {
+            let (src, _) = self.source(db, &url)?;
+            let scratch = self.scratch(db, &url);
+            return Some(completed(db, src, scratch, offset));
+        }
+        match self.programs.lock().unwrap().get(&url) {
+            Some(&src) => Some(src),
+            None => builtin_file(db, &data.url),
+        }
+    }
+
+    /// The semantic tokens of the document at `url` within the bytes `range`, all of them
+    /// without a range.
+    fn encoded_tokens(
+        &self,
+        url: &Url,
+        range: Option<std::ops::Range<usize>>,
+    ) -> Option<Vec<lsp::SemanticToken>> {
+        let db = &*self.db.lock().unwrap();
+        let (src, text) = self.source(db, url)?;
+        Some(encode(&text, semantic_tokens(db, src), range))
+    }
+
+    /// All the semantic tokens of `url`, remembered under a new result id.
+    fn full_tokens(&self, url: &Url) -> Option<lsp::SemanticTokens> {
+        let data = self.encoded_tokens(url, None)?;
+        let id = self.next_result_id.fetch_add(1, Ordering::Relaxed);
+        let tokens = lsp::SemanticTokens {
+            result_id: Some(id.to_string()),
+            data,
+        };
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(url.clone(), tokens.clone());
+        Some(tokens)
+    }
+
+    /// Makes `publication` the one pending for `url`, aborting the previous one.
+    pub fn schedule(&self, url: &Url, publication: AbortHandle) {
+        let previous = self
+            .publications
+            .lock()
+            .unwrap()
+            .insert(url.clone(), publication);
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+    }
+
+    /// Aborts the publication pending for `url`, if any.
+    pub fn cancel(&self, url: &Url) {
+        if let Some(publication) = self.publications.lock().unwrap().remove(url) {
+            publication.abort();
+        }
+    }
+
+    /// The diagnostics of the document at `url`, `None` when it is not open.
+    pub fn diagnostics(&self, url: &Url) -> Option<Vec<lsp::Diagnostic>> {
+        let db = &*self.db.lock().unwrap();
+        let (src, text) = self.source(db, url)?;
+        Some(
+            check(db, src)
+                .iter()
+                .map(|diagnostic| diagnostics::to_lsp(url, &text, diagnostic))
+                .collect(),
+        )
+    }
+}
+/// The url and range of each of `occurrences`.
+fn locate<'a>(
+    db: &dyn srclang::Db,
+    occurrences: &'a [Occurrence],
+) -> Vec<(Url, lsp::Range, &'a Occurrence)> {
+    // the occurrences of one file are next to each other
+    let mut text: Option<(SourceProgram, Rope)> = None;
+    occurrences
+        .iter()
+        .filter_map(|occurrence| {
+            let uri = Url::parse(&occurrence.src.url(db)).ok()?;
+            if text.as_ref().map(|(src, _)| *src) != Some(occurrence.src) {
+                text = Some((occurrence.src, Rope::from_str(occurrence.src.text(db))));
+            }
+            let (_, text) = text.as_ref()?;
+            Some((uri, to_range(text, occurrence.span.clone()), occurrence))
+        })
+        .collect()
 }
+
 #[tower_lsp::async_trait]
 impl LanguageServer for LspServerDatabase {
-    async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
-        web_sys::console::log_1(&"server::initialize".into());
+    async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+        trace("server::initialize");
         Ok(InitializeResult {
             ..InitializeResult::default()
         })
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
-        web_sys::console::log_1(&"server::shutdown".into());
+        trace("server::shutdown");
         Ok(())
     }
 
     async fn initialized(&self, _: lsp::InitializedParams) {
-        web_sys::console::log_1(&"server::initialized".into());
+        trace("server::initialized");
     }
 
     async fn did_open(&self, params: lsp::DidOpenTextDocumentParams) {
         let url = params.text_document.uri;
         let text = params.text_document.text;
-        let db = &*self.db.lock().unwrap();
-        let document = text::Document::new(db, url.to_string(), ropey::Rope::from_str(&text));
-        FILES.lock().unwrap().insert(url, document);
+        let db = &mut *self.db.lock().unwrap();
+        let rope = Rope::from_str(&text);
+        let document = self.files.lock().unwrap().get(&url).copied();
+        match document {
+            Some(document) => {
+                document.set_text(db).to(rope);
+            }
+            None => {
+                let document = Document::new(db, url.to_string(), rope);
+                self.files.lock().unwrap().insert(url.clone(), document);
+            }
+        }
+        self.set_program(db, &url, text.clone());
         update_channel(&text);
     }
 
     async fn did_change(&self, params: lsp::DidChangeTextDocumentParams) {
         let url = params.text_document.uri;
-        let db = &*self.db.lock().unwrap();
-        let mut files = FILES.lock().unwrap();
-        let file = files.get(&url).unwrap();
-        let mut rope = file.text(db);
-        let text_edit = rope.build_edit(&params.content_changes[0]).unwrap();
-        rope.apply_edit(&text_edit);
-        let text = rope.clone().to_string();
-        files.insert(url.clone(), text::Document::new(db, url.to_string(), rope));
+        let db = &mut *self.db.lock().unwrap();
+        let Some(document) = self.files.lock().unwrap().get(&url).copied() else {
+            return;
+        };
+        // each change is to the text the ones before it left
+        let mut rope = document.text(db);
+        for change in params.content_changes.iter() {
+            apply(&mut rope, change);
+        }
+        let text = rope.to_string();
+        document.set_text(db).to(rope);
+        self.set_program(db, &url, text.clone());
         update_channel(&text);
     }
 
+    async fn did_close(&self, params: lsp::DidCloseTextDocumentParams) {
+        let url = params.text_document.uri;
+        let db = &mut *self.db.lock().unwrap();
+        self.files.lock().unwrap().remove(&url);
+        self.tokens.lock().unwrap().remove(&url);
+        // the editor may have dropped unsaved changes, what is on disk stays in the workspace
+        match workspace::read(&url) {
+            Some(text) => self.set_program(db, &url, text),
+            None => self.remove_program(db, &url),
+        }
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: lsp::SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensResult>> {
+        let tokens = self.full_tokens(&params.text_document.uri);
+        Ok(tokens.map(lsp::SemanticTokensResult::Tokens))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: lsp::SemanticTokensDeltaParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensFullDeltaResult>> {
+        let url = params.text_document.uri;
+        let previous = self.tokens.lock().unwrap().get(&url).cloned();
+        let Some(tokens) = self.full_tokens(&url) else {
+            return Ok(None);
+        };
+        // the client may ask for a delta to a result that was replaced since
+        let result = match previous {
+            Some(previous) if previous.result_id == Some(params.previous_result_id) => {
+                lsp::SemanticTokensFullDeltaResult::TokensDelta(lsp::SemanticTokensDelta {
+                    result_id: tokens.result_id,
+                    edits: diff(&previous.data, &tokens.data),
+                })
+            }
+            _ => lsp::SemanticTokensFullDeltaResult::Tokens(tokens),
+        };
+        Ok(Some(result))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: lsp::SemanticTokensRangeParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensRangeResult>> {
+        let url = params.text_document.uri;
+        let range = {
+            let db = &*self.db.lock().unwrap();
+            let Some(document) = self.files.lock().unwrap().get(&url).copied() else {
+                return Ok(None);
+            };
+            let text = document.text(db);
+            to_offset(&text, params.range.start)..to_offset(&text, params.range.end)
+        };
+        let tokens = self.encoded_tokens(&url, Some(range));
+        Ok(tokens.map(|data| {
+            lsp::SemanticTokensRangeResult::Tokens(lsp::SemanticTokens {
+                result_id: None,
+                data,
+            })
+        }))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: lsp::GotoDefinitionParams,
+    ) -> jsonrpc::Result<Option<lsp::GotoDefinitionResponse>> {
+        let location = self.navigate(params.text_document_position_params, navigation::definition);
+        Ok(location.map(lsp::GotoDefinitionResponse::Scalar))
+    }
+
+    async fn goto_declaration(
+        &self,
+        params: lsp::request::GotoDeclarationParams,
+    ) -> jsonrpc::Result<Option<lsp::request::GotoDeclarationResponse>> {
+        let location = self.navigate(
+            params.text_document_position_params,
+            navigation::declaration,
+        );
+        Ok(location.map(lsp::GotoDefinitionResponse::Scalar))
+    }
+
+    async fn goto_type_definition(
+        &self,
+        params: lsp::request::GotoTypeDefinitionParams,
+    ) -> jsonrpc::Result<Option<lsp::request::GotoTypeDefinitionResponse>> {
+        let location = self.navigate(
+            params.text_document_position_params,
+            navigation::type_definition,
+        );
+        Ok(location.map(lsp::GotoDefinitionResponse::Scalar))
+    }
+
     async fn document_symbol(
         &self,
         params: lsp::DocumentSymbolParams,
     ) -> jsonrpc::Result<Option<lsp::DocumentSymbolResponse>> {
-        web_sys::console::log_1(&"server::document_symbol".into());
         let db = &*self.db.lock().unwrap();
+        let Some((src, text)) = self.source(db, &params.text_document.uri) else {
+            return Ok(None);
+        };
+        let symbols = document_symbols(db, self.workspace, src)
+            .iter()
+            .map(|symbol| document_symbol(&text, symbol))
+            .collect();
+        Ok(Some(lsp::DocumentSymbolResponse::Nested(symbols)))
+    }
 
-        Ok(None)
+    async fn symbol(
+        &self,
+        params: lsp::WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp::SymbolInformation>>> {
+        let db = &*self.db.lock().unwrap();
+        let symbols = workspace_symbols(db, self.workspace, &params.query);
+        let mut text: Option<(SourceProgram, Rope)> = None;
+        let found = symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let uri = Url::parse(&symbol.src.url(db)).ok()?;
+                if text.as_ref().map(|(src, _)| *src) != Some(symbol.src) {
+                    text = Some((symbol.src, Rope::from_str(symbol.src.text(db))));
+                }
+                let (_, text) = text.as_ref()?;
+                let range = to_range(text, symbol.name_span.clone());
+                #[allow(deprecated)]
+                Some(lsp::SymbolInformation {
+                    name: symbol.name.clone(),
+                    kind: symbols::kind(symbol.kind),
+                    tags: None,
+                    deprecated: None,
+                    location: lsp::Location::new(uri, range),
+                    container_name: symbol.container.clone(),
+                })
+            })
+            .collect();
+        Ok(Some(found))
     }
 
     async fn hover(&self, params: lsp::HoverParams) -> jsonrpc::Result<Option<lsp::Hover>> {
-        web_sys::console::log_1(&"server::hover".into());
         let db = &*self.db.lock().unwrap();
-        let url = params.text_document_position_params.text_document.uri;
-        let position = params.text_document_position_params.position;
-        let files = FILES.lock().unwrap();
-        let text = files.get(&url).unwrap();
-        let text = text.text(db);
-        let text = text::SourceProgram::new(db, url.to_string(), text.to_string());
-        let line: usize = position.line.try_into().unwrap();
-        let character: usize = position.character.try_into().unwrap();
-        web_sys::console::log_1(&format!("line: {}, character: {}", line, character).into());
-        let spanned = analyzer::get_symbol(
-            db,
-            text,
-            ByteOrLineColOrCoordInterned::new(db, ByteOrLineColOrCoord::LineCol(line, character)),
-        );
-
-        web_sys::console::log_1(&format!("{:#?}", spanned).into());
-        let hover = spanned.map(|span| {
-            // let text = span_text(db, span);
-            lsp::Hover {
-                contents: lsp::HoverContents::Markup(lsp::MarkupContent {
-                    kind: lsp::MarkupKind::Markdown,
-                    value: format!("```src\n{:#?}\n```src anaylzer", span),
-                }),
-                range: None,
-            }
+        let Some((src, text, offset)) = self.at(db, &params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let hover = hover(db, self.workspace, src, offset).map(|hover| lsp::Hover {
+            contents: lsp::HoverContents::Markup(lsp::MarkupContent {
+                kind: lsp::MarkupKind::Markdown,
+                value: hover.markdown,
+            }),
+            range: Some(to_range(&text, hover.span)),
         });
         Ok(hover)
     }
 
+    async fn completion(
+        &self,
+        params: lsp::CompletionParams,
+    ) -> jsonrpc::Result<Option<lsp::CompletionResponse>> {
+        let db = &mut *self.db.lock().unwrap();
+        let position = &params.text_document_position;
+        let Some((src, _, offset)) = self.at(db, position) else {
+            return Ok(None);
+        };
+        let scratch = self.scratch(db, &position.text_document.uri);
+        let completed = completed(db, src, scratch, offset);
+        let items = completions(db, self.workspace, completed, offset)
+            .into_iter()
+            .map(|found| completion::item(db, found, src, offset))
+            .collect();
+        Ok(Some(lsp::CompletionResponse::Array(items)))
+    }
+
+    async fn completion_resolve(
+        &self,
+        mut item: lsp::CompletionItem,
+    ) -> jsonrpc::Result<lsp::CompletionItem> {
+        let db = &mut *self.db.lock().unwrap();
+        let Some(data) = item.data.as_ref().and_then(Data::from_json) else {
+            return Ok(item);
+        };
+        let Some(src) = self.completed_file(db, &data) else {
+            return Ok(item);
+        };
+        // the file may have changed since the item was sent
+        let res = resolve(db, src);
+        if !res
+            .iter()
+            .any(|(id, def)| id == data.def && def.name == item.label)
+        {
+            return Ok(item);
+        }
+        item.detail = Some(signature(db, src, data.def));
+        item.documentation = docs(db, src, data.def).map(|docs| {
+            lsp::Documentation::MarkupContent(lsp::MarkupContent {
+                kind: lsp::MarkupKind::Markdown,
+                value: docs,
+            })
+        });
+        Ok(item)
+    }
+
+    async fn signature_help(
+        &self,
+        params: lsp::SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<lsp::SignatureHelp>> {
+        let db = &mut *self.db.lock().unwrap();
+        let position = &params.text_document_position_params;
+        let Some((src, _, offset)) = self.at(db, position) else {
+            return Ok(None);
+        };
+        let scratch = self.scratch(db, &position.text_document.uri);
+        let Some(help) = signature_help(db, self.workspace, src, scratch, offset) else {
+            return Ok(None);
+        };
+        // offsets into the label count UTF-16 code units, like positions
+        let units = |bytes: usize| help.label[..bytes].encode_utf16().count() as u32;
+        let parameters: Vec<_> = help
+            .params
+            .iter()
+            .map(|param| lsp::ParameterInformation {
+                label: lsp::ParameterLabel::LabelOffsets([units(param.start), units(param.end)]),
+                documentation: None,
+            })
+            .collect();
+        let active = (help.active < parameters.len()).then_some(help.active as u32);
+        let signature = lsp::SignatureInformation {
+            label: help.label.clone(),
+            documentation: Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+                kind: lsp::MarkupKind::Markdown,
+                value: help.documentation.clone(),
+            })),
+            parameters: Some(parameters),
+            active_parameter: active,
+        };
+        Ok(Some(lsp::SignatureHelp {
+            signatures: vec![signature],
+            active_signature: Some(0),
+            active_parameter: active,
+        }))
+    }
+
     async fn document_highlight(
         &self,
         params: lsp::DocumentHighlightParams,
     ) -> jsonrpc::Result<Option<Vec<lsp::DocumentHighlight>>> {
-        web_sys::console::log_1(&"server::document_highlight".into());
         let db = &*self.db.lock().unwrap();
-        let url = params.text_document_position_params.text_document.uri;
-        let position = params.text_document_position_params.position;
-        let text = FILES.lock().unwrap();
-        let text = text.get(&url).unwrap();
-        let text = text.text(db);
-        let text = text::SourceProgram::new(db, url.to_string(), text.to_string());
-        let text = analyzer::get_symbol(
-            db,
-            text,
-            ByteOrLineColOrCoordInterned::new(
-                db,
-                ByteOrLineColOrCoord::LineCol(position.line as usize, position.character as usize),
-            ),
-        );
-        let hightlight_kind = lsp::DocumentHighlightKind::TEXT;
-        let highlights = text.map(|text| {
-            vec![lsp::DocumentHighlight {
-                range: lsp::Range {
-                    start: lsp::Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: lsp::Position {
-                        line: 0,
-                        character: 0,
-                    },
-                },
-                kind: Some(hightlight_kind),
-            }]
-        });
-        Ok(highlights)
+        let Some((src, _, offset)) = self.at(db, &params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let occurrences = references::highlights(db, self.workspace, src, offset);
+        let highlights = locate(db, &occurrences)
+            .into_iter()
+            .map(|(_, range, occurrence)| lsp::DocumentHighlight {
+                range,
+                // src has no assignment, a name is only written where it is bound
+                kind: Some(match occurrence.declaration {
+                    true => lsp::DocumentHighlightKind::WRITE,
+                    false => lsp::DocumentHighlightKind::READ,
+                }),
+            })
+            .collect();
+        Ok(Some(highlights))
+    }
+
+    async fn references(
+        &self,
+        params: lsp::ReferenceParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp::Location>>> {
+        let db = &*self.db.lock().unwrap();
+        let Some((src, _, offset)) = self.at(db, &params.text_document_position) else {
+            return Ok(None);
+        };
+        let occurrences = references::references(db, self.workspace, src, offset);
+        let locations = locate(db, &occurrences)
+            .into_iter()
+            .filter(|(_, _, occurrence)| {
+                params.context.include_declaration || !occurrence.declaration
+            })
+            .map(|(uri, range, _)| lsp::Location::new(uri, range))
+            .collect();
+        Ok(Some(locations))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: lsp::TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<lsp::PrepareRenameResponse>> {
+        let db = &*self.db.lock().unwrap();
+        let Some((src, text, offset)) = self.at(db, &params) else {
+            return Ok(None);
+        };
+        let span = references::prepare_rename(db, self.workspace, src, offset)
+            .map_err(jsonrpc::Error::invalid_params)?;
+        Ok(Some(lsp::PrepareRenameResponse::Range(to_range(
+            &text, span,
+        ))))
+    }
+
+    async fn rename(
+        &self,
+        params: lsp::RenameParams,
+    ) -> jsonrpc::Result<Option<lsp::WorkspaceEdit>> {
+        let db = &*self.db.lock().unwrap();
+        let Some((src, _, offset)) = self.at(db, &params.text_document_position) else {
+            return Ok(None);
+        };
+        let occurrences = references::rename(db, self.workspace, src, offset, &params.new_name)
+            .map_err(jsonrpc::Error::invalid_params)?;
+        let mut changes: HashMap<Url, Vec<lsp::TextEdit>> = HashMap::new();
+        for (uri, range, _) in locate(db, &occurrences) {
+            let edit = lsp::TextEdit::new(range, params.new_name.clone());
+            changes.entry(uri).or_default().push(edit);
+        }
+        Ok(Some(lsp::WorkspaceEdit::new(changes)))
     }
 }
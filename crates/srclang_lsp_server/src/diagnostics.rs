@@ -0,0 +1,43 @@
+//! The problems the analysis finds in open documents, as the client shows them.
+
+use std::time::Duration;
+
+use ropey::Rope;
+use srclang::analyzer::diagnostics::{Diagnostic, Severity};
+
+use crate::position::to_range;
+
+/// How long the text has to stay the same before its diagnostics are published, so typing
+/// does not check every intermediate state of the document.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+fn severity(severity: Severity) -> lsp::DiagnosticSeverity {
+    match severity {
+        Severity::Error => lsp::DiagnosticSeverity::ERROR,
+        Severity::Warning => lsp::DiagnosticSeverity::WARNING,
+        Severity::Hint => lsp::DiagnosticSeverity::HINT,
+    }
+}
+
+/// `diagnostic` of the document at `uri` with `text`, its related spans are in the same document.
+pub fn to_lsp(uri: &lsp::Url, text: &Rope, diagnostic: &Diagnostic) -> lsp::Diagnostic {
+    let span = |span: &std::ops::Range<srclang::lexer::Location>| {
+        to_range(text, span.start.offset..span.end.offset)
+    };
+    let related: Vec<lsp::DiagnosticRelatedInformation> = diagnostic
+        .related
+        .iter()
+        .map(|(related, message)| lsp::DiagnosticRelatedInformation {
+            location: lsp::Location::new(uri.clone(), span(related)),
+            message: message.clone(),
+        })
+        .collect();
+    lsp::Diagnostic {
+        range: span(&diagnostic.span),
+        severity: Some(severity(diagnostic.severity)),
+        source: Some("src".to_string()),
+        message: diagnostic.message.clone(),
+        related_information: (!related.is_empty()).then_some(related),
+        ..Default::default()
+    }
+}
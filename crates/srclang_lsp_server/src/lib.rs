@@ -5,10 +5,47 @@
 // pub mod handler;
 mod server;
 
+mod completion;
+
 mod db;
 
-use srclang::compiler;
+mod diagnostics;
+
+mod position;
+
+mod semantic_tokens;
+
+mod symbols;
+
+mod workspace;
+
+#[cfg(feature = "stdio")]
+mod stdio;
 
 pub use server::*;
 
+#[cfg(feature = "stdio")]
+pub use stdio::{serve, serve_stdio};
+
 pub use srclang::Jar;
+
+/// Writes `message` to the browser console with the `wasm` feature and to [`log`] otherwise,
+/// stdout is the protocol channel when serving editors.
+pub(crate) fn trace(message: &str) {
+    #[cfg(feature = "wasm")]
+    web_sys::console::log_1(&message.into());
+    #[cfg(not(feature = "wasm"))]
+    log::trace!("{}", message);
+}
+
+/// Runs `future` in the background, on the page's event loop with the `wasm` feature and
+/// on tokio when serving editors over stdio.
+#[cfg(feature = "wasm")]
+pub(crate) fn spawn(future: impl std::future::Future<Output = ()> + Send + 'static) {
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+#[cfg(all(feature = "stdio", not(feature = "wasm")))]
+pub(crate) fn spawn(future: impl std::future::Future<Output = ()> + Send + 'static) {
+    tokio::spawn(future);
+}
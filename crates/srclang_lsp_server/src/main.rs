@@ -0,0 +1,9 @@
+//! `srclang-lsp`, the src language server for editors.
+//!
+//! Speaks the protocol on stdin and stdout, configure the editor to start the binary for
+//! `.src` files.
+
+#[tokio::main]
+async fn main() {
+    srclang_lsp_server::serve_stdio().await;
+}
@@ -0,0 +1,98 @@
+//! Conversions between byte offsets into a document and protocol positions, whose
+//! characters count UTF-16 code units.
+
+use std::ops::Range;
+
+use ropey::Rope;
+
+/// The position of byte `offset` of `text`, past the end is clamped to the end.
+pub fn to_position(text: &Rope, offset: usize) -> lsp::Position {
+    let offset = offset.min(text.len_bytes());
+    let line = text.byte_to_line(offset);
+    let char = text.byte_to_char(offset);
+    let line_start = text.line_to_char(line);
+    let character = text.char_to_utf16_cu(char) - text.char_to_utf16_cu(line_start);
+    lsp::Position::new(line as u32, character as u32)
+}
+
+pub fn to_range(text: &Rope, span: Range<usize>) -> lsp::Range {
+    lsp::Range::new(to_position(text, span.start), to_position(text, span.end))
+}
+
+/// The byte offset of `position` in `text`, positions past the end of a line or of the
+/// text are clamped to it.
+pub fn to_offset(text: &Rope, position: lsp::Position) -> usize {
+    let line = position.line as usize;
+    if line >= text.len_lines() {
+        return text.len_bytes();
+    }
+    let line_start = text.line_to_char(line);
+    let mut line_end = line_start + text.line(line).len_chars();
+    while line_end > line_start && matches!(text.char(line_end - 1), '\n' | '\r') {
+        line_end -= 1;
+    }
+    let units = text.char_to_utf16_cu(line_start) + position.character as usize;
+    let char = text
+        .utf16_cu_to_char(units.min(text.len_utf16_cu()))
+        .min(line_end);
+    text.char_to_byte(char)
+}
+
+/// Applies `change` to `text`, a change without a range replaces the whole text.
+pub fn apply(text: &mut Rope, change: &lsp::TextDocumentContentChangeEvent) {
+    let Some(range) = change.range else {
+        *text = Rope::from_str(&change.text);
+        return;
+    };
+    let start = text.byte_to_char(to_offset(text, range.start));
+    let end = text.byte_to_char(to_offset(text, range.end)).max(start);
+    text.remove(start..end);
+    text.insert(start, &change.text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf16_round_trip() {
+        let text = Rope::from_str("let é = \"😀\"\nb");
+        let offset = "let é = \"😀".len();
+        let position = to_position(&text, offset);
+        // `é` is one code unit, the emoji two
+        assert_eq!(position, lsp::Position::new(0, 11));
+        assert_eq!(to_offset(&text, position), offset);
+        assert_eq!(
+            to_position(&text, text.len_bytes()),
+            lsp::Position::new(1, 1)
+        );
+        assert_eq!(
+            to_offset(&text, lsp::Position::new(0, 100)),
+            offset + "\"\n".len() - 1
+        );
+        assert_eq!(to_offset(&text, lsp::Position::new(5, 0)), text.len_bytes());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut text = Rope::from_str("let a = \"😀\"\nb");
+        let change = |range: Option<lsp::Range>, text: &str| lsp::TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.to_string(),
+        };
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Some(lsp::Range::new(
+                lsp::Position::new(start.0, start.1),
+                lsp::Position::new(end.0, end.1),
+            ))
+        };
+        apply(&mut text, &change(range((0, 8), (0, 12)), "1"));
+        assert_eq!(text.to_string(), "let a = 1\nb");
+        apply(&mut text, &change(range((1, 1), (1, 1)), " = a"));
+        apply(&mut text, &change(range((0, 9), (1, 0)), "\nlet "));
+        assert_eq!(text.to_string(), "let a = 1\nlet b = a");
+        apply(&mut text, &change(None, "c"));
+        assert_eq!(text.to_string(), "c");
+    }
+}
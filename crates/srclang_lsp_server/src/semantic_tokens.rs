@@ -0,0 +1,207 @@
+//! Semantic highlighting, the tokens the analyzer classified encoded the way the protocol
+//! sends them: relative to the token before, in UTF-16 code units, one line at a time.
+
+use std::ops::Range;
+
+use ropey::Rope;
+use srclang::analyzer::semantic::{Modifiers, SemanticToken, TokenKind};
+
+use crate::position::to_position;
+
+/// The token types of the legend, a token's type is its index here.
+const TYPES: &[(TokenKind, lsp::SemanticTokenType)] = &[
+    (TokenKind::Namespace, lsp::SemanticTokenType::NAMESPACE),
+    (TokenKind::Type, lsp::SemanticTokenType::TYPE),
+    (TokenKind::Struct, lsp::SemanticTokenType::STRUCT),
+    (
+        TokenKind::TypeParameter,
+        lsp::SemanticTokenType::TYPE_PARAMETER,
+    ),
+    // effects declare operations for handlers to implement, the closest standard type
+    (TokenKind::Effect, lsp::SemanticTokenType::INTERFACE),
+    (TokenKind::Function, lsp::SemanticTokenType::FUNCTION),
+    (TokenKind::Method, lsp::SemanticTokenType::METHOD),
+    (TokenKind::Field, lsp::SemanticTokenType::PROPERTY),
+    (TokenKind::Parameter, lsp::SemanticTokenType::PARAMETER),
+    (TokenKind::Variable, lsp::SemanticTokenType::VARIABLE),
+    (TokenKind::Keyword, lsp::SemanticTokenType::KEYWORD),
+    (TokenKind::String, lsp::SemanticTokenType::STRING),
+    (TokenKind::Number, lsp::SemanticTokenType::NUMBER),
+    (TokenKind::Comment, lsp::SemanticTokenType::COMMENT),
+];
+
+/// The token modifiers of the legend, bit `i` of a token's set is the modifier at `i`.
+const MODIFIERS: &[(Modifiers, lsp::SemanticTokenModifier)] = &[
+    (
+        Modifiers::DECLARATION,
+        lsp::SemanticTokenModifier::DECLARATION,
+    ),
+    (Modifiers::PUBLIC, lsp::SemanticTokenModifier::new("public")),
+    (
+        Modifiers::MUTABLE,
+        lsp::SemanticTokenModifier::new("mutable"),
+    ),
+];
+
+pub fn legend() -> lsp::SemanticTokensLegend {
+    lsp::SemanticTokensLegend {
+        token_types: TYPES.iter().map(|(_, ty)| ty.clone()).collect(),
+        token_modifiers: MODIFIERS
+            .iter()
+            .map(|(_, modifier)| modifier.clone())
+            .collect(),
+    }
+}
+
+fn token_type(kind: TokenKind) -> u32 {
+    TYPES.iter().position(|(ty, _)| *ty == kind).unwrap() as u32
+}
+
+fn modifiers_bitset(modifiers: Modifiers) -> u32 {
+    MODIFIERS
+        .iter()
+        .enumerate()
+        .filter(|(_, (modifier, _))| modifiers.contains(*modifier))
+        .fold(0, |set, (i, _)| set | 1 << i)
+}
+
+/// The tokens overlapping the bytes `range` of `text`, all of them without a range.
+pub fn encode(
+    text: &Rope,
+    tokens: &[SemanticToken],
+    range: Option<Range<usize>>,
+) -> Vec<lsp::SemanticToken> {
+    let mut encoded = vec![];
+    let mut last = lsp::Position::new(0, 0);
+    for token in tokens {
+        if let Some(range) = &range {
+            if token.span.end <= range.start || range.end <= token.span.start {
+                continue;
+            }
+        }
+        let start = to_position(text, token.span.start);
+        let end = to_position(text, token.span.end);
+        // a string spanning lines is sent a line at a time
+        for line in start.line..=end.line {
+            let from = if line == start.line {
+                start.character
+            } else {
+                0
+            };
+            let to = match line == end.line {
+                true => end.character,
+                false => {
+                    let line_end = text.line_to_byte(line as usize + 1);
+                    to_position(text, line_end.saturating_sub(1)).character
+                }
+            };
+            if to <= from {
+                continue;
+            }
+            encoded.push(lsp::SemanticToken {
+                delta_line: line - last.line,
+                delta_start: match line == last.line {
+                    true => from - last.character,
+                    false => from,
+                },
+                length: to - from,
+                token_type: token_type(token.kind),
+                token_modifiers_bitset: modifiers_bitset(token.modifiers),
+            });
+            last = lsp::Position::new(line, from);
+        }
+    }
+    encoded
+}
+
+/// The edit turning `old` into `new`: the tokens between their common start and end.
+pub fn diff(
+    old: &[lsp::SemanticToken],
+    new: &[lsp::SemanticToken],
+) -> Vec<lsp::SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+    // each token is sent as 5 integers, the edit counts integers
+    vec![lsp::SemanticTokensEdit {
+        start: 5 * prefix as u32,
+        delete_count: 5 * deleted as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(delta_line: u32, delta_start: u32, length: u32) -> lsp::SemanticToken {
+        lsp::SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn test_encode_relative_utf16() {
+        let text = Rope::from_str("let a = \"😀\nb\" c");
+        let tokens = [
+            (0..3, TokenKind::Keyword),
+            (4..5, TokenKind::Variable),
+            (8..16, TokenKind::String),
+            (17..18, TokenKind::Variable),
+        ]
+        .map(|(span, kind)| SemanticToken {
+            span,
+            kind,
+            modifiers: Modifiers::DECLARATION | Modifiers::PUBLIC,
+        });
+        let encoded = encode(&text, &tokens, None);
+        let spans: Vec<_> = encoded
+            .iter()
+            .map(|token| (token.delta_line, token.delta_start, token.length))
+            .collect();
+        // the string is cut at the line break, the emoji is two code units
+        assert_eq!(
+            spans,
+            vec![(0, 0, 3), (0, 4, 1), (0, 4, 3), (1, 0, 2), (0, 3, 1)]
+        );
+        assert_eq!(encoded[0].token_type, token_type(TokenKind::Keyword));
+        assert_eq!(encoded[0].token_modifiers_bitset, 0b011);
+
+        let ranged = encode(&text, &tokens, Some(3..9));
+        assert_eq!(ranged.len(), 3);
+        assert_eq!((ranged[0].delta_start, ranged[0].length), (4, 1));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![token(0, 0, 3), token(0, 4, 1), token(1, 0, 2)];
+        let new = vec![
+            token(0, 0, 3),
+            token(0, 4, 2),
+            token(0, 3, 1),
+            token(1, 0, 2),
+        ];
+        assert_eq!(
+            diff(&old, &new),
+            vec![lsp::SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![token(0, 4, 2), token(0, 3, 1)]),
+            }]
+        );
+        assert_eq!(diff(&new, &new), vec![]);
+    }
+}
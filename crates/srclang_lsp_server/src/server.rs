@@ -1,10 +1,9 @@
-use anyhow::anyhow;
-use srclang::parser;
+use futures::future::{AbortHandle, Abortable};
 use std::result::Result::Ok;
 use std::sync::Arc;
 use tower_lsp::{jsonrpc, lsp_types::*, LanguageServer};
-use wasm_bindgen::JsCast;
-use web_sys::HtmlTextAreaElement;
+
+use crate::{diagnostics::DEBOUNCE, spawn, trace};
 
 pub fn capabilities() -> lsp::ServerCapabilities {
     let document_symbol_provider = Some(lsp::OneOf::Left(true));
@@ -12,28 +11,43 @@ pub fn capabilities() -> lsp::ServerCapabilities {
     let text_document_sync = {
         let options = lsp::TextDocumentSyncOptions {
             open_close: Some(true),
-            change: Some(lsp::TextDocumentSyncKind::FULL),
+            change: Some(lsp::TextDocumentSyncKind::INCREMENTAL),
             ..Default::default()
         };
         Some(lsp::TextDocumentSyncCapability::Options(options))
     };
 
+    let workspace_symbol_provider = Some(lsp::OneOf::Left(true));
+
     let hover_provider = Some(true.into());
 
+    let definition_provider = Some(lsp::OneOf::Left(true));
+    let declaration_provider = Some(lsp::DeclarationCapability::Simple(true));
+    let type_definition_provider = Some(lsp::TypeDefinitionProviderCapability::Simple(true));
+
     let semantic_tokens_provider = Some(
         lsp::SemanticTokensServerCapabilities::SemanticTokensOptions(lsp::SemanticTokensOptions {
-            legend: lsp::SemanticTokensLegend {
-                token_types: vec![],
-                token_modifiers: vec![],
-            },
+            legend: crate::semantic_tokens::legend(),
             range: Some(true),
             full: Some(lsp::SemanticTokensFullOptions::Delta { delta: Some(true) }),
             ..Default::default()
         }),
     );
 
-    let _document_highlight_provider: Option<OneOf<bool, DocumentHighlightOptions>> =
-        Some(lsp::OneOf::Left(true));
+    let document_highlight_provider = Some(lsp::OneOf::Left(true));
+
+    let references_provider = Some(lsp::OneOf::Left(true));
+
+    let rename_provider = Some(lsp::OneOf::Right(lsp::RenameOptions {
+        prepare_provider: Some(true),
+        work_done_progress_options: Default::default(),
+    }));
+
+    let signature_help_provider = Some(lsp::SignatureHelpOptions {
+        trigger_characters: Some(vec!["(".into(), ",".into()]),
+        retrigger_characters: None,
+        work_done_progress_options: Default::default(),
+    });
 
     let code_lens_provider = Some(lsp::CodeLensOptions {
         resolve_provider: Some(true),
@@ -41,21 +55,26 @@ pub fn capabilities() -> lsp::ServerCapabilities {
 
     let completion_provider = Some(CompletionOptions {
         resolve_provider: Some(true),
-        trigger_characters: Some(vec![".".into()]),
-        work_done_progress_options: WorkDoneProgressOptions {
-            work_done_progress: None,
-        },
-        all_commit_characters: None,
+        // members after a dot, effects in the brackets of a prototype
+        trigger_characters: Some(vec![".".into(), "[".into()]),
+        ..Default::default()
     });
 
     lsp::ServerCapabilities {
         text_document_sync,
         document_symbol_provider,
+        workspace_symbol_provider,
         hover_provider,
+        definition_provider,
+        declaration_provider,
+        type_definition_provider,
         semantic_tokens_provider,
-        // document_highlight_provider,
+        document_highlight_provider,
+        references_provider,
+        rename_provider,
         code_lens_provider,
         completion_provider,
+        signature_help_provider,
         ..Default::default()
     }
 }
@@ -72,71 +91,212 @@ impl Server {
             db: Arc::new(crate::db::LspServerDatabase::new()),
         }
     }
+
+    /// Publishes the diagnostics of `uri` once its text stayed the same for [`DEBOUNCE`].
+    /// The wait runs in a task of its own, so the change that scheduled it is handled at
+    /// once, and the next change aborts it.
+    fn publish_diagnostics(&self, uri: Url) {
+        let (client, db) = (self.client.clone(), self.db.clone());
+        let (publication, registration) = AbortHandle::new_pair();
+        self.db.schedule(&uri, publication);
+        let publish = async move {
+            futures_timer::Delay::new(DEBOUNCE).await;
+            let Some(diagnostics) = db.diagnostics(&uri) else {
+                return;
+            };
+            client.publish_diagnostics(uri, diagnostics, None).await;
+        };
+        spawn(async move {
+            let _ = Abortable::new(publish, registration).await;
+        });
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Server {
     async fn initialize(&self, params: InitializeParams) -> jsonrpc::Result<InitializeResult> {
-        web_sys::console::log_1(&"server::initialize".into());
+        trace("server::initialize");
+        #[allow(deprecated)]
+        let folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        self.db.load_workspace(&folders);
         let capabilities = capabilities();
         Ok(InitializeResult {
-            server_info: Some(
-                ServerInfo {
-                    name: "src language server".to_string(),
-                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
-                }
-                .into(),
-            ),
+            server_info: Some(ServerInfo {
+                name: "src language server".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
             capabilities,
         })
     }
 
     async fn initialized(&self, _: lsp::InitializedParams) {
-        web_sys::console::log_1(&"server::initialized".into());
+        trace("server::initialized");
         let typ = lsp::MessageType::INFO;
         let message = "src language server initialized!";
         self.client.log_message(typ, message).await;
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
-        web_sys::console::log_1(&"server::shutdown".into());
+        trace("server::shutdown");
         Ok(())
     }
 
     // FIXME: for some reason this doesn't trigger
     async fn did_open(&self, params: lsp::DidOpenTextDocumentParams) {
-        web_sys::console::log_1(&"server::did_open".into());
+        trace("server::did_open");
+        let uri = params.text_document.uri.clone();
         self.db.did_open(params).await;
+        self.publish_diagnostics(uri);
     }
 
     async fn did_change(&self, params: lsp::DidChangeTextDocumentParams) {
-        web_sys::console::log_1(&"server::did_change".into());
+        trace("server::did_change");
+        let uri = params.text_document.uri.clone();
         self.db.did_change(params).await;
+        self.publish_diagnostics(uri);
+    }
+
+    async fn did_close(&self, params: lsp::DidCloseTextDocumentParams) {
+        trace("server::did_close");
+        let uri = params.text_document.uri.clone();
+        self.db.did_close(params).await;
+        // drops a publication still waiting for the text to settle
+        self.db.cancel(&uri);
+        self.client.publish_diagnostics(uri, vec![], None).await;
+    }
+
+    async fn goto_definition(
+        &self,
+        params: lsp::GotoDefinitionParams,
+    ) -> jsonrpc::Result<Option<lsp::GotoDefinitionResponse>> {
+        trace("server::goto_definition");
+        self.db.goto_definition(params).await
+    }
+
+    async fn goto_declaration(
+        &self,
+        params: lsp::request::GotoDeclarationParams,
+    ) -> jsonrpc::Result<Option<lsp::request::GotoDeclarationResponse>> {
+        trace("server::goto_declaration");
+        self.db.goto_declaration(params).await
+    }
+
+    async fn goto_type_definition(
+        &self,
+        params: lsp::request::GotoTypeDefinitionParams,
+    ) -> jsonrpc::Result<Option<lsp::request::GotoTypeDefinitionResponse>> {
+        trace("server::goto_type_definition");
+        self.db.goto_type_definition(params).await
     }
 
     async fn document_symbol(
         &self,
         params: lsp::DocumentSymbolParams,
     ) -> jsonrpc::Result<Option<lsp::DocumentSymbolResponse>> {
-        web_sys::console::log_1(&"server::document_symbol".into());
+        trace("server::document_symbol");
         self.db.document_symbol(params).await
     }
 
+    async fn symbol(
+        &self,
+        params: lsp::WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp::SymbolInformation>>> {
+        trace("server::symbol");
+        self.db.symbol(params).await
+    }
+
     async fn hover(&self, params: lsp::HoverParams) -> jsonrpc::Result<Option<lsp::Hover>> {
-        web_sys::console::log_1(&"server::hover".into());
+        trace("server::hover");
         self.db.hover(params).await
     }
 
+    async fn signature_help(
+        &self,
+        params: lsp::SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<lsp::SignatureHelp>> {
+        trace("server::signature_help");
+        self.db.signature_help(params).await
+    }
+
     async fn document_highlight(
         &self,
         params: lsp::DocumentHighlightParams,
     ) -> jsonrpc::Result<Option<Vec<lsp::DocumentHighlight>>> {
-        web_sys::console::log_1(&"server::document_highlight".into());
+        trace("server::document_highlight");
         self.db.document_highlight(params).await
     }
+
+    async fn references(
+        &self,
+        params: lsp::ReferenceParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp::Location>>> {
+        trace("server::references");
+        self.db.references(params).await
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: lsp::TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<lsp::PrepareRenameResponse>> {
+        trace("server::prepare_rename");
+        self.db.prepare_rename(params).await
+    }
+
+    async fn rename(
+        &self,
+        params: lsp::RenameParams,
+    ) -> jsonrpc::Result<Option<lsp::WorkspaceEdit>> {
+        trace("server::rename");
+        self.db.rename(params).await
+    }
+
+    async fn completion(
+        &self,
+        params: lsp::CompletionParams,
+    ) -> jsonrpc::Result<Option<lsp::CompletionResponse>> {
+        trace("server::completion");
+        self.db.completion(params).await
+    }
+
+    async fn completion_resolve(
+        &self,
+        item: lsp::CompletionItem,
+    ) -> jsonrpc::Result<lsp::CompletionItem> {
+        trace("server::completion_resolve");
+        self.db.completion_resolve(item).await
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: lsp::SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensResult>> {
+        trace("server::semantic_tokens_full");
+        self.db.semantic_tokens_full(params).await
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: lsp::SemanticTokensDeltaParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensFullDeltaResult>> {
+        trace("server::semantic_tokens_full_delta");
+        self.db.semantic_tokens_full_delta(params).await
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: lsp::SemanticTokensRangeParams,
+    ) -> jsonrpc::Result<Option<lsp::SemanticTokensRangeResult>> {
+        trace("server::semantic_tokens_range");
+        self.db.semantic_tokens_range(params).await
+    }
 }
 
+#[cfg(feature = "wasm")]
 pub fn get_channel_syntax() -> anyhow::Result<web_sys::HtmlTextAreaElement> {
+    use anyhow::anyhow;
     use wasm_bindgen::JsCast;
     let element_id = "channel-syntax";
     let channel_syntax = web_sys::window()
@@ -149,7 +309,13 @@ pub fn get_channel_syntax() -> anyhow::Result<web_sys::HtmlTextAreaElement> {
     Ok(channel_syntax)
 }
 
-pub fn update_channel(input: &str) {
+/// Shows the syntax of `input` in the page hosting the server, there is no page without the
+/// `wasm` feature.
+#[cfg(not(feature = "wasm"))]
+pub fn update_channel(_input: &str) {}
+
+#[cfg(feature = "wasm")]
+pub fn update_channel(_input: &str) {
     // let tree = parser::parse(input);
     // // assume errors; use red
     // let element_id = "channel-syntax";
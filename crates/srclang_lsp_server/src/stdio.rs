@@ -0,0 +1,27 @@
+//! The transport editors talk to the server over.
+//!
+//! tower-lsp is built for any runtime, the tokio streams are adapted to the `futures` io
+//! traits it reads and writes.
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tower_lsp::LspService;
+
+use crate::Server;
+
+/// Serves the protocol on `input` and `output` until the client exits.
+pub async fn serve<I, O>(input: I, output: O)
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    let (service, socket) = LspService::new(Server::new);
+    tower_lsp::Server::new(input.compat(), output.compat_write(), socket)
+        .serve(service)
+        .await;
+}
+
+/// Serves the protocol on the stdin and stdout of the process.
+pub async fn serve_stdio() {
+    serve(tokio::io::stdin(), tokio::io::stdout()).await;
+}
@@ -0,0 +1,44 @@
+//! Document and workspace symbols, the outline the analyzer found in the protocol's types.
+
+use ropey::Rope;
+use srclang::analyzer::symbols::{Symbol, SymbolKind};
+
+use crate::position::to_range;
+
+pub fn kind(kind: SymbolKind) -> lsp::SymbolKind {
+    match kind {
+        SymbolKind::Module => lsp::SymbolKind::MODULE,
+        SymbolKind::Struct => lsp::SymbolKind::STRUCT,
+        SymbolKind::Field => lsp::SymbolKind::FIELD,
+        // effects declare operations for handlers to implement, as in the semantic tokens
+        SymbolKind::Effect => lsp::SymbolKind::INTERFACE,
+        SymbolKind::Operation | SymbolKind::Method => lsp::SymbolKind::METHOD,
+        SymbolKind::Impl => lsp::SymbolKind::OBJECT,
+        SymbolKind::Function => lsp::SymbolKind::FUNCTION,
+        SymbolKind::Unknown => lsp::SymbolKind::VARIABLE,
+    }
+}
+
+/// `symbol` and its children in `text`.
+pub fn document_symbol(text: &Rope, symbol: &Symbol) -> lsp::DocumentSymbol {
+    let children = symbol
+        .children
+        .iter()
+        .map(|child| document_symbol(text, child))
+        .collect();
+    // the protocol wants the range of an item to contain its name
+    let start = symbol.span.start.min(symbol.name_span.start);
+    let end = symbol.span.end.max(symbol.name_span.end);
+    let range = to_range(text, start..end);
+    #[allow(deprecated)]
+    lsp::DocumentSymbol {
+        name: symbol.name.clone(),
+        detail: symbol.detail.clone(),
+        kind: kind(symbol.kind),
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: to_range(text, symbol.name_span.clone()),
+        children: Some(children),
+    }
+}
@@ -0,0 +1,81 @@
+//! The `.src` files of the folders an editor opened, so names imported from a module the
+//! editor has not opened can be followed.
+//!
+//! Files are read from disk when serving editors over stdio, a browser has no disk and
+//! only knows the documents it opened.
+
+use lsp::Url;
+
+/// Directories that hold build output and tooling rather than source files.
+#[cfg(feature = "stdio")]
+const SKIPPED: &[&str] = &["node_modules", "target"];
+
+/// The text of the file at `url`, `None` when it is not a readable file.
+#[cfg(feature = "stdio")]
+pub fn read(url: &Url) -> Option<String> {
+    std::fs::read_to_string(url.to_file_path().ok()?).ok()
+}
+
+#[cfg(not(feature = "stdio"))]
+pub fn read(_url: &Url) -> Option<String> {
+    None
+}
+
+/// The urls of the `.src` files in `folder` and below, hidden directories are skipped.
+/// Symlinks are not followed, one pointing at a parent would never end.
+#[cfg(feature = "stdio")]
+pub fn scan(folder: &Url) -> Vec<Url> {
+    let mut found = vec![];
+    let mut pending = folder.to_file_path().into_iter().collect::<Vec<_>>();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED.contains(&name.as_str()) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() && name.ends_with(".src") {
+                found.extend(Url::from_file_path(path));
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+#[cfg(not(feature = "stdio"))]
+pub fn scan(_folder: &Url) -> Vec<Url> {
+    vec![]
+}
+
+#[cfg(all(test, unix, feature = "stdio"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_skips_symlinks() {
+        let folder = std::env::temp_dir().join(format!("srclang-workspace-{}", std::process::id()));
+        std::fs::create_dir_all(folder.join("lib")).unwrap();
+        std::fs::write(folder.join("main.src"), "").unwrap();
+        std::fs::write(folder.join("lib/util.src"), "").unwrap();
+        std::fs::write(folder.join("lib/notes.txt"), "").unwrap();
+        // a loop back to the folder, and a second name for a file
+        std::os::unix::fs::symlink(&folder, folder.join("lib/up")).unwrap();
+        std::os::unix::fs::symlink(folder.join("main.src"), folder.join("alias.src")).unwrap();
+
+        let url = Url::from_file_path(&folder).unwrap();
+        let found: Vec<String> = scan(&url)
+            .iter()
+            .map(|found| found.as_str()[url.as_str().len()..].to_string())
+            .collect();
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(found, ["/lib/util.src", "/main.src"]);
+    }
+}
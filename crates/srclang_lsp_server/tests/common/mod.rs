@@ -0,0 +1,125 @@
+//! A client driving the server over an in-process pipe the way an editor drives
+//! `srclang-lsp`.
+
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use tokio::io::{
+    duplex, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf,
+    WriteHalf,
+};
+
+pub const UTIL_URI: &str = "file:///w/util.src";
+pub const MAIN_URI: &str = "file:///w/main.src";
+
+/// A module defining a struct and functions, opened at [`UTIL_URI`].
+pub const UTIL: &str = include_str!("../../../../tests/fixtures/util.src");
+
+/// A module using [`UTIL`], opened at [`MAIN_URI`].
+pub const MAIN: &str = include_str!("../../../../tests/fixtures/main.src");
+
+/// The position of `needle` in `text`.
+pub fn position(text: &str, needle: &str) -> Value {
+    let offset = text.find(needle).unwrap();
+    let line = text[..offset].matches('\n').count();
+    let character = offset - text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    json!({ "line": line, "character": character })
+}
+
+pub struct Client {
+    input: WriteHalf<DuplexStream>,
+    output: BufReader<ReadHalf<DuplexStream>>,
+}
+
+impl Client {
+    pub fn start() -> Self {
+        let (client, server) = duplex(1 << 16);
+        let (server_output, server_input) = tokio::io::split(server);
+        tokio::spawn(srclang_lsp_server::serve(server_output, server_input));
+        let (output, input) = tokio::io::split(client);
+        Client {
+            input,
+            output: BufReader::new(output),
+        }
+    }
+
+    pub async fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.input.write_all(frame.as_bytes()).await.unwrap();
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+    }
+
+    pub async fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.output.read_line(&mut header).await.unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.output.read_exact(&mut body).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Sends a request and returns its response, skipping the notifications before it.
+    pub async fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+        let mut request = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if !params.is_null() {
+            request["params"] = params;
+        }
+        self.send(request).await;
+        loop {
+            let message = self.receive().await;
+            if message["id"] == id {
+                return message;
+            }
+        }
+    }
+
+    /// Waits for the next notification of `method`, skipping other messages.
+    pub async fn notification(&mut self, method: &str) -> Value {
+        loop {
+            let message = self.receive().await;
+            if message["method"] == method && message.get("id").is_none() {
+                return message["params"].clone();
+            }
+        }
+    }
+
+    /// Initializes the server and opens a document with `text` at `uri`.
+    pub async fn open(&mut self, uri: &str, text: &str) {
+        self.request(0, "initialize", json!({ "capabilities": {} }))
+            .await;
+        self.notify("initialized", json!({})).await;
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "src", "version": 1, "text": text }
+            }),
+        )
+        .await;
+    }
+
+    /// Initializes the server and opens [`UTIL`] and `main` at [`MAIN_URI`].
+    pub async fn open_workspace(&mut self, main: &str) {
+        self.open(UTIL_URI, UTIL).await;
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": MAIN_URI, "languageId": "src", "version": 1, "text": main }
+            }),
+        )
+        .await;
+    }
+}
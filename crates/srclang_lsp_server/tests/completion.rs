@@ -0,0 +1,71 @@
+mod common;
+
+use common::{Client, MAIN_URI};
+use serde_json::{json, Value};
+
+/// A use of [`common::UTIL`] being typed.
+const TEXT: &str = r#"use { Point } from util
+
+fn main(p: Point)[] {
+    p.
+}"#;
+
+/// The position right after `needle` in `text` as the params of a request.
+fn after(text: &str, needle: &str) -> Value {
+    let offset = text.find(needle).unwrap() + needle.len();
+    let line = text[..offset].matches('\n').count();
+    let character = offset - text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "textDocument": { "uri": MAIN_URI },
+        "position": { "line": line, "character": character },
+    })
+}
+
+#[tokio::test]
+async fn test_completion_and_resolve() {
+    let mut client = Client::start();
+    client.open_workspace(TEXT).await;
+
+    let completion = client
+        .request(1, "textDocument/completion", after(TEXT, "p."))
+        .await;
+    let items = completion["result"].as_array().unwrap();
+    let labels: Vec<&str> = items
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["x", "moved"]);
+    // a field
+    assert_eq!(items[0]["kind"], 5);
+
+    let resolved = client
+        .request(2, "completionItem/resolve", items[0].clone())
+        .await;
+    assert_eq!(resolved["result"]["detail"], "x: i32");
+    assert_eq!(
+        resolved["result"]["documentation"],
+        json!({ "kind": "markdown", "value": "How far right." })
+    );
+
+    let typed = TEXT.replace("p.", "p");
+    client
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": MAIN_URI, "version": 2 },
+                "contentChanges": [{ "text": typed }],
+            }),
+        )
+        .await;
+    let completion = client
+        .request(3, "textDocument/completion", after(&typed, "    p"))
+        .await;
+    let items = completion["result"].as_array().unwrap();
+    let p = items.iter().find(|item| item["label"] == "p").unwrap();
+    // a variable
+    assert_eq!(p["kind"], 6);
+    let resolved = client.request(4, "completionItem/resolve", p.clone()).await;
+    assert_eq!(resolved["result"]["detail"], "p: Point");
+    let keyword = items.iter().find(|item| item["label"] == "fn");
+    assert!(keyword.is_none());
+}
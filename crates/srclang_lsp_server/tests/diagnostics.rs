@@ -0,0 +1,121 @@
+mod common;
+
+use common::Client;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///main.src";
+
+fn change(version: u32, text: &str) -> Value {
+    json!({
+        "textDocument": { "uri": URI, "version": version },
+        "contentChanges": [{ "text": text }],
+    })
+}
+
+fn messages(published: &Value) -> Vec<&str> {
+    assert_eq!(published["uri"], URI);
+    published["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|diagnostic| diagnostic["message"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_publish_and_clear() {
+    let mut client = Client::start();
+    client
+        .open(URI, "fn main()[] {\n    let a = \"😀\" b\n}")
+        .await;
+    let published = client.notification("textDocument/publishDiagnostics").await;
+    let diagnostic = &published["diagnostics"][0];
+    assert!(diagnostic["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("unexpected `b`"));
+    assert_eq!(diagnostic["severity"], 1);
+    // the emoji is two UTF-16 code units
+    assert_eq!(
+        diagnostic["range"],
+        json!({
+            "start": { "line": 1, "character": 17 },
+            "end": { "line": 1, "character": 18 },
+        })
+    );
+
+    // only the text the document settles on is checked
+    client
+        .notify(
+            "textDocument/didChange",
+            change(2, "fn main()[] {\n    let a = b\n}"),
+        )
+        .await;
+    client
+        .notify(
+            "textDocument/didChange",
+            change(3, "fn main()[] {\n    let a = 1\n}"),
+        )
+        .await;
+    let published = client.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(messages(&published), Vec::<&str>::new());
+
+    client
+        .notify(
+            "textDocument/didChange",
+            change(4, "fn main()[] {\n    let a = b\n}"),
+        )
+        .await;
+    let published = client.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(
+        messages(&published),
+        vec!["cannot find value `b` in this scope"]
+    );
+
+    client
+        .notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await;
+    let published = client.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(messages(&published), Vec::<&str>::new());
+}
+
+#[tokio::test]
+async fn test_requests_dont_wait_for_the_publication() {
+    let mut client = Client::start();
+    client.open(URI, "fn main()[] {\n    let a = b\n}").await;
+    // as many changes as the server handles at once
+    for version in 2..5 {
+        client
+            .notify(
+                "textDocument/didChange",
+                change(version, "fn main()[] {\n    let a = b\n}"),
+            )
+            .await;
+    }
+    let sent = std::time::Instant::now();
+    client
+        .send(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/documentSymbol",
+            "params": { "textDocument": { "uri": URI } },
+        }))
+        .await;
+    loop {
+        let message = client.receive().await;
+        assert_ne!(message["method"], "textDocument/publishDiagnostics");
+        if message["id"] == 1 {
+            break;
+        }
+    }
+    // the response comes while the publications still wait for the text to settle
+    assert!(sent.elapsed() < std::time::Duration::from_millis(100));
+    let published = client.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(
+        messages(&published),
+        vec!["cannot find value `b` in this scope"]
+    );
+}
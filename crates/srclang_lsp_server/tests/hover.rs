@@ -0,0 +1,71 @@
+mod common;
+
+use common::Client;
+use serde_json::json;
+
+const URI: &str = "file:///w/main.src";
+
+const MAIN: &str = r#"# Writes and flushes.
+effect Log: async {
+    log(msg: string) []
+}
+
+fn main()[Log] {
+    main()
+}"#;
+
+#[tokio::test]
+async fn test_hover() {
+    let mut client = Client::start();
+    client.open(URI, MAIN).await;
+
+    let hover = client
+        .request(
+            1,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 6, "character": 5 },
+            }),
+        )
+        .await;
+    let result = &hover["result"];
+    assert_eq!(result["contents"]["kind"], "markdown");
+    assert_eq!(
+        result["contents"]["value"],
+        "```src\nfn main()[Log]\n```\n\n`fn()[Log, async] -> ()`\n\nPerforms `[Log, async]`\n\nPrivate to `main`"
+    );
+    assert_eq!(
+        result["range"],
+        json!({
+            "start": { "line": 6, "character": 4 },
+            "end": { "line": 6, "character": 8 },
+        })
+    );
+
+    let hover = client
+        .request(
+            2,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 5, "character": 11 },
+            }),
+        )
+        .await;
+    let value = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert!(value.starts_with("```src\neffect Log: async\n```\n\nStands for `[Log, async]`"));
+    assert!(value.ends_with("---\n\nWrites and flushes."));
+
+    let hover = client
+        .request(
+            3,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 4, "character": 0 },
+            }),
+        )
+        .await;
+    assert_eq!(hover["result"], json!(null));
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::{position, Client, MAIN, UTIL};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_definition_declaration_and_type_definition() {
+    // `util.src` is only on disk, the server reads it from the workspace folder
+    let folder = std::env::temp_dir().join(format!("srclang-navigation-{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(folder.join("util.src"), UTIL).unwrap();
+    let folder_uri = url(&folder);
+    let util = format!("{}/util.src", folder_uri);
+    let main = format!("{}/main.src", folder_uri);
+
+    let mut client = Client::start();
+    let initialized = client
+        .request(
+            1,
+            "initialize",
+            json!({
+                "capabilities": {},
+                "workspaceFolders": [{ "uri": folder_uri, "name": "w" }],
+            }),
+        )
+        .await;
+    let capabilities = &initialized["result"]["capabilities"];
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["declarationProvider"], true);
+    assert_eq!(capabilities["typeDefinitionProvider"], true);
+    client.notify("initialized", json!({})).await;
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": main, "languageId": "src", "version": 1, "text": MAIN }
+            }),
+        )
+        .await;
+
+    let definition = "textDocument/definition";
+    assert_eq!(
+        goto(&mut client, &main, definition, "Point, n").await,
+        Some((util.clone(), 1, 11))
+    );
+    assert_eq!(
+        goto(&mut client, &main, definition, "origin()\n").await,
+        Some((util.clone(), 13, 7))
+    );
+    assert_eq!(
+        goto(&mut client, &main, definition, "x\n").await,
+        Some((util.clone(), 3, 8))
+    );
+    assert_eq!(
+        goto(&mut client, &main, definition, "p.x").await,
+        Some((main.clone(), 15, 8))
+    );
+    assert_eq!(
+        goto(&mut client, &main, definition, "q\n}").await,
+        Some((main.clone(), 16, 8))
+    );
+    assert_eq!(
+        goto(&mut client, &main, definition, "log(self").await,
+        Some((main.clone(), 11, 7))
+    );
+    assert_eq!(goto(&mut client, &main, definition, "async").await, None);
+
+    let declaration = "textDocument/declaration";
+    assert_eq!(
+        goto(&mut client, &main, declaration, "log(self").await,
+        Some((main.clone(), 4, 4))
+    );
+
+    let type_definition = "textDocument/typeDefinition";
+    assert_eq!(
+        goto(&mut client, &main, type_definition, "r = p").await,
+        Some((util.clone(), 1, 11))
+    );
+    assert_eq!(
+        goto(&mut client, &main, type_definition, "Log]").await,
+        Some((main.clone(), 3, 7))
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+/// The file, line and character `method` jumps to from `needle` in the document at `uri`.
+async fn goto(
+    client: &mut Client,
+    uri: &str,
+    method: &str,
+    needle: &str,
+) -> Option<(String, u64, u64)> {
+    let params = json!({ "textDocument": { "uri": uri }, "position": position(MAIN, needle) });
+    let response = client.request(2, method, params).await;
+    let location = &response["result"];
+    if location.is_null() {
+        return None;
+    }
+    Some((
+        location["uri"].as_str().unwrap().to_string(),
+        location["range"]["start"]["line"].as_u64().unwrap(),
+        location["range"]["start"]["character"].as_u64().unwrap(),
+    ))
+}
+
+fn url(path: &std::path::Path) -> String {
+    format!("file://{}", path.display())
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{position, Client, MAIN, MAIN_URI, UTIL_URI};
+use serde_json::{json, Value};
+
+/// `needle` in [`MAIN`] as the position params of a request.
+fn at(needle: &str) -> Value {
+    json!({
+        "textDocument": { "uri": MAIN_URI },
+        "position": position(MAIN, needle),
+    })
+}
+
+/// The line and character a range starts at.
+fn start(range: &Value) -> (u64, u64) {
+    let start = &range["start"];
+    (
+        start["line"].as_u64().unwrap(),
+        start["character"].as_u64().unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_references_highlights_and_rename() {
+    let mut client = Client::start();
+    client.open_workspace(MAIN).await;
+
+    let mut params = at("Point,");
+    params["context"] = json!({ "includeDeclaration": false });
+    let references = client.request(1, "textDocument/references", params).await;
+    let found: Vec<(&str, (u64, u64))> = references["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|location| (location["uri"].as_str().unwrap(), start(&location["range"])))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (MAIN_URI, (15, 11)),
+            (UTIL_URI, (6, 5)),
+            (UTIL_URI, (7, 29)),
+            (UTIL_URI, (13, 21))
+        ]
+    );
+
+    let highlights = client
+        .request(2, "textDocument/documentHighlight", at("q\n}"))
+        .await;
+    let found: Vec<((u64, u64), u64)> = highlights["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|highlight| {
+            (
+                start(&highlight["range"]),
+                highlight["kind"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    // written where it is bound, read where it is used
+    assert_eq!(found, [((16, 8), 3), ((20, 4), 2)]);
+
+    let prepared = client
+        .request(3, "textDocument/prepareRename", at("origin()"))
+        .await;
+    assert_eq!(start(&prepared["result"]), (16, 12));
+    assert_eq!(prepared["result"]["end"]["character"], 18);
+
+    let mut params = at("origin()");
+    params["newName"] = json!("start");
+    let renamed = client.request(4, "textDocument/rename", params).await;
+    let changes = &renamed["result"]["changes"];
+    let edits = |uri: &str| -> Vec<(u64, u64)> {
+        changes[uri]
+            .as_array()
+            .unwrap()
+            .iter()
+            .inspect(|edit| assert_eq!(edit["newText"], "start"))
+            .map(|edit| start(&edit["range"]))
+            .collect()
+    };
+    assert_eq!(edits(MAIN_URI), [(0, 13), (16, 12)]);
+    assert_eq!(edits(UTIL_URI), [(13, 7), (14, 4)]);
+
+    let mut params = at("p:");
+    params["newName"] = json!("origin");
+    let refused = client.request(5, "textDocument/rename", params).await;
+    assert_eq!(
+        refused["error"]["message"],
+        "`p` would shadow the `origin` on line 1"
+    );
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::Client;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///main.src";
+
+/// The tokens of `data` as `(line, character, length, type)`, made absolute.
+fn absolute(data: &Value) -> Vec<(u64, u64, u64, u64)> {
+    let data: Vec<u64> = data
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| n.as_u64().unwrap())
+        .collect();
+    let (mut line, mut character) = (0, 0);
+    data.chunks(5)
+        .map(|token| {
+            if token[0] > 0 {
+                character = 0;
+            }
+            line += token[0];
+            character += token[1];
+            (line, character, token[2], token[3])
+        })
+        .collect()
+}
+
+fn type_index(legend: &Value, name: &str) -> u64 {
+    legend["tokenTypes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .position(|ty| ty == name)
+        .unwrap() as u64
+}
+
+#[tokio::test]
+async fn test_full_delta_and_range() {
+    let mut client = Client::start();
+    let initialized = client
+        .request(1, "initialize", json!({ "capabilities": {} }))
+        .await;
+    let legend = initialized["result"]["capabilities"]["semanticTokensProvider"]["legend"].clone();
+    assert_eq!(
+        legend["tokenModifiers"],
+        json!(["declaration", "public", "mutable"])
+    );
+    let keyword = type_index(&legend, "keyword");
+    let function = type_index(&legend, "function");
+    let parameter = type_index(&legend, "parameter");
+
+    client.notify("initialized", json!({})).await;
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": URI,
+                    "languageId": "src",
+                    "version": 1,
+                    "text": "fn id(a: i32) [] {\n    a\n}",
+                }
+            }),
+        )
+        .await;
+
+    let full = client
+        .request(
+            2,
+            "textDocument/semanticTokens/full",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await;
+    let tokens = absolute(&full["result"]["data"]);
+    assert_eq!(tokens[0], (0, 0, 2, keyword));
+    assert_eq!(tokens[1], (0, 3, 2, function));
+    assert_eq!(tokens[2], (0, 6, 1, parameter));
+    assert_eq!(tokens.last(), Some(&(1, 4, 1, parameter)));
+    let result_id = full["result"]["resultId"].clone();
+
+    client
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": "fn id(a: i32) [] {\n    let b = a\n    b\n}" }],
+            }),
+        )
+        .await;
+    let delta = client
+        .request(
+            3,
+            "textDocument/semanticTokens/full/delta",
+            json!({ "textDocument": { "uri": URI }, "previousResultId": result_id }),
+        )
+        .await;
+    assert_ne!(delta["result"]["resultId"], result_id);
+    let edits = delta["result"]["edits"].as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    // the tokens of the signature are kept
+    assert_eq!(edits[0]["start"], 5 * 4);
+
+    // a stale result id gets all the tokens
+    let stale = client
+        .request(
+            4,
+            "textDocument/semanticTokens/full/delta",
+            json!({ "textDocument": { "uri": URI }, "previousResultId": result_id }),
+        )
+        .await;
+    assert!(stale["result"]["data"].is_array(), "{}", stale);
+
+    let range = client
+        .request(
+            5,
+            "textDocument/semanticTokens/range",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": {
+                    "start": { "line": 1, "character": 0 },
+                    "end": { "line": 2, "character": 0 },
+                },
+            }),
+        )
+        .await;
+    let tokens = absolute(&range["result"]["data"]);
+    assert_eq!(tokens.len(), 3, "{:?}", tokens);
+    assert_eq!(tokens[0], (1, 4, 3, keyword));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::Client;
+use serde_json::json;
+
+const URI: &str = "file:///w/main.src";
+
+const TEXT: &str = r#"# Moves a point.
+fn shift(x: i32, y: i32)[] -> i32 {
+    x
+}
+
+fn main()[] {
+    let a = shift(1,
+}"#;
+
+#[tokio::test]
+async fn test_signature_help() {
+    let mut client = Client::start();
+    client.open(URI, TEXT).await;
+
+    let help = client
+        .request(
+            1,
+            "textDocument/signatureHelp",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 6, "character": 20 },
+            }),
+        )
+        .await;
+    let help = &help["result"];
+    assert_eq!(help["activeSignature"], 0);
+    let signature = &help["signatures"][0];
+    assert_eq!(signature["label"], "shift(x: i32, y: i32)[] -> i32");
+    assert_eq!(
+        signature["parameters"],
+        json!([{ "label": [6, 12] }, { "label": [14, 20] }])
+    );
+    assert_eq!(signature["activeParameter"], 1);
+    assert_eq!(
+        signature["documentation"]["value"],
+        "Returns `i32`\n\nPerforms `[]`\n\n---\n\nMoves a point."
+    );
+
+    // outside of a call
+    let help = client
+        .request(
+            2,
+            "textDocument/signatureHelp",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 2, "character": 5 },
+            }),
+        )
+        .await;
+    assert_eq!(help["result"], json!(null));
+}
@@ -0,0 +1,54 @@
+//! Drives the server over an in-process pipe the way an editor drives `srclang-lsp`.
+
+mod common;
+
+use common::Client;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_initialize_open_hover() {
+    let mut client = Client::start();
+
+    let initialized = client
+        .request(1, "initialize", json!({ "capabilities": {} }))
+        .await;
+    assert_eq!(
+        initialized["result"]["serverInfo"]["name"],
+        "src language server"
+    );
+    let capabilities = &initialized["result"]["capabilities"];
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(capabilities["textDocumentSync"]["openClose"], true);
+
+    client.notify("initialized", json!({})).await;
+    let uri = "file:///main.src";
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "src",
+                    "version": 1,
+                    "text": "fn main()[] {\n    let a = 1\n}",
+                }
+            }),
+        )
+        .await;
+
+    let hover = client
+        .request(
+            2,
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 1, "character": 8 },
+            }),
+        )
+        .await;
+    assert_eq!(hover["error"], Value::Null, "{}", hover);
+    assert!(hover.get("result").is_some(), "{}", hover);
+
+    let shutdown = client.request(3, "shutdown", Value::Null).await;
+    assert_eq!(shutdown["error"], Value::Null, "{}", shutdown);
+}
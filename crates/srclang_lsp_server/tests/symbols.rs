@@ -0,0 +1,82 @@
+mod common;
+
+use common::{Client, MAIN, MAIN_URI, UTIL_URI};
+use serde_json::{json, Value};
+
+/// Each symbol as its name, with its children after it.
+fn names(symbols: &Value) -> Vec<String> {
+    symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|symbol| {
+            let name = symbol["name"].as_str().unwrap().to_string();
+            let children = names(&symbol["children"]);
+            std::iter::once(name).chain(children.into_iter().map(|child| format!("  {}", child)))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_document_and_workspace_symbols() {
+    let mut client = Client::start();
+    client.open_workspace(MAIN).await;
+
+    let symbols = client
+        .request(
+            1,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": MAIN_URI } }),
+        )
+        .await;
+    let symbols = &symbols["result"];
+    assert_eq!(
+        names(symbols),
+        [
+            "util",
+            "  Point",
+            "  origin",
+            "Log",
+            "  log",
+            "Console",
+            "impl Log for Console",
+            "  log",
+            "main"
+        ]
+    );
+    let main = &symbols[4];
+    assert_eq!(main["detail"], "fn main(p: Point, n: i32)[Log]");
+    // a function
+    assert_eq!(main["kind"], 12);
+    assert_eq!(
+        main["selectionRange"],
+        json!({
+            "start": { "line": 15, "character": 3 },
+            "end": { "line": 15, "character": 7 },
+        })
+    );
+
+    let found = client
+        .request(2, "workspace/symbol", json!({ "query": "pnt" }))
+        .await;
+    let found = found["result"].as_array().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["name"], "Point");
+    assert_eq!(found[0]["location"]["uri"], UTIL_URI);
+
+    let found = client
+        .request(3, "workspace/symbol", json!({ "query": "x" }))
+        .await;
+    let found: Vec<(&str, &str)> = found["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| {
+            (
+                symbol["name"].as_str().unwrap(),
+                symbol["containerName"].as_str().unwrap_or_default(),
+            )
+        })
+        .collect();
+    assert_eq!(found, [("x", "Point")]);
+}
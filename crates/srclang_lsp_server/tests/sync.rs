@@ -0,0 +1,127 @@
+mod common;
+
+use common::Client;
+use serde_json::{json, Value};
+
+const INCREMENTAL_URI: &str = "file:///w/incremental.src";
+const FULL_URI: &str = "file:///w/full.src";
+
+const TEXT: &str = "fn main()[] {\n    let a = 1\n}";
+
+/// A change replacing the text between two positions, the whole text without them.
+type Change = (Option<((u32, u32), (u32, u32))>, &'static str);
+
+/// The notifications of an editing session, each holding changes applied in order.
+const SCRIPT: &[&[Change]] = &[
+    &[(Some(((1, 13), (1, 13))), "\n    let b = a")],
+    &[(Some(((0, 3), (0, 7))), "start")],
+    &[
+        (
+            Some(((0, 0), (0, 0))),
+            "struct Point {\n    pub x: i32\n}\n\n",
+        ),
+        // positions count UTF-16 code units, the emoji is two
+        (Some(((5, 12), (5, 13))), "\"😀\""),
+        (Some(((5, 16), (5, 16))), "\n    let c = \"é\""),
+    ],
+    &[(Some(((5, 12), (5, 16))), "2")],
+    &[(Some(((0, 0), (4, 0))), "")],
+    &[(None, "fn other()[] {\n}")],
+];
+
+/// The byte offset of `character` UTF-16 code units into `line` of `text`.
+fn offset(text: &str, (line, character): (u32, u32)) -> usize {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line as usize)
+        .map(str::len)
+        .sum();
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character as usize || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn open(uri: &str) -> Value {
+    json!({
+        "textDocument": { "uri": uri, "languageId": "src", "version": 1, "text": TEXT }
+    })
+}
+
+/// What the server knows about the text of `uri`: its semantic tokens and its outline.
+async fn state(client: &mut Client, id: u64, uri: &str) -> (Value, Value) {
+    let document = json!({ "textDocument": { "uri": uri } });
+    let tokens = client
+        .request(id, "textDocument/semanticTokens/full", document.clone())
+        .await;
+    let symbols = client
+        .request(id + 1, "textDocument/documentSymbol", document)
+        .await;
+    (tokens["result"]["data"].clone(), symbols["result"].clone())
+}
+
+#[tokio::test]
+async fn test_incremental_sync_matches_full_sync() {
+    let mut client = Client::start();
+    client.open(INCREMENTAL_URI, TEXT).await;
+    client.notify("textDocument/didOpen", open(FULL_URI)).await;
+
+    let mut text = TEXT.to_string();
+    let mut id = 1;
+    for (version, changes) in SCRIPT.iter().enumerate() {
+        let mut content_changes = vec![];
+        for (range, new_text) in changes.iter() {
+            match range {
+                Some((start, end)) => {
+                    let span = offset(&text, *start)..offset(&text, *end);
+                    text.replace_range(span, new_text);
+                    content_changes.push(json!({
+                        "range": {
+                            "start": { "line": start.0, "character": start.1 },
+                            "end": { "line": end.0, "character": end.1 },
+                        },
+                        "text": new_text,
+                    }));
+                }
+                None => {
+                    text = new_text.to_string();
+                    content_changes.push(json!({ "text": new_text }));
+                }
+            }
+        }
+        let version = version + 2;
+        client
+            .notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": INCREMENTAL_URI, "version": version },
+                    "contentChanges": content_changes,
+                }),
+            )
+            .await;
+        client
+            .notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": FULL_URI, "version": version },
+                    "contentChanges": [{ "text": text }],
+                }),
+            )
+            .await;
+
+        let incremental = state(&mut client, id, INCREMENTAL_URI).await;
+        let full = state(&mut client, id + 2, FULL_URI).await;
+        id += 4;
+        assert_eq!(
+            incremental, full,
+            "after change {}, the text being {:?}",
+            version, text
+        );
+        assert_ne!(incremental.0, json!(null));
+    }
+    assert_eq!(text, "fn other()[] {\n}");
+}
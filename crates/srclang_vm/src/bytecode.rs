@@ -0,0 +1,443 @@
+//! The bytecode and its binary file format.
+//!
+//! A file is the magic `srcb`, the format [`VERSION`], then the constant pool, the function
+//! table and the handler table. Integers are little endian, strings and tables are prefixed
+//! by their length as a `u32`. Reading checks the version, files from another version are
+//! refused rather than misread.
+
+use std::fmt::Display;
+
+pub const MAGIC: [u8; 4] = *b"srcb";
+/// Bumped whenever the encoding of anything in a module changes.
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Unit,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    /// A function used as a value, by its index in the function table.
+    Function(u32),
+    /// A function the module does not define, by its mangled name.
+    Import(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Pushes a constant.
+    Const(u32),
+    Load(u16),
+    /// Pops into a local.
+    Store(u16),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// Pops a struct, pushes the field named by the string constant.
+    Field(u32),
+    /// Calls a function by index with the arguments on top of the stack.
+    Call(u32, u8),
+    /// Pops a function value, then calls it like [`Op::Call`].
+    CallIndirect(u8),
+    /// Performs an operation, the effect and operation names are string constants. Traps
+    /// to the host when no handler installed by [`Op::Handle`] handles the effect.
+    Perform(u32, u32, u8),
+    /// Pops the result of the operation the current handler method was called for, and
+    /// returns it to where the operation was performed.
+    Resume,
+    /// Installs the handler named by a string constant, until [`Op::Unhandle`].
+    Handle(u32),
+    Unhandle,
+    Jump(u32),
+    /// Pops a bool, jumps when it is false.
+    JumpIfFalse(u32),
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: u16,
+    /// The parameters and every other local.
+    pub locals: u16,
+    pub code: Vec<Op>,
+}
+
+/// `impl Effect for Target`, the function implementing each operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handler {
+    pub effect: String,
+    pub target: String,
+    pub ops: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Function>,
+    pub handlers: Vec<Handler>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEof,
+    InvalidTag(u8),
+    InvalidUtf8,
+    TrailingBytes(usize),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a src bytecode module"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "module has version {}, this VM reads version {}",
+                version, VERSION
+            ),
+            DecodeError::UnexpectedEof => write!(f, "module ends unexpectedly"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid tag {:#04x}", tag),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::TrailingBytes(len) => write!(f, "{} bytes after the module", len),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<u32> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+            .map(|index| index as u32)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(MAGIC.to_vec());
+        w.u16(VERSION);
+        w.u32(self.constants.len() as u32);
+        for constant in self.constants.iter() {
+            match constant {
+                Constant::Unit => w.u8(0),
+                Constant::Bool(b) => {
+                    w.u8(1);
+                    w.u8(*b as u8);
+                }
+                Constant::Integer(i) => {
+                    w.u8(2);
+                    w.0.extend(i.to_le_bytes());
+                }
+                Constant::Float(f) => {
+                    w.u8(3);
+                    w.0.extend(f.to_le_bytes());
+                }
+                Constant::String(s) => {
+                    w.u8(4);
+                    w.str(s);
+                }
+                Constant::Function(index) => {
+                    w.u8(5);
+                    w.u32(*index);
+                }
+                Constant::Import(name) => {
+                    w.u8(6);
+                    w.str(name);
+                }
+            }
+        }
+        w.u32(self.functions.len() as u32);
+        for function in self.functions.iter() {
+            w.str(&function.name);
+            w.u16(function.params);
+            w.u16(function.locals);
+            w.u32(function.code.len() as u32);
+            for op in function.code.iter() {
+                w.op(*op);
+            }
+        }
+        w.u32(self.handlers.len() as u32);
+        for handler in self.handlers.iter() {
+            w.str(&handler.effect);
+            w.str(&handler.target);
+            w.u32(handler.ops.len() as u32);
+            for (op, function) in handler.ops.iter() {
+                w.str(op);
+                w.u32(*function);
+            }
+        }
+        w.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, DecodeError> {
+        let mut r = Reader(bytes);
+        if r.take(4).map_err(|_| DecodeError::BadMagic)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let mut module = Module::default();
+        for _ in 0..r.u32()? {
+            let constant = match r.u8()? {
+                0 => Constant::Unit,
+                1 => Constant::Bool(r.u8()? != 0),
+                2 => Constant::Integer(i64::from_le_bytes(r.array()?)),
+                3 => Constant::Float(f64::from_le_bytes(r.array()?)),
+                4 => Constant::String(r.str()?),
+                5 => Constant::Function(r.u32()?),
+                6 => Constant::Import(r.str()?),
+                tag => return Err(DecodeError::InvalidTag(tag)),
+            };
+            module.constants.push(constant);
+        }
+        for _ in 0..r.u32()? {
+            let name = r.str()?;
+            let params = r.u16()?;
+            let locals = r.u16()?;
+            let mut code = vec![];
+            for _ in 0..r.u32()? {
+                code.push(r.op()?);
+            }
+            module.functions.push(Function {
+                name,
+                params,
+                locals,
+                code,
+            });
+        }
+        for _ in 0..r.u32()? {
+            let effect = r.str()?;
+            let target = r.str()?;
+            let mut ops = vec![];
+            for _ in 0..r.u32()? {
+                ops.push((r.str()?, r.u32()?));
+            }
+            module.handlers.push(Handler {
+                effect,
+                target,
+                ops,
+            });
+        }
+        if !r.0.is_empty() {
+            return Err(DecodeError::TrailingBytes(r.0.len()));
+        }
+        Ok(module)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend(value.as_bytes());
+    }
+
+    fn op(&mut self, op: Op) {
+        match op {
+            Op::Const(index) => {
+                self.u8(0);
+                self.u32(index);
+            }
+            Op::Load(local) => {
+                self.u8(1);
+                self.u16(local);
+            }
+            Op::Store(local) => {
+                self.u8(2);
+                self.u16(local);
+            }
+            Op::Add => self.u8(3),
+            Op::Sub => self.u8(4),
+            Op::Mul => self.u8(5),
+            Op::Div => self.u8(6),
+            Op::Field(name) => {
+                self.u8(7);
+                self.u32(name);
+            }
+            Op::Call(function, argc) => {
+                self.u8(8);
+                self.u32(function);
+                self.u8(argc);
+            }
+            Op::CallIndirect(argc) => {
+                self.u8(9);
+                self.u8(argc);
+            }
+            Op::Perform(effect, op, argc) => {
+                self.u8(10);
+                self.u32(effect);
+                self.u32(op);
+                self.u8(argc);
+            }
+            Op::Resume => self.u8(11),
+            Op::Handle(target) => {
+                self.u8(12);
+                self.u32(target);
+            }
+            Op::Unhandle => self.u8(13),
+            Op::Jump(to) => {
+                self.u8(14);
+                self.u32(to);
+            }
+            Op::JumpIfFalse(to) => {
+                self.u8(15);
+                self.u32(to);
+            }
+            Op::Return => self.u8(16),
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn str(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn op(&mut self) -> Result<Op, DecodeError> {
+        let op = match self.u8()? {
+            0 => Op::Const(self.u32()?),
+            1 => Op::Load(self.u16()?),
+            2 => Op::Store(self.u16()?),
+            3 => Op::Add,
+            4 => Op::Sub,
+            5 => Op::Mul,
+            6 => Op::Div,
+            7 => Op::Field(self.u32()?),
+            8 => Op::Call(self.u32()?, self.u8()?),
+            9 => Op::CallIndirect(self.u8()?),
+            10 => Op::Perform(self.u32()?, self.u32()?, self.u8()?),
+            11 => Op::Resume,
+            12 => Op::Handle(self.u32()?),
+            13 => Op::Unhandle,
+            14 => Op::Jump(self.u32()?),
+            15 => Op::JumpIfFalse(self.u32()?),
+            16 => Op::Return,
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        Ok(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module() -> Module {
+        Module {
+            constants: vec![
+                Constant::Unit,
+                Constant::Bool(true),
+                Constant::Integer(-42),
+                Constant::Float(1.5),
+                Constant::String("héllo".to_string()),
+                Constant::Function(0),
+                Constant::Import("fs::read".to_string()),
+            ],
+            functions: vec![Function {
+                name: "main".to_string(),
+                params: 1,
+                locals: 3,
+                code: vec![
+                    Op::Load(0),
+                    Op::Const(2),
+                    Op::Add,
+                    Op::Store(1),
+                    Op::Handle(4),
+                    Op::Perform(4, 4, 2),
+                    Op::Unhandle,
+                    Op::JumpIfFalse(9),
+                    Op::Call(0, 1),
+                    Op::Return,
+                ],
+            }],
+            handlers: vec![Handler {
+                effect: "Fs".to_string(),
+                target: "Mock".to_string(),
+                ops: vec![("read".to_string(), 0)],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let module = module();
+        let bytes = module.to_bytes();
+        assert_eq!(&bytes[..4], b"srcb");
+        assert_eq!(Module::from_bytes(&bytes), Ok(module));
+    }
+
+    #[test]
+    fn test_refuses_other_versions() {
+        let mut bytes = module().to_bytes();
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Module::from_bytes(&bytes),
+            Err(DecodeError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(Module::from_bytes(b"wasm"), Err(DecodeError::BadMagic));
+        let bytes = module().to_bytes();
+        assert_eq!(
+            Module::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn test_refuses_trailing_bytes() {
+        let mut bytes = module().to_bytes();
+        bytes.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            Module::from_bytes(&bytes),
+            Err(DecodeError::TrailingBytes(2))
+        );
+    }
+}
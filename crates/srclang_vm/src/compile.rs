@@ -0,0 +1,299 @@
+//! Compiles the monomorphized functions of a program to bytecode.
+//!
+//! Every local of a [`mir::Body`] gets a slot in the frame. An instruction pushes its
+//! operands, runs and stores its result, so the operand stack is empty between
+//! instructions. Blocks are laid out in order and jumps patched once every block's offset
+//! is known.
+
+use std::{collections::HashMap, fmt::Display};
+
+use srclang::{
+    compiler::{
+        self,
+        ir::Mangled,
+        mir::{self, Callee, Const, Instr, Operand, Terminator},
+        monomorphize,
+        text::SourceProgram,
+    },
+    parser::ast::Operator,
+    Db,
+};
+
+use crate::bytecode::{Constant, Function, Handler, Module, Op};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Something the bytecode has no instruction for, such as an operator.
+    Unsupported { function: String, what: String },
+    /// A generic function without a finite set of instances.
+    Generic(monomorphize::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unsupported { function, what } => {
+                write!(f, "{} in `{}` is not supported in bytecode", what, function)
+            }
+            Error::Generic(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Compiler<'db> {
+    db: &'db dyn Db,
+    module: Module,
+    functions: HashMap<Mangled, u32>,
+}
+
+impl<'db> Compiler<'db> {
+    fn constant(&mut self, constant: Constant) -> u32 {
+        if let Some(index) = self.module.constants.iter().position(|c| *c == constant) {
+            return index as u32;
+        }
+        self.module.constants.push(constant);
+        self.module.constants.len() as u32 - 1
+    }
+
+    fn string(&mut self, s: &str) -> u32 {
+        self.constant(Constant::String(s.to_string()))
+    }
+
+    /// A function as a constant, by index when this program defines it.
+    fn function(&mut self, name: Mangled) -> u32 {
+        let constant = match self.functions.get(&name) {
+            Some(index) => Constant::Function(*index),
+            None => Constant::Import(name.mangled(self.db).clone()),
+        };
+        self.constant(constant)
+    }
+
+    fn operand(
+        &mut self,
+        code: &mut Vec<Op>,
+        operand: &Operand,
+        function: &str,
+    ) -> Result<(), Error> {
+        let op = match operand {
+            Operand::Local(local) => Op::Load(slot(*local, function)?),
+            Operand::Const(Const::Function(name)) => Op::Const(self.function(*name)),
+            Operand::Const(value) => {
+                let constant = match value {
+                    Const::Bool(b) => Constant::Bool(*b),
+                    Const::Integer(i) => Constant::Integer(*i),
+                    Const::Float(f) => Constant::Float(*f),
+                    Const::String(s) => Constant::String(s.clone()),
+                    Const::Unit | Const::Function(_) => Constant::Unit,
+                };
+                Op::Const(self.constant(constant))
+            }
+        };
+        code.push(op);
+        Ok(())
+    }
+
+    fn body(&mut self, name: String, body: &mir::Body) -> Result<Function, Error> {
+        let mut code = vec![];
+        let mut starts = vec![];
+        // jumps to blocks, by the index of the jump and the block it goes to
+        let mut patches = vec![];
+        for block in body.blocks.iter() {
+            starts.push(code.len() as u32);
+            for instr in block.instrs.iter() {
+                self.instr(&mut code, instr, &name)?;
+            }
+            match &block.terminator {
+                Terminator::Return(value) => {
+                    self.operand(&mut code, value, &name)?;
+                    code.push(Op::Return);
+                }
+                Terminator::Jump(to) => {
+                    patches.push((code.len(), *to));
+                    code.push(Op::Jump(0));
+                }
+                Terminator::Branch(cond, then, otherwise) => {
+                    self.operand(&mut code, cond, &name)?;
+                    patches.push((code.len(), *otherwise));
+                    code.push(Op::JumpIfFalse(0));
+                    patches.push((code.len(), *then));
+                    code.push(Op::Jump(0));
+                }
+            }
+        }
+        for (at, block) in patches {
+            let start = starts[block.0 as usize];
+            code[at] = match code[at] {
+                Op::JumpIfFalse(_) => Op::JumpIfFalse(start),
+                _ => Op::Jump(start),
+            };
+        }
+        Ok(Function {
+            params: fit(body.params.len(), &name, "parameters")?,
+            locals: fit(body.locals.len(), &name, "locals")?,
+            name,
+            code,
+        })
+    }
+
+    fn instr(&mut self, code: &mut Vec<Op>, instr: &Instr, function: &str) -> Result<(), Error> {
+        match instr {
+            Instr::Assign(dest, value) => {
+                self.operand(code, value, function)?;
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Binary(dest, op, lhs, rhs) => {
+                self.operand(code, lhs, function)?;
+                self.operand(code, rhs, function)?;
+                code.push(match op {
+                    Operator::Add => Op::Add,
+                    Operator::Sub => Op::Sub,
+                    Operator::Mul => Op::Mul,
+                    Operator::Div => Op::Div,
+                    op => {
+                        return Err(Error::Unsupported {
+                            function: function.to_string(),
+                            what: format!("the operator `{}`", op),
+                        })
+                    }
+                });
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Call(dest, callee, args) => {
+                for arg in args {
+                    self.operand(code, arg, function)?;
+                }
+                let argc = fit(args.len(), function, "arguments")?;
+                match callee {
+                    Callee::Function(name) => match self.functions.get(name) {
+                        Some(index) => code.push(Op::Call(*index, argc)),
+                        None => {
+                            let import = self.function(*name);
+                            code.extend([Op::Const(import), Op::CallIndirect(argc)]);
+                        }
+                    },
+                    Callee::Local(local) => {
+                        code.extend([Op::Load(slot(*local, function)?), Op::CallIndirect(argc)])
+                    }
+                }
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Perform(dest, effect, op, args) => {
+                for arg in args {
+                    self.operand(code, arg, function)?;
+                }
+                let effect = self.string(effect.mangled(self.db));
+                let op = self.string(op.mangled(self.db));
+                let argc = fit(args.len(), function, "arguments")?;
+                code.push(Op::Perform(effect, op, argc));
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Field(dest, base, field) => {
+                self.operand(code, base, function)?;
+                let field = self.string(field);
+                code.push(Op::Field(field));
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Resume(dest, value) => {
+                self.operand(code, value, function)?;
+                code.push(Op::Resume);
+                code.push(Op::Store(slot(*dest, function)?));
+            }
+            Instr::Handle(handler) => {
+                let handler = self.string(handler.mangled(self.db));
+                code.push(Op::Handle(handler));
+            }
+            Instr::Unhandle => code.push(Op::Unhandle),
+        }
+        Ok(())
+    }
+}
+
+/// `count` as the operand of an instruction, more `what` than it holds are unsupported.
+fn fit<T: TryFrom<usize>>(count: usize, function: &str, what: &str) -> Result<T, Error> {
+    T::try_from(count).map_err(|_| Error::Unsupported {
+        function: function.to_string(),
+        what: format!("{} {}", count, what),
+    })
+}
+
+/// The frame slot of `local`, a frame holds as many locals as [`Op::Load`] can index.
+fn slot(local: mir::Local, function: &str) -> Result<u16, Error> {
+    fit(local.0 as usize + 1, function, "locals").map(|locals: u16| locals - 1)
+}
+
+/// Compiles every function and handler of `src` into a module, generic ones once for
+/// each of their instances.
+pub fn compile(db: &dyn Db, src: SourceProgram) -> Result<Module, Error> {
+    let program = compiler::compile(db, src);
+    let monomorphic = monomorphize::monomorphize(db, program);
+    if let Some(error) = monomorphic.errors.first() {
+        return Err(Error::Generic(error.clone()));
+    }
+    let mut compiler = Compiler {
+        db,
+        module: Module::default(),
+        functions: monomorphic
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| (*name, index as u32))
+            .collect(),
+    };
+    for (name, body) in monomorphic.functions.iter() {
+        let function = compiler.body(name.mangled(db).clone(), body)?;
+        compiler.module.functions.push(function);
+    }
+    for handler in program.handlers(db).iter() {
+        // generic methods are only there as the instances filling added slots
+        let added = monomorphic
+            .ops
+            .iter()
+            .filter(|(of, _, _)| of == handler)
+            .map(|(_, slot, method)| (slot, method));
+        let ops = handler
+            .ops(db)
+            .iter()
+            .map(|(op, method)| (op, method))
+            .chain(added)
+            .filter_map(|(op, method)| {
+                let index = compiler.functions.get(method)?;
+                Some((op.mangled(db).clone(), *index))
+            })
+            .collect();
+        compiler.module.handlers.push(Handler {
+            effect: handler.effect(db).mangled(db).clone(),
+            target: handler.target(db).mangled(db).clone(),
+            ops,
+        });
+    }
+    Ok(compiler.module)
+}
+
+#[cfg(test)]
+mod tests {
+    use srclang::analyzer::db::Database;
+
+    use super::*;
+
+    #[test]
+    fn test_refuses_calls_wider_than_an_instruction() {
+        let db = &Database::default();
+        let params: Vec<String> = (0..256).map(|i| format!("a{}: i32", i)).collect();
+        let args = vec!["0"; 256];
+        let text = format!(
+            "fn wide({})[] -> i32 {{\n    a0\n}}\n\nfn main()[] -> i32 {{\n    wide({})\n}}",
+            params.join(", "),
+            args.join(", ")
+        );
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text);
+        assert_eq!(
+            compile(db, src),
+            Err(Error::Unsupported {
+                function: "main".to_string(),
+                what: "256 arguments".to_string(),
+            })
+        );
+    }
+}
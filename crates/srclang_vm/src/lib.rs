@@ -0,0 +1,15 @@
+//! # srclang_vm
+//! A bytecode compiler and stack VM for src programs.
+//!
+//! [`compile`] turns the monomorphized functions of a program into a [`Module`], which
+//! can be written to a file with [`Module::to_bytes`] and run anywhere by a [`Vm`]. The
+//! host provides the effects a program performs and does not handle itself, the same way
+//! it does for the interpreter in `srclang::interp`.
+
+pub mod bytecode;
+pub mod compile;
+pub mod vm;
+
+pub use bytecode::Module;
+pub use compile::compile;
+pub use vm::{Trap, Vm};
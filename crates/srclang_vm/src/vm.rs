@@ -0,0 +1,553 @@
+//! The stack machine running a [`Module`].
+//!
+//! Each call gets a frame holding its locals, all frames share one operand stack. An
+//! operation calls the method of the innermost installed handler, or traps to the
+//! [`EffectHandler`] the host registered for its effect, as in the interpreter. A method
+//! returning without resuming stops the program with [`interp::Error::Unresumed`] like it.
+
+use std::{collections::HashMap, fmt::Display};
+
+use srclang::{
+    interp::{self, EffectHandler, Value},
+    parser::ast::Operator,
+};
+
+use crate::bytecode::{Constant, Module, Op};
+
+/// Why a program stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trap {
+    /// An error the interpreter would report too.
+    Error(interp::Error),
+    /// Bytecode that does not fit the module, such as an index out of range.
+    Invalid(String),
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trap::Error(error) => write!(f, "{}", error),
+            Trap::Invalid(message) => write!(f, "invalid bytecode: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Trap {}
+
+impl From<interp::Error> for Trap {
+    fn from(error: interp::Error) -> Self {
+        Trap::Error(error)
+    }
+}
+
+struct Frame {
+    function: u32,
+    pc: usize,
+    locals: Vec<Value>,
+    /// For a handler method, the handlers installed inside the handled block, which are
+    /// reinstalled when it resumes.
+    resumes: Option<Vec<String>>,
+}
+
+pub struct Vm<'h> {
+    module: Module,
+    host: HashMap<String, Box<dyn EffectHandler + 'h>>,
+}
+
+impl<'h> Vm<'h> {
+    pub fn new(module: Module) -> Self {
+        Self {
+            module,
+            host: HashMap::new(),
+        }
+    }
+
+    /// Handles the operations of `effect` the program leaves unhandled with `handler`.
+    pub fn with_handler(mut self, effect: &str, handler: impl EffectHandler + 'h) -> Self {
+        self.host.insert(effect.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Runs the function `name` with `args` to completion.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Trap> {
+        let function = self
+            .module
+            .function(name)
+            .ok_or_else(|| interp::Error::UnknownFunction(name.to_string()))?;
+        let mut run = Run {
+            vm: self,
+            stack: vec![],
+            frames: vec![],
+            handling: vec![],
+        };
+        run.enter(function, args, None)?;
+        run.run()
+    }
+}
+
+/// The state of one call from the host.
+struct Run<'vm, 'h> {
+    vm: &'vm mut Vm<'h>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    /// The handlers installed by `Handle`, innermost last.
+    handling: Vec<String>,
+}
+
+impl<'vm, 'h> Run<'vm, 'h> {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a frame is running")
+    }
+
+    fn pop(&mut self) -> Result<Value, Trap> {
+        self.stack
+            .pop()
+            .ok_or_else(|| Trap::Invalid("pop from an empty stack".to_string()))
+    }
+
+    fn args(&mut self, argc: u8) -> Result<Vec<Value>, Trap> {
+        let argc = argc as usize;
+        if self.stack.len() < argc {
+            return Err(Trap::Invalid("missing arguments".to_string()));
+        }
+        Ok(self.stack.split_off(self.stack.len() - argc))
+    }
+
+    fn constant(&self, index: u32) -> Result<&Constant, Trap> {
+        self.vm
+            .module
+            .constants
+            .get(index as usize)
+            .ok_or_else(|| Trap::Invalid(format!("no constant {}", index)))
+    }
+
+    fn string(&self, index: u32) -> Result<String, Trap> {
+        match self.constant(index)? {
+            Constant::String(s) => Ok(s.clone()),
+            _ => Err(Trap::Invalid(format!("constant {} is not a string", index))),
+        }
+    }
+
+    fn enter(
+        &mut self,
+        function: u32,
+        args: Vec<Value>,
+        resumes: Option<Vec<String>>,
+    ) -> Result<(), Trap> {
+        let info = self
+            .vm
+            .module
+            .functions
+            .get(function as usize)
+            .ok_or_else(|| Trap::Invalid(format!("no function {}", function)))?;
+        if args.len() != info.params as usize {
+            return Err(interp::Error::Mismatch(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                info.name,
+                info.params,
+                args.len()
+            ))
+            .into());
+        }
+        let mut locals = args;
+        locals.resize(info.locals.max(info.params) as usize, Value::Unit);
+        self.frames.push(Frame {
+            function,
+            pc: 0,
+            locals,
+            resumes,
+        });
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<Value, Trap> {
+        loop {
+            let op = {
+                let frame = self.frames.last_mut().expect("a frame is running");
+                let code = &self.vm.module.functions[frame.function as usize].code;
+                let op = *code
+                    .get(frame.pc)
+                    .ok_or_else(|| Trap::Invalid("ran off the end of a function".to_string()))?;
+                frame.pc += 1;
+                op
+            };
+            match op {
+                Op::Const(index) => {
+                    let value = match self.constant(index)? {
+                        Constant::Unit => Value::Unit,
+                        Constant::Bool(b) => Value::Bool(*b),
+                        Constant::Integer(i) => Value::Integer(*i),
+                        Constant::Float(f) => Value::Float(*f),
+                        Constant::String(s) => Value::String(s.clone()),
+                        Constant::Function(function) => {
+                            let function = self.vm.module.functions.get(*function as usize);
+                            let function = function.ok_or_else(|| {
+                                Trap::Invalid(format!("constant {} is not a function", index))
+                            })?;
+                            Value::Function(function.name.clone())
+                        }
+                        Constant::Import(name) => Value::Function(name.clone()),
+                    };
+                    self.stack.push(value);
+                }
+                Op::Load(local) => {
+                    let value = self
+                        .frame()
+                        .locals
+                        .get(local as usize)
+                        .cloned()
+                        .ok_or_else(|| Trap::Invalid(format!("no local {}", local)))?;
+                    self.stack.push(value);
+                }
+                Op::Store(local) => {
+                    let value = self.pop()?;
+                    let slot = self
+                        .frame()
+                        .locals
+                        .get_mut(local as usize)
+                        .ok_or_else(|| Trap::Invalid(format!("no local {}", local)))?;
+                    *slot = value;
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    let op = match op {
+                        Op::Add => Operator::Add,
+                        Op::Sub => Operator::Sub,
+                        Op::Mul => Operator::Mul,
+                        _ => Operator::Div,
+                    };
+                    self.stack.push(interp::binary(&op, lhs, rhs)?);
+                }
+                Op::Field(name) => {
+                    let field = self.string(name)?;
+                    let value = match self.pop()? {
+                        Value::Struct(name, mut fields) => match fields.remove(&field) {
+                            Some(value) => value,
+                            None => {
+                                let value = Value::Struct(name, fields);
+                                return Err(interp::Error::NoField { value, field }.into());
+                            }
+                        },
+                        value => return Err(interp::Error::NoField { value, field }.into()),
+                    };
+                    self.stack.push(value);
+                }
+                Op::Call(function, argc) => {
+                    let args = self.args(argc)?;
+                    self.enter(function, args, None)?;
+                }
+                Op::CallIndirect(argc) => {
+                    let callee = self.pop()?;
+                    let args = self.args(argc)?;
+                    let name = match callee {
+                        Value::Function(name) => name,
+                        value => {
+                            let message = format!("`{}` is not a function", value);
+                            return Err(interp::Error::Mismatch(message).into());
+                        }
+                    };
+                    let function = self
+                        .vm
+                        .module
+                        .function(&name)
+                        .ok_or(interp::Error::UnknownFunction(name))?;
+                    self.enter(function, args, None)?;
+                }
+                Op::Perform(effect, op, argc) => {
+                    let args = self.args(argc)?;
+                    let effect = self.string(effect)?;
+                    let op = self.string(op)?;
+                    self.perform(effect, op, args)?;
+                }
+                Op::Resume => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("a frame is running");
+                    let handling = frame
+                        .resumes
+                        .ok_or(Trap::Error(interp::Error::NotResumable))?;
+                    self.handling.extend(handling);
+                    self.stack.push(value);
+                }
+                Op::Handle(target) => {
+                    let target = self.string(target)?;
+                    self.handling.push(target);
+                }
+                Op::Unhandle => {
+                    self.handling.pop();
+                }
+                Op::Jump(to) => self.frame().pc = to as usize,
+                Op::JumpIfFalse(to) => match self.pop()? {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.frame().pc = to as usize,
+                    value => {
+                        let message = format!("expected a `bool`, found `{}`", value);
+                        return Err(interp::Error::Mismatch(message).into());
+                    }
+                },
+                Op::Return => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("a frame is running");
+                    if frame.resumes.is_some() {
+                        let name = &self.vm.module.functions[frame.function as usize].name;
+                        return Err(interp::Error::Unresumed(name.clone()).into());
+                    }
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    fn perform(&mut self, effect: String, op: String, mut args: Vec<Value>) -> Result<(), Trap> {
+        let module = &self.vm.module;
+        let found = self
+            .handling
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, target)| {
+                let handler = module
+                    .handlers
+                    .iter()
+                    .find(|handler| &handler.target == target && handler.effect == effect)?;
+                let (_, method) = handler.ops.iter().find(|(name, _)| *name == op)?;
+                Some((depth, target.clone(), *method))
+            });
+        let Some((depth, target, method)) = found else {
+            let value = match self.vm.host.get_mut(&effect) {
+                Some(handler) => handler.perform(&op, args)?,
+                None => return Err(interp::Error::Unhandled { effect, op }.into()),
+            };
+            self.stack.push(value);
+            return Ok(());
+        };
+        let takes_self = module
+            .functions
+            .get(method as usize)
+            .is_some_and(|function| function.params as usize == args.len() + 1);
+        if takes_self {
+            // handlers are structs without fields, named by the `with`
+            args.insert(0, Value::Struct(target, Default::default()));
+        }
+        // the method runs on behalf of the block, outside of it and the handlers within
+        let handling = self.handling.split_off(depth);
+        self.enter(method, args, Some(handling))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use srclang::{
+        analyzer::db::Database,
+        compiler::text::SourceProgram,
+        interp::{Error, Interpreter},
+    };
+
+    use super::*;
+
+    const PROGRAM: &str = r#"effect Console: async {
+    print(line: string)[] -> unit
+}
+
+effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Mock {
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents of " + path)
+    }
+}
+
+fn add(a: i32, b: i32)[] -> i32 {
+    let c = a + b
+    c
+}
+
+fn load()[Fs] -> string {
+    let contents = read("config")
+    contents
+}
+
+fn mocked()[] -> string {
+    handle {
+        load()
+    } with Mock
+}
+
+fn main(loud: bool)[Console] -> i32 {
+    let n = add(40, 2)
+    if loud {
+        print("loud")
+    } else {
+        let contents = mocked()
+        print(contents)
+    }
+    n
+}"#;
+
+    /// Compiles `PROGRAM`, passing it through the binary format.
+    fn module(db: &Database) -> Module {
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), PROGRAM.to_string());
+        let bytes = crate::compile(db, src).unwrap().to_bytes();
+        Module::from_bytes(&bytes).unwrap()
+    }
+
+    fn console(output: Rc<RefCell<Vec<String>>>) -> impl EffectHandler {
+        move |op: &str, args: Vec<Value>| -> Result<Value, Error> {
+            assert_eq!(op, "print");
+            output.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        }
+    }
+
+    #[test]
+    fn test_runs_like_the_interpreter() {
+        let db = &Database::default();
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), PROGRAM.to_string());
+        for loud in [true, false] {
+            let vm_output = Rc::new(RefCell::new(vec![]));
+            let mut vm = Vm::new(module(db)).with_handler("Console", console(vm_output.clone()));
+            let interp_output = Rc::new(RefCell::new(vec![]));
+            let mut interp =
+                Interpreter::new(db, src).with_handler("Console", console(interp_output.clone()));
+
+            let args = vec![Value::Bool(loud)];
+            assert_eq!(vm.call("main", args.clone()), Ok(Value::Integer(42)));
+            assert_eq!(interp.call("main", args), Ok(Value::Integer(42)));
+            assert_eq!(*vm_output.borrow(), *interp_output.borrow());
+            let printed = if loud { "loud" } else { "contents of config" };
+            assert_eq!(*vm_output.borrow(), [printed]);
+        }
+    }
+
+    #[test]
+    fn test_traps_to_the_host() {
+        let db = &Database::default();
+        let mut vm = Vm::new(module(db));
+        assert_eq!(
+            vm.call("main", vec![Value::Bool(true)]),
+            Err(Trap::Error(Error::Unhandled {
+                effect: "Console".to_string(),
+                op: "print".to_string()
+            }))
+        );
+        let mut vm = Vm::new(module(db)).with_handler(
+            "Fs",
+            |_: &str, args: Vec<Value>| -> Result<Value, Error> {
+                Ok(Value::String(format!("host {}", args[0])))
+            },
+        );
+        assert_eq!(
+            vm.call("load", vec![]),
+            Ok(Value::String("host config".to_string()))
+        );
+        assert_eq!(
+            vm.call("missing", vec![]),
+            Err(Trap::Error(Error::UnknownFunction("missing".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_stops_when_a_handler_does_not_resume() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Missing {
+}
+
+impl Fs for Missing {
+    fn read(self, path: string)[] -> string {
+        path
+    }
+}
+
+fn load()[Fs] -> string {
+    let contents = read("config")
+    contents
+}
+
+fn main()[] -> string {
+    handle {
+        load()
+    } with Missing
+}"#
+            .to_string(),
+        );
+        let unresumed = Error::Unresumed("<Missing as Fs>::read".to_string());
+        assert_eq!(
+            Interpreter::new(db, src).call("main", vec![]),
+            Err(unresumed.clone())
+        );
+        let mut vm = Vm::new(crate::compile(db, src).unwrap());
+        assert_eq!(vm.call("main", vec![]), Err(Trap::Error(unresumed)));
+    }
+
+    #[test]
+    fn test_runs_instances_of_generics() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"effect Pick: throws {
+    pick<T>(a: T)[] -> T
+}
+
+struct First {
+}
+
+impl Pick for First {
+    fn pick<T>(self, a: T)[] -> T {
+        resume(a)
+    }
+}
+
+fn id<T>(x: T)[] -> T {
+    x
+}
+
+fn main()[] -> string {
+    handle {
+        let n = pick(1)
+        let s = id("one")
+        let picked = pick(s)
+        picked
+    } with First
+}"#
+            .to_string(),
+        );
+        let module = crate::compile(db, src).unwrap();
+        let names: Vec<&str> = module
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "main",
+                "<First as Pick>::pick<i32>",
+                "id<string>",
+                "<First as Pick>::pick<string>"
+            ]
+        );
+        let mut vm = Vm::new(module);
+        assert_eq!(
+            vm.call("main", vec![]),
+            Ok(Value::String("one".to_string()))
+        );
+    }
+}
@@ -0,0 +1,1112 @@
+//! Generates a WebAssembly module from the monomorphized functions of a program.
+//!
+//! Every local of a [`mir::Body`] is a wasm local. Blocks are laid out as the arms of a
+//! `br_table` inside a loop, a jump sets the block to run next and branches back to the
+//! loop, which is enough for the graphs lowering makes without restructuring them.
+//!
+//! Each effect a program handles gets a global holding the handler installed for it, `0`
+//! when the host handles it. An operation checks the global and calls the handler's method
+//! or the imported operation. The method runs with the global cleared, so the operations
+//! it performs on its own effect go to the host, and must end in `resume`; a method
+//! returning without resuming traps, where the interpreter and the VM stop with an error.
+//! Operations are imported from the module
+//! named by the symbol of their effect, `host::reads`, so effects of the same name in
+//! different modules stay apart.
+
+use std::{collections::HashMap, fmt::Display};
+
+use srclang::{
+    compiler::{
+        self,
+        ir::Mangled,
+        mir::{self, BlockId, Callee, Const, Instr, Operand, Terminator},
+        monomorphize,
+        text::SourceProgram,
+    },
+    parser::ast::Operator,
+    types::Type,
+    Db,
+};
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, DataSection, ElementSection, Elements, EntityType,
+    ExportKind, ExportSection, Function, FunctionSection, GlobalSection, GlobalType, ImportSection,
+    Instruction, MemorySection, MemoryType, Module, RefType, TableSection, TableType, TypeSection,
+    ValType,
+};
+
+/// The module functions the program calls but does not define are imported from.
+pub const HOST: &str = "env";
+/// The name the memory holding strings is exported under.
+pub const MEMORY: &str = "memory";
+/// The name of the exported function a host calls with a length to get room for a string.
+pub const ALLOC: &str = "alloc";
+
+const PAGE: u32 = 1 << 16;
+
+// globals every module has, the handler globals follow them
+const HEAP: u32 = 0;
+const RESUMED: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Something wasm has no representation for yet, such as reading a field of a struct.
+    Unsupported { function: String, what: String },
+    /// A function named like an export of the runtime.
+    Reserved(String),
+    /// A generic function without a finite set of instances.
+    Generic(monomorphize::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unsupported { function, what } => {
+                write!(f, "{} in `{}` is not supported in wasm", what, function)
+            }
+            Error::Reserved(name) => write!(f, "`{}` is reserved in wasm modules", name),
+            Error::Generic(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A handler of the program, by the order of `Program::handlers`.
+struct Handler {
+    effect: Mangled,
+    target: Mangled,
+    /// The operations it implements, with the index of the method and whether the method
+    /// takes `self`.
+    ops: Vec<(Mangled, u32, bool)>,
+}
+
+/// Installing handler `id` in `global`, saving the previous one to the wasm local `save`.
+#[derive(Clone, Copy)]
+struct Install {
+    global: u32,
+    save: u32,
+    id: i32,
+}
+
+struct Codegen<'db> {
+    db: &'db dyn Db,
+    /// The arity of each function type, all parameters and results are `i64`.
+    types: Vec<usize>,
+    /// Imported functions, by module, name and arity.
+    imports: Vec<(String, String, usize)>,
+    /// Every function by its wasm index, imported or defined.
+    functions: HashMap<Mangled, u32>,
+    /// The imported operations, by effect and operation.
+    ops: HashMap<(Mangled, Mangled), u32>,
+    /// The wasm index of the first defined function.
+    defined: u32,
+    /// The parameters of each defined function.
+    params: Vec<usize>,
+    handlers: Vec<Handler>,
+    /// The global holding the installed handler of each handled effect.
+    globals: HashMap<Mangled, u32>,
+    /// String constants, by their offset in the data segment.
+    strings: HashMap<String, u32>,
+    data: Vec<u8>,
+}
+
+impl<'db> Codegen<'db> {
+    fn ty(&mut self, arity: usize) -> u32 {
+        if let Some(index) = self.types.iter().position(|a| *a == arity) {
+            return index as u32;
+        }
+        self.types.push(arity);
+        self.types.len() as u32 - 1
+    }
+
+    fn import(&mut self, module: String, name: String, arity: usize) -> u32 {
+        self.ty(arity);
+        self.imports.push((module, name, arity));
+        self.imports.len() as u32 - 1
+    }
+
+    fn alloc(&self) -> u32 {
+        self.defined + self.params.len() as u32
+    }
+
+    fn concat(&self) -> u32 {
+        self.alloc() + 1
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        let offset = match self.strings.get(s) {
+            Some(offset) => *offset,
+            None => {
+                let offset = self.data.len() as u32;
+                self.data.extend_from_slice(s.as_bytes());
+                self.strings.insert(s.to_string(), offset);
+                offset
+            }
+        };
+        ((offset as i64) << 32) | s.len() as i64
+    }
+
+    /// Imports the functions and operations `bodies` use but the program does not define.
+    ///
+    /// A function's arity comes from the calls to it, or from the type of the local it is
+    /// assigned to when it is only ever used as a value.
+    fn scan(&mut self, bodies: &[&mir::Body]) {
+        let mut used = vec![];
+        for instr in bodies
+            .iter()
+            .flat_map(|body| body.blocks.iter())
+            .flat_map(|block| block.instrs.iter())
+        {
+            match instr {
+                Instr::Call(_, Callee::Function(name), args) => used.push((*name, args.len())),
+                Instr::Perform(_, effect, op, args) if !self.ops.contains_key(&(*effect, *op)) => {
+                    let module = effect.mangled(self.db).clone();
+                    let index = self.import(module, op.mangled(self.db).clone(), args.len());
+                    self.ops.insert((*effect, *op), index);
+                }
+                _ => {}
+            }
+        }
+        for body in bodies {
+            for block in body.blocks.iter() {
+                let instrs = block.instrs.iter().flat_map(|instr| match instr {
+                    Instr::Assign(dest, value) => {
+                        let arity = match &body.local(*dest).ty {
+                            Type::Fn(params, _, _) => params.len(),
+                            _ => 0,
+                        };
+                        vec![(value, arity)]
+                    }
+                    Instr::Field(_, value, _) | Instr::Resume(_, value) => vec![(value, 0)],
+                    Instr::Binary(_, _, lhs, rhs) => vec![(lhs, 0), (rhs, 0)],
+                    Instr::Call(_, _, args) | Instr::Perform(_, _, _, args) => {
+                        args.iter().map(|arg| (arg, 0)).collect()
+                    }
+                    Instr::Handle(_) | Instr::Unhandle => vec![],
+                });
+                let terminator = match &block.terminator {
+                    Terminator::Return(value) | Terminator::Branch(value, _, _) => Some((value, 0)),
+                    Terminator::Jump(_) => None,
+                };
+                for (operand, arity) in instrs.chain(terminator) {
+                    if let Operand::Const(Const::Function(name)) = operand {
+                        used.push((*name, arity));
+                    }
+                }
+            }
+        }
+        for (name, arity) in used {
+            if !self.functions.contains_key(&name) {
+                let index = self.import(HOST.to_string(), name.mangled(self.db).clone(), arity);
+                self.functions.insert(name, index);
+            }
+        }
+    }
+
+    fn operand(&mut self, f: &mut Function, operand: &Operand) {
+        let value = match operand {
+            Operand::Local(local) => {
+                f.instruction(&Instruction::LocalGet(local.0));
+                return;
+            }
+            Operand::Const(Const::Unit) => 0,
+            Operand::Const(Const::Bool(b)) => *b as i64,
+            Operand::Const(Const::Integer(i)) => *i,
+            Operand::Const(Const::Float(x)) => x.to_bits() as i64,
+            Operand::Const(Const::String(s)) => self.string(s),
+            Operand::Const(Const::Function(name)) => self.functions[name] as i64,
+        };
+        f.instruction(&Instruction::I64Const(value));
+    }
+
+    /// The handlers each `Handle` and `Unhandle` installs and removes, by block and
+    /// instruction, and the number of wasm locals they save the previous handlers to.
+    fn installs(
+        &self,
+        body: &mir::Body,
+        first: u32,
+    ) -> (HashMap<(usize, usize), Vec<Install>>, u32) {
+        let mut installs = HashMap::new();
+        let mut saves = 0;
+        let mut visited = vec![false; body.blocks.len()];
+        let mut work = vec![(BlockId(0), vec![])];
+        while let Some((id, mut stack)) = work.pop() {
+            if std::mem::replace(&mut visited[id.0 as usize], true) {
+                continue;
+            }
+            let block = body.block(id);
+            for (i, instr) in block.instrs.iter().enumerate() {
+                match instr {
+                    Instr::Handle(target) => {
+                        let mut installed = vec![];
+                        for (index, handler) in self.handlers.iter().enumerate() {
+                            if handler.target == *target {
+                                installed.push(Install {
+                                    global: self.globals[&handler.effect],
+                                    save: first + saves,
+                                    id: index as i32 + 1,
+                                });
+                                saves += 1;
+                            }
+                        }
+                        installs.insert((id.0 as usize, i), installed.clone());
+                        stack.push(installed);
+                    }
+                    Instr::Unhandle => {
+                        installs.insert((id.0 as usize, i), stack.pop().unwrap_or_default());
+                    }
+                    _ => {}
+                }
+            }
+            match &block.terminator {
+                Terminator::Return(_) => {}
+                Terminator::Jump(to) => work.push((*to, stack)),
+                Terminator::Branch(_, then, otherwise) => {
+                    work.push((*then, stack.clone()));
+                    work.push((*otherwise, stack));
+                }
+            }
+        }
+        (installs, saves)
+    }
+
+    fn body(&mut self, name: &str, body: &mir::Body) -> Result<Function, Error> {
+        let params = body.params.len() as u32;
+        let locals = body.locals.len() as u32;
+        // the block to run next, the saved handlers, then the saved `RESUMED`
+        let pc = locals;
+        let (installs, saves) = self.installs(body, pc + 1);
+        let resumed = pc + 1 + saves;
+        let mut f = Function::new([(locals - params, ValType::I64), (2 + saves, ValType::I32)]);
+
+        let blocks = body.blocks.len() as u32;
+        f.instruction(&Instruction::Loop(BlockType::Empty));
+        for _ in 0..blocks {
+            f.instruction(&Instruction::Block(BlockType::Empty));
+        }
+        f.instruction(&Instruction::LocalGet(pc));
+        let arms: Vec<u32> = (0..blocks).collect();
+        f.instruction(&Instruction::BrTable(arms.into(), blocks - 1));
+        for (k, block) in body.blocks.iter().enumerate() {
+            f.instruction(&Instruction::End);
+            // the blocks of the later arms are still open, then the loop
+            let dispatch = blocks - 1 - k as u32;
+            for (i, instr) in block.instrs.iter().enumerate() {
+                match instr {
+                    Instr::Handle(_) | Instr::Unhandle => {
+                        let mut installed = installs.get(&(k, i)).cloned().unwrap_or_default();
+                        if let Instr::Handle(_) = instr {
+                            for install in installed.iter() {
+                                f.instruction(&Instruction::GlobalGet(install.global));
+                                f.instruction(&Instruction::LocalSet(install.save));
+                                f.instruction(&Instruction::I32Const(install.id));
+                                f.instruction(&Instruction::GlobalSet(install.global));
+                            }
+                        } else {
+                            installed.reverse();
+                            for install in installed.iter() {
+                                f.instruction(&Instruction::LocalGet(install.save));
+                                f.instruction(&Instruction::GlobalSet(install.global));
+                            }
+                        }
+                    }
+                    _ => self.instr(&mut f, name, body, resumed, instr)?,
+                }
+            }
+            match &block.terminator {
+                Terminator::Return(value) => {
+                    self.operand(&mut f, value);
+                    f.instruction(&Instruction::Return);
+                }
+                Terminator::Jump(to) => {
+                    f.instruction(&Instruction::I32Const(to.0 as i32));
+                    f.instruction(&Instruction::LocalSet(pc));
+                    f.instruction(&Instruction::Br(dispatch));
+                }
+                Terminator::Branch(cond, then, otherwise) => {
+                    f.instruction(&Instruction::I32Const(then.0 as i32));
+                    f.instruction(&Instruction::I32Const(otherwise.0 as i32));
+                    self.operand(&mut f, cond);
+                    f.instruction(&Instruction::I32WrapI64);
+                    f.instruction(&Instruction::Select);
+                    f.instruction(&Instruction::LocalSet(pc));
+                    f.instruction(&Instruction::Br(dispatch));
+                }
+            }
+        }
+        f.instruction(&Instruction::End);
+        f.instruction(&Instruction::Unreachable);
+        f.instruction(&Instruction::End);
+        Ok(f)
+    }
+
+    fn instr(
+        &mut self,
+        f: &mut Function,
+        name: &str,
+        body: &mir::Body,
+        resumed: u32,
+        instr: &Instr,
+    ) -> Result<(), Error> {
+        match instr {
+            Instr::Assign(dest, value) => {
+                self.operand(f, value);
+                f.instruction(&Instruction::LocalSet(dest.0));
+            }
+            Instr::Binary(dest, op, lhs, rhs) => {
+                let ty = &body.local(*dest).ty;
+                let unsupported = || Error::Unsupported {
+                    function: name.to_string(),
+                    what: format!("the operator `{}` on `{}`", op, ty),
+                };
+                self.operand(f, lhs);
+                if *ty == Type::con("f64") {
+                    f.instruction(&Instruction::F64ReinterpretI64);
+                    self.operand(f, rhs);
+                    f.instruction(&Instruction::F64ReinterpretI64);
+                    f.instruction(&match op {
+                        Operator::Add => Instruction::F64Add,
+                        Operator::Sub => Instruction::F64Sub,
+                        Operator::Mul => Instruction::F64Mul,
+                        Operator::Div => Instruction::F64Div,
+                        _ => return Err(unsupported()),
+                    });
+                    f.instruction(&Instruction::I64ReinterpretF64);
+                } else {
+                    self.operand(f, rhs);
+                    f.instruction(&match (op, *ty == Type::con("string")) {
+                        (Operator::Add, true) => Instruction::Call(self.concat()),
+                        (_, true) => return Err(unsupported()),
+                        (Operator::Add, false) => Instruction::I64Add,
+                        (Operator::Sub, false) => Instruction::I64Sub,
+                        (Operator::Mul, false) => Instruction::I64Mul,
+                        (Operator::Div, false) => Instruction::I64DivS,
+                        _ => return Err(unsupported()),
+                    });
+                }
+                f.instruction(&Instruction::LocalSet(dest.0));
+            }
+            Instr::Call(dest, callee, args) => {
+                for arg in args {
+                    self.operand(f, arg);
+                }
+                match callee {
+                    Callee::Function(name) => {
+                        f.instruction(&Instruction::Call(self.functions[name]));
+                    }
+                    Callee::Local(local) => {
+                        f.instruction(&Instruction::LocalGet(local.0));
+                        f.instruction(&Instruction::I32WrapI64);
+                        f.instruction(&Instruction::CallIndirect {
+                            type_index: self.ty(args.len()),
+                            table_index: 0,
+                        });
+                    }
+                }
+                f.instruction(&Instruction::LocalSet(dest.0));
+            }
+            Instr::Perform(dest, effect, op, args) => {
+                let methods: Vec<_> = self
+                    .handlers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, handler)| handler.effect == *effect)
+                    .filter_map(|(id, handler)| {
+                        let (_, method, receiver) =
+                            handler.ops.iter().find(|(name, _, _)| name == op)?;
+                        Some((id as i32 + 1, *method, *receiver))
+                    })
+                    .collect();
+                for (id, method, receiver) in methods.iter() {
+                    let global = self.globals[effect];
+                    f.instruction(&Instruction::GlobalGet(global));
+                    f.instruction(&Instruction::I32Const(*id));
+                    f.instruction(&Instruction::I32Eq);
+                    f.instruction(&Instruction::If(BlockType::Result(ValType::I64)));
+                    f.instruction(&Instruction::I32Const(0));
+                    f.instruction(&Instruction::GlobalSet(global));
+                    // restored after the call, a handler method may perform once it resumed
+                    f.instruction(&Instruction::GlobalGet(RESUMED));
+                    f.instruction(&Instruction::LocalSet(resumed));
+                    f.instruction(&Instruction::I32Const(0));
+                    f.instruction(&Instruction::GlobalSet(RESUMED));
+                    if *receiver {
+                        // handlers are structs without fields, named by the `with`
+                        f.instruction(&Instruction::I64Const(0));
+                    }
+                    for arg in args {
+                        self.operand(f, arg);
+                    }
+                    f.instruction(&Instruction::Call(*method));
+                    f.instruction(&Instruction::GlobalGet(RESUMED));
+                    f.instruction(&Instruction::I32Eqz);
+                    f.instruction(&Instruction::If(BlockType::Empty));
+                    f.instruction(&Instruction::Unreachable);
+                    f.instruction(&Instruction::End);
+                    f.instruction(&Instruction::LocalGet(resumed));
+                    f.instruction(&Instruction::GlobalSet(RESUMED));
+                    f.instruction(&Instruction::I32Const(*id));
+                    f.instruction(&Instruction::GlobalSet(global));
+                    f.instruction(&Instruction::Else);
+                }
+                for arg in args {
+                    self.operand(f, arg);
+                }
+                f.instruction(&Instruction::Call(self.ops[&(*effect, *op)]));
+                for _ in methods.iter() {
+                    f.instruction(&Instruction::End);
+                }
+                f.instruction(&Instruction::LocalSet(dest.0));
+            }
+            Instr::Field(_, _, field) => {
+                return Err(Error::Unsupported {
+                    function: name.to_string(),
+                    what: format!("reading the field `{}`", field),
+                })
+            }
+            Instr::Resume(dest, value) => {
+                f.instruction(&Instruction::I32Const(1));
+                f.instruction(&Instruction::GlobalSet(RESUMED));
+                self.operand(f, value);
+                f.instruction(&Instruction::LocalSet(dest.0));
+            }
+            Instr::Handle(_) | Instr::Unhandle => {}
+        }
+        Ok(())
+    }
+}
+
+/// `alloc(len)`, bumping the heap and growing the memory when it runs out.
+fn alloc() -> Function {
+    const PTR: u32 = 1;
+    const END: u32 = 2;
+    let mut f = Function::new([(2, ValType::I32)]);
+    let size = [
+        Instruction::MemorySize(0),
+        Instruction::I32Const(16),
+        Instruction::I32Shl,
+    ];
+    f.instruction(&Instruction::GlobalGet(HEAP));
+    f.instruction(&Instruction::LocalTee(PTR));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(END));
+    for i in size.iter() {
+        f.instruction(i);
+    }
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(END));
+    for i in size.iter() {
+        f.instruction(i);
+    }
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::I32Const(PAGE as i32 - 1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Const(16));
+    f.instruction(&Instruction::I32ShrU);
+    f.instruction(&Instruction::MemoryGrow(0));
+    f.instruction(&Instruction::I32Const(-1));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(END));
+    f.instruction(&Instruction::GlobalSet(HEAP));
+    f.instruction(&Instruction::LocalGet(PTR));
+    f.instruction(&Instruction::I64ExtendI32U);
+    f.instruction(&Instruction::I64Const(32));
+    f.instruction(&Instruction::I64Shl);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I64Or);
+    f.instruction(&Instruction::End);
+    f
+}
+
+/// `concat(a, b)`, copying both strings to a new one.
+fn concat(alloc: u32) -> Function {
+    const DST: u32 = 2;
+    const LEN: u32 = 3;
+    const RESULT: u32 = 4;
+    let mut f = Function::new([(2, ValType::I32), (1, ValType::I64)]);
+    let ptr = |f: &mut Function, s: u32| {
+        f.instruction(&Instruction::LocalGet(s));
+        f.instruction(&Instruction::I64Const(32));
+        f.instruction(&Instruction::I64ShrU);
+        f.instruction(&Instruction::I32WrapI64);
+    };
+    let len = |f: &mut Function, s: u32| {
+        f.instruction(&Instruction::LocalGet(s));
+        f.instruction(&Instruction::I64Const(u32::MAX as i64));
+        f.instruction(&Instruction::I64And);
+    };
+    let copy = Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    };
+    len(&mut f, 0);
+    len(&mut f, 1);
+    f.instruction(&Instruction::I64Add);
+    f.instruction(&Instruction::Call(alloc));
+    f.instruction(&Instruction::LocalTee(RESULT));
+    f.instruction(&Instruction::I64Const(32));
+    f.instruction(&Instruction::I64ShrU);
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::LocalSet(DST));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::LocalSet(LEN));
+
+    f.instruction(&Instruction::LocalGet(DST));
+    ptr(&mut f, 0);
+    f.instruction(&Instruction::LocalGet(LEN));
+    f.instruction(&copy);
+    f.instruction(&Instruction::LocalGet(DST));
+    f.instruction(&Instruction::LocalGet(LEN));
+    f.instruction(&Instruction::I32Add);
+    ptr(&mut f, 1);
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&copy);
+    f.instruction(&Instruction::LocalGet(RESULT));
+    f.instruction(&Instruction::End);
+    f
+}
+
+/// Compiles every function of `src` into the bytes of a wasm module, generic ones once
+/// for each of their instances.
+pub fn compile(db: &dyn Db, src: SourceProgram) -> Result<Vec<u8>, Error> {
+    let program = compiler::compile(db, src);
+    let monomorphic = monomorphize::monomorphize(db, program);
+    if let Some(error) = monomorphic.errors.first() {
+        return Err(Error::Generic(error.clone()));
+    }
+    let functions: Vec<Mangled> = monomorphic
+        .functions
+        .iter()
+        .map(|(name, _)| *name)
+        .collect();
+    let bodies: Vec<_> = monomorphic.functions.iter().map(|(_, body)| body).collect();
+    let names: Vec<_> = functions
+        .iter()
+        .map(|function| function.mangled(db).clone())
+        .collect();
+    if let Some(name) = names.iter().find(|name| *name == MEMORY || *name == ALLOC) {
+        return Err(Error::Reserved(name.clone()));
+    }
+
+    let mut codegen = Codegen {
+        db,
+        types: vec![],
+        imports: vec![],
+        functions: HashMap::new(),
+        ops: HashMap::new(),
+        defined: 0,
+        params: bodies.iter().map(|body| body.params.len()).collect(),
+        handlers: vec![],
+        globals: HashMap::new(),
+        strings: HashMap::new(),
+        data: vec![],
+    };
+    for (function, body) in functions.iter().zip(bodies.iter()) {
+        codegen.functions.insert(*function, u32::MAX);
+        codegen.ty(body.params.len());
+    }
+    codegen.scan(&bodies);
+    codegen.defined = codegen.imports.len() as u32;
+    for (index, function) in functions.iter().enumerate() {
+        codegen
+            .functions
+            .insert(*function, codegen.defined + index as u32);
+    }
+    codegen.ty(1);
+    codegen.ty(2);
+
+    for handler in program.handlers(db).iter() {
+        let effect = handler.effect(db);
+        // generic methods are only there as the instances filling added slots
+        let added = monomorphic
+            .ops
+            .iter()
+            .filter(|(of, _, _)| of == handler)
+            .map(|(_, slot, method)| (slot, method));
+        let ops = handler
+            .ops(db)
+            .iter()
+            .map(|(op, method)| (op, method))
+            .chain(added)
+            .filter_map(|(op, method)| {
+                let index = *codegen.functions.get(method)?;
+                let body = bodies[(index - codegen.defined) as usize];
+                let receiver = body
+                    .params
+                    .first()
+                    .is_some_and(|param| body.local(*param).name.as_deref() == Some("self"));
+                Some((*op, index, receiver))
+            })
+            .collect();
+        let globals = codegen.globals.len() as u32;
+        codegen
+            .globals
+            .entry(effect)
+            .or_insert(RESUMED + 1 + globals);
+        codegen.handlers.push(Handler {
+            effect,
+            target: handler.target(db),
+            ops,
+        });
+    }
+
+    let mut code = CodeSection::new();
+    for (name, body) in names.iter().zip(bodies.iter()) {
+        code.function(&codegen.body(name, body)?);
+    }
+    code.function(&alloc());
+    code.function(&concat(codegen.alloc()));
+
+    let mut types = TypeSection::new();
+    for arity in codegen.types.iter() {
+        types.function(vec![ValType::I64; *arity], [ValType::I64]);
+    }
+    let mut imports = ImportSection::new();
+    for (module, name, arity) in codegen.imports.iter() {
+        let ty = codegen.types.iter().position(|a| a == arity).unwrap() as u32;
+        imports.import(module, name, EntityType::Function(ty));
+    }
+    let mut defined = FunctionSection::new();
+    for params in codegen.params.iter().chain([1, 2].iter()) {
+        defined.function(codegen.types.iter().position(|a| a == params).unwrap() as u32);
+    }
+    let count = codegen.concat() + 1;
+    let mut tables = TableSection::new();
+    tables.table(TableType {
+        element_type: RefType::FUNCREF,
+        table64: false,
+        minimum: count as u64,
+        maximum: Some(count as u64),
+        shared: false,
+    });
+    let heap = (codegen.data.len() as u32 + 7) & !7;
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: (heap / PAGE + 1) as u64,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    let mut globals = GlobalSection::new();
+    let mutable = GlobalType {
+        val_type: ValType::I32,
+        mutable: true,
+        shared: false,
+    };
+    globals.global(mutable, &ConstExpr::i32_const(heap as i32));
+    globals.global(mutable, &ConstExpr::i32_const(0));
+    for _ in codegen.globals.iter() {
+        globals.global(mutable, &ConstExpr::i32_const(0));
+    }
+    let mut exports = ExportSection::new();
+    exports.export(MEMORY, ExportKind::Memory, 0);
+    for (index, name) in names.iter().enumerate() {
+        exports.export(name, ExportKind::Func, codegen.defined + index as u32);
+    }
+    exports.export(ALLOC, ExportKind::Func, codegen.alloc());
+    let mut elements = ElementSection::new();
+    let table: Vec<u32> = (0..count).collect();
+    elements.active(
+        Some(0),
+        &ConstExpr::i32_const(0),
+        Elements::Functions(&table),
+    );
+    let mut data = DataSection::new();
+    data.active(0, &ConstExpr::i32_const(0), codegen.data.iter().copied());
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&defined)
+        .section(&tables)
+        .section(&memories)
+        .section(&globals)
+        .section(&exports)
+        .section(&elements)
+        .section(&code)
+        .section(&data);
+    Ok(module.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use srclang::{
+        analyzer::db::Database,
+        interp::{Interpreter, Value},
+    };
+    use wasmi::{Caller, Engine, Extern, Linker, Store};
+
+    use super::*;
+
+    /// The file the programs are compiled from, their symbols start with its module `app`.
+    const URL: &str = "file:///w/app.src";
+
+    const PROGRAM: &str = r#"effect Console: async {
+    print(line: string)[] -> unit
+}
+
+effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Mock {
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents of " + path)
+    }
+}
+
+fn add(a: i32, b: i32)[] -> i32 {
+    let c = a + b
+    c
+}
+
+fn load()[Fs] -> string {
+    let contents = read("config")
+    contents
+}
+
+fn mocked()[] -> string {
+    handle {
+        load()
+    } with Mock
+}
+
+fn main(loud: bool)[Console] -> i32 {
+    let n = add(40, 2)
+    if loud {
+        print("loud")
+    } else {
+        let contents = mocked()
+        print(contents)
+    }
+    n
+}"#;
+
+    fn wasm(db: &Database) -> Vec<u8> {
+        let src = SourceProgram::new(db, URL.to_string(), PROGRAM.to_string());
+        compile(db, src).unwrap()
+    }
+
+    fn read(caller: &Caller<'_, Vec<String>>, s: i64) -> String {
+        let memory = caller
+            .get_export(MEMORY)
+            .and_then(Extern::into_memory)
+            .unwrap();
+        let (ptr, len) = ((s >> 32) as usize, s as u32 as usize);
+        String::from_utf8(memory.data(caller)[ptr..ptr + len].to_vec()).unwrap()
+    }
+
+    fn write(caller: &mut Caller<'_, Vec<String>>, s: &str) -> i64 {
+        let alloc = caller
+            .get_export(ALLOC)
+            .and_then(Extern::into_func)
+            .unwrap();
+        let alloc = alloc.typed::<i64, i64>(&*caller).unwrap();
+        let value = alloc.call(&mut *caller, s.len() as i64).unwrap();
+        let memory = caller
+            .get_export(MEMORY)
+            .and_then(Extern::into_memory)
+            .unwrap();
+        let ptr = (value >> 32) as usize;
+        memory.data_mut(caller)[ptr..ptr + s.len()].copy_from_slice(s.as_bytes());
+        value
+    }
+
+    fn instance(bytes: &[u8]) -> (Store<Vec<String>>, wasmi::Instance) {
+        let engine = Engine::default();
+        let module = wasmi::Module::new(&engine, bytes).unwrap();
+        let mut store = Store::new(&engine, vec![]);
+        let mut linker = <Linker<Vec<String>>>::new(&engine);
+        linker
+            .func_wrap(
+                "app::Console",
+                "print",
+                |mut caller: Caller<'_, Vec<String>>, line: i64| {
+                    let line = read(&caller, line);
+                    caller.data_mut().push(line);
+                    0i64
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap(
+                "app::Fs",
+                "read",
+                |mut caller: Caller<'_, Vec<String>>, path: i64| {
+                    let path = read(&caller, path);
+                    write(&mut caller, &format!("host {}", path))
+                },
+            )
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        (store, instance)
+    }
+
+    #[test]
+    fn test_validates() {
+        let db = &Database::default();
+        let bytes = wasm(db);
+        wasmparser::validate(&bytes).unwrap();
+
+        let mut imports = vec![];
+        let mut exports = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+            match payload.unwrap() {
+                wasmparser::Payload::ImportSection(section) => {
+                    for import in section {
+                        let import = import.unwrap();
+                        imports.push(format!("{}.{}", import.module, import.name));
+                    }
+                }
+                wasmparser::Payload::ExportSection(section) => {
+                    for export in section {
+                        exports.push(export.unwrap().name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        // the operations handled by `Mock` can still come from the host outside of it
+        assert_eq!(imports, ["app::Fs.read", "app::Console.print"]);
+        assert_eq!(
+            exports,
+            [
+                MEMORY,
+                "app::add",
+                "app::load",
+                "app::mocked",
+                "app::main",
+                "app::<Mock as app::Fs>::read",
+                ALLOC
+            ]
+        );
+    }
+
+    #[test]
+    fn test_runs_like_the_interpreter() {
+        let db = &Database::default();
+        let src = SourceProgram::new(db, URL.to_string(), PROGRAM.to_string());
+        let bytes = wasm(db);
+        for loud in [true, false] {
+            let (mut store, instance) = instance(&bytes);
+            let main = instance
+                .get_typed_func::<i64, i64>(&store, "app::main")
+                .unwrap();
+            assert_eq!(main.call(&mut store, loud as i64).unwrap(), 42);
+
+            let printed = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let output = printed.clone();
+            let mut interp = Interpreter::new(db, src).with_handler(
+                "app::Console",
+                move |_: &str, args: Vec<Value>| -> Result<Value, srclang::interp::Error> {
+                    output.borrow_mut().push(args[0].to_string());
+                    Ok(Value::Unit)
+                },
+            );
+            assert_eq!(
+                interp.call("app::main", vec![Value::Bool(loud)]),
+                Ok(Value::Integer(42))
+            );
+            assert_eq!(*store.data(), *printed.borrow());
+        }
+
+        // outside of `handle`, the host reads
+        let (mut store, instance) = instance(&bytes);
+        let load = instance
+            .get_typed_func::<(), i64>(&store, "app::load")
+            .unwrap();
+        let contents = load.call(&mut store, ()).unwrap();
+        let memory = instance.get_memory(&store, MEMORY).unwrap();
+        let (ptr, len) = ((contents >> 32) as usize, contents as u32 as usize);
+        assert_eq!(&memory.data(&store)[ptr..ptr + len], b"host config");
+    }
+
+    #[test]
+    fn test_runs_instances_of_generics() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            URL.to_string(),
+            r#"effect Pick: throws {
+    pick<T>(a: T)[] -> T
+}
+
+struct First {
+}
+
+impl Pick for First {
+    fn pick<T>(self, a: T)[] -> T {
+        resume(a)
+    }
+}
+
+fn id<T>(x: T)[] -> T {
+    x
+}
+
+fn main()[] -> i32 {
+    handle {
+        let s = id("one")
+        let picked = pick(s)
+        let n = id(40)
+        pick(n + 2)
+    } with First
+}"#
+            .to_string(),
+        );
+        let bytes = compile(db, src).unwrap();
+        wasmparser::validate(&bytes).unwrap();
+
+        // each instance of an operation is its own import, the host never gets to pick here
+        let engine = Engine::default();
+        let module = wasmi::Module::new(&engine, &bytes[..]).unwrap();
+        let mut store = Store::new(&engine, ());
+        let mut linker = <Linker<()>>::new(&engine);
+        for field in ["pick<string>", "pick<i32>"] {
+            linker.func_wrap("app::Pick", field, |_: i64| 0i64).unwrap();
+        }
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let main = instance
+            .get_typed_func::<(), i64>(&store, "app::main")
+            .unwrap();
+        assert_eq!(main.call(&mut store, ()).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_handlers_perform_after_resuming() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            URL.to_string(),
+            r#"effect Note: throws {
+    note(n: i32)[] -> i32
+}
+
+effect Pick: throws {
+    pick(n: i32)[] -> i32
+}
+
+struct Quiet {
+}
+
+impl Note for Quiet {
+    fn note(self, n: i32)[] -> i32 {
+        resume(n)
+    }
+}
+
+struct First {
+}
+
+impl Pick for First {
+    fn pick(self, n: i32)[Note] -> i32 {
+        let picked = resume(n)
+        note(picked)
+    }
+}
+
+fn main()[] -> i32 {
+    handle {
+        handle {
+            pick(42)
+        } with First
+    } with Quiet
+}"#
+            .to_string(),
+        );
+        let bytes = compile(db, src).unwrap();
+        let engine = Engine::default();
+        let module = wasmi::Module::new(&engine, &bytes[..]).unwrap();
+        let mut store = Store::new(&engine, ());
+        let mut linker = <Linker<()>>::new(&engine);
+        linker
+            .func_wrap("app::Note", "note", |_: i64| 0i64)
+            .unwrap();
+        linker
+            .func_wrap("app::Pick", "pick", |_: i64| 0i64)
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let main = instance
+            .get_typed_func::<(), i64>(&store, "app::main")
+            .unwrap();
+        assert_eq!(main.call(&mut store, ()).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_imports_functions_used_as_values_with_their_arity() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            URL.to_string(),
+            r#"use { shout } from util
+
+fn main()[] -> i32 {
+    let f = shout
+    shout(1, 2)
+}"#
+            .to_string(),
+        );
+        let bytes = compile(db, src).unwrap();
+        wasmparser::validate(&bytes).unwrap();
+        let mut imports = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+            if let wasmparser::Payload::ImportSection(section) = payload.unwrap() {
+                for import in section {
+                    let import = import.unwrap();
+                    imports.push(format!("{}.{}", import.module, import.name));
+                }
+            }
+        }
+        assert_eq!(imports, [format!("{}.util::shout", HOST)]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            URL.to_string(),
+            r#"struct Point {
+    x: i32
+}
+
+fn x(p: Point)[] -> i32 {
+    p.x
+}"#
+            .to_string(),
+        );
+        assert_eq!(
+            compile(db, src),
+            Err(Error::Unsupported {
+                function: "app::x".to_string(),
+                what: "reading the field `x`".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! # srclang_wasm
+//! A WebAssembly backend for src programs.
+//!
+//! [`compile`] turns the lowered functions of a program into a `.wasm` module exporting
+//! every function under its mangled name. Every value is an `i64`: integers as they are,
+//! booleans as `0` or `1`, floats by their bits and strings as a pointer into the exported
+//! `memory` in the high half and their length in the low half. A host passing a string in
+//! gets room for it from the exported `alloc` function.
+//!
+//! The operations a program performs are imported, from a module named after the mangled
+//! effect and under the mangled name of the operation, and called when no handler of the
+//! program is installed. Functions the program calls but does not define are imported
+//! from [`HOST`].
+
+pub mod codegen;
+
+pub use codegen::{compile, Error, ALLOC, HOST, MEMORY};
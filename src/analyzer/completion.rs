@@ -0,0 +1,314 @@
+//! What can be typed at a position.
+//!
+//! The text around the position tells what goes there: a member after `value.`, an effect
+//! in the `[..]` of a prototype, an item of the module in `use { .. } from module`, and a
+//! name in scope or an item keyword anywhere else. What is half typed often does not
+//! parse, so names are looked up in the text with a placeholder name typed at the position.
+
+use crate::{
+    analyzer::{
+        ident_at, is_ident,
+        modules::ModuleName,
+        navigation::{module_files, origin, owner, Workspace},
+        resolve::{resolve, DefId, DefKind, Resolution, ScopeKind},
+        semantic::{self, TokenKind},
+    },
+    compiler::text::SourceProgram,
+    parser::{self, ast::Visibility},
+    Db,
+};
+
+/// Typed where nothing was, so what is around it parses as a use of a name.
+const PLACEHOLDER: &str = "__completion";
+
+/// Items snippets write out, with the tab stops of the protocol.
+const SNIPPETS: &[(&str, &str)] = &[
+    ("fn", "fn ${1:name}($2) [$3] {\n    $0\n}"),
+    ("struct", "struct ${1:Name} {\n    $0\n}"),
+    ("effect", "effect ${1:Name}: $2 {\n    $0\n}"),
+    ("impl", "impl ${1:Name} {\n    $0\n}"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: TokenKind,
+    /// What to insert instead of the label, a snippet with tab stops.
+    pub snippet: Option<String>,
+    /// The definition completed and the file defining it.
+    pub def: Option<(SourceProgram, DefId)>,
+}
+
+/// Where in the text a position is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Context {
+    /// After the `.` at this offset.
+    Member(usize),
+    /// In the effects of a prototype.
+    Effects,
+    /// Between the braces of `use { .. } from` the module.
+    Use(String),
+    /// Anywhere else.
+    Name,
+}
+
+/// Where the name being typed at `offset` starts and what goes there.
+fn context(text: &str, offset: usize) -> (usize, Context) {
+    let start = ident_at(text, offset).start;
+    let before = &text[..start];
+    if before.ends_with('.') {
+        return (start, Context::Member(start - 1));
+    }
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = &before[line_start..];
+    let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let rest = &text[offset..line_end];
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let is_use = matches!(words.as_slice(), ["use", ..] | ["pub" | "priv", "use", ..]);
+    if is_use && line.contains('{') && !line.contains('}') {
+        // the module is named after the braces
+        let module = rest
+            .split_once('}')
+            .and_then(|(_, after)| after.trim_start().strip_prefix("from"))
+            .map(|after| after.trim_start())
+            .map(|after| after[..after.find(|c| !is_ident(c)).unwrap_or(after.len())].to_string())
+            .unwrap_or_default();
+        return (start, Context::Use(module));
+    }
+    if let Some(open) = line.rfind('[') {
+        let closed = line[open..].contains(']');
+        if !closed && line[..open].trim_end().ends_with(')') {
+            return (start, Context::Effects);
+        }
+    }
+    (start, Context::Name)
+}
+
+/// The program completions at `offset` of `src` are looked up in. When nothing is typed at
+/// the position, `scratch` is given the text of `src` with a placeholder typed there and is
+/// that program if it parses. Salsa keeps every input as long as the database, a document
+/// has a single scratch program whose text each completion sets again. The same text gives
+/// the same definitions, a completion can be described later from it.
+pub fn completed(
+    db: &mut dyn Db,
+    src: SourceProgram,
+    scratch: SourceProgram,
+    offset: usize,
+) -> SourceProgram {
+    let text = src.text(db);
+    let (start, _) = context(text, offset);
+    if start < offset {
+        return src;
+    }
+    let patched = format!("{}{}{}", &text[..offset], PLACEHOLDER, &text[offset..]);
+    scratch.set_text(db).to(patched);
+    match parser::parse(db, scratch).0.is_empty() {
+        true => src,
+        false => scratch,
+    }
+}
+
+/// What the definition `def` of `src` is, following imports.
+fn classify(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    def: DefId,
+) -> Option<(TokenKind, (SourceProgram, DefId))> {
+    let (file, id) = origin(db, workspace, src, def)?;
+    let kind = semantic::kind(resolve(db, file).def(id).kind)?;
+    Some((kind, (file, id)))
+}
+
+fn completion(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    res: &Resolution,
+    def: DefId,
+) -> Option<Completion> {
+    let name = &res.def(def).name;
+    if name == PLACEHOLDER {
+        return None;
+    }
+    let (kind, def) = match classify(db, workspace, src, def) {
+        Some(found) => found,
+        // an import of a module that is not found is still a name in scope
+        None => (TokenKind::Variable, (src, def)),
+    };
+    Some(Completion {
+        label: name.clone(),
+        kind,
+        snippet: None,
+        def: Some(def),
+    })
+}
+
+/// What can be typed at `offset` of `src`, the program [`completed`] looks them up in.
+pub fn completions(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Vec<Completion> {
+    let (_, context) = context(src.text(db), offset);
+    let res = resolve(db, src);
+    let mut found: Vec<Completion> = match &context {
+        Context::Member(dot) => {
+            let Some((file, strukt)) = owner(db, workspace, src, *dot) else {
+                return vec![];
+            };
+            let res = resolve(db, file);
+            res.members(strukt)
+                .filter_map(|(id, _)| completion(db, workspace, file, res, id))
+                .collect()
+        }
+        Context::Use(module) if module.is_empty() => vec![],
+        Context::Use(module) => {
+            let module = ModuleName::new(db, module.clone());
+            module_files(db, workspace, module)
+                .iter()
+                .flat_map(|&file| {
+                    let res = resolve(db, file);
+                    res.scope(res.module_scope())
+                        .defs
+                        .iter()
+                        .filter(|&&id| {
+                            let def = res.def(id);
+                            def.vis == Visibility::Public && def.parent.is_none()
+                        })
+                        .filter_map(|&id| completion(db, workspace, file, res, id))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        }
+        Context::Effects => res
+            .visible_at(offset)
+            .into_iter()
+            .filter_map(|id| completion(db, workspace, src, res, id))
+            .filter(|completion| completion.kind == TokenKind::Effect)
+            .collect(),
+        Context::Name => res
+            .visible_at(offset)
+            .into_iter()
+            .filter(|&id| {
+                // members are named through their owner, modules only by `use`
+                !matches!(
+                    res.def(id).kind,
+                    DefKind::Field | DefKind::Method | DefKind::Module
+                )
+            })
+            .filter_map(|id| completion(db, workspace, src, res, id))
+            .collect(),
+    };
+    if context == Context::Name {
+        let scope = res.scope(res.scope_at(offset)).kind;
+        let snippets = SNIPPETS
+            .iter()
+            .filter(|(keyword, _)| match scope {
+                ScopeKind::Module => true,
+                ScopeKind::Impl => *keyword == "fn",
+                _ => false,
+            })
+            .map(|(keyword, snippet)| Completion {
+                label: keyword.to_string(),
+                kind: TokenKind::Keyword,
+                snippet: Some(snippet.to_string()),
+                def: None,
+            });
+        found.extend(snippets);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{cursor, workspace},
+    };
+
+    /// The labels completed where `|` is in `text`, builtins and the prelude left out.
+    fn labels(text: &str) -> Vec<String> {
+        let db = &mut Database::default();
+        let (text, offset) = cursor(text);
+        let (workspace, _, main) = workspace(db, &text);
+        let scratch = SourceProgram::new(db, "file:///w/main.src".to_string(), String::new());
+        let main = completed(db, main, scratch, offset);
+        completions(db, workspace, main, offset)
+            .into_iter()
+            .filter(|completion| match completion.def {
+                Some((file, def)) => {
+                    let span = &resolve(db, file).def(def).span;
+                    file.url(db).starts_with("file:///w/") && span.start < span.end
+                }
+                None => true,
+            })
+            .map(|completion| format!("{} {:?}", completion.label, completion.kind))
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_completions() {
+        assert_eq!(
+            labels(
+                r#"use { Point } from util
+
+fn main(p: Point)[] {
+    let a = 1
+    |
+}"#
+            ),
+            ["a Variable", "p Parameter", "main Function", "Point Struct"]
+        );
+        assert_eq!(
+            labels(
+                r#"use { Point } from util
+
+fn main(p: Point)[] {
+    p.|
+}"#
+            ),
+            ["x Field", "moved Method"]
+        );
+        assert_eq!(
+            labels(
+                r#"use { Point } from util
+
+fn main(p: Point)[] {
+    p.mo|
+}"#
+            ),
+            ["x Field", "moved Method"]
+        );
+        // the name starts after characters of more than a byte
+        assert_eq!(labels("fn main()[] {\n    print(\"😀|\")\n}"), ["main Function"]);
+        assert_eq!(labels("fn main()[] {\n    print(\"éma|\")\n}"), ["main Function"]);
+        assert_eq!(
+            labels("use { | } from util"),
+            ["Point Struct", "origin Function", "shift Function"]
+        );
+        assert_eq!(
+            labels(
+                r#"effect Trace: async {
+    trace() []
+}
+
+fn main()[Tr|] {
+}"#
+            ),
+            ["Trace Effect"]
+        );
+        assert_eq!(
+            labels("struct A {\n}\n|"),
+            [
+                "A Struct",
+                "fn Keyword",
+                "struct Keyword",
+                "effect Keyword",
+                "impl Keyword"
+            ]
+        );
+    }
+}
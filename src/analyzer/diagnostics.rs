@@ -0,0 +1,137 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::{
+    analyzer::resolve,
+    compiler::text::SourceProgram,
+    lexer::Location,
+    parser,
+    types::{conformance, effects, infer},
+    Db,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Hint,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Hint => write!(f, "hint"),
+        }
+    }
+}
+
+/// A problem found while analyzing a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Range<Location>,
+    /// Other places worth looking at, e.g. the earlier definition a duplicate clashes with.
+    pub related: Vec<(Range<Location>, String)>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Range<Location>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            related: vec![],
+        }
+    }
+
+    pub fn warning(span: Range<Location>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(span, message)
+        }
+    }
+
+    pub fn with_related(mut self, span: Range<Location>, message: impl Into<String>) -> Self {
+        self.related.push((span, message.into()));
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}: {}",
+            self.severity, self.span.start.line, self.span.start.col, self.message
+        )
+    }
+}
+
+/// Diagnostics pushed by the analysis queries, read back with `query::accumulated::<Diagnostics>`.
+#[salsa::accumulator]
+pub struct Diagnostics(Diagnostic);
+
+/// Every problem the analysis finds in `src`, from lexing to the conformance of handlers,
+/// in the order the passes found them.
+pub fn check(db: &dyn Db, src: SourceProgram) -> Vec<Diagnostic> {
+    // the passes call the ones before them, whose diagnostics they accumulate as well
+    let passes = [
+        parser::parse::accumulated::<Diagnostics>(db, src),
+        resolve::resolve::accumulated::<Diagnostics>(db, src),
+        effects::expansions::accumulated::<Diagnostics>(db, src),
+        infer::infer::accumulated::<Diagnostics>(db, src),
+        conformance::conformance::accumulated::<Diagnostics>(db, src),
+    ];
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    for diagnostic in passes.into_iter().flatten() {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    fn messages(db: &Database, text: &str) -> Vec<String> {
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text.to_string());
+        check(db, src)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_check() {
+        let db = &Database::default();
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = 1\n}"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = b\n}"),
+            vec!["cannot find value `b` in this scope"]
+        );
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = 1\n"),
+            vec!["unexpected end of file, expected one of end of line, `handle`, `ident`, `if`, `let`, `self`, `}`"]
+        );
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = 1 ^ 2\n}"),
+            vec!["unexpected character `^`"]
+        );
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = \"b\n}"),
+            vec!["unexpected end of input"]
+        );
+        assert_eq!(
+            messages(db, "fn main()[] {\n    let a = 1 2\n}"),
+            vec!["unexpected `2`, expected one of end of line, `*`, `+`, `-`, `/`, `}`"]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! What editors show about a definition: its signature and its doc comment.
+//!
+//! A doc comment is the `#` comments on the lines right above a definition:
+//!
+//! ```text
+//! # Where points start.
+//! pub fn origin()[] -> Point {
+//! ```
+
+use crate::{
+    analyzer::resolve::{resolve, DefId, DefKind},
+    compiler::text::SourceProgram,
+    types::infer::infer,
+    Db,
+};
+
+/// The doc comment of the definition starting at byte `start` of `text`, its lines without
+/// the `#`.
+pub fn doc_comment(text: &str, start: usize) -> Option<String> {
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let mut lines: Vec<&str> = text[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with('#') && !line.starts_with("#!"))
+        .map(|line| line.trim_start_matches('#').trim())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(lines.join("\n"))
+}
+
+/// The signature of `def` of `src` as written, `pub fn origin()[] -> Point`, with the
+/// inferred type of a binding, `q: Point`.
+pub fn signature(db: &dyn Db, src: SourceProgram, def: DefId) -> String {
+    let definition = resolve(db, src).def(def);
+    let span = definition.full_span.start.offset..definition.full_span.end.offset;
+    if span.is_empty() {
+        let keyword = match definition.kind {
+            DefKind::BuiltinEffect => "effect",
+            _ => "type",
+        };
+        return format!("{} {}", keyword, definition.name);
+    }
+    if definition.kind.is_local() {
+        return match infer(db, src).scheme(def) {
+            Some(scheme) => format!("{}: {}", definition.name, scheme.ty),
+            None => definition.name.clone(),
+        };
+    }
+    // the first line, without the opening brace of a body
+    let text = &src.text(db)[span];
+    let line = text.lines().next().unwrap_or_default();
+    line.split('{')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// The doc comment of `def` of `src`.
+pub fn docs(db: &dyn Db, src: SourceProgram, def: DefId) -> Option<String> {
+    let definition = resolve(db, src).def(def);
+    doc_comment(src.text(db), definition.full_span.start.offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    #[okstd::test]
+    fn test_signature_and_docs() {
+        let db = &Database::default();
+        let text = r#"# A place.
+# On a plane.
+pub struct Point {
+    pub x: i32
+}
+
+# not about origin
+
+fn origin(p: Point)[] -> Point {
+    let q = p
+    q
+}"#;
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text.to_string());
+        let res = resolve(db, src);
+        let def = |name: &str| {
+            res.iter()
+                .find(|(_, def)| def.name == name && def.span.start < def.span.end)
+                .unwrap()
+                .0
+        };
+        assert_eq!(signature(db, src, def("Point")), "pub struct Point");
+        assert_eq!(signature(db, src, def("x")), "x: i32");
+        assert_eq!(
+            signature(db, src, def("origin")),
+            "fn origin(p: Point)[] -> Point"
+        );
+        assert_eq!(signature(db, src, def("q")), "q: Point");
+        assert_eq!(
+            docs(db, src, def("Point")).as_deref(),
+            Some("A place.\nOn a plane.")
+        );
+        assert_eq!(docs(db, src, def("x")), None);
+        assert_eq!(docs(db, src, def("origin")), None);
+    }
+}
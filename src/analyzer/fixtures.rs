@@ -0,0 +1,25 @@
+//! The workspace the tests of the editor features look at: a `util` module defining a
+//! struct and functions, and a `main` module using them.
+
+use crate::{
+    analyzer::{db::Database, navigation::Workspace},
+    compiler::text::SourceProgram,
+};
+
+/// `util.src`, shared with the tests of the language server.
+pub const UTIL: &str = include_str!("../../tests/fixtures/util.src");
+
+/// `main.src`, using [`UTIL`].
+pub const MAIN: &str = include_str!("../../tests/fixtures/main.src");
+
+/// The files `util.src` holding [`UTIL`] and `main.src` holding `main`, in one workspace.
+pub fn workspace(db: &Database, main: &str) -> (Workspace, SourceProgram, SourceProgram) {
+    let util = SourceProgram::new(db, "file:///w/util.src".to_string(), UTIL.to_string());
+    let main = SourceProgram::new(db, "file:///w/main.src".to_string(), main.to_string());
+    (Workspace::new(db, vec![util, main]), util, main)
+}
+
+/// `text` without the `|` marking a position, and the offset of the position.
+pub fn cursor(text: &str) -> (String, usize) {
+    (text.replace('|', ""), text.find('|').unwrap())
+}
@@ -0,0 +1,152 @@
+//! What an editor shows about the name under the cursor.
+//!
+//! A hover is Markdown: the definition as written, the type inferred for it, the effects
+//! calling it performs with every effect expanded to the effects it stands for, where it
+//! can be used from and its doc comment. Imported names are described by the definition
+//! they import.
+
+use std::ops::Range;
+
+use crate::{
+    analyzer::{
+        docs::{docs, signature},
+        ident_at,
+        modules::{self, module_name},
+        navigation::{def_at, origin, Workspace},
+        resolve::{resolve, DefKind},
+    },
+    compiler::text::SourceProgram,
+    parser::ast::Visibility,
+    types::{effects::expansions, infer::infer, Row, Type},
+    Db,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hover {
+    /// The bytes of the name hovered.
+    pub span: Range<usize>,
+    pub markdown: String,
+}
+
+/// The description of the name at `offset` of `src`.
+pub fn hover(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<Hover> {
+    let span = ident_at(src.text(db), offset);
+    if span.is_empty() {
+        return None;
+    }
+    let (file, def) = def_at(db, workspace, src, offset)?;
+    // an import of a module that is not found is described as the import
+    let (file, def) = origin(db, workspace, file, def).unwrap_or((file, def));
+    let definition = resolve(db, file).def(def);
+
+    let mut sections = vec![format!("```src\n{}\n```", signature(db, file, def))];
+    match definition.kind {
+        DefKind::Function | DefKind::Method | DefKind::EffectOp => {
+            if let Some(scheme) = infer(db, file).scheme(def) {
+                sections.push(format!("`{}`", scheme));
+                if let Type::Fn(_, _, row) = &scheme.ty {
+                    sections.push(format!("Performs `{}`", row));
+                }
+            }
+        }
+        DefKind::Effect | DefKind::BuiltinEffect => {
+            if let Some(expanded) = expansions(db, file).get(&def) {
+                let row = Row::closed(expanded.iter().cloned());
+                sections.push(format!("Stands for `{}`", row));
+            }
+        }
+        _ => {}
+    }
+    let builtin = definition.span.start == definition.span.end;
+    if !definition.kind.is_local() && !builtin {
+        let url = file.url(db);
+        let module = modules::module_of(db, file)
+            .or_else(|| module_name(&url).map(str::to_string))
+            .unwrap_or(url);
+        sections.push(match definition.vis {
+            Visibility::Public => format!("Public, defined in `{}`", module),
+            Visibility::Private => format!("Private to `{}`", module),
+        });
+    }
+    if let Some(docs) = docs(db, file, def) {
+        sections.push(format!("---\n\n{}", docs));
+    }
+    Some(Hover {
+        span,
+        markdown: sections.join("\n\n"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{workspace, MAIN, UTIL},
+    };
+
+    #[okstd::test]
+    fn test_hover() {
+        let db = &Database::default();
+        let (workspace, util, main) = workspace(db, MAIN);
+
+        let at = |needle: &str| MAIN.find(needle).unwrap();
+        let found = hover(db, workspace, main, at("origin") + 2).unwrap();
+        assert_eq!(found.span, at("origin")..at("origin") + 6);
+        assert_eq!(
+            found.markdown,
+            r#"```src
+pub fn origin()[] -> Point
+```
+
+`fn() -> Point`
+
+Performs `[]`
+
+Public, defined in `util`
+
+---
+
+Where points start."#
+        );
+
+        let found = hover(db, workspace, main, at("main(")).unwrap();
+        assert_eq!(
+            found.markdown,
+            r#"```src
+fn main(p: Point, n: i32)[Log]
+```
+
+`fn(Point, i32)[Log, async] -> ()`
+
+Performs `[Log, async]`
+
+Private to `main`"#
+        );
+
+        let found = hover(db, workspace, main, at("r = p")).unwrap();
+        assert_eq!(found.markdown, "```src\nr: Point\n```");
+
+        let found = hover(db, workspace, main, at("Log:")).unwrap();
+        assert_eq!(
+            found.markdown,
+            r#"```src
+effect Log: async
+```
+
+Stands for `[Log, async]`
+
+Private to `main`
+
+---
+
+Writes and flushes."#
+        );
+        assert_eq!(hover(db, workspace, util, UTIL.find("{\n").unwrap()), None);
+    }
+}
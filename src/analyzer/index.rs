@@ -0,0 +1,461 @@
+//! Positional indexes over spans.
+//!
+//! A [`SpanIndex`] is an implicit augmented interval tree: the spans are sorted by their
+//! start offset and every span at an odd position of the array doubles as an interior node
+//! of a balanced binary tree, carrying the largest end offset below it. This lets
+//! [`SpanIndex::innermost`] and [`SpanIndex::overlapping`] answer in `O(log n + k)` without
+//! any pointers, see <https://github.com/lh3/cgranges> for the original layout. The
+//! [`span_index`] of a file holds every spanned node of it, the
+//! [`Resolution`](super::resolve::Resolution) of a file the names and scopes in it.
+
+use std::ops::Range;
+
+use crate::{
+    compiler::text::SourceProgram,
+    parser::{
+        self,
+        ast::{self, FnArg, Keyword, Node},
+        span::{ByteOrLineColOrCoord, Spanned},
+    },
+    Db,
+};
+
+/// Below this level a subtree is small enough to scan linearly.
+const SCAN_LEVEL: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry<T> {
+    /// Start offset, inclusive.
+    start: usize,
+    /// End offset, exclusive. A span also covers the offset just past its last byte,
+    /// so that a cursor sitting at the end of an identifier still finds it.
+    end: usize,
+    /// The largest `end` in the subtree rooted at this entry.
+    max: usize,
+    value: T,
+}
+
+/// An interval index over spans of a source file, each with a `T`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanIndex<T> {
+    entries: Vec<Entry<T>>,
+    /// The level of the root of the implicit tree, `None` when the index is empty.
+    root: Option<usize>,
+}
+
+impl<T> Default for SpanIndex<T> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            root: None,
+        }
+    }
+}
+
+impl<T> SpanIndex<T> {
+    /// Indexes the byte `spans`. Of spans that are the same, the last one is the innermost.
+    pub fn new(spans: impl IntoIterator<Item = (Range<usize>, T)>) -> Self {
+        let mut entries: Vec<Entry<T>> = spans
+            .into_iter()
+            .map(|(span, value)| Entry {
+                start: span.start,
+                end: span.end + 1,
+                max: 0,
+                value,
+            })
+            .collect();
+        // stable, so a parent stays in front of a child that starts at the same offset
+        entries.sort_by_key(|entry| entry.start);
+        let root = augment(&mut entries);
+        Self { entries, root }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The smallest span containing `offset`, preferring the deepest one when two are the same.
+    pub fn innermost(&self, offset: usize) -> Option<&T> {
+        self.query(offset..offset + 1)
+            .into_iter()
+            .min_by_key(|&i| {
+                let entry = &self.entries[i];
+                (entry.end - entry.start, std::cmp::Reverse(i))
+            })
+            .map(|i| &self.entries[i].value)
+    }
+
+    /// Every span overlapping the byte `range`, ordered by start offset, outermost first.
+    pub fn overlapping(&self, range: Range<usize>) -> Vec<&T> {
+        let mut found = self.query(range);
+        found.sort_unstable();
+        found.into_iter().map(|i| &self.entries[i].value).collect()
+    }
+
+    /// Indices of the entries overlapping `range`, in no particular order.
+    fn query(&self, range: Range<usize>) -> Vec<usize> {
+        let mut found = vec![];
+        let Some(root) = self.root else {
+            return found;
+        };
+        let n = self.entries.len();
+        let Range { start, end } = range;
+        // (level, index, whether the left subtree has been visited)
+        let mut stack = vec![(root, (1 << root) - 1, false)];
+        while let Some((level, x, visited_left)) = stack.pop() {
+            if level <= SCAN_LEVEL {
+                let first = x >> level << level;
+                let last = (first + (1 << (level + 1)) - 1).min(n);
+                for i in first..last {
+                    if self.entries[i].start >= end {
+                        break;
+                    }
+                    if start < self.entries[i].end {
+                        found.push(i);
+                    }
+                }
+            } else if !visited_left {
+                stack.push((level, x, true));
+                let left = x - (1 << (level - 1));
+                if left >= n || self.entries[left].max > start {
+                    stack.push((level - 1, left, false));
+                }
+            } else if x < n && self.entries[x].start < end {
+                if start < self.entries[x].end {
+                    found.push(x);
+                }
+                stack.push((level - 1, x + (1 << (level - 1)), false));
+            }
+        }
+        found
+    }
+}
+
+/// Fills in `max` for every entry, returning the level of the root.
+fn augment<T>(entries: &mut [Entry<T>]) -> Option<usize> {
+    let n = entries.len();
+    if n == 0 {
+        return None;
+    }
+    // leaves sit at even indices
+    let mut last_i = 0;
+    let mut last = 0;
+    for i in (0..n).step_by(2) {
+        entries[i].max = entries[i].end;
+        last_i = i;
+        last = entries[i].end;
+    }
+    let mut level = 1;
+    while 1 << level <= n {
+        let x = 1 << (level - 1);
+        let step = x << 2;
+        for i in ((x << 1) - 1..n).step_by(step) {
+            let left = entries[i - x].max;
+            // a right child past the end stands in for the last real subtree
+            let right = if i + x < n { entries[i + x].max } else { last };
+            entries[i].max = entries[i].end.max(left).max(right);
+        }
+        last_i = if (last_i >> level) & 1 == 1 {
+            last_i - x
+        } else {
+            last_i + x
+        };
+        if last_i < n && entries[last_i].max > last {
+            last = entries[last_i].max;
+        }
+        level += 1;
+    }
+    Some(level - 1)
+}
+
+/// Collects every spanned node reachable from a module, parents before children.
+#[derive(Default)]
+struct Collector(Vec<Spanned<Node>>);
+
+impl Collector {
+    fn node(&mut self, node: &Spanned<Node>) {
+        self.0.push(node.clone());
+        match &node.1 {
+            Node::BinaryExpression(bin) => {
+                self.node(&bin.lhs);
+                self.node(&bin.rhs);
+            }
+            // the node already covers the identifier, only its generics are new
+            Node::Ident(ident) => self.generics(ident),
+            Node::Binding(ast::Binding(name, value)) => {
+                self.ident(name);
+                self.node(value);
+            }
+            Node::FnCall(ast::FnCall(name, args)) => {
+                self.ident(name);
+                args.iter().for_each(|arg| self.node(arg));
+            }
+            Node::FnDef(ast::FnDef(kwv, proto, block)) => {
+                self.keyword_and_visibility(kwv);
+                self.prototype(proto);
+                block.0.iter().for_each(|node| self.node(node));
+            }
+            Node::EffectDef(ast::EffectDef(kwv, name, effects, block)) => {
+                self.keyword_and_visibility(kwv);
+                self.ident(name);
+                effects.iter().for_each(|effect| self.ident(effect));
+                block.0.iter().for_each(|proto| self.prototype(proto));
+            }
+            Node::StructDef(ast::StructDef(kwv, name, fields)) => {
+                self.keyword_and_visibility(kwv);
+                self.ident(name);
+                fields.0.iter().for_each(|field| self.field(field));
+            }
+            Node::UseDef(ast::UseDef(kwv, imports, module)) => {
+                self.keyword_and_visibility(kwv);
+                imports.iter().for_each(|import| self.ident(import));
+                self.ident(module);
+            }
+            Node::ImplDef(ast::ImplDef(kwv, name, target, block)) => {
+                self.keyword_and_visibility(kwv);
+                self.ident(name);
+                if let Some(target) = target {
+                    self.ident(target);
+                }
+                block.0.iter().for_each(|node| self.node(node));
+            }
+            Node::Branch(ast::BranchDef(cond, arms)) => {
+                self.node(cond);
+                for (arm, block) in arms {
+                    self.node(arm);
+                    block.0.iter().for_each(|node| self.node(node));
+                }
+            }
+            Node::FieldAccess(ast::FieldAccess(lhs, rhs)) => {
+                self.node(lhs);
+                self.node(rhs);
+            }
+            Node::Handle(ast::HandleDef(handle, body, with, handler)) => {
+                self.keyword(handle);
+                body.0.iter().for_each(|node| self.node(node));
+                self.keyword(with);
+                self.ident(handler);
+            }
+            Node::Bool(_)
+            | Node::Integer(_)
+            | Node::Float(_)
+            | Node::String(_)
+            | Node::Keyword(_)
+            | Node::Visibility(_)
+            | Node::Error => {}
+        }
+    }
+
+    fn ident(&mut self, ident: &Spanned<ast::Ident>) {
+        self.0
+            .push(Spanned(ident.0, Node::Ident(ident.clone()), ident.2));
+        self.generics(ident);
+    }
+
+    fn generics(&mut self, ident: &Spanned<ast::Ident>) {
+        if let Some(generics) = &ident.1 .1 {
+            generics.iter().for_each(|generic| self.ident(generic));
+        }
+    }
+
+    fn keyword(&mut self, kw: &Spanned<Keyword>) {
+        self.0.push(Spanned(kw.0, Node::Keyword(kw.1.clone()), kw.2));
+    }
+
+    fn keyword_and_visibility(&mut self, kwv: &Spanned<ast::KeywordAndVisibility>) {
+        let ast::KeywordAndVisibility(kw, vis) = &kwv.1;
+        self.0.push(Spanned(vis.0, Node::Visibility(vis.1.clone()), vis.2));
+        self.keyword(kw);
+    }
+
+    fn prototype(&mut self, proto: &Spanned<ast::Prototype>) {
+        self.ident(&proto.1.name);
+        for arg in proto.1.args.iter() {
+            match &arg.1 {
+                FnArg::Reciever => self
+                    .0
+                    .push(Spanned(arg.0, Node::Keyword(Keyword::SelfValue), arg.2)),
+                FnArg::Field(field) => self.field(field),
+            }
+        }
+        if let Some(ret) = &proto.1.ret {
+            self.ident(ret);
+        }
+        proto.1.effects.iter().for_each(|effect| self.ident(effect));
+    }
+
+    fn field(&mut self, field: &Spanned<ast::FieldDef>) {
+        let ast::FieldDef(vis, name, ty) = &field.1;
+        self.0.push(Spanned(vis.0, Node::Visibility(vis.1.clone()), vis.2));
+        self.ident(name);
+        self.ident(ty);
+    }
+}
+
+/// The spanned nodes of a file, found by byte offset or by line and column.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NodeIndex {
+    nodes: SpanIndex<Spanned<Node>>,
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    text: String,
+}
+
+impl NodeIndex {
+    pub fn new(text: &str, nodes: impl IntoIterator<Item = Spanned<Node>>) -> Self {
+        let nodes = nodes
+            .into_iter()
+            // zero width spans are synthesized by the parser, e.g. the implicit `priv`
+            .filter(|node| node.0.offset < node.2.offset)
+            .map(|node| (node.0.offset..node.2.offset, node));
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            nodes: SpanIndex::new(nodes),
+            line_starts,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Resolves `pos` to a byte offset into the indexed text.
+    pub fn offset(&self, pos: &ByteOrLineColOrCoord) -> Option<usize> {
+        match pos {
+            ByteOrLineColOrCoord::Byte(offset) => Some(*offset),
+            ByteOrLineColOrCoord::Coord(location) => Some(location.offset),
+            ByteOrLineColOrCoord::LineCol(line, col) => {
+                let start = *self.line_starts.get(*line)?;
+                let end = self
+                    .line_starts
+                    .get(line + 1)
+                    .copied()
+                    .unwrap_or(self.text.len());
+                // columns count chars, like the lexer does
+                let line_text = &self.text[start..end];
+                let within = line_text
+                    .char_indices()
+                    .nth(*col)
+                    .map(|(i, _)| i)
+                    .unwrap_or(line_text.len());
+                Some(start + within)
+            }
+        }
+    }
+
+    /// The smallest node containing `pos`, preferring the deepest one when two nodes share a span.
+    pub fn innermost(&self, pos: &ByteOrLineColOrCoord) -> Option<&Spanned<Node>> {
+        self.nodes.innermost(self.offset(pos)?)
+    }
+
+    /// Every node overlapping the byte `range`, ordered by start offset, outermost first.
+    pub fn overlapping(&self, range: Range<usize>) -> Vec<&Spanned<Node>> {
+        self.nodes.overlapping(range)
+    }
+}
+
+/// Builds the positional index of `src`, once per revision.
+#[salsa::tracked(return_ref)]
+pub fn span_index(db: &dyn Db, src: SourceProgram) -> NodeIndex {
+    let mut collector = Collector::default();
+    for node in parser::parse(db, src).0.iter() {
+        collector.node(node);
+    }
+    NodeIndex::new(src.text(db), collector.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+    use proptest::prelude::*;
+
+    fn index_of(spans: &[(usize, usize)]) -> SpanIndex<usize> {
+        SpanIndex::new(
+            spans
+                .iter()
+                .enumerate()
+                .map(|(i, (start, end))| (*start..*end, i)),
+        )
+    }
+
+    fn ids(found: Vec<&usize>) -> Vec<usize> {
+        let mut ids: Vec<_> = found.into_iter().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    proptest! {
+        #[test]
+        fn test_overlapping_matches_linear_scan(
+            spans in prop::collection::vec((0usize..200, 1usize..40), 0..300),
+            query in (0usize..240, 0usize..20),
+        ) {
+            let spans: Vec<_> = spans.into_iter().map(|(start, len)| (start, start + len)).collect();
+            let index = index_of(&spans);
+            let (start, len) = query;
+            let range = start..start + len + 1;
+            let expected: Vec<usize> = spans
+                .iter()
+                .enumerate()
+                .filter(|(_, (s, e))| *s < range.end && range.start < e + 1)
+                .map(|(i, _)| i)
+                .collect();
+            prop_assert_eq!(ids(index.overlapping(range)), expected);
+        }
+    }
+
+    #[okstd::test]
+    fn test_innermost_prefers_smallest_span() {
+        let index = index_of(&[(0, 20), (2, 10), (4, 6), (12, 18)]);
+        let innermost = |offset| index.innermost(offset).copied();
+        assert_eq!(innermost(5), Some(2));
+        assert_eq!(innermost(8), Some(1));
+        assert_eq!(innermost(11), Some(0));
+        assert_eq!(innermost(13), Some(3));
+        assert_eq!(innermost(30), None);
+    }
+
+    #[okstd::test]
+    fn test_line_col_offsets() {
+        let index = NodeIndex::new("fn main()[] {\n  let é = 1\n}", vec![]);
+        assert_eq!(index.offset(&ByteOrLineColOrCoord::LineCol(0, 3)), Some(3));
+        assert_eq!(index.offset(&ByteOrLineColOrCoord::LineCol(1, 2)), Some(16));
+        // `é` is two bytes wide
+        assert_eq!(index.offset(&ByteOrLineColOrCoord::LineCol(1, 7)), Some(22));
+        assert_eq!(index.offset(&ByteOrLineColOrCoord::LineCol(3, 0)), None);
+    }
+
+    #[okstd::test]
+    fn test_span_index_covers_nested_nodes() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            "fn main(a: i32)[] {\n    let b = call(a)\n}".to_string(),
+        );
+        let index = span_index(db, src);
+        let text = |offset| {
+            index
+                .innermost(&ByteOrLineColOrCoord::Byte(offset))
+                .map(|node| node.1.to_string())
+        };
+        assert_eq!(text(3).as_deref(), Some("main"));
+        assert_eq!(text(11).as_deref(), Some("i32"));
+        assert_eq!(text(33).as_deref(), Some("call"));
+        assert_eq!(text(37).as_deref(), Some("a"));
+        // the function and the binding, `let` itself is not kept in the ast
+        assert_eq!(index.overlapping(24..26).len(), 2);
+    }
+}
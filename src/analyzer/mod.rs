@@ -1,56 +1,63 @@
 pub mod db;
+pub mod completion;
+pub mod diagnostics;
+pub mod docs;
+#[cfg(test)]
+mod fixtures;
+pub mod hover;
+pub mod index;
+pub mod modules;
+pub mod navigation;
+pub mod references;
+pub mod resolve;
+pub mod semantic;
+pub mod signature_help;
+pub mod symbols;
+
+use std::ops::Range;
 
 use crate::{
     compiler::text::{self, SourceProgram},
     parser::{
-        ast::{self, Node},
-        span::{self, ByteOrLineColOrCoord, Spanned},
+        ast,
+        span::{self, Spanned},
     },
     Db,
 };
 
-use okstd::prelude::*;
+/// Whether `c` can be part of a name. Every feature finds the name at a position in the
+/// text with it, so they agree on where a name starts and ends.
+pub fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The bytes of the name `offset` of `text` is in or at either end of, empty when there is
+/// none.
+pub fn ident_at(text: &str, offset: usize) -> Range<usize> {
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_ident(c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .find(|c: char| !is_ident(c))
+        .map_or(text.len(), |i| offset + i);
+    start..end
+}
 
 #[salsa::input]
 pub struct SyntaxTree {
     exprs: Vec<Spanned<ast::Node>>,
 }
 
-impl SyntaxTree {
-    fn find_span(self, db: &dyn Db, pos: &ByteOrLineColOrCoord) -> Option<Spanned<Node>> {
-        self.exprs(db).iter().fold(None, |acc, expr| {
-            debug!("Checking if {:#?} overlaps with {:?}\n", expr, pos);
-            if expr.overlap(pos) {
-                debug!("{:?} overlaps with {:#?}\n", expr, pos);
-                match acc {
-                    Some(acc) => {
-                        debug!(
-                            "Comparing {:#?} with {:#?}\n",
-                            expr.span_size(),
-                            acc.span_size()
-                        );
-                        if expr.span_size() < acc.span_size() {
-                            Some(expr.clone())
-                        } else {
-                            Some(acc)
-                        }
-                    }
-                    None => Some(expr.clone()),
-                }
-            } else {
-                acc
-            }
-        })
-    }
-}
-
 #[salsa::tracked]
 pub fn get_symbol(
-    _db: &dyn Db,
-    _src: text::SourceProgram,
-    _pos: span::ByteOrLineColOrCoordInterned,
+    db: &dyn Db,
+    src: text::SourceProgram,
+    pos: span::ByteOrLineColOrCoordInterned,
 ) -> Option<Spanned<ast::Node>> {
-    None
+    index::span_index(db, src).innermost(pos.pos(db)).cloned()
 }
 
 #[salsa::input]
@@ -104,7 +111,7 @@ mod tests {
                     "inmemory://test".to_string(),
                     $src.to_string(),
                 );
-                let symb: Option<Spanned<Node>> = get_symbol(
+                let symb: Option<Spanned<ast::Node>> = get_symbol(
                     db,
                     src,
                     ByteOrLineColOrCoordInterned::new(db, ByteOrLineColOrCoord::LineCol($line, $col)),
@@ -117,17 +124,18 @@ mod tests {
         };
     }
 
-//     test_span_text!(
-//         test_get_body,
-//         r#"fn main()[] {
-// let a = 1
-//     }"#,
-//         "let",
-//         1,
-//         1
-//     );
+    test_span_text!(
+        test_get_body,
+        r#"fn main()[] {
+    let a = 1
+}"#,
+        "a = 1",
+        1,
+        5
+    );
 
-//     test_span_text!(test_get_symbol, "fn main()[] {}", "priv fn", 0, 0);
+    test_span_text!(test_get_symbol, "fn main()[] {}", "fn", 0, 0);
+    test_span_text!(test_get_name, "fn main()[] {}", "main", 0, 4);
 }
 
 #[salsa::tracked]
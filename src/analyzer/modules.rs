@@ -0,0 +1,63 @@
+//! Modules a program can `use` from without bringing a file of its own.
+//!
+//! `use { reads } from host` declares the items it names in the importing module, the
+//! resolver records where each came from in [`Resolution::origin`](super::resolve::Resolution::origin)
+//! so later passes can read their effects and types from the module defining them.
+//! The public items of `std` are in scope everywhere, see [`prelude`].
+
+use crate::{compiler::text::SourceProgram, host, stdlib, Db};
+
+/// The scheme of the urls built-in files are loaded under, `builtin://host/host.src`.
+const SCHEME: &str = "builtin://";
+
+#[salsa::interned]
+pub struct ModuleName {
+    #[return_ref]
+    pub name: String,
+}
+
+/// The files of the built-in module `name`, empty for any other module.
+#[salsa::tracked(return_ref)]
+pub fn builtin(db: &dyn Db, name: ModuleName) -> Vec<SourceProgram> {
+    let files: &[(&str, &str)] = match name.name(db).as_str() {
+        host::MODULE => &[("host.src", host::SOURCE)],
+        stdlib::MODULE => stdlib::FILES,
+        _ => return vec![],
+    };
+    files
+        .iter()
+        .map(|(file, text)| {
+            SourceProgram::new(
+                db,
+                format!("{}{}/{}", SCHEME, name.name(db), file),
+                text.to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The files of `std`, whose public items every other module sees without importing them.
+pub fn prelude(db: &dyn Db) -> &[SourceProgram] {
+    builtin(db, ModuleName::new(db, stdlib::MODULE.to_string()))
+}
+
+/// The built-in module `src` is a file of, `None` for the programs being compiled.
+pub fn module_of(db: &dyn Db, src: SourceProgram) -> Option<String> {
+    let url = src.url(db);
+    let path = url.strip_prefix(SCHEME)?;
+    path.split('/').next().map(str::to_string)
+}
+
+/// The module the file at `url` makes up, its file name without the `.src` extension.
+pub fn module_name(url: &str) -> Option<&str> {
+    url.rsplit('/').next()?.strip_suffix(".src")
+}
+
+/// The built-in file loaded under `url`, `builtin://host/host.src`.
+pub fn builtin_file(db: &dyn Db, url: &str) -> Option<SourceProgram> {
+    let module = url.strip_prefix(SCHEME)?.split('/').next()?;
+    builtin(db, ModuleName::new(db, module.to_string()))
+        .iter()
+        .copied()
+        .find(|src| src.url(db) == url)
+}
@@ -0,0 +1,350 @@
+//! Where a name is defined, declared and typed, for an editor to jump to.
+//!
+//! The queries here read the tracked [`resolve`] and [`infer`] of the files they visit, so
+//! asking again about files that did not change is a lookup. Names imported from a module
+//! that is not built in are found among the files of a [`Workspace`], a module being every
+//! file named after it: `use { helper } from util` reads `util.src`.
+
+use std::ops::Range;
+
+use crate::{
+    analyzer::{
+        ident_at,
+        modules::{self, module_name, ModuleName},
+        resolve::{resolve, DefId, DefKind, Namespace, Resolution, ScopeKind},
+    },
+    compiler::text::SourceProgram,
+    lexer::Location,
+    parser::ast::Visibility,
+    types::{infer::infer, Type},
+    Db,
+};
+
+/// The files a program is made of, besides the built-in modules.
+#[salsa::input]
+pub struct Workspace {
+    #[return_ref]
+    pub files: Vec<SourceProgram>,
+}
+
+/// A definition to jump to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub src: SourceProgram,
+    /// The defining identifier.
+    pub span: Range<Location>,
+    /// The whole definition, e.g. the function with its block.
+    pub full_span: Range<Location>,
+}
+
+/// The files of module `name`: those of a built-in module, else the workspace files named
+/// after it.
+#[salsa::tracked(return_ref)]
+pub fn module_files(db: &dyn Db, workspace: Workspace, name: ModuleName) -> Vec<SourceProgram> {
+    let builtin = modules::builtin(db, name);
+    if !builtin.is_empty() {
+        return builtin.clone();
+    }
+    workspace
+        .files(db)
+        .iter()
+        .copied()
+        .filter(|src| module_name(&src.url(db)) == Some(name.name(db).as_str()))
+        .collect()
+}
+
+/// The definition named or used at `offset` and the file defining it, which is another
+/// one for a member of a struct from another module.
+pub fn def_at(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<(SourceProgram, DefId)> {
+    let local = resolve(db, src).def_at(offset).or_else(|| {
+        infer(db, src)
+            .members
+            .iter()
+            .find(|((start, end), _)| *start <= offset && offset <= *end)
+            .map(|(_, &def)| def)
+    });
+    match local {
+        Some(def) => Some((src, def)),
+        None => member_at(db, workspace, src, offset),
+    }
+}
+
+/// The field or method named at `offset` after a `.`, looked up on the struct the value
+/// before the dot is an instance of. Inference only looks into the structs of a module.
+fn member_at(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<(SourceProgram, DefId)> {
+    let text = src.text(db);
+    let span = ident_at(text, offset);
+    let dot = text[..span.start].strip_suffix('.')?.len();
+    let name = &text[span];
+    let (file, strukt) = owner(db, workspace, src, dot)?;
+    resolve(db, file)
+        .members(strukt)
+        .find(|(_, def)| def.name == name)
+        .map(|(id, _)| (file, id))
+}
+
+/// The struct the value ending right before the `.` at `dot` is an instance of, and the
+/// file defining it.
+pub fn owner(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    dot: usize,
+) -> Option<(SourceProgram, DefId)> {
+    let value = infer(db, src)
+        .exprs
+        .iter()
+        .filter(|((_, end), _)| *end == dot)
+        .max_by_key(|((start, end), _)| end - start)?
+        .1;
+    let res = resolve(db, src);
+    let strukt = res.visible_at(dot).into_iter().find(|&id| {
+        Some(&res.def(id).name) == type_name(value).as_ref()
+            && res.def(id).kind.is_in(Namespace::Type)
+    })?;
+    origin(db, workspace, src, strukt)
+}
+
+/// The file and definition `def` of `src` stands for, following imports to the module
+/// defining the name. `None` when the module or the name is nowhere to be found.
+pub fn origin(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    def: DefId,
+) -> Option<(SourceProgram, DefId)> {
+    let (mut src, mut def) = (src, def);
+    // a module can import what it exports from another, which may import it back
+    for _ in 0..=workspace.files(db).len() {
+        let res = resolve(db, src);
+        if let Some(origin) = res.origin(def) {
+            return Some(origin);
+        }
+        let definition = res.def(def);
+        if definition.kind != DefKind::Import {
+            return Some((src, def));
+        }
+        let module = ModuleName::new(db, res.def(definition.parent?).name.clone());
+        (src, def) = module_files(db, workspace, module)
+            .iter()
+            .find_map(|&file| {
+                let res = resolve(db, file);
+                exported(res, &definition.name).map(|id| (file, id))
+            })?;
+    }
+    None
+}
+
+/// The public item of a module named `name`.
+fn exported(res: &Resolution, name: &str) -> Option<DefId> {
+    res.scope(res.module_scope())
+        .defs
+        .iter()
+        .copied()
+        .find(|&id| {
+            let def = res.def(id);
+            def.name == name && def.parent.is_none() && def.vis == Visibility::Public
+        })
+}
+
+/// Where to jump to for `def` of `src`: its definition, or the first file of a module.
+pub fn target(db: &dyn Db, workspace: Workspace, src: SourceProgram, def: DefId) -> Option<Target> {
+    let res = resolve(db, src);
+    if res.def(def).kind == DefKind::Module {
+        let module = ModuleName::new(db, res.def(def).name.clone());
+        let &file = module_files(db, workspace, module).first()?;
+        let res = resolve(db, file);
+        let whole = res.scope(res.module_scope()).span.clone();
+        return Some(Target {
+            src: file,
+            span: whole.start..whole.start,
+            full_span: whole,
+        });
+    }
+    let (file, id) = origin(db, workspace, src, def)?;
+    let definition = resolve(db, file).def(id);
+    // builtins are defined by the compiler
+    if definition.span.start == definition.span.end {
+        return None;
+    }
+    Some(Target {
+        src: file,
+        span: definition.span.clone(),
+        full_span: definition.full_span.clone(),
+    })
+}
+
+/// The definition of the name at `offset`.
+pub fn definition(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<Target> {
+    let (file, def) = def_at(db, workspace, src, offset)?;
+    target(db, workspace, file, def)
+}
+
+/// The declaration of the name at `offset`: the prototype a method of an effect handler
+/// implements, the definition for anything else.
+pub fn declaration(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<Target> {
+    let (file, def) = def_at(db, workspace, src, offset)?;
+    let (file, def) = implemented(db, workspace, file, def).unwrap_or((file, def));
+    target(db, workspace, file, def)
+}
+
+/// The effect operation `method` of `src` implements when it is in an
+/// `impl Effect for Type` block, and the file declaring it.
+pub fn implemented(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    method: DefId,
+) -> Option<(SourceProgram, DefId)> {
+    let res = resolve(db, src);
+    let method = res.def(method);
+    let scope = res.scope(method.scope);
+    if method.kind != DefKind::Method || scope.kind != ScopeKind::Impl {
+        return None;
+    }
+    // the name after `impl` is the first use in the block
+    let effect = res
+        .refs
+        .iter()
+        .find(|reference| {
+            scope.span.start <= reference.span.start && reference.span.end <= scope.span.end
+        })?
+        .def;
+    let (file, effect) = origin(db, workspace, src, effect)?;
+    let res = resolve(db, file);
+    if res.def(effect).kind != DefKind::Effect {
+        return None;
+    }
+    res.members(effect)
+        .find(|(_, op)| op.kind == DefKind::EffectOp && op.name == method.name)
+        .map(|(id, _)| (file, id))
+}
+
+/// The struct or effect typing the name at `offset`: the type of a binding, field or
+/// expression, the return type of a function, a type itself.
+pub fn type_definition(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<Target> {
+    let (file, def) = def_at(db, workspace, src, offset)?;
+    let (file, def) = origin(db, workspace, file, def)?;
+    let res = resolve(db, file);
+    if matches!(
+        res.def(def).kind,
+        DefKind::Struct | DefKind::Effect | DefKind::TypeParam
+    ) {
+        return target(db, workspace, file, def);
+    }
+    let definition = res.def(def);
+    let name = match infer(db, file).scheme(def).map(|scheme| &scheme.ty) {
+        Some(Type::Fn(_, ret, _)) => type_name(ret),
+        Some(ty) => type_name(ty),
+        None => definition.ty.as_ref().map(|ty| ty.0.clone()),
+    }?;
+    let ty = res
+        .visible_at(definition.span.start.offset)
+        .into_iter()
+        .find(|&id| res.def(id).name == name && res.def(id).kind.is_in(Namespace::Type))?;
+    target(db, workspace, file, ty)
+}
+
+/// The name of the struct or type parameter `ty` is an instance of.
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Con(name, _) | Type::Param(name) => Some(name.clone()),
+        Type::Var(_) | Type::Fn(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{workspace, MAIN},
+    };
+
+    /// The file and text of `target`, and the line the definition starts on.
+    fn show(db: &Database, target: Option<Target>) -> String {
+        let Some(target) = target else {
+            return "none".to_string();
+        };
+        let text = target.src.text(db);
+        let line = text[target.full_span.start.offset..]
+            .lines()
+            .next()
+            .unwrap_or("");
+        format!(
+            "{} {} `{}`",
+            module_name(&target.src.url(db)).unwrap(),
+            &text[target.span.start.offset..target.span.end.offset],
+            line.trim()
+        )
+    }
+
+    #[okstd::test]
+    fn test_definition_across_files() {
+        let db = &Database::default();
+        let (workspace, _, main) = workspace(db, MAIN);
+        let at = |needle: &str| MAIN.find(needle).unwrap();
+        let definition = |needle| show(db, definition(db, workspace, main, at(needle)));
+        assert_eq!(definition("Point, n"), "util Point `pub struct Point {`");
+        assert_eq!(
+            definition("origin()"),
+            "util origin `pub fn origin()[] -> Point {`"
+        );
+        assert_eq!(definition("x\n"), "util x `x: i32`");
+        assert_eq!(definition("q\n}"), "main q `let q = origin()`");
+        assert_eq!(definition("p.x"), "main p `p: Point, n: i32)[Log] {`");
+        assert_eq!(definition("log(\"main"), "main log `log(msg: string) []`");
+        assert_eq!(definition("util"), "util  `# A place on a plane.`");
+        assert_eq!(definition("async"), "none");
+
+        let declaration = |needle| show(db, declaration(db, workspace, main, at(needle)));
+        assert_eq!(declaration("log(self"), "main log `log(msg: string) []`");
+        assert_eq!(
+            definition("log(self"),
+            "main log `fn log(self, msg: string) [] {`"
+        );
+
+        let type_definition = |needle| show(db, type_definition(db, workspace, main, at(needle)));
+        assert_eq!(type_definition("r = p"), "util Point `pub struct Point {`");
+        assert_eq!(type_definition("p.x"), "util Point `pub struct Point {`");
+        assert_eq!(
+            type_definition("origin()"),
+            "util Point `pub struct Point {`"
+        );
+        assert_eq!(type_definition("Log]"), "main Log `effect Log: async {`");
+    }
+
+    #[okstd::test]
+    fn test_missing_module() {
+        let db = &Database::default();
+        let main = SourceProgram::new(db, "file:///w/main.src".to_string(), MAIN.to_string());
+        let workspace = Workspace::new(db, vec![main]);
+        let offset = MAIN.find("Point, n").unwrap();
+        assert_eq!(definition(db, workspace, main, offset), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! Every place a definition is named, and renaming it in all of them.
+//!
+//! A definition is followed through imports, see [`origin`]: the function a module
+//! exports, the names importing it and every use of those are one definition. The files
+//! searched are the files of the [`Workspace`].
+
+use std::ops::Range;
+
+use crate::{
+    analyzer::{
+        modules,
+        navigation::{def_at, implemented, origin, Workspace},
+        resolve::{clashes, resolve, DefId, DefKind, Resolution},
+    },
+    compiler::text::SourceProgram,
+    lexer::{Token, TripleIterator, Word},
+    types::infer::infer,
+    Db,
+};
+
+/// A place a definition is named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub src: SourceProgram,
+    /// The bytes of the name.
+    pub span: Range<usize>,
+    /// The definition in `src` the name is bound to, an import of it in another module.
+    pub def: DefId,
+    /// Whether the name is being defined, it is a use otherwise.
+    pub declaration: bool,
+}
+
+/// The definition at `offset` and the file defining it, after following imports.
+fn canonical(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Option<(SourceProgram, DefId)> {
+    let (file, def) = def_at(db, workspace, src, offset)?;
+    origin(db, workspace, file, def)
+}
+
+/// The files to search, `src` first.
+fn files(db: &dyn Db, workspace: Workspace, src: SourceProgram) -> Vec<SourceProgram> {
+    let mut files = vec![src];
+    files.extend(workspace.files(db).iter().filter(|&&file| file != src));
+    files
+}
+
+/// The places the definition `target` is named in `src`, in order.
+fn occurrences_in(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    target: (SourceProgram, DefId),
+) -> Vec<Occurrence> {
+    let res = resolve(db, src);
+    let name = &resolve(db, target.0).def(target.1).name;
+    let is_target = |def: DefId| origin(db, workspace, src, def) == Some(target);
+    // a use of `vec<T>` spans the type arguments
+    let occurrence = |start: usize, def: DefId, declaration: bool| Occurrence {
+        src,
+        span: start..start + name.len(),
+        def,
+        declaration,
+    };
+    let mut found = vec![];
+    for (id, def) in res.iter() {
+        // builtins and the prelude are defined elsewhere
+        if def.span.start < def.span.end && &def.name == name && is_target(id) {
+            found.push(occurrence(def.span.start.offset, id, true));
+        }
+    }
+    for reference in res.refs.iter() {
+        if &res.def(reference.def).name == name && is_target(reference.def) {
+            found.push(occurrence(
+                reference.span.start.offset,
+                reference.def,
+                false,
+            ));
+        }
+    }
+    for (&(start, _), &def) in infer(db, src).members.iter() {
+        if is_target(def) {
+            found.push(occurrence(start, def, false));
+        }
+    }
+    // members of a struct of another module are not known to inference, try each `.name`
+    let text = src.text(db);
+    let member = format!(".{}", name);
+    for (dot, _) in text.match_indices(&member) {
+        let start = dot + 1;
+        if found
+            .iter()
+            .any(|occurrence| occurrence.span.start == start)
+        {
+            continue;
+        }
+        if let Some((file, def)) = def_at(db, workspace, src, start) {
+            if origin(db, workspace, file, def) == Some(target) {
+                found.push(occurrence(start, def, false));
+            }
+        }
+    }
+    found.sort_by_key(|occurrence| occurrence.span.start);
+    found.dedup_by_key(|occurrence| occurrence.span.start);
+    found
+}
+
+/// Every place the name at `offset` is named in the workspace, `src` first.
+pub fn references(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Vec<Occurrence> {
+    let Some(target) = canonical(db, workspace, src, offset) else {
+        return vec![];
+    };
+    files(db, workspace, src)
+        .into_iter()
+        .flat_map(|file| occurrences_in(db, workspace, file, target))
+        .collect()
+}
+
+/// The definitions renaming `target` renames: an effect operation and the methods of the
+/// handlers implementing it, whichever of them is renamed, so the handlers keep conforming
+/// to the effect.
+fn renamed_with(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    target: (SourceProgram, DefId),
+) -> Vec<(SourceProgram, DefId)> {
+    let op = implemented(db, workspace, target.0, target.1).unwrap_or(target);
+    let definition = resolve(db, op.0).def(op.1);
+    if definition.kind != DefKind::EffectOp {
+        return vec![target];
+    }
+    let mut found = vec![op];
+    for file in files(db, workspace, src) {
+        let res = resolve(db, file);
+        for (id, def) in res.iter() {
+            if def.kind == DefKind::Method
+                && def.name == definition.name
+                && implemented(db, workspace, file, id) == Some(op)
+            {
+                found.push((file, id));
+            }
+        }
+    }
+    found
+}
+
+/// The places the name at `offset` is named in `src`.
+pub fn highlights(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Vec<Occurrence> {
+    match canonical(db, workspace, src, offset) {
+        Some(target) => occurrences_in(db, workspace, src, target),
+        None => vec![],
+    }
+}
+
+/// The bytes of the name at `offset` when it can be renamed, why not otherwise.
+pub fn prepare_rename(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+) -> Result<Range<usize>, String> {
+    let (file, def) = canonical(db, workspace, src, offset)
+        .ok_or_else(|| "there is no name to rename here".to_string())?;
+    let definition = resolve(db, file).def(def);
+    match definition.kind {
+        DefKind::Module => return Err("modules are named by their files".to_string()),
+        DefKind::SelfParam | DefKind::Resume => {
+            return Err(format!("`{}` is a keyword", definition.name))
+        }
+        _ => {}
+    }
+    if definition.span.start == definition.span.end {
+        return Err(format!("`{}` is built in", definition.name));
+    }
+    if let Some(module) = modules::module_of(db, file) {
+        return Err(format!(
+            "`{}` is defined in the built-in module `{}`",
+            definition.name, module
+        ));
+    }
+    highlights(db, workspace, src, offset)
+        .into_iter()
+        .map(|occurrence| occurrence.span)
+        .find(|span| span.start <= offset && offset <= span.end)
+        .ok_or_else(|| "there is no name to rename here".to_string())
+}
+
+/// Every place to write `new_name` in to rename the name at `offset`. Refused when
+/// `new_name` is not an identifier, or when it would clash with or shadow another
+/// definition wherever the name is defined or used.
+pub fn rename(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    offset: usize,
+    new_name: &str,
+) -> Result<Vec<Occurrence>, String> {
+    prepare_rename(db, workspace, src, offset)?;
+    let mut tokens = TripleIterator::new(new_name);
+    let valid = matches!(
+        (tokens.next(), tokens.next(), tokens.error()),
+        (Some((start, Token::Word(Word::Ident(_) | Word::FnIdent(_)), end)), None, None)
+            if start.offset == 0 && end.offset == new_name.len()
+    );
+    if !valid {
+        return Err(format!("`{}` is not a valid name", new_name));
+    }
+    let Some(target) = canonical(db, workspace, src, offset) else {
+        return Ok(vec![]);
+    };
+    let targets = renamed_with(db, workspace, src, target);
+    let occurrences: Vec<_> = files(db, workspace, src)
+        .into_iter()
+        .flat_map(|file| {
+            let mut found: Vec<_> = targets
+                .iter()
+                .flat_map(|&target| occurrences_in(db, workspace, file, target))
+                .collect();
+            found.sort_by_key(|occurrence| occurrence.span.start);
+            found.dedup_by_key(|occurrence| occurrence.span.start);
+            found
+        })
+        .collect();
+    for occurrence in occurrences.iter() {
+        let res = resolve(db, occurrence.src);
+        check(res, occurrence, new_name)?;
+    }
+    Ok(occurrences)
+}
+
+/// Whether naming `occurrence` `new_name` keeps every name of its module bound to the
+/// same definition.
+fn check(res: &Resolution, occurrence: &Occurrence, new_name: &str) -> Result<(), String> {
+    let renamed = res.def(occurrence.def);
+    let line = |def: DefId| res.def(def).span.start.line + 1;
+    let other = |def: DefId| {
+        def != occurrence.def
+            && res.def(def).name == new_name
+            && clashes(res.def(def).kind, renamed.kind)
+    };
+    // fields and methods are only named through their owner
+    if let Some(parent) = renamed.parent.filter(|_| !renamed.kind.is_local()) {
+        if let Some((sibling, _)) = res.members(parent).find(|&(id, _)| other(id)) {
+            let line = line(sibling);
+            return Err(format!(
+                "`{}` is already defined on line {}",
+                new_name, line
+            ));
+        }
+    }
+    let visible = res.visible_at(occurrence.span.start);
+    // a use another definition would capture is also where that one is visible from the
+    // renamed definition
+    let Some(found) = visible.iter().position(|&def| other(def)) else {
+        return Ok(());
+    };
+    let inner = match visible.iter().position(|&def| def == occurrence.def) {
+        Some(renamed) => found < renamed,
+        None => true,
+    };
+    let found = visible[found];
+    let message = if res.def(found).span.start == res.def(found).span.end {
+        format!(
+            "`{}` would shadow the built-in `{}`",
+            renamed.name, new_name
+        )
+    } else if occurrence.declaration && res.def(found).scope == renamed.scope {
+        format!("`{}` is already defined on line {}", new_name, line(found))
+    } else if inner {
+        format!(
+            "uses of `{}` would refer to the `{}` on line {}",
+            renamed.name,
+            new_name,
+            line(found)
+        )
+    } else {
+        format!(
+            "`{}` would shadow the `{}` on line {}",
+            renamed.name,
+            new_name,
+            line(found)
+        )
+    };
+    Err(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{workspace, MAIN},
+    };
+
+    /// Each occurrence as `file:line:column`, with a `*` for declarations.
+    fn show(db: &Database, occurrences: &[Occurrence]) -> Vec<String> {
+        occurrences
+            .iter()
+            .map(|occurrence| {
+                let text = occurrence.src.text(db);
+                let before = &text[..occurrence.span.start];
+                let line = before.matches('\n').count();
+                let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+                let file = occurrence.src.url(db).replace("file:///w/", "");
+                let star = if occurrence.declaration { "*" } else { "" };
+                format!("{}:{}:{}{}", file, line, column, star)
+            })
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_references_across_files() {
+        let db = &Database::default();
+        let (workspace, _, main) = workspace(db, MAIN);
+        let at = |needle: &str| MAIN.find(needle).unwrap();
+        assert_eq!(
+            show(db, &references(db, workspace, main, at("Point,"))),
+            [
+                "main.src:0:6*",
+                "main.src:15:11",
+                "util.src:1:11*",
+                "util.src:6:5",
+                "util.src:7:29",
+                "util.src:13:21"
+            ]
+        );
+        assert_eq!(
+            show(db, &references(db, workspace, main, at("x\n"))),
+            ["main.src:18:6", "util.src:3:8*"]
+        );
+        assert_eq!(
+            show(db, &highlights(db, workspace, main, at("q\n}"))),
+            ["main.src:16:8*", "main.src:20:4"]
+        );
+        assert!(references(db, workspace, main, at("fn")).is_empty());
+    }
+
+    #[okstd::test]
+    fn test_rename() {
+        let db = &Database::default();
+        let (workspace, _, main) = workspace(db, MAIN);
+        let at = |needle: &str| MAIN.find(needle).unwrap();
+        let rename = |needle, new_name| {
+            rename(db, workspace, main, at(needle), new_name).map(|found| show(db, &found))
+        };
+        assert_eq!(
+            rename("origin()", "start"),
+            Ok(vec![
+                "main.src:0:13*".to_string(),
+                "main.src:16:12".to_string(),
+                "util.src:13:7*".to_string(),
+                "util.src:14:4".to_string(),
+            ])
+        );
+        // the handlers implementing an operation are renamed with it
+        let renamed = vec![
+            "main.src:4:4*".to_string(),
+            "main.src:11:7*".to_string(),
+            "main.src:19:4".to_string(),
+        ];
+        assert_eq!(rename("log(msg", "write"), Ok(renamed.clone()));
+        assert_eq!(rename("log(self", "write"), Ok(renamed));
+        assert_eq!(
+            prepare_rename(db, workspace, main, at("origin()") + 2),
+            Ok(at("origin()")..at("origin()") + 6)
+        );
+
+        let refused = |needle, new_name| rename(needle, new_name).unwrap_err();
+        assert_eq!(
+            refused("q =", "r"),
+            "uses of `q` would refer to the `r` on line 18"
+        );
+        assert_eq!(
+            refused("p: ", "origin"),
+            "`p` would shadow the `origin` on line 1"
+        );
+        assert_eq!(
+            refused("main", "origin"),
+            "`origin` is already defined on line 1"
+        );
+        assert_eq!(refused("r =", "q"), "`q` is already defined on line 17");
+        assert_eq!(refused("x\n", "y x"), "`y x` is not a valid name");
+        assert_eq!(refused("x\n", "fn"), "`fn` is not a valid name");
+        assert_eq!(
+            refused("i32", "int"),
+            "`i32` is defined in the built-in module `std`"
+        );
+        assert_eq!(refused("util", "lib"), "modules are named by their files");
+    }
+}
@@ -0,0 +1,1113 @@
+//! Name resolution.
+//!
+//! Walks a parsed module, building the lexical scopes of modules, effects, structs, impls,
+//! functions and branch arms, and binds every identifier to its [`Definition`]. Problems
+//! are pushed as [`Diagnostics`]: undefined names and duplicate definitions are errors,
+//! a binding shadowing an earlier one is a warning.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{
+    analyzer::{
+        diagnostics::{Diagnostic, Diagnostics},
+        index::SpanIndex,
+        modules::{self, ModuleName},
+    },
+    compiler::text::SourceProgram,
+    host,
+    lexer::Location,
+    parser::{
+        self,
+        ast::{self, FnArg, Node, Visibility},
+        span::Spanned,
+    },
+    Db,
+};
+
+/// Types every module can name without importing them. The standard library is written
+/// against these, other modules see its definitions of them in the prelude instead.
+pub const BUILTIN_TYPES: &[&str] = &["bool", "i32", "i64", "f64", "string", "str", "stringvec"];
+
+/// Effects every module can name without importing them.
+pub const BUILTIN_EFFECTS: &[&str] = &["async", "throws"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScopeId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefKind {
+    /// The module named by `use { .. } from module`.
+    Module,
+    /// A name brought in by `use`.
+    Import,
+    Function,
+    Struct,
+    Field,
+    Effect,
+    /// A prototype declared by an effect.
+    EffectOp,
+    /// A function in an impl block.
+    Method,
+    Param,
+    /// The `self` receiver of a method.
+    SelfParam,
+    /// A `let` binding.
+    Local,
+    /// `resume` in a method implementing an effect operation, continues the handled block.
+    Resume,
+    /// A generic parameter such as the `T` in `await<T>`.
+    TypeParam,
+    BuiltinType,
+    BuiltinEffect,
+}
+
+/// The kinds of name a position in the source can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    Type,
+    Value,
+    Effect,
+}
+
+impl DefKind {
+    pub fn is_in(self, ns: Namespace) -> bool {
+        use DefKind::*;
+        match self {
+            // an import or module can be anything, we only find out once its module is read
+            Module | Import => true,
+            Struct | TypeParam | BuiltinType => ns == Namespace::Type,
+            Effect => ns == Namespace::Type || ns == Namespace::Effect,
+            BuiltinEffect => ns == Namespace::Effect,
+            Function | EffectOp | Param | SelfParam | Local | Resume => ns == Namespace::Value,
+            // only reachable as members
+            Field | Method => false,
+        }
+    }
+
+    /// Whether the definition lives in a function body rather than at item level.
+    pub fn is_local(self) -> bool {
+        matches!(
+            self,
+            DefKind::Param | DefKind::SelfParam | DefKind::Local | DefKind::Resume
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefKind,
+    /// The span of the defining identifier, empty for builtins.
+    pub span: Range<Location>,
+    /// The span of the whole definition, e.g. the struct body or the function with its block.
+    pub full_span: Range<Location>,
+    pub vis: Visibility,
+    /// The struct owning a field or method, the effect owning an operation, the module of an import.
+    pub parent: Option<DefId>,
+    /// The annotated type of a field or parameter, the return type of a function.
+    pub ty: Option<ast::Ident>,
+    pub scope: ScopeId,
+}
+
+/// A use of a name, bound to its definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Range<Location>,
+    pub def: DefId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    /// Holds the builtin types and effects, and the public items of `std`.
+    Prelude,
+    Module,
+    Effect,
+    Struct,
+    Impl,
+    Function,
+    Arm,
+    /// The block of a `handle .. with`.
+    Handle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeId>,
+    pub span: Range<Location>,
+    /// Definitions in the order they were declared.
+    pub defs: Vec<DefId>,
+}
+
+/// Every definition, reference and scope of a module.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Resolution {
+    pub defs: Vec<Definition>,
+    pub refs: Vec<Reference>,
+    pub scopes: Vec<Scope>,
+    /// Items imported from a built-in module or the prelude, with the file and their
+    /// definition there.
+    pub origins: BTreeMap<DefId, (SourceProgram, DefId)>,
+    /// The spans of `refs` and of the names of `defs`, see [`Resolution::def_at`].
+    pub ref_spans: SpanIndex<DefId>,
+    pub def_spans: SpanIndex<DefId>,
+    /// The spans of `scopes`, see [`Resolution::scope_at`].
+    pub scope_spans: SpanIndex<ScopeId>,
+}
+
+impl Resolution {
+    pub fn def(&self, id: DefId) -> &Definition {
+        &self.defs[id.0 as usize]
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0 as usize]
+    }
+
+    /// The module `def` was imported from and its definition there.
+    pub fn origin(&self, def: DefId) -> Option<(SourceProgram, DefId)> {
+        self.origins.get(&def).copied()
+    }
+
+    /// The scope holding the items of the module, right below the prelude.
+    pub fn module_scope(&self) -> ScopeId {
+        ScopeId(1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DefId, &Definition)> {
+        self.defs
+            .iter()
+            .enumerate()
+            .map(|(i, def)| (DefId(i as u32), def))
+    }
+
+    /// The definition named or used at `offset`, the end of an identifier included. Of
+    /// nested uses the innermost, `T` rather than `vec` in `vec<T>`.
+    pub fn def_at(&self, offset: usize) -> Option<DefId> {
+        self.ref_spans
+            .innermost(offset)
+            .or_else(|| self.def_spans.innermost(offset))
+            .copied()
+    }
+
+    /// Every use of `def`, not including the definition itself.
+    pub fn references(&self, def: DefId) -> impl Iterator<Item = &Reference> {
+        self.refs
+            .iter()
+            .filter(move |reference| reference.def == def)
+    }
+
+    /// The fields and methods of a struct, the operations of an effect, the imports of a module.
+    pub fn members(&self, parent: DefId) -> impl Iterator<Item = (DefId, &Definition)> {
+        self.iter()
+            .filter(move |(_, def)| def.parent == Some(parent) && !def.kind.is_local())
+    }
+
+    /// The innermost scope containing `offset`.
+    pub fn scope_at(&self, offset: usize) -> ScopeId {
+        self.scope_spans
+            .innermost(offset)
+            .copied()
+            .unwrap_or(ScopeId(0))
+    }
+
+    /// The definitions visible at `offset`, innermost first. Locals declared after
+    /// `offset` are left out.
+    pub fn visible_at(&self, offset: usize) -> Vec<DefId> {
+        let mut visible: Vec<DefId> = vec![];
+        let mut scope = Some(self.scope_at(offset));
+        while let Some(id) = scope {
+            let current = self.scope(id);
+            for &def in current.defs.iter().rev() {
+                let definition = self.def(def);
+                if definition.kind.is_local() && definition.span.start.offset > offset {
+                    continue;
+                }
+                if !visible
+                    .iter()
+                    .any(|seen| self.def(*seen).name == definition.name)
+                {
+                    visible.push(def);
+                }
+            }
+            scope = current.parent;
+        }
+        visible
+    }
+}
+
+struct Resolver<'db> {
+    db: &'db dyn Db,
+    res: Resolution,
+    stack: Vec<ScopeId>,
+}
+
+impl<'db> Resolver<'db> {
+    fn new(db: &'db dyn Db, span: Range<Location>, prelude: &[SourceProgram]) -> Self {
+        let mut resolver = Self {
+            db,
+            res: Resolution::default(),
+            stack: vec![],
+        };
+        resolver.push(ScopeKind::Prelude, span);
+        let builtins = BUILTIN_TYPES
+            .iter()
+            .map(|name| (name, DefKind::BuiltinType))
+            .chain(
+                BUILTIN_EFFECTS
+                    .iter()
+                    .map(|name| (name, DefKind::BuiltinEffect)),
+            );
+        for (name, kind) in builtins {
+            resolver.define(Definition {
+                name: name.to_string(),
+                kind,
+                span: Location::default()..Location::default(),
+                full_span: Location::default()..Location::default(),
+                vis: Visibility::Public,
+                parent: None,
+                ty: None,
+                scope: ScopeId(0),
+            });
+        }
+        for &src in prelude {
+            let res = resolve(db, src);
+            for &id in res.scope(res.module_scope()).defs.iter() {
+                let def = res.def(id);
+                if def.parent.is_some() || def.vis != Visibility::Public {
+                    continue;
+                }
+                let local = resolver.define(Definition {
+                    span: Location::default()..Location::default(),
+                    full_span: Location::default()..Location::default(),
+                    scope: ScopeId(0),
+                    ..def.clone()
+                });
+                resolver.res.origins.insert(local, (src, id));
+            }
+        }
+        resolver
+    }
+
+    fn current(&self) -> ScopeId {
+        *self.stack.last().unwrap()
+    }
+
+    fn push(&mut self, kind: ScopeKind, span: Range<Location>) -> ScopeId {
+        let id = ScopeId(self.res.scopes.len() as u32);
+        self.res.scopes.push(Scope {
+            kind,
+            parent: self.stack.last().copied(),
+            span,
+            defs: vec![],
+        });
+        self.stack.push(id);
+        id
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    fn define(&mut self, def: Definition) -> DefId {
+        let id = DefId(self.res.defs.len() as u32);
+        let scope = self.current();
+        self.res.defs.push(Definition { scope, ..def });
+        self.res.scopes[scope.0 as usize].defs.push(id);
+        id
+    }
+
+    /// Declares `name` in the current scope, reporting clashes with earlier definitions.
+    fn declare(
+        &mut self,
+        name: &Spanned<ast::Ident>,
+        kind: DefKind,
+        full_span: Range<Location>,
+        vis: Visibility,
+        parent: Option<DefId>,
+        ty: Option<ast::Ident>,
+    ) -> DefId {
+        let text = &name.1 .0;
+        let clash = self
+            .res
+            .scope(self.current())
+            .defs
+            .iter()
+            .copied()
+            .find(|&id| {
+                let def = self.res.def(id);
+                &def.name == text && clashes(def.kind, kind)
+            });
+        match clash {
+            Some(earlier) if kind == DefKind::Local => {
+                self.shadowed(name, earlier);
+            }
+            Some(earlier) => {
+                let earlier = self.res.def(earlier);
+                Diagnostics::push(
+                    self.db,
+                    Diagnostic::error(name.span(), format!("`{}` is defined more than once", text))
+                        .with_related(
+                            earlier.span.clone(),
+                            format!("`{}` first defined here", text),
+                        ),
+                );
+            }
+            None if kind.is_local() => {
+                if let Some(earlier) = self.lookup_local(text) {
+                    self.shadowed(name, earlier);
+                }
+            }
+            None => {}
+        }
+        self.define(Definition {
+            name: text.clone(),
+            kind,
+            span: name.span(),
+            full_span,
+            vis,
+            parent,
+            ty,
+            scope: self.current(),
+        })
+    }
+
+    fn shadowed(&self, name: &Spanned<ast::Ident>, earlier: DefId) {
+        let earlier = self.res.def(earlier);
+        Diagnostics::push(
+            self.db,
+            Diagnostic::warning(
+                name.span(),
+                format!("`{}` shadows an earlier binding", name.1 .0),
+            )
+            .with_related(earlier.span.clone(), "previous binding".to_string()),
+        );
+    }
+
+    /// A parameter or local of the enclosing function, searching outwards from the current scope.
+    fn lookup_local(&self, name: &str) -> Option<DefId> {
+        for &scope in self.stack.iter().rev() {
+            let scope = self.res.scope(scope);
+            let found = scope.defs.iter().rev().copied().find(|&id| {
+                let def = self.res.def(id);
+                def.name == name && def.kind.is_local()
+            });
+            if found.is_some() {
+                return found;
+            }
+            if scope.kind == ScopeKind::Function {
+                break;
+            }
+        }
+        None
+    }
+
+    fn lookup(&self, name: &str, ns: Namespace) -> Option<DefId> {
+        self.stack.iter().rev().find_map(|&scope| {
+            self.res
+                .scope(scope)
+                .defs
+                .iter()
+                .rev()
+                .copied()
+                .find(|&id| {
+                    let def = self.res.def(id);
+                    def.name == name && def.kind.is_in(ns)
+                })
+        })
+    }
+
+    fn reference(&mut self, span: Range<Location>, def: DefId) {
+        self.res.refs.push(Reference { span, def });
+    }
+
+    /// Binds a use of `name`, reporting it when nothing by that name is in scope.
+    fn use_name(&mut self, name: &Spanned<ast::Ident>, ns: Namespace) -> Option<DefId> {
+        let found = self.lookup(&name.1 .0, ns);
+        match found {
+            Some(def) => self.reference(name.span(), def),
+            None => {
+                let what = match ns {
+                    Namespace::Type => "type",
+                    Namespace::Value => "value",
+                    Namespace::Effect => "effect",
+                };
+                Diagnostics::push(
+                    self.db,
+                    Diagnostic::error(
+                        name.span(),
+                        format!("cannot find {} `{}` in this scope", what, name.1 .0),
+                    ),
+                );
+            }
+        }
+        self.generics(name);
+        found
+    }
+
+    fn generics(&mut self, name: &Spanned<ast::Ident>) {
+        if let Some(generics) = &name.1 .1 {
+            for generic in generics {
+                self.use_name(generic, Namespace::Type);
+            }
+        }
+    }
+
+    fn module(&mut self, module: &ast::Module) {
+        // items can be used before they are defined, so they are all declared up front
+        let mut items = vec![];
+        for item in module.0.iter() {
+            items.push(self.declare_item(item));
+        }
+        for (item, def) in module.0.iter().zip(items) {
+            self.item(item, def);
+        }
+    }
+
+    fn declare_item(&mut self, item: &Spanned<Node>) -> Option<DefId> {
+        match &item.1 {
+            Node::FnDef(ast::FnDef(kwv, proto, _)) => Some(self.declare(
+                &proto.1.name,
+                DefKind::Function,
+                item.span(),
+                kwv.1 .1 .1.clone(),
+                None,
+                proto.1.ret.as_ref().map(|ret| ret.1.clone()),
+            )),
+            Node::StructDef(ast::StructDef(kwv, name, _)) => Some(self.declare(
+                name,
+                DefKind::Struct,
+                item.span(),
+                kwv.1 .1 .1.clone(),
+                None,
+                None,
+            )),
+            Node::EffectDef(ast::EffectDef(kwv, name, _, protos)) => {
+                let vis = kwv.1 .1 .1.clone();
+                let effect =
+                    self.declare(name, DefKind::Effect, item.span(), vis.clone(), None, None);
+                for proto in protos.0.iter() {
+                    self.declare(
+                        &proto.1.name,
+                        DefKind::EffectOp,
+                        proto.span(),
+                        vis.clone(),
+                        Some(effect),
+                        proto.1.ret.as_ref().map(|ret| ret.1.clone()),
+                    );
+                }
+                Some(effect)
+            }
+            Node::UseDef(ast::UseDef(kwv, imports, module)) => {
+                let vis = kwv.1 .1 .1.clone();
+                // several `use`s can read from the same module
+                let existing = self
+                    .res
+                    .scope(self.current())
+                    .defs
+                    .iter()
+                    .copied()
+                    .find(|&id| {
+                        let def = self.res.def(id);
+                        def.kind == DefKind::Module && def.name == module.1 .0
+                    });
+                let module_def = match existing {
+                    Some(def) => {
+                        self.reference(module.span(), def);
+                        def
+                    }
+                    None => self.declare(
+                        module,
+                        DefKind::Module,
+                        module.span(),
+                        Visibility::Private,
+                        None,
+                        None,
+                    ),
+                };
+                let builtin =
+                    modules::builtin(self.db, ModuleName::new(self.db, module.1 .0.clone()));
+                for import in imports {
+                    if builtin.is_empty() {
+                        self.declare(
+                            import,
+                            DefKind::Import,
+                            item.span(),
+                            vis.clone(),
+                            Some(module_def),
+                            None,
+                        );
+                    } else {
+                        self.import(import, item.span(), vis.clone(), module_def, builtin);
+                    }
+                }
+                Some(module_def)
+            }
+            _ => None,
+        }
+    }
+
+    /// Declares `name` as the public item of that name in one of the `files` of a built-in
+    /// module, with the operations of an effect.
+    fn import(
+        &mut self,
+        name: &Spanned<ast::Ident>,
+        full_span: Range<Location>,
+        vis: Visibility,
+        module: DefId,
+        files: &[SourceProgram],
+    ) {
+        let text = &name.1 .0;
+        let found = files.iter().find_map(|&src| {
+            let res = resolve(self.db, src);
+            res.scope(res.module_scope())
+                .defs
+                .iter()
+                .copied()
+                .find(|&id| {
+                    let def = res.def(id);
+                    &def.name == text && def.parent.is_none() && def.kind != DefKind::Module
+                })
+                .map(|id| (src, res, id))
+        });
+        let module_name = &self.res.def(module).name;
+        let Some((src, res, id)) =
+            found.filter(|(_, res, id)| res.def(*id).vis == Visibility::Public)
+        else {
+            let message = match found {
+                Some(_) => format!("`{}` is private to module `{}`", text, module_name),
+                None => format!("cannot find `{}` in module `{}`", text, module_name),
+            };
+            Diagnostics::push(self.db, Diagnostic::error(name.span(), message));
+            return;
+        };
+        let def = res.def(id);
+        let local = self.declare(
+            name,
+            def.kind,
+            full_span,
+            vis.clone(),
+            Some(module),
+            def.ty.clone(),
+        );
+        self.res.origins.insert(local, (src, id));
+        if def.kind != DefKind::Effect {
+            return;
+        }
+        for (op, definition) in res.members(id) {
+            // declared where the effect is, like the operations of a local effect
+            let op_def = self.define(Definition {
+                name: definition.name.clone(),
+                kind: definition.kind,
+                span: Location::default()..Location::default(),
+                full_span: Location::default()..Location::default(),
+                vis: vis.clone(),
+                parent: Some(local),
+                ty: definition.ty.clone(),
+                scope: self.current(),
+            });
+            self.res.origins.insert(op_def, (src, op));
+        }
+    }
+
+    fn item(&mut self, item: &Spanned<Node>, def: Option<DefId>) {
+        match &item.1 {
+            Node::FnDef(ast::FnDef(_, proto, block)) => {
+                self.function(item.span(), proto, Some(block), None, None);
+            }
+            Node::StructDef(ast::StructDef(_, name, fields)) => {
+                self.push(ScopeKind::Struct, item.span());
+                self.type_params(name);
+                for field in fields.0.iter() {
+                    self.field(field, DefKind::Field, def);
+                }
+                self.pop();
+            }
+            Node::EffectDef(ast::EffectDef(_, _, effects, protos)) => {
+                for effect in effects {
+                    self.use_name(effect, Namespace::Effect);
+                }
+                self.push(ScopeKind::Effect, item.span());
+                for proto in protos.0.iter() {
+                    self.function(proto.span(), proto, None, None, None);
+                }
+                self.pop();
+            }
+            Node::ImplDef(ast::ImplDef(_, name, target, block)) => {
+                let handles = target.is_some();
+                let target = match target {
+                    Some(target) => {
+                        self.use_name(name, Namespace::Effect);
+                        self.use_name(target, Namespace::Type)
+                    }
+                    None => self.use_name(name, Namespace::Type),
+                };
+                self.push(ScopeKind::Impl, item.span());
+                let mut methods = vec![];
+                for method in block.0.iter() {
+                    if let Node::FnDef(ast::FnDef(kwv, proto, body)) = &method.1 {
+                        let def = self.declare(
+                            &proto.1.name,
+                            DefKind::Method,
+                            method.span(),
+                            kwv.1 .1 .1.clone(),
+                            target,
+                            proto.1.ret.as_ref().map(|ret| ret.1.clone()),
+                        );
+                        methods.push((method.span(), proto, body, def));
+                    }
+                }
+                for (span, proto, body, def) in methods {
+                    let resumes = handles.then_some(def);
+                    self.function(span, proto, Some(body), Some(target), resumes);
+                }
+                self.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fn field(&mut self, field: &Spanned<ast::FieldDef>, kind: DefKind, parent: Option<DefId>) {
+        let ast::FieldDef(vis, name, ty) = &field.1;
+        self.use_name(ty, Namespace::Type);
+        self.declare(
+            name,
+            kind,
+            field.span(),
+            vis.1.clone(),
+            parent,
+            Some(ty.1.clone()),
+        );
+    }
+
+    /// Declares the generic parameters of a struct or function in the current scope.
+    fn type_params(&mut self, name: &Spanned<ast::Ident>) {
+        if let Some(generics) = &name.1 .1 {
+            for generic in generics {
+                self.declare(
+                    generic,
+                    DefKind::TypeParam,
+                    generic.span(),
+                    Visibility::Private,
+                    None,
+                    None,
+                );
+            }
+        }
+    }
+
+    /// Resolves a function or prototype. `receiver` is `Some` inside an impl, holding the
+    /// struct the impl is for when that resolved. `resumes` is the method when it implements
+    /// an effect operation, which makes `resume` available in its body.
+    fn function(
+        &mut self,
+        span: Range<Location>,
+        proto: &Spanned<ast::Prototype>,
+        body: Option<&ast::Block<Spanned<Node>>>,
+        receiver: Option<Option<DefId>>,
+        resumes: Option<DefId>,
+    ) {
+        self.push(ScopeKind::Function, span.clone());
+        let ast::Prototype {
+            name,
+            args,
+            ret,
+            effects,
+        } = &proto.1;
+        self.type_params(name);
+        for arg in args {
+            match &arg.1 {
+                FnArg::Reciever => {
+                    let this = Spanned(arg.0, ast::Ident("self".to_string(), None), arg.2);
+                    match receiver {
+                        Some(target) => {
+                            self.declare(
+                                &this,
+                                DefKind::SelfParam,
+                                arg.span(),
+                                Visibility::Private,
+                                target,
+                                None,
+                            );
+                        }
+                        // effect prototypes take `self` on behalf of their implementors
+                        None if body.is_none() => {}
+                        None => Diagnostics::push(
+                            self.db,
+                            Diagnostic::error(
+                                arg.span(),
+                                "`self` is only allowed in functions of an impl",
+                            ),
+                        ),
+                    }
+                }
+                FnArg::Field(field) => self.field(field, DefKind::Param, None),
+            }
+        }
+        if let Some(ret) = ret {
+            self.use_name(ret, Namespace::Type);
+        }
+        for effect in effects {
+            self.use_name(effect, Namespace::Effect);
+        }
+        if let Some(method) = resumes {
+            // implicit, so it gets an empty span like the builtins
+            self.define(Definition {
+                name: "resume".to_string(),
+                kind: DefKind::Resume,
+                span: proto.0..proto.0,
+                full_span: proto.span(),
+                vis: Visibility::Private,
+                parent: Some(method),
+                ty: None,
+                scope: self.current(),
+            });
+        }
+        if let Some(body) = body {
+            self.block(body);
+        }
+        self.pop();
+    }
+
+    fn block(&mut self, block: &ast::Block<Spanned<Node>>) {
+        for statement in block.0.iter() {
+            self.expr(statement);
+        }
+    }
+
+    /// Resolves an expression, returning the definition it names, if it names one.
+    fn expr(&mut self, node: &Spanned<Node>) -> Option<DefId> {
+        match &node.1 {
+            Node::Ident(ident) => self.use_name(ident, Namespace::Value),
+            Node::Binding(ast::Binding(name, value)) => {
+                // the value is resolved first, `let a = a` reads the outer `a`
+                self.expr(value);
+                self.declare(
+                    name,
+                    DefKind::Local,
+                    node.span(),
+                    Visibility::Private,
+                    None,
+                    None,
+                );
+                None
+            }
+            Node::FnCall(ast::FnCall(name, args)) => {
+                let callee = self.use_name(name, Namespace::Value);
+                for arg in args {
+                    self.expr(arg);
+                }
+                callee
+            }
+            Node::BinaryExpression(bin) => {
+                self.expr(&bin.lhs);
+                self.expr(&bin.rhs);
+                None
+            }
+            Node::FieldAccess(ast::FieldAccess(lhs, rhs)) => {
+                let owner = self.expr(lhs).and_then(|def| self.owner(def));
+                self.member(owner, rhs)
+            }
+            Node::Branch(ast::BranchDef(cond, arms)) => {
+                self.expr(cond);
+                for (arm, block) in arms {
+                    let mut end = arm.2;
+                    if let Some(last) = block.0.last() {
+                        if last.2 > end {
+                            end = last.2;
+                        }
+                    }
+                    self.push(ScopeKind::Arm, arm.0..end);
+                    self.expr(arm);
+                    self.block(block);
+                    self.pop();
+                }
+                None
+            }
+            Node::Handle(ast::HandleDef(_, body, _, handler)) => {
+                self.use_name(handler, Namespace::Type);
+                self.push(ScopeKind::Handle, node.span());
+                self.block(body);
+                self.pop();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves the right hand side of a field access against the members of `owner`.
+    /// Members of anything but a known struct are left for type checking.
+    fn member(&mut self, owner: Option<DefId>, rhs: &Spanned<Node>) -> Option<DefId> {
+        let (name, args) = match &rhs.1 {
+            Node::Ident(name) => (name, None),
+            Node::FnCall(ast::FnCall(name, args)) => (name, Some(args)),
+            _ => return self.expr(rhs),
+        };
+        let found = owner.and_then(|owner| {
+            self.res
+                .members(owner)
+                .find(|(_, def)| def.name == name.1 .0)
+                .map(|(id, _)| id)
+        });
+        if let Some(def) = found {
+            self.reference(name.span(), def);
+        }
+        self.generics(name);
+        for arg in args.into_iter().flatten() {
+            self.expr(arg);
+        }
+        found
+    }
+
+    /// The struct whose members are reachable through `def`.
+    fn owner(&self, def: DefId) -> Option<DefId> {
+        let def = self.res.def(def);
+        match def.kind {
+            DefKind::Struct => None,
+            DefKind::SelfParam => def.parent,
+            _ => {
+                let ty = def.ty.as_ref()?;
+                let found = self.lookup(&ty.0, Namespace::Type)?;
+                (self.res.def(found).kind == DefKind::Struct).then_some(found)
+            }
+        }
+    }
+}
+
+/// Whether a definition of kind `b` clashes with an earlier `a` of the same name in one scope.
+pub fn clashes(a: DefKind, b: DefKind) -> bool {
+    use Namespace::*;
+    // an item may be named like a module it imports from, and is found before it
+    if a == DefKind::Module || b == DefKind::Module {
+        return false;
+    }
+    [Type, Value, Effect]
+        .into_iter()
+        .any(|ns| a.is_in(ns) && b.is_in(ns))
+        // members are only looked up through their owner, but two of them still collide
+        || (a == b && matches!(a, DefKind::Field | DefKind::Method))
+}
+
+/// Resolves every name in `src`.
+#[salsa::tracked(return_ref)]
+pub fn resolve(db: &dyn Db, src: SourceProgram) -> Resolution {
+    let text = src.text(db);
+    let end = Location {
+        offset: text.len(),
+        line: text.lines().count(),
+        col: 0,
+    };
+    // `std` and `host`, which `std` builds on, see only the builtins, everything else the
+    // public items of `std` too
+    let prelude = modules::prelude(db);
+    let builds_std = modules::module_of(db, src).as_deref() == Some(host::MODULE);
+    let prelude = if builds_std || prelude.contains(&src) {
+        &[]
+    } else {
+        prelude
+    };
+    let mut resolver = Resolver::new(db, Location::default()..end, prelude);
+    resolver.push(ScopeKind::Module, Location::default()..end);
+    resolver.module(parser::parse(db, src));
+    let mut res = resolver.res;
+    let span = |span: &Range<Location>| span.start.offset..span.end.offset;
+    // of the same spans, the first reference or definition is the innermost
+    res.ref_spans = SpanIndex::new(
+        res.refs
+            .iter()
+            .rev()
+            .map(|reference| (span(&reference.span), reference.def)),
+    );
+    res.def_spans = SpanIndex::new(
+        res.defs
+            .iter()
+            .enumerate()
+            .rev()
+            // builtins and the prelude are defined elsewhere
+            .filter(|(_, def)| def.span.start < def.span.end)
+            .map(|(i, def)| (span(&def.span), DefId(i as u32))),
+    );
+    res.scope_spans = SpanIndex::new(
+        res.scopes
+            .iter()
+            .enumerate()
+            .map(|(i, scope)| (span(&scope.span), ScopeId(i as u32))),
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{db::Database, diagnostics::Severity};
+
+    fn source(db: &Database, text: &str) -> SourceProgram {
+        SourceProgram::new(db, "inmemory://test".to_string(), text.to_string())
+    }
+
+    /// The definition the identifier at `needle` resolves to, as `name: kind`.
+    fn resolved_at(db: &Database, src: SourceProgram, needle: &str) -> String {
+        let offset = src.text(db).find(needle).unwrap();
+        let res = resolve(db, src);
+        match res.def_at(offset) {
+            Some(def) => format!("{}: {:?}", res.def(def).name, res.def(def).kind),
+            None => "unresolved".to_string(),
+        }
+    }
+
+    fn messages(db: &Database, src: SourceProgram) -> Vec<String> {
+        resolve::accumulated::<Diagnostics>(db, src)
+            .into_iter()
+            .map(|diag| match diag.severity {
+                Severity::Warning => format!("warning: {}", diag.message),
+                _ => diag.message,
+            })
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_binds_locals_params_and_items() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"struct Point {
+    x: i32
+}
+
+fn make(p: Point)[] -> Point {
+    let q = p
+    make(q)
+}"#,
+        );
+        assert_eq!(resolved_at(db, src, "p\n"), "p: Param");
+        assert_eq!(resolved_at(db, src, "q)"), "q: Local");
+        assert_eq!(resolved_at(db, src, "make(q"), "make: Function");
+        assert_eq!(resolved_at(db, src, "Point)"), "Point: Struct");
+        assert_eq!(resolved_at(db, src, "i32"), "i32: Struct");
+        assert!(messages(db, src).is_empty(), "{:?}", messages(db, src));
+    }
+
+    #[okstd::test]
+    fn test_prelude_and_std_imports() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"use { vec } from std
+
+struct Cache<T> {
+    hit: Option<T>
+    all: vec<T>
+}"#,
+        );
+        assert!(messages(db, src).is_empty(), "{:?}", messages(db, src));
+        assert_eq!(resolved_at(db, src, "Option"), "Option: Struct");
+        // the innermost of nested uses, the end of a name included
+        assert_eq!(resolved_at(db, src, "T>\n    all"), "T: TypeParam");
+        assert_eq!(resolved_at(db, src, ">\n    all"), "T: TypeParam");
+        assert_eq!(resolved_at(db, src, "<T>\n}"), "vec: Struct");
+
+        // prelude items and imports lead to their definition in `std`
+        let res = resolve(db, src);
+        for needle in ["Option", "vec<T>\n"] {
+            let offset = src.text(db).find(needle).unwrap();
+            let (file, origin) = res.origin(res.def_at(offset).unwrap()).unwrap();
+            assert!(file.url(db).starts_with("builtin://std/"));
+            let name = &resolve(db, file).def(origin).name;
+            assert!(file.text(db).contains(&format!("pub struct {}", name)));
+        }
+
+        let src = source(db, "use { fs, host } from std");
+        assert!(messages(db, src).is_empty(), "{:?}", messages(db, src));
+        let src = source(db, "use { sockets } from std");
+        assert_eq!(messages(db, src), ["cannot find `sockets` in module `std`"]);
+    }
+
+    #[okstd::test]
+    fn test_binds_self_members_and_imports() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"use { exec } from system
+
+effect Run: async {
+    run(cmd: string) [async]
+}
+
+struct Local {
+    host: system
+}
+
+impl Run for Local {
+    fn run(self, cmd: string) [async] {
+        self.host.exec(cmd)
+        exec(cmd)
+    }
+}"#,
+        );
+        let res = resolve(db, src);
+        assert_eq!(resolved_at(db, src, "host.exec"), "host: Field");
+        assert_eq!(resolved_at(db, src, "exec(cmd)\n    }"), "exec: Import");
+        assert_eq!(resolved_at(db, src, "Run for"), "Run: Effect");
+        assert_eq!(resolved_at(db, src, "Local {\n    fn"), "Local: Struct");
+        let local = res.def_at(src.text(db).find("Local {").unwrap()).unwrap();
+        let members: Vec<_> = res
+            .members(local)
+            .map(|(_, def)| def.name.clone())
+            .collect();
+        assert_eq!(members, vec!["host", "run"]);
+        assert!(messages(db, src).is_empty(), "{:?}", messages(db, src));
+    }
+
+    #[okstd::test]
+    fn test_reports_undefined_duplicate_and_shadowed() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"struct Point {
+    x: i32
+    x: i32
+}
+
+fn main(a: i32)[trhows] {
+    let a = missing
+    let b = 1
+    let b = 2
+}
+
+fn main()[] {}"#,
+        );
+        assert_eq!(
+            messages(db, src),
+            vec![
+                "`main` is defined more than once",
+                "`x` is defined more than once",
+                "cannot find effect `trhows` in this scope",
+                "cannot find value `missing` in this scope",
+                "warning: `a` shadows an earlier binding",
+                "warning: `b` shadows an earlier binding",
+            ]
+        );
+    }
+
+    #[okstd::test]
+    fn test_visible_at() {
+        let db = &Database::default();
+        let text = r#"fn main(a: i32)[] {
+    let b = a
+    let c = b
+}"#;
+        let src = source(db, text);
+        let res = resolve(db, src);
+        let names = |offset| {
+            res.visible_at(offset)
+                .into_iter()
+                .filter(|&def| res.def(def).scope != ScopeId(0))
+                .map(|def| res.def(def).name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(text.find("let c").unwrap()), vec!["b", "a", "main"]);
+    }
+}
@@ -0,0 +1,254 @@
+//! What each name and keyword of a module is, for highlighting.
+//!
+//! Keywords, literals and comments come from the lexer. Names are classified by what the
+//! resolver bound them to, and members accessed on a value by what inference found, so a
+//! name is highlighted the same at its definition and at every use.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use bitflags::bitflags;
+
+use crate::{
+    analyzer::{
+        is_ident,
+        resolve::{resolve, DefId, DefKind, Definition, Resolution},
+    },
+    compiler::text::SourceProgram,
+    lexer::{Token, TripleIterator, Word},
+    parser::ast::Visibility,
+    types::infer::infer,
+    Db,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TokenKind {
+    /// A module named by `use`.
+    Namespace,
+    /// A builtin type.
+    Type,
+    Struct,
+    TypeParameter,
+    Effect,
+    Function,
+    /// A function of an impl block or an operation of an effect.
+    Method,
+    Field,
+    Parameter,
+    /// A `let` binding.
+    Variable,
+    /// Includes `self`, which reads like one wherever it is used.
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Modifiers: u8 {
+        /// The name being defined rather than used.
+        const DECLARATION = 0b001;
+        const PUBLIC = 0b010;
+        /// A binding that can be assigned to. src has no such bindings yet, the modifier is
+        /// part of the set so clients keep the same legend once it does.
+        const MUTABLE = 0b100;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    /// The bytes of the token in the source text.
+    pub span: Range<usize>,
+    pub kind: TokenKind,
+    pub modifiers: Modifiers,
+}
+
+fn keyword(word: &Word<'_>) -> bool {
+    !matches!(word, Word::Ident(_) | Word::FnIdent(_) | Word::Any(_))
+}
+
+/// What a definition of `kind` is, `None` for an import, which is what it imports.
+pub fn kind(kind: DefKind) -> Option<TokenKind> {
+    Some(match kind {
+        DefKind::Module => TokenKind::Namespace,
+        // classified by what they import
+        DefKind::Import => return None,
+        DefKind::Function | DefKind::Resume => TokenKind::Function,
+        DefKind::Struct => TokenKind::Struct,
+        DefKind::Field => TokenKind::Field,
+        DefKind::Effect | DefKind::BuiltinEffect => TokenKind::Effect,
+        DefKind::EffectOp | DefKind::Method => TokenKind::Method,
+        DefKind::Param => TokenKind::Parameter,
+        DefKind::SelfParam => TokenKind::Keyword,
+        DefKind::Local => TokenKind::Variable,
+        DefKind::TypeParam => TokenKind::TypeParameter,
+        DefKind::BuiltinType => TokenKind::Type,
+    })
+}
+
+/// The definition `def` stands for, following an import to the module it came from.
+fn target(db: &dyn Db, res: &Resolution, def: DefId) -> Definition {
+    match res.origin(def) {
+        Some((file, origin)) => resolve(db, file).def(origin).clone(),
+        None => res.def(def).clone(),
+    }
+}
+
+/// The classified tokens of `src`, ordered by their position and never overlapping.
+#[salsa::tracked(return_ref)]
+pub fn semantic_tokens(db: &dyn Db, src: SourceProgram) -> Vec<SemanticToken> {
+    let text = src.text(db);
+    let mut tokens: BTreeMap<usize, SemanticToken> = BTreeMap::new();
+    let mut push = |span: Range<usize>, kind: TokenKind, modifiers: Modifiers| {
+        if span.start < span.end {
+            tokens.insert(
+                span.start,
+                SemanticToken {
+                    span,
+                    kind,
+                    modifiers,
+                },
+            );
+        }
+    };
+
+    for (start, token, end) in TripleIterator::new(text) {
+        let kind = match token {
+            Token::Word(word) if keyword(&word) => TokenKind::Keyword,
+            Token::String(_) => TokenKind::String,
+            Token::Integer(_) | Token::Float(_) => TokenKind::Number,
+            Token::Comment(_) => TokenKind::Comment,
+            _ => continue,
+        };
+        push(start.offset..end.offset, kind, Modifiers::empty());
+    }
+
+    // a reference to `vec<T>` spans the type arguments, the token is the name alone
+    let name = |start: usize| {
+        let len = text[start..]
+            .find(|c: char| !is_ident(c))
+            .unwrap_or(text.len() - start);
+        start..start + len
+    };
+    let res = resolve(db, src);
+    let mut use_of = |start: usize, def: DefId, modifiers: Modifiers| {
+        let def = target(db, res, def);
+        let Some(kind) = kind(def.kind) else {
+            return;
+        };
+        let mut modifiers = modifiers;
+        if def.vis == Visibility::Public {
+            modifiers |= Modifiers::PUBLIC;
+        }
+        push(name(start), kind, modifiers);
+    };
+    for (id, def) in res.iter() {
+        // builtins and the prelude are defined elsewhere
+        if def.span.start < def.span.end {
+            use_of(def.span.start.offset, id, Modifiers::DECLARATION);
+        }
+    }
+    for reference in res.refs.iter() {
+        use_of(
+            reference.span.start.offset,
+            reference.def,
+            Modifiers::empty(),
+        );
+    }
+    for (&(start, _), &def) in infer(db, src).members.iter() {
+        use_of(start, def, Modifiers::empty());
+    }
+
+    tokens.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    /// `text` of each token with its kind and modifiers.
+    fn tokens(text: &str) -> Vec<String> {
+        let db = &Database::default();
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text.to_string());
+        semantic_tokens(db, src)
+            .iter()
+            .map(|token| {
+                let mut line = format!("{} {:?}", &text[token.span.clone()], token.kind);
+                for (name, _) in token.modifiers.iter_names() {
+                    line.push(' ');
+                    line.push_str(name);
+                }
+                line
+            })
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_semantic_tokens() {
+        let text = r#"pub struct Point {
+    pub x: i32
+    y: vec<i32>
+}
+
+effect Log: async {
+    log(msg: string) []
+}
+
+impl Point {
+    fn left(self) [] -> i32 {
+        self.x
+        log("left")
+    }
+}
+
+pub fn main(p: Point) [Log] {
+    let a = 1
+    p.x
+    p.left()
+}"#;
+        assert_eq!(
+            tokens(text),
+            vec![
+                "pub Keyword",
+                "struct Keyword",
+                "Point Struct DECLARATION PUBLIC",
+                "pub Keyword",
+                "x Field DECLARATION PUBLIC",
+                "i32 Struct PUBLIC",
+                "y Field DECLARATION",
+                "vec Struct PUBLIC",
+                "i32 Struct PUBLIC",
+                "effect Keyword",
+                "Log Effect DECLARATION",
+                "async Effect PUBLIC",
+                "log Method DECLARATION",
+                "msg Parameter DECLARATION",
+                "string Struct PUBLIC",
+                "impl Keyword",
+                "Point Struct PUBLIC",
+                "fn Keyword",
+                "left Method DECLARATION",
+                "self Keyword DECLARATION",
+                "i32 Struct PUBLIC",
+                "self Keyword",
+                "x Field PUBLIC",
+                "log Method",
+                "\"left\" String",
+                "pub Keyword",
+                "fn Keyword",
+                "main Function DECLARATION PUBLIC",
+                "p Parameter DECLARATION",
+                "Point Struct PUBLIC",
+                "Log Effect",
+                "let Keyword",
+                "a Variable DECLARATION",
+                "1 Number",
+                "p Parameter",
+                "x Field PUBLIC",
+                "p Parameter",
+                "left Method",
+            ]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! The prototype of the call being typed, for an editor to show while the arguments are.
+//!
+//! The call is found in the text: the innermost `(` left open before the position, the
+//! commas after it counting the arguments typed. What the callee is comes from the
+//! resolution of the file, whose body the parser recovers around the unfinished call. A
+//! method is found through the type of the value it is called on, which the call has to
+//! parse for, so the call is closed where the position is when it does not.
+
+use std::ops::Range;
+
+use crate::{
+    analyzer::{
+        docs::docs,
+        is_ident,
+        navigation::{origin, owner, Workspace},
+        resolve::{resolve, DefId, DefKind, Namespace},
+    },
+    compiler::text::SourceProgram,
+    parser::{
+        self,
+        ast::{self, FnArg, Node},
+        span::Spanned,
+    },
+    types::{infer::infer, Type},
+    Db,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHelp {
+    /// The prototype called as written, `exec(arg0: string, args: stringvec)[] -> i32`.
+    pub label: String,
+    /// The bytes of each parameter in `label`, without the `self` of a method called on a
+    /// value.
+    pub params: Vec<Range<usize>>,
+    /// The parameter the argument at the position is passed to.
+    pub active: usize,
+    /// The return type, the effects calling it performs and the doc comment, as Markdown.
+    pub documentation: String,
+}
+
+/// The call being typed at the position.
+struct Call {
+    /// The bytes of the name called.
+    name: Range<usize>,
+    /// The `.` before the name of a method.
+    dot: Option<usize>,
+    /// The number of arguments before the one at the position.
+    active: usize,
+}
+
+/// The call whose arguments `offset` of `text` is in.
+fn call_at(text: &str, offset: usize) -> Option<Call> {
+    let mut depth = 0;
+    let mut active = 0;
+    let mut in_string = false;
+    let mut open = None;
+    for (i, c) in text[..offset].char_indices().rev() {
+        match c {
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            ')' | ']' => depth += 1,
+            '(' | '[' if depth > 0 => depth -= 1,
+            '(' => {
+                open = Some(i);
+                break;
+            }
+            ',' if depth == 0 => active += 1,
+            // a call does not span blocks
+            '{' | '}' | '[' => return None,
+            _ => {}
+        }
+    }
+    let before = text[..open?].trim_end();
+    // the type arguments of `await<T>(`
+    let before = match before.strip_suffix('>') {
+        Some(generic) => &generic[..generic.rfind('<')?],
+        None => before,
+    };
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_ident(c))
+        .last()?
+        .0;
+    let dot = before[..start].strip_suffix('.').map(str::len);
+    Some(Call {
+        name: start..before.len(),
+        dot,
+        active,
+    })
+}
+
+/// The function, method or operation `call` of `src` calls.
+fn callee(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    call: &Call,
+) -> Option<(SourceProgram, DefId)> {
+    let name = &src.text(db)[call.name.clone()];
+    if let Some(dot) = call.dot {
+        let (file, owner) = owner(db, workspace, src, dot)?;
+        return resolve(db, file)
+            .members(owner)
+            .find(|(_, def)| def.name == name)
+            .map(|(id, _)| (file, id));
+    }
+    let res = resolve(db, src);
+    let def = res.visible_at(call.name.start).into_iter().find(|&id| {
+        let def = res.def(id);
+        def.name == name && def.kind.is_in(Namespace::Value) && !def.kind.is_local()
+    })?;
+    origin(db, workspace, src, def)
+}
+
+/// The prototype declaring `def` of `src`.
+fn prototype(db: &dyn Db, src: SourceProgram, def: DefId) -> Option<&Spanned<ast::Prototype>> {
+    let span = &resolve(db, src).def(def).span;
+    let declares = |proto: &&Spanned<ast::Prototype>| &proto.1.name.span() == span;
+    parser::parse(db, src)
+        .0
+        .iter()
+        .flat_map(|item| match &item.1 {
+            Node::FnDef(ast::FnDef(_, proto, _)) => vec![proto],
+            Node::EffectDef(ast::EffectDef(_, _, _, protos)) => protos.0.iter().collect(),
+            Node::ImplDef(ast::ImplDef(_, _, _, block)) => block
+                .0
+                .iter()
+                .filter_map(|method| match &method.1 {
+                    Node::FnDef(ast::FnDef(_, proto, _)) => Some(proto),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        })
+        .find(declares)
+}
+
+/// The prototype of the call whose arguments `offset` of `src` is in. When the callee is
+/// only found with the call closed, `scratch` is given the text of `src` closed at the
+/// position: salsa keeps every input as long as the database, a document has a single
+/// scratch program whose text is set again each time.
+pub fn signature_help(
+    db: &mut dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    scratch: SourceProgram,
+    offset: usize,
+) -> Option<SignatureHelp> {
+    let text = src.text(db);
+    let call = call_at(text, offset)?;
+    let found = match callee(db, workspace, src, &call) {
+        Some(found) => found,
+        None => {
+            let closed = format!("{}){}", &text[..offset], &text[offset..]);
+            scratch.set_text(db).to(closed);
+            callee(db, workspace, scratch, &call)?
+        }
+    };
+    let db: &dyn Db = db;
+    let (file, def) = found;
+    let kind = resolve(db, file).def(def).kind;
+    if !matches!(
+        kind,
+        DefKind::Function | DefKind::Method | DefKind::EffectOp
+    ) {
+        return None;
+    }
+    let proto = prototype(db, file, def)?;
+    let start = proto.0.offset;
+    let label = file.text(db)[start..proto.2.offset].to_string();
+    let params = proto
+        .1
+        .args
+        .iter()
+        .filter(|arg| !(call.dot.is_some() && arg.1 == FnArg::Reciever))
+        .map(|arg| arg.0.offset - start..arg.2.offset - start)
+        .collect();
+
+    let mut sections = vec![];
+    if let Some(Type::Fn(_, ret, row)) = infer(db, file).scheme(def).map(|scheme| &scheme.ty) {
+        sections.push(format!("Returns `{}`", ret));
+        sections.push(format!("Performs `{}`", row));
+    }
+    if let Some(docs) = docs(db, file, def) {
+        sections.push(format!("---\n\n{}", docs));
+    }
+    Some(SignatureHelp {
+        label,
+        params,
+        active: call.active,
+        documentation: sections.join("\n\n"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{cursor, workspace},
+    };
+
+    /// The label, parameters and active parameter of the call where `|` is in `text`.
+    fn help(text: &str) -> Option<(String, Vec<String>, usize)> {
+        let db = &mut Database::default();
+        let (text, offset) = cursor(text);
+        let (workspace, _, main) = workspace(db, &text);
+        let scratch = SourceProgram::new(db, "file:///w/main.src".to_string(), String::new());
+        let help = signature_help(db, workspace, main, scratch, offset)?;
+        let params = help
+            .params
+            .iter()
+            .map(|param| help.label[param.clone()].to_string())
+            .collect();
+        Some((help.label, params, help.active))
+    }
+
+    #[okstd::test]
+    fn test_signature_help() {
+        let shift = |active| {
+            Some((
+                "shift(x: i32, y: i32)[] -> i32".to_string(),
+                vec!["x: i32".to_string(), "y: i32".to_string()],
+                active,
+            ))
+        };
+        let main = |body: &str| {
+            format!(
+                "use {{ shift }} from util\nuse {{ execs }} from host\n\nstruct Runner {{\n    exec: execs\n}}\n\nfn main(r: Runner)[execs] {{\n    {}\n}}",
+                body
+            )
+        };
+        assert_eq!(help(&main("shift(|)")), shift(0));
+        assert_eq!(help(&main("shift(1, |)")), shift(1));
+        // the call is not closed yet
+        assert_eq!(help(&main("shift(|")), shift(0));
+        assert_eq!(help(&main("let a = shift(1,|")), shift(1));
+        assert_eq!(help(&main("shift(shift(1, 2), |")), shift(1));
+        assert_eq!(help(&main("shift(\"(,\", |")), shift(1));
+        assert_eq!(
+            help(&main("r.exec.exec(\"ls\", |")),
+            Some((
+                "exec(arg0: string, args: stringvec)[] -> i32".to_string(),
+                vec!["arg0: string".to_string(), "args: stringvec".to_string()],
+                1
+            ))
+        );
+        assert_eq!(help(&main("shift|")), None);
+        assert_eq!(help(&main("r(|")), None);
+    }
+
+    #[okstd::test]
+    fn test_signature_documentation() {
+        let db = &mut Database::default();
+        let text = "# Moves a point.\nfn shift(x: i32)[async] -> i32 {\n    shift(\n}";
+        let src = SourceProgram::new(db, "file:///w/main.src".to_string(), text.to_string());
+        let scratch = SourceProgram::new(db, "file:///w/main.src".to_string(), String::new());
+        let workspace = Workspace::new(db, vec![src]);
+        let offset = text.rfind('(').unwrap() + 1;
+        let help = signature_help(db, workspace, src, scratch, offset).unwrap();
+        assert_eq!(
+            help.documentation,
+            "Returns `i32`\n\nPerforms `[async]`\n\n---\n\nMoves a point."
+        );
+    }
+}
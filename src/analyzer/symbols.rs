@@ -0,0 +1,331 @@
+//! The outline of a file and a search over the items of the whole workspace.
+//!
+//! A file's symbols nest the way its items do: fields under their struct, operations
+//! under their effect, methods under their impl block and the names a `use` imports under
+//! the module it imports them from. The workspace index is every item of every file, kept
+//! by salsa, so a search after an edit only reads the outline of the file edited again.
+
+use std::ops::Range;
+
+use crate::{
+    analyzer::{
+        docs::signature,
+        navigation::{origin, Workspace},
+        resolve::{resolve, DefId, DefKind, Resolution, ScopeKind},
+    },
+    compiler::text::SourceProgram,
+    Db,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Module,
+    Struct,
+    Field,
+    Effect,
+    /// A prototype declared by an effect.
+    Operation,
+    Impl,
+    Method,
+    Function,
+    /// An import of a module that is not found.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The signature, see [`signature`].
+    pub detail: Option<String>,
+    /// The bytes of the whole item.
+    pub span: Range<usize>,
+    /// The bytes of its name.
+    pub name_span: Range<usize>,
+    pub children: Vec<Symbol>,
+}
+
+/// An item of the workspace, see [`symbol_index`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IndexedSymbol {
+    pub src: SourceProgram,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub name_span: Range<usize>,
+    /// The struct, effect or impl it is a member of.
+    pub container: Option<String>,
+}
+
+fn symbol_kind(kind: DefKind) -> Option<SymbolKind> {
+    match kind {
+        DefKind::Module => Some(SymbolKind::Module),
+        DefKind::Struct | DefKind::BuiltinType => Some(SymbolKind::Struct),
+        DefKind::Field => Some(SymbolKind::Field),
+        DefKind::Effect | DefKind::BuiltinEffect => Some(SymbolKind::Effect),
+        DefKind::EffectOp => Some(SymbolKind::Operation),
+        DefKind::Method => Some(SymbolKind::Method),
+        DefKind::Function => Some(SymbolKind::Function),
+        _ => None,
+    }
+}
+
+fn symbol(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    res: &Resolution,
+    def: DefId,
+) -> Option<Symbol> {
+    let definition = res.def(def);
+    let kind = match definition.kind {
+        // an import is whatever it imports
+        DefKind::Import => match origin(db, workspace, src, def) {
+            Some((file, id)) => symbol_kind(resolve(db, file).def(id).kind)?,
+            None => SymbolKind::Unknown,
+        },
+        kind => symbol_kind(kind)?,
+    };
+    let children = match definition.kind {
+        DefKind::Struct => vec![DefKind::Field],
+        DefKind::Effect => vec![DefKind::EffectOp],
+        DefKind::Module => vec![DefKind::Import],
+        _ => vec![],
+    };
+    let children = res
+        .members(def)
+        .filter(|(_, member)| children.contains(&member.kind))
+        .filter_map(|(id, _)| symbol(db, workspace, src, res, id))
+        .collect();
+    let detail = match definition.kind {
+        DefKind::Module | DefKind::Import => None,
+        _ => Some(signature(db, src, def)),
+    };
+    Some(Symbol {
+        name: definition.name.clone(),
+        kind,
+        detail,
+        span: definition.full_span.start.offset..definition.full_span.end.offset,
+        name_span: definition.span.start.offset..definition.span.end.offset,
+        children,
+    })
+}
+
+/// An impl block, named by its first line, `impl Log for Console`.
+fn impl_symbol(
+    db: &dyn Db,
+    workspace: Workspace,
+    src: SourceProgram,
+    res: &Resolution,
+    defs: &[DefId],
+    span: Range<usize>,
+) -> Symbol {
+    let text = &src.text(db)[span.clone()];
+    let name = text.split('{').next().unwrap_or_default().trim_end();
+    let children = defs
+        .iter()
+        .filter(|&&id| res.def(id).kind == DefKind::Method)
+        .filter_map(|&id| symbol(db, workspace, src, res, id))
+        .collect();
+    Symbol {
+        name: name.to_string(),
+        kind: SymbolKind::Impl,
+        detail: None,
+        name_span: span.start..span.start + name.len(),
+        span,
+        children,
+    }
+}
+
+/// The items of `src`, in the order they are written.
+#[salsa::tracked(return_ref)]
+pub fn document_symbols(db: &dyn Db, workspace: Workspace, src: SourceProgram) -> Vec<Symbol> {
+    let res = resolve(db, src);
+    let module = res.module_scope();
+    let mut symbols: Vec<Symbol> = res
+        .scope(module)
+        .defs
+        .iter()
+        // imports are listed under their module and operations under their effect, they
+        // are declared here to be named without them
+        .filter(|&&id| res.def(id).parent.is_none())
+        .filter_map(|&id| symbol(db, workspace, src, res, id))
+        .collect();
+    for scope in res.scopes.iter() {
+        if scope.kind == ScopeKind::Impl && scope.parent == Some(module) {
+            let span = scope.span.start.offset..scope.span.end.offset;
+            symbols.push(impl_symbol(db, workspace, src, res, &scope.defs, span));
+        }
+    }
+    symbols.sort_by_key(|symbol| symbol.span.start);
+    symbols
+}
+
+/// The items and members defined in the files of `workspace`, imports left out.
+#[salsa::tracked(return_ref)]
+pub fn symbol_index(db: &dyn Db, workspace: Workspace) -> Vec<IndexedSymbol> {
+    fn index(
+        src: SourceProgram,
+        symbols: &[Symbol],
+        container: Option<&str>,
+        found: &mut Vec<IndexedSymbol>,
+    ) {
+        for symbol in symbols {
+            if symbol.kind == SymbolKind::Module {
+                continue;
+            }
+            // an impl is found through its methods
+            if symbol.kind != SymbolKind::Impl {
+                found.push(IndexedSymbol {
+                    src,
+                    name: symbol.name.clone(),
+                    kind: symbol.kind,
+                    name_span: symbol.name_span.clone(),
+                    container: container.map(str::to_string),
+                });
+            }
+            index(src, &symbol.children, Some(&symbol.name), found);
+        }
+    }
+    let mut found = vec![];
+    for &src in workspace.files(db) {
+        index(src, document_symbols(db, workspace, src), None, &mut found);
+    }
+    found
+}
+
+/// How well `name` matches `query`, `None` when the characters of `query` are not all in
+/// `name` in order, ignoring case. Characters starting the name or a word of it, following
+/// the character matched before them or of the same case score higher.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut chars = name.char_indices();
+    let mut previous: Option<(usize, char)> = None;
+    let mut last_match: Option<usize> = None;
+    for wanted in query.chars() {
+        loop {
+            let (i, c) = chars.next()?;
+            let before = previous;
+            previous = Some((i, c));
+            if !c.eq_ignore_ascii_case(&wanted) {
+                continue;
+            }
+            score += 1;
+            if c == wanted {
+                score += 1;
+            }
+            let word_start = match before {
+                None => true,
+                Some((_, before)) => {
+                    before == '_' || (before.is_ascii_lowercase() && c.is_ascii_uppercase())
+                }
+            };
+            if word_start {
+                score += 3;
+            }
+            if last_match.is_some() && last_match == before.map(|(i, _)| i) {
+                score += 2;
+            }
+            last_match = Some(i);
+            break;
+        }
+    }
+    Some(score)
+}
+
+/// The items of the workspace matching `query`, best first.
+pub fn workspace_symbols<'db>(
+    db: &'db dyn Db,
+    workspace: Workspace,
+    query: &str,
+) -> Vec<&'db IndexedSymbol> {
+    let mut found: Vec<(u32, &IndexedSymbol)> = symbol_index(db, workspace)
+        .iter()
+        .filter_map(|symbol| Some((fuzzy_score(query, &symbol.name)?, symbol)))
+        .collect();
+    found.sort_by(|(a, left), (b, right)| {
+        b.cmp(a)
+            .then_with(|| left.name.len().cmp(&right.name.len()))
+            .then_with(|| left.name.cmp(&right.name))
+    });
+    found.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{
+        db::Database,
+        fixtures::{workspace, MAIN},
+    };
+
+    /// Each symbol as `kind name` indented under its parent.
+    fn outline(symbols: &[Symbol], depth: usize, lines: &mut Vec<String>) {
+        for symbol in symbols {
+            lines.push(format!(
+                "{}{:?} {}",
+                "  ".repeat(depth),
+                symbol.kind,
+                symbol.name
+            ));
+            outline(&symbol.children, depth + 1, lines);
+        }
+    }
+
+    #[okstd::test]
+    fn test_document_symbols() {
+        let db = &Database::default();
+        let (workspace, _, main) = workspace(db, MAIN);
+        let mut lines = vec![];
+        outline(document_symbols(db, workspace, main), 0, &mut lines);
+        assert_eq!(
+            lines,
+            [
+                "Module util",
+                "  Struct Point",
+                "  Function origin",
+                "Effect Log",
+                "  Operation log",
+                "Struct Console",
+                "Impl impl Log for Console",
+                "  Method log",
+                "Function main",
+            ]
+        );
+        let symbols = document_symbols(db, workspace, main);
+        let main_fn = symbols.last().unwrap();
+        assert_eq!(
+            main_fn.detail.as_deref(),
+            Some("fn main(p: Point, n: i32)[Log]")
+        );
+        assert_eq!(&MAIN[main_fn.name_span.clone()], "main");
+        assert!(MAIN[main_fn.span.clone()].starts_with("fn main"));
+    }
+
+    #[okstd::test]
+    fn test_workspace_symbols() {
+        let db = &Database::default();
+        let (workspace, _, _) = workspace(db, MAIN);
+        let search = |query: &str| -> Vec<String> {
+            workspace_symbols(db, workspace, query)
+                .into_iter()
+                .map(|symbol| match &symbol.container {
+                    Some(container) => format!("{}::{}", container, symbol.name),
+                    None => symbol.name.clone(),
+                })
+                .collect()
+        };
+        assert_eq!(search("pt"), ["Point"]);
+        assert_eq!(
+            search("lo"),
+            ["Log::log", "impl Log for Console::log", "Log"]
+        );
+        assert_eq!(search("cons"), ["Console"]);
+        assert!(search("zz").is_empty());
+        assert_eq!(search("").len(), 10);
+
+        assert_eq!(fuzzy_score("mn", "main"), Some(7));
+        assert!(fuzzy_score("ma", "main") > fuzzy_score("mn", "main"));
+        assert!(fuzzy_score("P", "Point") > fuzzy_score("p", "Point"));
+        assert_eq!(fuzzy_score("nm", "main"), None);
+    }
+}
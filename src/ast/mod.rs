@@ -1,12 +1,13 @@
 use crate::lexer::Location;
 use crate::ops;
-use crate::parser::span::Spanned;
+use crate::parser::span::{self, Spanned};
 use srclang_derive::node;
 use std::fmt::Display;
 use std::ops::Range;
 
 /// ast node representing an identifier.
 #[node]
+#[derive(Clone, PartialEq)]
 pub struct Ident {
     name: String,
     generics: Vec<Ident>,
@@ -14,6 +15,7 @@ pub struct Ident {
 
 /// ast node representing a field.
 #[node]
+#[derive(Clone, PartialEq)]
 pub struct Field {
     vis: Option<Visibility>,
     name: String,
@@ -21,6 +23,8 @@ pub struct Field {
 }
 
 /// An enum representing the different types of literals that can be used in an expression.
+#[node]
+#[derive(Clone, PartialEq)]
 pub enum Literal {
     Bool(bool),
     Float(f64),
@@ -28,7 +32,7 @@ pub enum Literal {
     String(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Kw {
     /// The `None` keyword.
     None,
@@ -63,11 +67,12 @@ pub enum Kw {
 }
 
 #[node]
+#[derive(Clone, PartialEq)]
 pub struct Keyword {
     kw: Kw,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 /// An enum representing the visibility of a field or method.
 pub enum Visibility {
     Private,
@@ -108,3 +113,94 @@ pub enum Node {
     Visibility(Visibility),
     Operator(Operator),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span;
+    use span::HasChildSpans;
+    use std::cell::RefCell;
+
+    fn at(offset: usize) -> Location {
+        Location {
+            offset,
+            ..Default::default()
+        }
+    }
+
+    fn ident(name: &str, start: usize, generics: Vec<Spanned<Ident>>) -> Ident {
+        Ident::new(
+            span!(at(start), name.to_string(), at(start + name.len())),
+            generics,
+        )
+    }
+
+    #[derive(Default)]
+    struct Collect {
+        seen: RefCell<Vec<String>>,
+        stop_at: Option<&'static str>,
+    }
+
+    impl IdentVisitor for Collect {
+        fn visit_generics(&self, node: &Ident, _span: &Range<Location>) -> ops::traversal::Control {
+            self.seen.borrow_mut().push(node.name_node().1.clone());
+            if self.stop_at == Some(node.name_node().1.as_str()) {
+                ops::traversal::Control::Break
+            } else {
+                ops::traversal::Control::Continue
+            }
+        }
+    }
+
+    #[okstd::test]
+    fn test_accept_visits_every_child() {
+        let vec = ident(
+            "map",
+            0,
+            vec![
+                span!(at(4), ident("string", 4, vec![]), at(10)),
+                span!(at(12), ident("i32", 12, vec![]), at(15)),
+            ],
+        );
+        let visitor = Collect::default();
+        assert_eq!(vec.accept(&visitor), ops::traversal::Control::Continue);
+        assert_eq!(*visitor.seen.borrow(), vec!["string", "i32"]);
+    }
+
+    #[okstd::test]
+    fn test_accept_stops_on_break() {
+        let vec = ident(
+            "map",
+            0,
+            vec![
+                span!(at(4), ident("string", 4, vec![]), at(10)),
+                span!(at(12), ident("i32", 12, vec![]), at(15)),
+            ],
+        );
+        let visitor = Collect {
+            stop_at: Some("string"),
+            ..Default::default()
+        };
+        assert_eq!(vec.accept(&visitor), ops::traversal::Control::Break);
+        assert_eq!(*visitor.seen.borrow(), vec!["string"]);
+    }
+
+    #[okstd::test]
+    fn test_children_and_getters() {
+        let field = Field::new(
+            None,
+            span!(at(0), "a".to_string(), at(1)),
+            span!(at(3), ident("string", 3, vec![]), at(9)),
+        );
+        let children: Vec<_> = field.children().into_iter().collect();
+        assert_eq!(children, vec![at(0)..at(1), at(3)..at(9)]);
+        assert_eq!(field.vis_span(), None);
+        assert_eq!(field.ty_span(), at(3)..at(9));
+        assert_eq!(field.clone(), field);
+
+        let literal = Literal::Integer(span!(at(0), 1, at(1)));
+        assert_eq!(literal.integer_span(), Some(at(0)..at(1)));
+        assert_eq!(literal.bool_span(), None);
+        assert_eq!(literal.children().into_iter().count(), 1);
+    }
+}
@@ -5,7 +5,8 @@ use std::{
     collections::BTreeMap,
 };
 
-use crate::{parser::ast};
+use super::{lower::define, mir, text::SourceProgram};
+use crate::Db;
 
 #[salsa::tracked]
 pub struct Program {
@@ -13,18 +14,68 @@ pub struct Program {
     pub modul: Vec<Function>,
     #[return_ref]
     pub symbols: BTreeMap<Mangled, Symbol>,
+    #[return_ref]
+    pub effects: Vec<EffectDef>,
+    #[return_ref]
+    pub handlers: Vec<Handler>,
+    /// What other compilation units can link against.
+    #[return_ref]
+    pub exports: Vec<Export>,
+}
+
+/// A public item of a program, by its symbol. The [`demangle`](super::mangle::demangle)d
+/// symbol names the module, impl and item it is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Export {
+    pub symbol: Mangled,
+    pub kind: ExportKind,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportKind {
+    Function,
+    Method,
+    Struct,
+    Effect,
+}
+
+/// A function or method, defined on its own by [`define`](super::lower::define) and
+/// lowered on its own by [`lower_function`](super::lower::lower_function).
 #[salsa::tracked]
 pub struct Function {
-    #[return_ref]
-    pub name: String,
+    #[id]
+    pub name: Mangled,
 
-    #[return_ref]
-    pub body: Vec<Box<Function>>,
+    /// The file defining it.
+    pub src: SourceProgram,
 
-    #[return_ref]
+    /// Where its name is in `src`, to find its definition again.
+    pub offset: usize,
+}
+
+impl Function {
+    /// The generic parameters, in the order they are declared. The types in the tree
+    /// refer to them as [`Type::Param`](crate::types::Type::Param).
+    pub fn generics(self, db: &dyn Db) -> &Vec<String> {
+        &define(db, self).generics
+    }
+
+    /// The expanded effects of its prototype.
+    pub fn effects(self, db: &dyn Db) -> &Vec<InternedEffect> {
+        &define(db, self).effects
+    }
+
+    pub fn tree(self, db: &dyn Db) -> &mir::Tree {
+        &define(db, self).tree
+    }
+}
+
+/// What a function is made of, built from its definition in the AST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub generics: Vec<String>,
     pub effects: Vec<InternedEffect>,
+    pub tree: mir::Tree,
 }
 
 #[salsa::interned]
@@ -38,10 +89,27 @@ pub struct Symbol {
     pub symbol: Mangled,
 }
 
+/// An effect, with the operations its handlers provide.
 #[salsa::tracked]
 pub struct EffectDef {
+    pub name: Mangled,
+    #[return_ref]
+    pub ops: Vec<Mangled>,
+    /// The effects this one is declared in terms of, `async + throws`.
+    #[return_ref]
+    pub requires: Vec<InternedEffect>,
+}
+
+/// `impl Effect for Target`, which `handle { .. } with Target` installs over a block.
+/// Performing an operation of `effect` in the block calls the method implementing it, and
+/// the method's `resume` continues the block.
+#[salsa::tracked]
+pub struct Handler {
+    pub effect: Mangled,
+    pub target: Mangled,
+    /// The methods implementing the operations, in the order of `compile_effect`.
     #[return_ref]
-    pub effect: ast::EffectDef,
+    pub ops: Vec<(Mangled, Mangled)>,
 }
 
 #[salsa::tracked]
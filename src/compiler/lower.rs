@@ -0,0 +1,697 @@
+//! Lowering from the resolved AST to the [`mir`].
+//!
+//! [`functions`] names every function and method of a module, each an [`ir::Function`].
+//! [`define`] builds the [`mir::Tree`] of one of them from its definition in the AST and
+//! [`lower_function`] lowers that tree, both tracked per function. An edit defines every
+//! function again, as the AST they are read from changed, but only the functions whose
+//! trees changed are lowered again.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    analyzer::resolve::{self, DefId, DefKind, Resolution},
+    compiler::{
+        add_imports, ir, item_symbol,
+        mangle::Demangled,
+        method_symbol,
+        mir::{self, BlockId, Callee, Const, Expr, ExprKind, Instr, Local, Operand, Terminator},
+        module_path,
+        text::SourceProgram,
+    },
+    parser::{
+        self,
+        ast::{self, FnArg, Node},
+        span::Spanned,
+    },
+    types::{
+        infer::{self, Inference, SpanKey},
+        RowVar, Type, TypeVar,
+    },
+    Db,
+};
+
+fn key(span: &std::ops::Range<crate::lexer::Location>) -> SpanKey {
+    (span.start.offset, span.end.offset)
+}
+
+/// Builds the tree of one function.
+struct Builder<'db> {
+    db: &'db dyn Db,
+    res: &'db Resolution,
+    inference: &'db Inference,
+    /// The module path of the symbols defined here.
+    path: Vec<String>,
+    /// Identifier uses, by span.
+    uses: HashMap<SpanKey, DefId>,
+    /// Identifier definitions, by span.
+    names: HashMap<SpanKey, DefId>,
+    /// The mangled name of every method, and whether it takes `self`.
+    methods: &'db HashMap<DefId, (ir::Mangled, bool)>,
+    /// The parameters and bindings of the current function.
+    locals: Vec<mir::LocalDecl>,
+    bound: HashMap<DefId, Local>,
+    /// Inference variables renumbered from zero in each function, so they do not depend on
+    /// how many variables the functions before it took.
+    vars: BTreeMap<TypeVar, Type>,
+    rows: BTreeMap<RowVar, RowVar>,
+}
+
+impl<'db> Builder<'db> {
+    fn ty(&mut self, ty: &Type) -> Type {
+        let mut vars = BTreeSet::new();
+        ty.free_vars(&mut vars);
+        for var in vars {
+            let next = Type::Var(TypeVar(self.vars.len() as u32));
+            self.vars.entry(var).or_insert(next);
+        }
+        let mut rows = BTreeSet::new();
+        ty.free_rows(&mut rows);
+        for row in rows {
+            let next = RowVar(self.rows.len() as u32);
+            self.rows.entry(row).or_insert(next);
+        }
+        let vars: Vec<(TypeVar, Type)> = self
+            .vars
+            .iter()
+            .map(|(var, ty)| (*var, ty.clone()))
+            .collect();
+        let rows: Vec<(RowVar, RowVar)> = self.rows.iter().map(|(a, b)| (*a, *b)).collect();
+        ty.replace(&vars, &rows)
+    }
+
+    fn def_type(&mut self, def: Option<DefId>) -> Type {
+        match def.and_then(|def| self.inference.scheme(def)) {
+            Some(scheme) => self.ty(&scheme.ty.clone()),
+            None => Type::unit(),
+        }
+    }
+
+    fn bind(&mut self, def: Option<DefId>, name: &str, ty: Type) -> Local {
+        let local = Local(self.locals.len() as u32);
+        self.locals.push(mir::LocalDecl {
+            name: Some(name.to_string()),
+            ty,
+        });
+        if let Some(def) = def {
+            self.bound.insert(def, local);
+        }
+        local
+    }
+
+    fn function(
+        &mut self,
+        proto: &Spanned<ast::Prototype>,
+        body: &ast::Block<Spanned<Node>>,
+    ) -> ir::Definition {
+        for arg in proto.1.args.iter() {
+            let (def, name) = match &arg.1 {
+                FnArg::Reciever => (self.names.get(&key(&arg.span())).copied(), "self"),
+                FnArg::Field(field) => (
+                    self.names.get(&key(&field.1 .1.span())).copied(),
+                    field.1 .1 .1 .0.as_str(),
+                ),
+            };
+            let ty = self.def_type(def);
+            self.bind(def, name, ty);
+        }
+        let params = self.locals.len();
+        let def = self.names.get(&key(&proto.1.name.span())).copied();
+        let (ret, effects) = match self.def_type(def) {
+            Type::Fn(_, ret, row) => (*ret, row.effects),
+            _ => (Type::unit(), BTreeSet::new()),
+        };
+        let body = self.block(body);
+        let tree = mir::Tree {
+            params,
+            locals: std::mem::take(&mut self.locals),
+            ret,
+            body,
+        };
+        let effects = effects
+            .into_iter()
+            .map(|effect| ir::InternedEffect::new(self.db, effect))
+            .collect();
+        let generics = proto
+            .1
+            .name
+            .1
+             .1
+            .iter()
+            .flatten()
+            .map(|generic| generic.1 .0.clone())
+            .collect();
+        ir::Definition {
+            generics,
+            effects,
+            tree,
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block<Spanned<Node>>) -> Vec<Expr> {
+        block
+            .0
+            .iter()
+            .map(|statement| self.expr(statement))
+            .collect()
+    }
+
+    /// The function an item-level definition names.
+    fn item(&self, def: DefId) -> Option<ir::Mangled> {
+        let definition = self.res.def(def);
+        match definition.kind {
+            DefKind::Function => Some(item_symbol(self.db, self.res, &self.path, def)),
+            DefKind::Method => self.methods.get(&def).map(|(name, _)| *name),
+            DefKind::Import => {
+                let module = definition
+                    .parent
+                    .map(|module| self.res.def(module).name.clone());
+                let import = ir::Import::new(
+                    self.db,
+                    vec![definition.name.clone()],
+                    module.unwrap_or_default(),
+                );
+                add_imports(self.db, import).first().copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, node: &Spanned<Node>) -> Expr {
+        let ty = match self.inference.type_of(&node.span()) {
+            Some(ty) => self.ty(&ty.clone()),
+            None => Type::unit(),
+        };
+        let kind = match &node.1 {
+            Node::Integer(i) => ExprKind::Const(Const::Integer(*i)),
+            Node::Float(f) => ExprKind::Const(Const::Float(*f)),
+            Node::String(s) => ExprKind::Const(Const::String(s.clone())),
+            Node::Bool(b) => ExprKind::Const(Const::Bool(*b)),
+            Node::Ident(ident) => {
+                let def = self.uses.get(&key(&ident.span())).copied();
+                match def {
+                    Some(def) if self.bound.contains_key(&def) => ExprKind::Local(self.bound[&def]),
+                    Some(def) => match self.item(def) {
+                        Some(name) => ExprKind::Const(Const::Function(name)),
+                        None => ExprKind::Error,
+                    },
+                    None => ExprKind::Error,
+                }
+            }
+            Node::FnCall(ast::FnCall(name, args)) => self.call(name, args),
+            Node::BinaryExpression(bin) => ExprKind::Binary(
+                bin.op.clone(),
+                Box::new(self.expr(&bin.lhs)),
+                Box::new(self.expr(&bin.rhs)),
+            ),
+            Node::Binding(ast::Binding(name, value)) => {
+                let value = self.expr(value);
+                let def = self.names.get(&key(&name.span())).copied();
+                let ty = match self.inference.type_of(&name.span()) {
+                    Some(ty) => self.ty(&ty.clone()),
+                    None => value.ty.clone(),
+                };
+                let local = self.bind(def, &name.1 .0, ty);
+                ExprKind::Let(local, Box::new(value))
+            }
+            Node::FieldAccess(ast::FieldAccess(lhs, rhs)) => {
+                let base = self.expr(lhs);
+                match &rhs.1 {
+                    Node::Ident(field) => ExprKind::Field(Box::new(base), field.1 .0.clone()),
+                    Node::FnCall(ast::FnCall(name, args)) => self.method(base, name, args),
+                    _ => ExprKind::Error,
+                }
+            }
+            Node::Branch(ast::BranchDef(cond, arms)) => {
+                let cond = self.expr(cond);
+                let mut then = vec![];
+                let mut otherwise = vec![];
+                for (arm, block) in arms {
+                    let block = self.block(block);
+                    match arm.1 {
+                        Node::Bool(false) => otherwise = block,
+                        _ => then = block,
+                    }
+                }
+                ExprKind::If(Box::new(cond), then, otherwise)
+            }
+            Node::Handle(ast::HandleDef(_, body, _, handler)) => {
+                let handler = Demangled::item(&self.path, &handler.1 .0).intern(self.db);
+                ExprKind::Handle(handler, self.block(body))
+            }
+            _ => ExprKind::Error,
+        };
+        Expr { kind, ty }
+    }
+
+    fn call(&mut self, name: &Spanned<ast::Ident>, args: &[Spanned<Node>]) -> ExprKind {
+        let args: Vec<Expr> = args.iter().map(|arg| self.expr(arg)).collect();
+        let Some(def) = self.uses.get(&key(&name.span())).copied() else {
+            return ExprKind::Error;
+        };
+        if let Some(local) = self.bound.get(&def) {
+            return ExprKind::Call(Callee::Local(*local), args);
+        }
+        let definition = self.res.def(def);
+        match definition.kind {
+            DefKind::EffectOp => match definition.parent {
+                Some(effect) => ExprKind::Perform(
+                    item_symbol(self.db, self.res, &self.path, effect),
+                    ir::Mangled::new(self.db, definition.name.clone()),
+                    args,
+                ),
+                None => ExprKind::Error,
+            },
+            DefKind::Resume => match <[Expr; 1]>::try_from(args) {
+                Ok([value]) => ExprKind::Resume(Box::new(value)),
+                Err(_) => ExprKind::Error,
+            },
+            _ => match self.item(def) {
+                Some(name) => ExprKind::Call(Callee::Function(name), args),
+                None => ExprKind::Error,
+            },
+        }
+    }
+
+    /// `base.name(args)`, passing `base` as the receiver when the method takes `self`.
+    fn method(
+        &mut self,
+        base: Expr,
+        name: &Spanned<ast::Ident>,
+        args: &[Spanned<Node>],
+    ) -> ExprKind {
+        let method = self
+            .inference
+            .members
+            .get(&key(&name.span()))
+            .and_then(|def| self.methods.get(def))
+            .copied();
+        let Some((method, takes_self)) = method else {
+            return ExprKind::Error;
+        };
+        let mut operands = vec![];
+        if takes_self {
+            operands.push(base);
+        }
+        operands.extend(args.iter().map(|arg| self.expr(arg)));
+        ExprKind::Call(Callee::Function(method), operands)
+    }
+}
+
+/// The mangled name of every method of `src`, and whether it takes `self`.
+#[salsa::tracked(return_ref)]
+pub fn methods(db: &dyn Db, src: SourceProgram) -> HashMap<DefId, (ir::Mangled, bool)> {
+    let module = parser::parse(db, src);
+    let res = resolve::resolve(db, src);
+    let path = module_path(db, src);
+    let mut methods = HashMap::new();
+    for item in module.0.iter() {
+        if let Node::ImplDef(ast::ImplDef(_, name, target, body)) = &item.1 {
+            for method in body.0.iter() {
+                if let Node::FnDef(ast::FnDef(_, proto, _)) = &method.1 {
+                    let Some(def) = res.def_at(proto.1.name.0.offset) else {
+                        continue;
+                    };
+                    let mangled =
+                        method_symbol(db, res, &path, name, target.as_ref(), proto).intern(db);
+                    let takes_self = proto.1.args.iter().any(|arg| arg.1 == FnArg::Reciever);
+                    methods.insert(def, (mangled, takes_self));
+                }
+            }
+        }
+    }
+    methods
+}
+
+/// The definition of every function and method in `module`.
+fn definitions(module: &ast::Module) -> impl Iterator<Item = &ast::FnDef> {
+    module
+        .0
+        .iter()
+        .flat_map(|item| match &item.1 {
+            Node::ImplDef(ast::ImplDef(_, _, _, body)) => body.0.iter().collect(),
+            _ => vec![item],
+        })
+        .filter_map(|item| match &item.1 {
+            Node::FnDef(function) => Some(function),
+            _ => None,
+        })
+}
+
+/// Every function and method of `src`, ready to be defined and lowered one at a time.
+#[salsa::tracked]
+pub fn functions(db: &dyn Db, src: SourceProgram) -> Vec<ir::Function> {
+    let module = parser::parse(db, src);
+    let res = resolve::resolve(db, src);
+    let path = module_path(db, src);
+    let mut functions = vec![];
+    let mut methods = vec![];
+    for item in module.0.iter() {
+        match &item.1 {
+            Node::FnDef(ast::FnDef(_, proto, _)) => {
+                let name = Demangled::item(&path, &proto.1.name.1 .0).intern(db);
+                functions.push(ir::Function::new(db, name, src, proto.1.name.0.offset));
+            }
+            Node::ImplDef(ast::ImplDef(_, name, target, body)) => {
+                for method in body.0.iter() {
+                    if let Node::FnDef(ast::FnDef(_, proto, _)) = &method.1 {
+                        let mangled =
+                            method_symbol(db, res, &path, name, target.as_ref(), proto).intern(db);
+                        methods.push(ir::Function::new(db, mangled, src, proto.1.name.0.offset));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    functions.extend(methods);
+    functions
+}
+
+/// Builds the tree of `function` from its definition, with its generics and effects.
+#[salsa::tracked(return_ref)]
+pub fn define(db: &dyn Db, function: ir::Function) -> ir::Definition {
+    let src = function.src(db);
+    let res = resolve::resolve(db, src);
+    let offset = function.offset(db);
+    let ast::FnDef(_, proto, body) = definitions(parser::parse(db, src))
+        .find(|ast::FnDef(_, proto, _)| proto.1.name.0.offset == offset)
+        .expect("a function is defined where `functions` found it");
+    let mut builder = Builder {
+        db,
+        res,
+        inference: infer::infer(db, src),
+        path: module_path(db, src),
+        uses: res
+            .refs
+            .iter()
+            .map(|reference| (key(&reference.span), reference.def))
+            .collect(),
+        names: res.iter().map(|(id, def)| (key(&def.span), id)).collect(),
+        methods: methods(db, src),
+        locals: vec![],
+        bound: HashMap::new(),
+        vars: BTreeMap::new(),
+        rows: BTreeMap::new(),
+    };
+    builder.function(proto, body)
+}
+
+/// Lowers the tree of `function` to basic blocks.
+#[salsa::tracked(return_ref)]
+pub fn lower_function(db: &dyn Db, function: ir::Function) -> mir::Body {
+    let tree = function.tree(db);
+    let mut lowering = Lowering {
+        locals: tree.locals.clone(),
+        blocks: vec![],
+        current: BlockId(0),
+    };
+    lowering.new_block();
+    let value = lowering.statements(&tree.body);
+    let value = if tree.ret == Type::unit() {
+        Operand::Const(Const::Unit)
+    } else {
+        value
+    };
+    lowering.terminate(Terminator::Return(value));
+    mir::Body {
+        params: (0..tree.params).map(|param| Local(param as u32)).collect(),
+        locals: lowering.locals,
+        blocks: lowering
+            .blocks
+            .into_iter()
+            .map(|(instrs, terminator)| mir::Block {
+                instrs,
+                terminator: terminator.unwrap_or(Terminator::Return(Operand::Const(Const::Unit))),
+            })
+            .collect(),
+    }
+}
+
+struct Lowering {
+    locals: Vec<mir::LocalDecl>,
+    /// The blocks built so far, the terminator is set once the block is finished.
+    blocks: Vec<(Vec<Instr>, Option<Terminator>)>,
+    current: BlockId,
+}
+
+impl Lowering {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((vec![], None));
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn temp(&mut self, ty: &Type) -> Local {
+        self.locals.push(mir::LocalDecl {
+            name: None,
+            ty: ty.clone(),
+        });
+        Local(self.locals.len() as u32 - 1)
+    }
+
+    fn push(&mut self, instr: Instr) {
+        self.blocks[self.current.0 as usize].0.push(instr);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0 as usize].1 = Some(terminator);
+    }
+
+    /// Lowers a block of statements, returning the value of the last one.
+    fn statements(&mut self, statements: &[Expr]) -> Operand {
+        let mut value = Operand::Const(Const::Unit);
+        for statement in statements {
+            value = self.operand(statement);
+            if statement.kind.is_statement() {
+                value = Operand::Const(Const::Unit);
+            }
+        }
+        value
+    }
+
+    fn operands(&mut self, exprs: &[Expr]) -> Vec<Operand> {
+        exprs.iter().map(|expr| self.operand(expr)).collect()
+    }
+
+    fn operand(&mut self, expr: &Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Const(value) => Operand::Const(value.clone()),
+            ExprKind::Local(local) => Operand::Local(*local),
+            ExprKind::Call(callee, args) => {
+                let args = self.operands(args);
+                let dest = self.temp(&expr.ty);
+                self.push(Instr::Call(dest, callee.clone(), args));
+                Operand::Local(dest)
+            }
+            ExprKind::Perform(effect, op, args) => {
+                let args = self.operands(args);
+                let dest = self.temp(&expr.ty);
+                self.push(Instr::Perform(dest, *effect, *op, args));
+                Operand::Local(dest)
+            }
+            ExprKind::Resume(value) => {
+                let value = self.operand(value);
+                let dest = self.temp(&expr.ty);
+                self.push(Instr::Resume(dest, value));
+                Operand::Local(dest)
+            }
+            ExprKind::Field(base, field) => {
+                let base = self.operand(base);
+                let dest = self.temp(&expr.ty);
+                self.push(Instr::Field(dest, base, field.clone()));
+                Operand::Local(dest)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.operand(lhs);
+                let rhs = self.operand(rhs);
+                let dest = self.temp(&expr.ty);
+                self.push(Instr::Binary(dest, op.clone(), lhs, rhs));
+                Operand::Local(dest)
+            }
+            ExprKind::Let(local, value) => {
+                let value = self.operand(value);
+                self.push(Instr::Assign(*local, value));
+                Operand::Const(Const::Unit)
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                let cond = self.operand(cond);
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let join = self.new_block();
+                self.terminate(Terminator::Branch(cond, then_block, else_block));
+                for (block, statements) in [(then_block, then), (else_block, otherwise)] {
+                    self.current = block;
+                    self.statements(statements);
+                    self.terminate(Terminator::Jump(join));
+                }
+                self.current = join;
+                Operand::Const(Const::Unit)
+            }
+            ExprKind::Handle(handler, body) => {
+                self.push(Instr::Handle(*handler));
+                let value = self.statements(body);
+                self.push(Instr::Unhandle);
+                value
+            }
+            ExprKind::Error => Operand::Const(Const::Unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    fn dump(db: &Database, src: SourceProgram, name: &str) -> String {
+        let function = functions(db, src)
+            .into_iter()
+            .find(|function| function.name(db).mangled(db) == name)
+            .unwrap();
+        lower_function(db, function).dump(db)
+    }
+
+    #[okstd::test]
+    fn test_lowers_calls_fields_and_branches() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"struct Point {
+    x: i32
+    y: i32
+}
+
+impl Point {
+    fn left(self, other: Point)[] -> i32 {
+        other.x
+    }
+}
+
+fn add(a: i32, b: i32)[] -> i32 {
+    let c = a + b
+    c
+}
+
+fn main(p: Point, flag: bool)[] -> i32 {
+    let s = add(1, 2)
+    if flag {
+        let t = add(s, 1)
+    } else {
+        p.left(p)
+    }
+    s
+}"#
+            .to_string(),
+        );
+        assert_eq!(
+            dump(db, src, "main"),
+            r#"_0: Point (p)
+_1: bool (flag)
+_2: i32 (s)
+_3: i32 (t)
+_4: i32
+_5: i32
+_6: i32
+bb0:
+    _4 = call add(1, 2)
+    _2 = _4
+    branch _1, bb1, bb2
+bb1:
+    _5 = call add(_2, 1)
+    _3 = _5
+    jump bb3
+bb2:
+    _6 = call <Point>::left(_0, _0)
+    jump bb3
+bb3:
+    return _2
+"#
+        );
+        assert_eq!(
+            dump(db, src, "<Point>::left"),
+            r#"_0: Point (self)
+_1: Point (other)
+_2: i32
+bb0:
+    _2 = _1.x
+    return _2
+"#
+        );
+        assert!(dump(db, src, "add").contains("_3 = _0 + _1"));
+    }
+
+    #[okstd::test]
+    fn test_lowers_effects_and_handlers() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"effect Fs: async {
+    read(path: string)[] -> string
+}
+
+struct Mock {
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents")
+    }
+}
+
+fn main()[] -> string {
+    handle {
+        read("config")
+    } with Mock
+}"#
+            .to_string(),
+        );
+        assert_eq!(
+            dump(db, src, "main"),
+            r#"_0: string
+bb0:
+    handle Mock
+    _0 = perform Fs.read("config")
+    unhandle
+    return _0
+"#
+        );
+        let read = dump(db, src, "<Mock as Fs>::read");
+        assert!(read.contains("resume \"contents\""), "{}", read);
+    }
+
+    #[okstd::test]
+    fn test_edits_leave_other_functions_alone() {
+        let db = &mut Database::default().enable_logging();
+        let before = r#"fn first()[] -> i32 {
+    let x = 1
+    x
+}
+
+fn second(a: i32)[] -> i32 {
+    let b = a
+    b
+}"#;
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), before.to_string());
+        let bodies = |db: &Database| -> Vec<mir::Body> {
+            functions(db, src)
+                .iter()
+                .map(|function| lower_function(db, *function).clone())
+                .collect()
+        };
+        let old = bodies(db);
+        db.take_logs();
+        src.set_text(db).to(before.replace("x = 1", "x = 40"));
+        let new = bodies(db);
+        assert_ne!(old[0], new[0]);
+        assert_eq!(old[1], new[1]);
+        // `second` moved down the file and is defined again, its tree is the same so only
+        // `first` is lowered again
+        let lowered = db
+            .take_logs()
+            .iter()
+            .filter(|log| log.contains("lower_function"))
+            .count();
+        assert_eq!(lowered, 1);
+    }
+}
@@ -0,0 +1,397 @@
+//! Symbol names.
+//!
+//! Every function, method, struct and effect the IR refers to is named by an
+//! [`ir::Mangled`], the string form of a [`Demangled`]:
+//!
+//! ```text
+//! symbol   = { module "::" } [ owner "::" ] name [ generics ]
+//! owner    = "<" type [ " as " { module "::" } effect ] ">"
+//! generics = "<" type { "," type } ">"
+//! type     = ident [ generics ]
+//! ```
+//!
+//! Identifiers only hold letters, digits and `_`, so the separators cannot appear in them
+//! and [`demangle`] gets back exactly what was mangled. The module path of an item is the
+//! module defining it, named like `use .. from` names it: the file name without `.src`,
+//! or the built-in module. A program with no file name, one held in memory, has none.
+//!
+//! | item                                   | symbol                              |
+//! |----------------------------------------|-------------------------------------|
+//! | `fn main` in `main.src`                | `main::main`                        |
+//! | `fn len` in its `impl Point`           | `main::<Point>::len`                |
+//! | `fn read` in its `impl reads for Mock` | `main::<Mock as host::reads>::read` |
+//! | its `fn id<T>` instantiated with `i32` | `main::id<i32>`                     |
+//! | `exec` imported from `system`          | `system::exec`                      |
+//! | `effect reads` of `host`               | `host::reads`                       |
+
+use std::fmt::{self, Display};
+
+use crate::{compiler::ir, parser::ast, Db};
+
+/// A type argument or impl target, `vec<i32>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeName {
+    pub name: String,
+    pub args: Vec<TypeName>,
+}
+
+impl TypeName {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            args: vec![],
+        }
+    }
+}
+
+impl From<&ast::Ident> for TypeName {
+    fn from(ident: &ast::Ident) -> Self {
+        Self {
+            name: ident.0.clone(),
+            args: ident
+                .1
+                .iter()
+                .flatten()
+                .map(|arg| TypeName::from(&arg.1))
+                .collect(),
+        }
+    }
+}
+
+impl Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        generics(f, &self.args)
+    }
+}
+
+/// An effect by the module defining it, so a program's own `reads` is not `host::reads`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EffectName {
+    pub module: Vec<String>,
+    pub name: String,
+}
+
+impl EffectName {
+    pub fn new(module: &[String], name: &str) -> Self {
+        Self {
+            module: module.to_vec(),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Display for EffectName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for module in self.module.iter() {
+            write!(f, "{}::", module)?;
+        }
+        write!(f, "{}", self.name)
+    }
+}
+
+/// The impl a method is defined in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Owner {
+    pub target: TypeName,
+    /// The effect of `impl Effect for Target`, `None` for `impl Target`.
+    pub effect: Option<EffectName>,
+}
+
+/// The parts of a symbol name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Demangled {
+    pub module: Vec<String>,
+    pub owner: Option<Owner>,
+    pub name: String,
+    /// The type arguments of an instantiated generic function.
+    pub generics: Vec<TypeName>,
+}
+
+impl Demangled {
+    /// An item at the top level of `module`.
+    pub fn item(module: &[String], name: &str) -> Self {
+        Self {
+            module: module.to_vec(),
+            owner: None,
+            name: name.to_string(),
+            generics: vec![],
+        }
+    }
+
+    /// A method of `target`, implementing an operation of `effect` when there is one.
+    pub fn method(
+        module: &[String],
+        target: TypeName,
+        effect: Option<EffectName>,
+        name: &str,
+    ) -> Self {
+        Self {
+            owner: Some(Owner { target, effect }),
+            ..Self::item(module, name)
+        }
+    }
+
+    /// The instance of this function for `generics`.
+    pub fn instantiate(self, generics: Vec<TypeName>) -> Self {
+        Self { generics, ..self }
+    }
+
+    pub fn intern(&self, db: &dyn Db) -> ir::Mangled {
+        ir::Mangled::new(db, self.to_string())
+    }
+}
+
+fn generics(f: &mut fmt::Formatter<'_>, args: &[TypeName]) -> fmt::Result {
+    if args.is_empty() {
+        return Ok(());
+    }
+    write!(f, "<")?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", arg)?;
+    }
+    write!(f, ">")
+}
+
+impl Display for Demangled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for module in self.module.iter() {
+            write!(f, "{}::", module)?;
+        }
+        match &self.owner {
+            Some(Owner {
+                target,
+                effect: Some(effect),
+            }) => write!(f, "<{} as {}>::", target, effect)?,
+            Some(Owner {
+                target,
+                effect: None,
+            }) => write!(f, "<{}>::", target)?,
+            None => {}
+        }
+        write!(f, "{}", self.name)?;
+        generics(f, &self.generics)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// `symbol` is not in the form [`Demangled`] writes, `expected` was missing at byte `at`.
+    Malformed {
+        symbol: String,
+        expected: &'static str,
+        at: usize,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed {
+                symbol,
+                expected,
+                at,
+            } => write!(
+                f,
+                "`{}` is not a mangled name, expected {} at {}",
+                symbol, expected, at
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Reader<'a> {
+    symbol: &'a str,
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.symbol[self.at..]
+    }
+
+    fn error(&self, expected: &'static str) -> Error {
+        Error::Malformed {
+            symbol: self.symbol.to_string(),
+            expected,
+            at: self.at,
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.at += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &'static str) -> Result<(), Error> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(token)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(self.error("an identifier"));
+        }
+        let ident = self.rest()[..len].to_string();
+        self.at += len;
+        Ok(ident)
+    }
+
+    fn generics(&mut self) -> Result<Vec<TypeName>, Error> {
+        let mut args = vec![];
+        if !self.eat("<") {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.ty()?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(">")?;
+        Ok(args)
+    }
+
+    fn effect(&mut self) -> Result<EffectName, Error> {
+        let mut module = vec![];
+        let mut name = self.ident()?;
+        while self.eat("::") {
+            module.push(name);
+            name = self.ident()?;
+        }
+        Ok(EffectName { module, name })
+    }
+
+    fn ty(&mut self) -> Result<TypeName, Error> {
+        Ok(TypeName {
+            name: self.ident()?,
+            args: self.generics()?,
+        })
+    }
+}
+
+/// The parts of `symbol`, the inverse of the [`Display`] of [`Demangled`].
+pub fn demangle(symbol: &str) -> Result<Demangled, Error> {
+    let mut reader = Reader { symbol, at: 0 };
+    let mut module = vec![];
+    let mut owner = None;
+    let name = loop {
+        if reader.eat("<") {
+            let target = reader.ty()?;
+            let effect = match reader.eat(" as ") {
+                true => Some(reader.effect()?),
+                false => None,
+            };
+            reader.expect(">")?;
+            reader.expect("::")?;
+            owner = Some(Owner { target, effect });
+            break reader.ident()?;
+        }
+        let ident = reader.ident()?;
+        if !reader.eat("::") {
+            break ident;
+        }
+        module.push(ident);
+    };
+    let generics = reader.generics()?;
+    if !reader.rest().is_empty() {
+        return Err(reader.error("the end of the symbol"));
+    }
+    Ok(Demangled {
+        module,
+        owner,
+        name,
+        generics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[okstd::test]
+    fn test_round_trips() {
+        let generic = TypeName {
+            name: "vec".to_string(),
+            args: vec![TypeName::new("i32")],
+        };
+        let module = ["a".to_string(), "b_c".to_string()];
+        let symbols = [
+            (Demangled::item(&[], "main"), "main"),
+            (Demangled::item(&module, "d"), "a::b_c::d"),
+            (
+                Demangled::method(&[], TypeName::new("Point"), None, "len"),
+                "<Point>::len",
+            ),
+            (
+                Demangled::method(
+                    &module,
+                    generic.clone(),
+                    Some(EffectName::new(&[], "Fs")),
+                    "read",
+                ),
+                "a::b_c::<vec<i32> as Fs>::read",
+            ),
+            (
+                Demangled::method(
+                    &[],
+                    TypeName::new("Mock"),
+                    Some(EffectName::new(&["host".to_string()], "reads")),
+                    "read",
+                ),
+                "<Mock as host::reads>::read",
+            ),
+            (
+                Demangled::item(&[], "zip").instantiate(vec![generic, TypeName::new("str")]),
+                "zip<vec<i32>,str>",
+            ),
+        ];
+        for (demangled, symbol) in symbols {
+            assert_eq!(demangled.to_string(), symbol);
+            assert_eq!(demangle(symbol), Ok(demangled));
+        }
+        // the separators keep paths that join to the same characters apart
+        assert_ne!(
+            Demangled::item(&["a_b".to_string()], "c").to_string(),
+            Demangled::item(&["a".to_string()], "b_c").to_string()
+        );
+    }
+
+    #[okstd::test]
+    fn test_rejects_malformed() {
+        for (symbol, expected, at) in [
+            ("", "an identifier", 0),
+            ("a::", "an identifier", 3),
+            ("<Point::len", ">", 6),
+            ("<Mock as host::>::read", "an identifier", 15),
+            ("id<i32", ">", 6),
+            ("f<>", "an identifier", 2),
+            ("a b", "the end of the symbol", 1),
+        ] {
+            assert_eq!(
+                demangle(symbol),
+                Err(Error::Malformed {
+                    symbol: symbol.to_string(),
+                    expected,
+                    at
+                }),
+                "{}",
+                symbol
+            );
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! The mid-level IR.
+//!
+//! A function body is a graph of basic blocks. Every instruction assigns a fresh local and
+//! no local is assigned twice, so a value is always known by the instruction that made it.
+//! Performing an effect operation, installing a handler and resuming are instructions of
+//! their own, later stages decide how they are dispatched.
+//!
+//! Lowering starts from a [`Tree`], the body of one function with names resolved and types
+//! inferred. A tree holds no spans, editing one function leaves the trees of the others
+//! equal and salsa reuses their lowering.
+
+use std::fmt::Write;
+
+use crate::{compiler::ir::Mangled, parser::ast::Operator, types::Type, Db};
+
+/// A parameter, `let` binding or temporary of a function, indexing [`Body::locals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Local(pub u32);
+
+/// A basic block, indexing [`Body::blocks`]. The entry block is `BlockId(0)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDecl {
+    /// The name in the source, `None` for temporaries.
+    pub name: Option<String>,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Unit,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    /// A function used as a value.
+    Function(Mangled),
+}
+
+impl Eq for Const {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Local(Local),
+    Const(Const),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+    Function(Mangled),
+    /// A parameter or binding holding a function.
+    Local(Local),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    /// `let` binding a value.
+    Assign(Local, Operand),
+    Binary(Local, Operator, Operand, Operand),
+    Call(Local, Callee, Vec<Operand>),
+    /// Performs the operation `op` of `effect`, calling the innermost handler of `effect`.
+    Perform(Local, Mangled, Mangled, Vec<Operand>),
+    /// Reads a field of a struct.
+    Field(Local, Operand, String),
+    /// Continues the handled block from inside a handler, with the operation's result.
+    /// Evaluates to what the rest of the block does.
+    Resume(Local, Operand),
+    /// Installs the impls of a handler until the matching `Unhandle`.
+    Handle(Mangled),
+    Unhandle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Return(Operand),
+    Jump(BlockId),
+    Branch(Operand, BlockId, BlockId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub instrs: Vec<Instr>,
+    pub terminator: Terminator,
+}
+
+/// A lowered function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Body {
+    /// The parameters, the receiver first for methods taking `self`.
+    pub params: Vec<Local>,
+    pub locals: Vec<LocalDecl>,
+    pub blocks: Vec<Block>,
+}
+
+impl Body {
+    pub fn local(&self, local: Local) -> &LocalDecl {
+        &self.locals[local.0 as usize]
+    }
+
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+
+    /// A readable listing of the body, for tests and debugging.
+    pub fn dump(&self, db: &dyn Db) -> String {
+        let mut out = String::new();
+        for (i, local) in self.locals.iter().enumerate() {
+            let _ = write!(out, "_{}: {}", i, local.ty);
+            if let Some(name) = &local.name {
+                let _ = write!(out, " ({})", name);
+            }
+            out.push('\n');
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            let _ = writeln!(out, "bb{}:", i);
+            for instr in block.instrs.iter() {
+                let _ = writeln!(out, "    {}", dump_instr(db, instr));
+            }
+            let terminator = match &block.terminator {
+                Terminator::Return(value) => format!("return {}", dump_operand(db, value)),
+                Terminator::Jump(to) => format!("jump bb{}", to.0),
+                Terminator::Branch(cond, then, otherwise) => format!(
+                    "branch {}, bb{}, bb{}",
+                    dump_operand(db, cond),
+                    then.0,
+                    otherwise.0
+                ),
+            };
+            let _ = writeln!(out, "    {}", terminator);
+        }
+        out
+    }
+}
+
+fn dump_operand(db: &dyn Db, operand: &Operand) -> String {
+    match operand {
+        Operand::Local(local) => format!("_{}", local.0),
+        Operand::Const(Const::Unit) => "()".to_string(),
+        Operand::Const(Const::Bool(b)) => b.to_string(),
+        Operand::Const(Const::Integer(i)) => i.to_string(),
+        Operand::Const(Const::Float(f)) => f.to_string(),
+        Operand::Const(Const::String(s)) => format!("{:?}", s),
+        Operand::Const(Const::Function(name)) => name.mangled(db).clone(),
+    }
+}
+
+fn dump_args(db: &dyn Db, args: &[Operand]) -> String {
+    args.iter()
+        .map(|arg| dump_operand(db, arg))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn dump_instr(db: &dyn Db, instr: &Instr) -> String {
+    match instr {
+        Instr::Assign(dest, value) => format!("_{} = {}", dest.0, dump_operand(db, value)),
+        Instr::Binary(dest, op, lhs, rhs) => format!(
+            "_{} = {} {} {}",
+            dest.0,
+            dump_operand(db, lhs),
+            op,
+            dump_operand(db, rhs)
+        ),
+        Instr::Call(dest, callee, args) => {
+            let callee = match callee {
+                Callee::Function(name) => name.mangled(db).clone(),
+                Callee::Local(local) => format!("_{}", local.0),
+            };
+            format!("_{} = call {}({})", dest.0, callee, dump_args(db, args))
+        }
+        Instr::Perform(dest, effect, op, args) => format!(
+            "_{} = perform {}.{}({})",
+            dest.0,
+            effect.mangled(db),
+            op.mangled(db),
+            dump_args(db, args)
+        ),
+        Instr::Field(dest, base, field) => {
+            format!("_{} = {}.{}", dest.0, dump_operand(db, base), field)
+        }
+        Instr::Resume(dest, value) => format!("_{} = resume {}", dest.0, dump_operand(db, value)),
+        Instr::Handle(handler) => format!("handle {}", handler.mangled(db)),
+        Instr::Unhandle => "unhandle".to_string(),
+    }
+}
+
+/// A function body with names resolved and types inferred, the input of lowering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tree {
+    /// The number of parameters, which come first in `locals`.
+    pub params: usize,
+    /// The parameters and `let` bindings.
+    pub locals: Vec<LocalDecl>,
+    pub ret: Type,
+    pub body: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Const(Const),
+    Local(Local),
+    Call(Callee, Vec<Expr>),
+    /// The operation `op` of `effect`.
+    Perform(Mangled, Mangled, Vec<Expr>),
+    Resume(Box<Expr>),
+    Field(Box<Expr>, String),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Let(Local, Box<Expr>),
+    If(Box<Expr>, Vec<Expr>, Vec<Expr>),
+    Handle(Mangled, Vec<Expr>),
+    /// Something that did not resolve, which was reported by an earlier pass.
+    Error,
+}
+
+impl ExprKind {
+    /// Whether the expression is a statement, evaluating to `()` whatever it contains.
+    pub fn is_statement(&self) -> bool {
+        matches!(self, ExprKind::Let(..) | ExprKind::If(..))
+    }
+}
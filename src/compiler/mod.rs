@@ -2,12 +2,29 @@ use std::collections::BTreeMap;
 
 use okstd::prelude::debug;
 
-use crate::{compiler::errors::Errors, lexer::Location, parser::ast, Db};
+use crate::{
+    analyzer::{
+        modules::{self, module_name},
+        resolve::{DefId, DefKind, Resolution},
+    },
+    parser::{
+        ast::{self, Node, Visibility},
+        span::Spanned,
+    },
+    Db,
+};
 
-use self::text::SourceProgram;
+use self::{
+    mangle::{Demangled, EffectName, TypeName},
+    text::SourceProgram,
+};
 
 pub mod errors;
 pub mod ir;
+pub mod lower;
+pub mod mangle;
+pub mod mir;
+pub mod monomorphize;
 pub mod text;
 
 #[cfg(test)]
@@ -15,61 +32,204 @@ mod tests;
 
 #[salsa::tracked]
 pub fn compile(db: &dyn Db, src: SourceProgram) -> ir::Program {
-    let mut errors: Vec<lalrpop_util::ErrorRecovery<Location, crate::lexer::Token<'_>, &str>> = vec![];
-    let wrapper = crate::lexer::TripleIterator::new(src.text(db));
-    let t = crate::parser::src::SourceParser::new().parse(&mut errors, db, wrapper);
-    // let mut errors_in_positions: Vec<ir::Position> = vec![];
+    let resolution = crate::analyzer::resolve::resolve(db, src);
+    let path = module_path(db, src);
+    let mut symbol_table = BTreeMap::new();
+    let mut exports = vec![];
+    for (id, def) in resolution.iter() {
+        if def.scope != resolution.module_scope() {
+            continue;
+        }
+        let mangled = match def.kind {
+            DefKind::Function | DefKind::Struct | DefKind::Effect | DefKind::EffectOp => {
+                debug!("symbol {}", def.name);
+                item_symbol(db, resolution, &path, id)
+            }
+            DefKind::Import => {
+                let module = def.parent.map(|module| resolution.def(module).name.clone());
+                let import = ir::Import::new(db, vec![def.name.clone()], module.unwrap_or_default());
+                add_imports(db, import)[0]
+            }
+            _ => continue,
+        };
+        symbol_table.insert(mangled, ir::Symbol::new(db, mangled));
+        let kind = match def.kind {
+            DefKind::Function => ir::ExportKind::Function,
+            DefKind::Struct => ir::ExportKind::Struct,
+            DefKind::Effect => ir::ExportKind::Effect,
+            _ => continue,
+        };
+        if def.vis == Visibility::Public && resolution.origin(id).is_none() {
+            exports.push(ir::Export {
+                symbol: mangled,
+                kind,
+            });
+        }
+    }
+
+    let module = crate::parser::parse(db, src);
+    let effects = effect_defs(db, src);
+    let mut handlers = vec![];
+    for item in module.0.iter() {
+        if let Node::ImplDef(ast::ImplDef(_, effect_name, Some(target), methods)) = &item.1 {
+            let def = resolution.def_at(effect_name.0.offset);
+            let effect = match def {
+                Some(def) => item_symbol(db, resolution, &path, def),
+                None => Demangled::item(&path, &effect_name.1 .0).intern(db),
+            };
+            // an imported effect, such as one of `host`, is declared by its module
+            let imported = def
+                .and_then(|def| resolution.origin(def))
+                .map(|(origin, _)| effect_defs(db, origin));
+            let Some(def) = imported
+                .as_ref()
+                .unwrap_or(&effects)
+                .iter()
+                .find(|def| def.name(db) == effect)
+            else {
+                continue;
+            };
+            let ops = compile_effect(db, *def)
+                .into_iter()
+                .filter_map(|op| {
+                    methods.0.iter().find_map(|method| match &method.1 {
+                        Node::FnDef(ast::FnDef(_, proto, _))
+                            if &proto.1.name.1 .0 == op.mangled(db) =>
+                        {
+                            let method = method_symbol(
+                                db,
+                                resolution,
+                                &path,
+                                effect_name,
+                                Some(target),
+                                proto,
+                            );
+                            Some((op, method.intern(db)))
+                        }
+                        _ => None,
+                    })
+                })
+                .collect();
+            let target = Demangled::item(&path, &target.1 .0).intern(db);
+            handlers.push(ir::Handler::new(db, effect, target, ops));
+        }
+    }
+    for item in module.0.iter() {
+        if let Node::ImplDef(ast::ImplDef(_, name, target, methods)) = &item.1 {
+            for method in methods.0.iter() {
+                if let Node::FnDef(ast::FnDef(kwv, proto, _)) = &method.1 {
+                    if kwv.1 .1 .1 == Visibility::Public {
+                        let method =
+                            method_symbol(db, resolution, &path, name, target.as_ref(), proto);
+                        exports.push(ir::Export {
+                            symbol: method.intern(db),
+                            kind: ir::ExportKind::Method,
+                        });
+                    }
+                }
+            }
+        }
+    }
 
-    if !errors.is_empty() {
-        let spans = text::to_spans(db, src);
-        let _tokens = spans.tokens(db);
+    ir::Program::new(
+        db,
+        lower::functions(db, src),
+        symbol_table,
+        effects,
+        handlers,
+        exports,
+    )
+}
 
-        for _error_range in Into::<Errors>::into(errors) {}
-        // panic!();
+/// The module path the symbols of items defined in `src` start with: the built-in module
+/// it is a file of, else its file name without `.src`, the name `use .. from` imports it
+/// by. Empty for a program without a file name.
+pub fn module_path(db: &dyn Db, src: SourceProgram) -> Vec<String> {
+    match modules::module_of(db, src) {
+        Some(module) => vec![module],
+        None => module_name(&src.url(db))
+            .map(str::to_string)
+            .into_iter()
+            .collect(),
     }
+}
 
-    let modul = t.unwrap();
-    let symbol_table = BTreeMap::new();
-    for toplevel in modul.0 {
-        match toplevel.1 {
-            ast::Node::Visibility(_) => todo!(),
-            ast::Node::BinaryExpression(_) => todo!(),
-            ast::Node::Bool(_) => todo!(),
-            ast::Node::Integer(_) => todo!(),
-            ast::Node::Float(_) => todo!(),
-            ast::Node::Ident(_) => todo!(),
-            ast::Node::Binding(_) => todo!(),
-            ast::Node::FnCall(_) => todo!(),
-            ast::Node::String(_) => todo!(),
-            ast::Node::FnDef(_) => {
-                debug!("Function definition");
-            }
-            ast::Node::EffectDef(_) => todo!(),
-            ast::Node::StructDef(_) => todo!(),
-            ast::Node::UseDef(_usedef) => {}
-            ast::Node::Keyword(_) => todo!(),
-            ast::Node::ImplDef(_) => todo!(),
-            ast::Node::Branch(_) => todo!(),
-            ast::Node::Error => todo!(),
-            ast::Node::FieldAccess(_) => todo!(),
+/// The module path of the module defining `def`, `path` for the items of the module itself.
+fn defining_module(db: &dyn Db, res: &Resolution, path: &[String], def: DefId) -> Vec<String> {
+    match res.origin(def) {
+        Some((origin, _)) => module_path(db, origin),
+        None => path.to_vec(),
+    }
+}
+
+/// The symbol of the function, struct or effect `def` at the top level of a module with
+/// the given `path`. Items brought in from a built-in module are named by where they are
+/// defined, so a program's own `reads` and `host::reads` are apart.
+pub fn item_symbol(db: &dyn Db, res: &Resolution, path: &[String], def: DefId) -> ir::Mangled {
+    let module = defining_module(db, res, path, def);
+    Demangled::item(&module, &res.def(def).name).intern(db)
+}
+
+/// The symbol of a method in `impl name` or `impl name for target`, the effect `name`
+/// named by the module defining it.
+pub fn method_symbol(
+    db: &dyn Db,
+    res: &Resolution,
+    path: &[String],
+    name: &Spanned<ast::Ident>,
+    target: Option<&Spanned<ast::Ident>>,
+    proto: &Spanned<ast::Prototype>,
+) -> Demangled {
+    let method = &proto.1.name.1 .0;
+    match target {
+        Some(target) => {
+            let module = match res.def_at(name.0.offset) {
+                Some(def) => defining_module(db, res, path, def),
+                None => path.to_vec(),
+            };
+            let effect = EffectName::new(&module, &name.1 .0);
+            Demangled::method(path, TypeName::from(&target.1), Some(effect), method)
         }
+        None => Demangled::method(path, TypeName::from(&name.1), None, method),
     }
-    
+}
 
-    ir::Program::new(db, vec![], symbol_table)
+/// The effects declared in `src`.
+fn effect_defs(db: &dyn Db, src: SourceProgram) -> Vec<ir::EffectDef> {
+    let path = module_path(db, src);
+    let mut effects = vec![];
+    for item in crate::parser::parse(db, src).0.iter() {
+        if let Node::EffectDef(ast::EffectDef(_, name, requires, protos)) = &item.1 {
+            effects.push(ir::EffectDef::new(
+                db,
+                Demangled::item(&path, &name.1 .0).intern(db),
+                protos
+                    .0
+                    .iter()
+                    .map(|proto| ir::Mangled::new(db, proto.1.name.1 .0.clone()))
+                    .collect(),
+                requires
+                    .iter()
+                    .map(|effect| ir::InternedEffect::new(db, effect.1 .0.clone()))
+                    .collect(),
+            ));
+        }
+    }
+    effects
 }
 
+/// The operation table of an effect: the slots a handler fills, in dispatch order.
 #[salsa::tracked]
-pub fn compile_effect(_db: &dyn Db, _effect: ir::EffectDef) {}
+pub fn compile_effect(db: &dyn Db, effect: ir::EffectDef) -> Vec<ir::Mangled> {
+    effect.ops(db).clone()
+}
 
 #[salsa::tracked]
 pub fn add_imports(db: &dyn Db, import: ir::Import) -> Vec<ir::Mangled> {
     let mut mangled = vec![];
     for imp in import.imports(db) {
-        mangled.push(ir::Mangled::new(
-            db,
-            format!("{}_{}", import.module(db), imp),
-        ));
+        let module = [import.module(db).clone()];
+        mangled.push(Demangled::item(&module, imp).intern(db));
     }
     mangled
 }
@@ -0,0 +1,582 @@
+//! Monomorphization.
+//!
+//! Backends get bodies without generic parameters: [`monomorphize`] starts from the
+//! functions that have none and follows their calls, replacing every call of a generic
+//! function by a call of its [`Instance`] for the type arguments the call site implies.
+//! An operation performed with type arguments is handled by instances of the generic
+//! methods implementing it, under a slot named with the arguments, `pick<i32>`.
+//!
+//! The body of an instance is [`instantiate`]d once per function and type arguments,
+//! however many callers it has.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+
+use crate::{
+    compiler::{
+        ir,
+        lower::lower_function,
+        mangle::{demangle, Demangled, TypeName},
+        mir::{self, Callee, Const, Instr, Operand},
+    },
+    types::Type,
+    Db,
+};
+
+/// A function applied to type arguments, one for each of its generic parameters.
+#[salsa::interned]
+pub struct Instance {
+    pub function: ir::Function,
+    #[return_ref]
+    pub args: Vec<Type>,
+}
+
+impl Instance {
+    /// The symbol of the function with the type arguments appended, `id<i32>`.
+    pub fn symbol(self, db: &dyn Db) -> ir::Mangled {
+        let name = self.function(db).name(db);
+        if self.args(db).is_empty() {
+            return name;
+        }
+        let args = self.args(db).iter().map(type_name).collect();
+        instantiated(db, name, args)
+    }
+}
+
+fn instantiated(db: &dyn Db, name: ir::Mangled, args: Vec<TypeName>) -> ir::Mangled {
+    let demangled = match demangle(name.mangled(db)) {
+        Ok(demangled) => demangled,
+        // operation slots and functions are named by `Demangled`, this is never reached
+        Err(_) => Demangled::item(&[], name.mangled(db)),
+    };
+    demangled.instantiate(args).intern(db)
+}
+
+/// The name of a type argument in a symbol. A function type is named `fn`, a keyword no
+/// type can be named, applied to its parameters and result.
+fn type_name(ty: &Type) -> TypeName {
+    match ty {
+        Type::Con(name, args) => TypeName {
+            name: name.clone(),
+            args: args.iter().map(type_name).collect(),
+        },
+        Type::Fn(args, ret, _) => TypeName {
+            name: "fn".to_string(),
+            args: args.iter().chain([&**ret]).map(type_name).collect(),
+        },
+        // only concrete types are instantiated with
+        Type::Param(name) => TypeName::new(name),
+        Type::Var(_) => TypeName::new(&ty.to_string()),
+    }
+}
+
+fn concrete(ty: &Type) -> bool {
+    match ty {
+        Type::Con(_, args) => args.iter().all(concrete),
+        Type::Fn(args, ret, _) => args.iter().all(concrete) && concrete(ret),
+        Type::Param(_) | Type::Var(_) => false,
+    }
+}
+
+fn size(ty: &Type) -> usize {
+    match ty {
+        Type::Con(_, args) => 1 + args.iter().map(size).sum::<usize>(),
+        Type::Fn(args, ret, _) => 1 + args.iter().map(size).sum::<usize>() + size(ret),
+        Type::Param(_) | Type::Var(_) => 1,
+    }
+}
+
+/// Whether `ty` is `within` or a part of it.
+fn occurs(ty: &Type, within: &Type) -> bool {
+    ty == within
+        || match within {
+            Type::Con(_, args) => args.iter().any(|arg| occurs(ty, arg)),
+            Type::Fn(args, ret, _) => args.iter().any(|arg| occurs(ty, arg)) || occurs(ty, ret),
+            Type::Param(_) | Type::Var(_) => false,
+        }
+}
+
+fn substitute(ty: &Type, params: &BTreeMap<String, Type>) -> Type {
+    match ty {
+        Type::Param(name) => params.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Con(name, args) => Type::Con(
+            name.clone(),
+            args.iter().map(|arg| substitute(arg, params)).collect(),
+        ),
+        Type::Fn(args, ret, row) => Type::Fn(
+            args.iter().map(|arg| substitute(arg, params)).collect(),
+            Box::new(substitute(ret, params)),
+            row.clone(),
+        ),
+        Type::Var(_) => ty.clone(),
+    }
+}
+
+/// Binds the parameters in `declared` to the parts of `actual` in their place.
+fn bind(declared: &Type, actual: &Type, params: &mut BTreeMap<String, Type>) {
+    match (declared, actual) {
+        (Type::Param(name), _) => {
+            params.entry(name.clone()).or_insert_with(|| actual.clone());
+        }
+        (Type::Con(_, declared), Type::Con(_, actual)) => {
+            for (declared, actual) in declared.iter().zip(actual) {
+                bind(declared, actual, params);
+            }
+        }
+        (Type::Fn(declared, declared_ret, _), Type::Fn(actual, actual_ret, _)) => {
+            for (declared, actual) in declared.iter().zip(actual) {
+                bind(declared, actual, params);
+            }
+            bind(declared_ret, actual_ret, params);
+        }
+        _ => {}
+    }
+}
+
+/// The body of the function of `instance`, its generic parameters replaced by the type
+/// arguments. Calls are left as they are, [`monomorphize`] points them at instances.
+#[salsa::tracked(return_ref)]
+pub fn instantiate(db: &dyn Db, instance: Instance) -> mir::Body {
+    let function = instance.function(db);
+    let mut body = lower_function(db, function).clone();
+    let params: BTreeMap<String, Type> = function
+        .generics(db)
+        .iter()
+        .cloned()
+        .zip(instance.args(db).iter().cloned())
+        .collect();
+    if !params.is_empty() {
+        for local in body.locals.iter_mut() {
+            local.ty = substitute(&local.ty, &params);
+        }
+    }
+    body
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// `function` calls itself through `instance`, with type arguments growing at every
+    /// turn, so there is no end to its instances.
+    PolymorphicRecursion { function: String, instance: String },
+    /// The type arguments `caller` calls the generic `callee` with do not follow from the
+    /// types at the call.
+    Ambiguous { caller: String, callee: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::PolymorphicRecursion { function, instance } => write!(
+                f,
+                "`{}` recurses polymorphically, it would need `{}` and ever larger instances",
+                function, instance
+            ),
+            Error::Ambiguous { caller, callee } => write!(
+                f,
+                "cannot tell the type arguments `{}` calls `{}` with",
+                caller, callee
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A program without generic functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monomorphic {
+    /// The functions without generic parameters and the instances they reach, by symbol.
+    pub functions: Vec<(ir::Mangled, mir::Body)>,
+    /// Slots added to handlers for operations performed with type arguments: the handler,
+    /// the slot and the instance of its method filling it.
+    pub ops: Vec<(ir::Handler, ir::Mangled, ir::Mangled)>,
+    pub errors: Vec<Error>,
+}
+
+struct Monomorphizer<'db> {
+    db: &'db dyn Db,
+    program: ir::Program,
+    functions: HashMap<ir::Mangled, ir::Function>,
+    queue: VecDeque<(Instance, Vec<Instance>)>,
+    out: Monomorphic,
+}
+
+impl<'db> Monomorphizer<'db> {
+    fn error(&mut self, error: Error) {
+        if !self.out.errors.contains(&error) {
+            self.out.errors.push(error);
+        }
+    }
+
+    /// The instance of `callee` the types at a call imply, queued to be monomorphized.
+    /// `declared` are the types of its parameters and result, `actual` the types in
+    /// their place at the call. `None` for functions without generic parameters.
+    fn instance(
+        &mut self,
+        chain: &[Instance],
+        callee: ir::Function,
+        declared: &[&Type],
+        actual: &[Option<Type>],
+    ) -> Option<Instance> {
+        let db = self.db;
+        if callee.generics(db).is_empty() {
+            return None;
+        }
+        let caller = *chain.last()?;
+        let mut params = BTreeMap::new();
+        for (declared, actual) in declared.iter().zip(actual) {
+            if let Some(actual) = actual {
+                bind(declared, actual, &mut params);
+            }
+        }
+        let args: Option<Vec<Type>> = callee
+            .generics(db)
+            .iter()
+            .map(|generic| params.get(generic).filter(|ty| concrete(ty)).cloned())
+            .collect();
+        let Some(args) = args else {
+            self.error(Error::Ambiguous {
+                caller: caller.symbol(db).mangled(db).clone(),
+                callee: callee.name(db).mangled(db).clone(),
+            });
+            return None;
+        };
+        let instance = Instance::new(db, callee, args);
+        // the arguments wrap those of an instance on the way here, and would again and again
+        let weight = |instance: &Instance| instance.args(db).iter().map(size).sum::<usize>();
+        let grows = chain.iter().any(|earlier| {
+            earlier.function(db) == callee
+                && weight(&instance) > weight(earlier)
+                && earlier
+                    .args(db)
+                    .iter()
+                    .zip(instance.args(db))
+                    .all(|(earlier, arg)| occurs(earlier, arg))
+        });
+        if grows {
+            self.error(Error::PolymorphicRecursion {
+                function: callee.name(db).mangled(db).clone(),
+                instance: instance.symbol(db).mangled(db).clone(),
+            });
+            return None;
+        }
+        let mut chain = chain.to_vec();
+        chain.push(instance);
+        self.queue.push_back((instance, chain));
+        Some(instance)
+    }
+
+    /// The parameter and result types of `function`, as declared.
+    fn signature(&self, function: ir::Function) -> (Vec<Type>, Type) {
+        let tree = function.tree(self.db);
+        let params = tree.locals[..tree.params]
+            .iter()
+            .map(|local| local.ty.clone())
+            .collect();
+        (params, tree.ret.clone())
+    }
+
+    fn call(
+        &mut self,
+        chain: &[Instance],
+        body: &mir::Body,
+        dest: mir::Local,
+        name: ir::Mangled,
+        operands: &[Operand],
+    ) -> Option<ir::Mangled> {
+        let callee = *self.functions.get(&name)?;
+        let (params, ret) = self.signature(callee);
+        let declared: Vec<&Type> = params.iter().chain([&ret]).collect();
+        let mut actual: Vec<Option<Type>> = operands
+            .iter()
+            .map(|operand| operand_type(body, operand))
+            .collect();
+        actual.push(Some(body.local(dest).ty.clone()));
+        let instance = self.instance(chain, callee, &declared, &actual)?;
+        Some(instance.symbol(self.db))
+    }
+
+    /// A generic function used as a value, instantiated by the type it is bound to.
+    fn value(
+        &mut self,
+        chain: &[Instance],
+        body: &mir::Body,
+        dest: mir::Local,
+        name: ir::Mangled,
+    ) -> Option<ir::Mangled> {
+        let callee = *self.functions.get(&name)?;
+        let (params, ret) = self.signature(callee);
+        let declared = Type::Fn(params, Box::new(ret), Box::default());
+        let actual = Some(body.local(dest).ty.clone());
+        let instance = self.instance(chain, callee, &[&declared], &[actual])?;
+        Some(instance.symbol(self.db))
+    }
+
+    /// Performing a generic operation goes to the instances of the methods handling it.
+    fn perform(
+        &mut self,
+        chain: &[Instance],
+        body: &mir::Body,
+        dest: mir::Local,
+        effect: ir::Mangled,
+        op: ir::Mangled,
+        operands: &[Operand],
+    ) -> Option<ir::Mangled> {
+        let db = self.db;
+        let mut slot = None;
+        for handler in self.program.handlers(db).clone() {
+            if handler.effect(db) != effect {
+                continue;
+            }
+            let Some((_, method)) = handler.ops(db).iter().find(|(name, _)| *name == op) else {
+                continue;
+            };
+            let Some(&function) = self.functions.get(method) else {
+                continue;
+            };
+            let (params, ret) = self.signature(function);
+            // the receiver is supplied by the handler, not the operation
+            let skip = params.len().saturating_sub(operands.len());
+            let declared: Vec<&Type> = params[skip..].iter().chain([&ret]).collect();
+            let mut actual: Vec<Option<Type>> = operands
+                .iter()
+                .map(|operand| operand_type(body, operand))
+                .collect();
+            actual.push(Some(body.local(dest).ty.clone()));
+            let Some(instance) = self.instance(chain, function, &declared, &actual) else {
+                continue;
+            };
+            let args = instance.args(db).iter().map(type_name).collect();
+            let instantiated = instantiated(db, op, args);
+            let added = (handler, instantiated, instance.symbol(db));
+            if !self.out.ops.contains(&added) {
+                self.out.ops.push(added);
+            }
+            slot = Some(instantiated);
+        }
+        slot
+    }
+
+    fn run(&mut self, instance: Instance, chain: Vec<Instance>) {
+        let mut body = instantiate(self.db, instance).clone();
+        let original = body.clone();
+        for block in body.blocks.iter_mut() {
+            for instr in block.instrs.iter_mut() {
+                match instr {
+                    Instr::Call(dest, Callee::Function(name), operands) => {
+                        if let Some(symbol) = self.call(&chain, &original, *dest, *name, operands) {
+                            *name = symbol;
+                        }
+                    }
+                    Instr::Assign(dest, Operand::Const(Const::Function(name))) => {
+                        if let Some(symbol) = self.value(&chain, &original, *dest, *name) {
+                            *name = symbol;
+                        }
+                    }
+                    Instr::Perform(dest, effect, op, operands) => {
+                        let performed =
+                            self.perform(&chain, &original, *dest, *effect, *op, operands);
+                        if let Some(slot) = performed {
+                            *op = slot;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.out.functions.push((instance.symbol(self.db), body));
+    }
+}
+
+fn operand_type(body: &mir::Body, operand: &Operand) -> Option<Type> {
+    match operand {
+        Operand::Local(local) => Some(body.local(*local).ty.clone()),
+        Operand::Const(Const::Unit) => Some(Type::unit()),
+        Operand::Const(Const::Bool(_)) => Some(Type::bool()),
+        Operand::Const(Const::Integer(_)) => Some(Type::con("i32")),
+        Operand::Const(Const::Float(_)) => Some(Type::con("f64")),
+        Operand::Const(Const::String(_)) => Some(Type::con("string")),
+        Operand::Const(Const::Function(_)) => None,
+    }
+}
+
+/// Instantiates the generic functions of `program` for the type arguments they are
+/// called with, starting from the functions without generic parameters.
+#[salsa::tracked(return_ref)]
+pub fn monomorphize(db: &dyn Db, program: ir::Program) -> Monomorphic {
+    let mut monomorphizer = Monomorphizer {
+        db,
+        program,
+        functions: program
+            .modul(db)
+            .iter()
+            .map(|function| (function.name(db), *function))
+            .collect(),
+        queue: VecDeque::new(),
+        out: Monomorphic {
+            functions: vec![],
+            ops: vec![],
+            errors: vec![],
+        },
+    };
+    for function in program.modul(db).iter() {
+        if function.generics(db).is_empty() {
+            let instance = Instance::new(db, *function, vec![]);
+            monomorphizer.queue.push_back((instance, vec![instance]));
+        }
+    }
+    let mut seen = HashSet::new();
+    while let Some((instance, chain)) = monomorphizer.queue.pop_front() {
+        if seen.insert(instance) {
+            monomorphizer.run(instance, chain);
+        }
+    }
+    monomorphizer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analyzer::db::Database,
+        compiler::{compile, text::SourceProgram},
+    };
+
+    fn program(db: &Database, text: &str) -> ir::Program {
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text.to_string());
+        compile(db, src)
+    }
+
+    fn symbols(db: &Database, monomorphic: &Monomorphic) -> Vec<String> {
+        monomorphic
+            .functions
+            .iter()
+            .map(|(name, _)| name.mangled(db).clone())
+            .collect()
+    }
+
+    #[okstd::test]
+    fn test_instantiates_per_type_arguments() {
+        let db = &Database::default();
+        let program = program(
+            db,
+            r#"fn id<T>(x: T)[] -> T {
+    x
+}
+
+fn twice<T>(x: T)[] -> T {
+    let y = id(x)
+    y
+}
+
+fn main()[] -> i32 {
+    let s = id("a")
+    let n = twice(1)
+    let m = id(n)
+    m
+}"#,
+        );
+        let monomorphic = monomorphize(db, program);
+        assert_eq!(monomorphic.errors, vec![]);
+        assert_eq!(
+            symbols(db, monomorphic),
+            ["main", "id<string>", "twice<i32>", "id<i32>"]
+        );
+
+        let (_, main) = &monomorphic.functions[0];
+        let main = main.dump(db);
+        assert!(main.contains("call id<string>(\"a\")"), "{}", main);
+        assert!(main.contains("call twice<i32>(1)"), "{}", main);
+        let (_, id) = &monomorphic.functions[3];
+        assert!(id.dump(db).starts_with("_0: i32 (x)"), "{}", id.dump(db));
+
+        // `main` and `twice<i32>` share the instance and its body
+        let id = program.modul(db)[0];
+        let instance = Instance::new(db, id, vec![Type::con("i32")]);
+        assert_eq!(instance, Instance::new(db, id, vec![Type::con("i32")]));
+        assert_eq!(instance.symbol(db).mangled(db), "id<i32>");
+        assert_eq!(instantiate(db, instance), &monomorphic.functions[3].1);
+    }
+
+    #[okstd::test]
+    fn test_reports_polymorphic_recursion() {
+        let db = &Database::default();
+        let program = program(
+            db,
+            r#"effect Box: throws {
+    wrap<T>(x: T)[] -> vec<T>
+}
+
+fn grow<T>(x: T)[Box] -> i32 {
+    let v = wrap(x)
+    let n = grow(v)
+    n
+}
+
+fn main()[Box] -> i32 {
+    let n = grow(1)
+    n
+}"#,
+        );
+        let monomorphic = monomorphize(db, program);
+        assert_eq!(
+            monomorphic.errors,
+            vec![Error::PolymorphicRecursion {
+                function: "grow".to_string(),
+                instance: "grow<vec<i32>>".to_string(),
+            }]
+        );
+        assert_eq!(symbols(db, monomorphic), ["main", "grow<i32>"]);
+    }
+
+    #[okstd::test]
+    fn test_instantiates_generic_handlers() {
+        let db = &Database::default();
+        let program = program(
+            db,
+            r#"effect Pick: throws {
+    pick<T>(a: T)[] -> T
+}
+
+struct First {
+}
+
+impl Pick for First {
+    fn pick<T>(self, a: T)[] -> T {
+        resume(a)
+    }
+}
+
+fn main()[] -> i32 {
+    handle {
+        let n = pick(1)
+        n
+    } with First
+}"#,
+        );
+        let monomorphic = monomorphize(db, program);
+        assert_eq!(monomorphic.errors, vec![]);
+        assert_eq!(
+            symbols(db, monomorphic),
+            ["main", "<First as Pick>::pick<i32>"]
+        );
+        let ops: Vec<(String, String)> = monomorphic
+            .ops
+            .iter()
+            .map(|(_, slot, method)| (slot.mangled(db).clone(), method.mangled(db).clone()))
+            .collect();
+        assert_eq!(
+            ops,
+            [(
+                "pick<i32>".to_string(),
+                "<First as Pick>::pick<i32>".to_string()
+            )]
+        );
+        let (_, main) = &monomorphic.functions[0];
+        assert!(
+            main.dump(db).contains("perform Pick.pick<i32>(1)"),
+            "{}",
+            main.dump(db)
+        );
+    }
+}
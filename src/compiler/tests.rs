@@ -23,3 +23,233 @@ struct Innitguv {
     let modul = res.modul(db);
     println!("{:?}", modul);
 }
+
+#[okstd::test]
+fn test_handlers_swap_for_mocks() {
+    let src = r#"effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Host {
+}
+
+struct Mock {
+}
+
+impl Fs for Host {
+    fn read(self, path: string)[] -> string {
+        resume(path)
+    }
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents")
+    }
+}
+
+fn main()[] -> string {
+    handle {
+        read("config")
+    } with Mock
+}"#;
+    let db = &crate::analyzer::db::Database::default();
+    let prog = SourceProgram::new(db, "test".to_string(), src.to_string());
+    let res = super::compile(db, prog);
+
+    let effects = res.effects(db);
+    assert_eq!(effects.len(), 1);
+    let ops: Vec<&String> = compile_effect(db, effects[0])
+        .iter()
+        .map(|op| op.mangled(db))
+        .collect();
+    assert_eq!(ops, ["read"]);
+
+    let handlers: Vec<(String, Vec<String>)> = res
+        .handlers(db)
+        .iter()
+        .map(|handler| {
+            let ops = handler
+                .ops(db)
+                .iter()
+                .map(|(_, method)| method.mangled(db).clone())
+                .collect();
+            (handler.target(db).mangled(db).clone(), ops)
+        })
+        .collect();
+    assert_eq!(
+        handlers,
+        vec![
+            ("Host".to_string(), vec!["<Host as Fs>::read".to_string()]),
+            ("Mock".to_string(), vec!["<Mock as Fs>::read".to_string()]),
+        ]
+    );
+}
+
+#[okstd::test]
+fn test_handler_symbols_keep_targets_apart() {
+    // `a_b` handling `c` and `a` handling `b_c` join to the same characters
+    let src = r#"effect E: async {
+    c()[]
+    b_c()[]
+}
+
+struct a_b {
+}
+
+struct a {
+}
+
+impl E for a_b {
+    fn c(self)[] {
+        resume(0)
+    }
+}
+
+impl E for a {
+    fn b_c(self)[] {
+        resume(0)
+    }
+}"#;
+    let db = &crate::analyzer::db::Database::default();
+    let prog = SourceProgram::new(db, "test".to_string(), src.to_string());
+    let res = super::compile(db, prog);
+
+    let methods: Vec<String> = res
+        .handlers(db)
+        .iter()
+        .flat_map(|handler| handler.ops(db).iter())
+        .map(|(_, method)| method.mangled(db).clone())
+        .collect();
+    assert_eq!(methods, ["<a_b as E>::c", "<a as E>::b_c"]);
+    let functions: Vec<&String> = res
+        .modul(db)
+        .iter()
+        .map(|function| function.name(db).mangled(db))
+        .collect();
+    assert_eq!(functions, ["<a_b as E>::c", "<a as E>::b_c"]);
+}
+
+#[okstd::test]
+fn test_exports_and_import_symbols() {
+    let src = r#"use { exec } from system
+
+pub struct Point {
+    x: i32
+}
+
+impl Point {
+    pub fn len(self)[] -> i32 {
+        let x = 1
+        x
+    }
+    fn scale(self)[] {
+    }
+}
+
+pub fn run()[] {
+    exec()
+}
+
+fn helper()[] {
+}"#;
+    let db = &crate::analyzer::db::Database::default();
+    let prog = SourceProgram::new(db, "test".to_string(), src.to_string());
+    let res = super::compile(db, prog);
+
+    let exports: Vec<(String, ir::ExportKind)> = res
+        .exports(db)
+        .iter()
+        .map(|export| (export.symbol.mangled(db).clone(), export.kind))
+        .collect();
+    assert_eq!(
+        exports,
+        vec![
+            ("Point".to_string(), ir::ExportKind::Struct),
+            ("run".to_string(), ir::ExportKind::Function),
+            ("<Point>::len".to_string(), ir::ExportKind::Method),
+        ]
+    );
+    let len = mangle::demangle(&exports[2].0).unwrap();
+    assert_eq!(len.owner.unwrap().target, mangle::TypeName::new("Point"));
+    assert_eq!(len.name, "len");
+
+    let symbols: Vec<&String> = res
+        .symbols(db)
+        .keys()
+        .map(|name| name.mangled(db))
+        .collect();
+    assert!(
+        symbols.contains(&&"system::exec".to_string()),
+        "{:?}",
+        symbols
+    );
+    let functions: Vec<&String> = res
+        .modul(db)
+        .iter()
+        .map(|function| function.name(db).mangled(db))
+        .collect();
+    assert_eq!(
+        functions,
+        ["run", "helper", "<Point>::len", "<Point>::scale"]
+    );
+}
+
+#[okstd::test]
+fn test_links_units_by_module() {
+    let db = &crate::analyzer::db::Database::default();
+    let util = SourceProgram::new(
+        db,
+        "file:///w/util.src".to_string(),
+        r#"pub fn helper()[] -> i32 {
+    let x = 1
+    x
+}
+
+effect reads: throws {
+    read(path: string)[] -> string
+}"#
+        .to_string(),
+    );
+    let main = SourceProgram::new(
+        db,
+        "file:///w/main.src".to_string(),
+        r#"use { helper } from util
+
+pub fn main()[] -> i32 {
+    helper()
+}"#
+        .to_string(),
+    );
+    let util = super::compile(db, util);
+    let main = super::compile(db, main);
+
+    let exports: Vec<&String> = util
+        .exports(db)
+        .iter()
+        .map(|export| export.symbol.mangled(db))
+        .collect();
+    assert_eq!(exports, ["util::helper"]);
+    let imports: Vec<&String> = main
+        .symbols(db)
+        .keys()
+        .map(|name| name.mangled(db))
+        .filter(|name| name.starts_with("util::"))
+        .collect();
+    assert_eq!(imports, ["util::helper"]);
+    let functions: Vec<&String> = main
+        .modul(db)
+        .iter()
+        .map(|function| function.name(db).mangled(db))
+        .collect();
+    assert_eq!(functions, ["main::main"]);
+
+    // the effect of `util` is not the one of `host` by the same name
+    let effects: Vec<&String> = util
+        .effects(db)
+        .iter()
+        .map(|effect| effect.name(db).mangled(db))
+        .collect();
+    assert_eq!(effects, ["util::reads"]);
+    assert_ne!(effects[0], crate::host::READS);
+}
@@ -0,0 +1,516 @@
+//! The built-in `host` module.
+//!
+//! `use { reads, writes, execs, env } from host` gives a program the effects declared in
+//! `host.src`, `native_fs` and `native_exec` group them for code wanting the whole
+//! filesystem or process API. Nothing handles them by default: the embedder passes the
+//! program the capabilities it grants, each one the handler of an effect scoped to what it
+//! was made with, e.g. [`Reads`] of one directory. A program can only reach what it was
+//! handed, so running it against a temporary directory is a sandbox. A program may handle
+//! them itself too, `impl reads for Fake` mocks the filesystem inside `handle .. with Fake`.
+//!
+//! ```ignore
+//! let dir = Dir::new(root)?;
+//! let interp = Interpreter::new(db, src)
+//!     .with_handler(host::READS, Reads::new(dir.clone()))
+//!     .with_handler(host::WRITES, Writes::new(dir));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use crate::interp::{EffectHandler, Error, Value};
+
+/// The name programs import the module by.
+pub const MODULE: &str = "host";
+/// The declarations of the module.
+pub const SOURCE: &str = include_str!("host.src");
+
+// the symbols of the effects, named by the module like every effect of a module
+pub const READS: &str = "host::reads";
+pub const WRITES: &str = "host::writes";
+pub const EXECS: &str = "host::execs";
+pub const ENV: &str = "host::env";
+
+fn string<'a>(effect: &str, op: &str, args: &'a [Value], index: usize) -> Result<&'a str, Error> {
+    match args.get(index) {
+        Some(Value::String(s)) => Ok(s),
+        _ => Err(Error::Mismatch(format!(
+            "argument {} of `{}.{}` must be a string",
+            index, effect, op
+        ))),
+    }
+}
+
+fn unknown(effect: &str, op: &str) -> Error {
+    Error::Unhandled {
+        effect: effect.to_string(),
+        op: op.to_string(),
+    }
+}
+
+fn failed(op: &str, path: &str, error: io::Error) -> Error {
+    Error::Host(format!("cannot {} `{}`: {}", op, path, error))
+}
+
+/// A directory the paths of a program are relative to and cannot leave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dir {
+    root: PathBuf,
+}
+
+impl Dir {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `path` below the directory. Absolute paths, `..` climbing out of it and symlinks
+    /// that do not lead to a path inside of it, dangling ones included, are refused.
+    pub fn path(&self, path: &str) -> Result<PathBuf, Error> {
+        let denied = || Error::Host(format!("`{}` is outside of the directory", path));
+        let mut resolved = self.root.clone();
+        let mut depth = 0usize;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    depth += 1;
+                }
+                Component::CurDir => continue,
+                Component::ParentDir if depth > 0 => {
+                    resolved.pop();
+                    depth -= 1;
+                    continue;
+                }
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(denied())
+                }
+            }
+            // every component could be a symlink leading elsewhere, the last one too
+            let symlink = resolved
+                .symlink_metadata()
+                .is_ok_and(|meta| meta.file_type().is_symlink());
+            if symlink
+                && !resolved
+                    .canonicalize()
+                    .is_ok_and(|real| real.starts_with(&self.root))
+            {
+                return Err(denied());
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Handles `reads` inside a [`Dir`].
+pub struct Reads {
+    dir: Dir,
+}
+
+impl Reads {
+    pub fn new(dir: Dir) -> Self {
+        Self { dir }
+    }
+}
+
+impl EffectHandler for Reads {
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let path = string(READS, op, &args, 0)?;
+        match op {
+            "read" => std::fs::read_to_string(self.dir.path(path)?)
+                .map(Value::String)
+                .map_err(|error| failed("read", path, error)),
+            "exists" => Ok(Value::Bool(self.dir.path(path)?.exists())),
+            _ => Err(unknown(READS, op)),
+        }
+    }
+}
+
+/// Handles `writes` inside a [`Dir`].
+pub struct Writes {
+    dir: Dir,
+}
+
+impl Writes {
+    pub fn new(dir: Dir) -> Self {
+        Self { dir }
+    }
+}
+
+impl EffectHandler for Writes {
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let path = string(WRITES, op, &args, 0)?;
+        let result = match op {
+            "write" => {
+                let contents = string(WRITES, op, &args, 1)?;
+                std::fs::write(self.dir.path(path)?, contents)
+            }
+            "remove" => std::fs::remove_file(self.dir.path(path)?),
+            _ => return Err(unknown(WRITES, op)),
+        };
+        result
+            .map(|()| Value::Unit)
+            .map_err(|error| failed(op, path, error))
+    }
+}
+
+/// Handles `execs` by running the programs it allows, in a [`Dir`] and with an empty
+/// environment.
+pub struct Execs {
+    dir: Dir,
+    allowed: Vec<String>,
+}
+
+impl Execs {
+    pub fn new(dir: Dir) -> Self {
+        Self {
+            dir,
+            allowed: vec![],
+        }
+    }
+
+    /// Lets the program run `program`, looked up on the `PATH` of the host.
+    pub fn allow(mut self, program: &str) -> Self {
+        self.allowed.push(program.to_string());
+        self
+    }
+}
+
+impl EffectHandler for Execs {
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error> {
+        if op != "exec" {
+            return Err(unknown(EXECS, op));
+        }
+        let program = string(EXECS, op, &args, 0)?;
+        let Some(Value::Strings(arguments)) = args.get(1) else {
+            return Err(Error::Mismatch(format!(
+                "argument 1 of `{}.{}` must be a stringvec",
+                EXECS, op
+            )));
+        };
+        if !self.allowed.iter().any(|allowed| allowed == program) {
+            return Err(Error::Host(format!("running `{}` is not allowed", program)));
+        }
+        let status = Command::new(program)
+            .args(arguments)
+            .current_dir(self.dir.root())
+            .env_clear()
+            .status()
+            .map_err(|error| failed("run", program, error))?;
+        // a process killed by a signal has no code
+        Ok(Value::Integer(status.code().unwrap_or(-1) as i64))
+    }
+}
+
+/// Handles `env` with the variables it was given, not those of the host.
+#[derive(Default)]
+pub struct Env {
+    vars: BTreeMap<String, String>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn var(mut self, name: &str, value: &str) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Passes the variable `name` of the host through, when it is set.
+    pub fn inherit(self, name: &str) -> Self {
+        match std::env::var(name) {
+            Ok(value) => self.var(name, &value),
+            Err(_) => self,
+        }
+    }
+}
+
+impl EffectHandler for Env {
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error> {
+        if op != "getenv" {
+            return Err(unknown(ENV, op));
+        }
+        let name = string(ENV, op, &args, 0)?;
+        self.vars
+            .get(name)
+            .map(|value| Value::String(value.clone()))
+            .ok_or_else(|| Error::Host(format!("`{}` is not set", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analyzer::{db::Database, diagnostics::Diagnostics, resolve},
+        compiler::{self, text::SourceProgram},
+        interp::Interpreter,
+        types::{conformance::conformance, effects::expansions, infer::infer},
+    };
+
+    const PROGRAM: &str = r#"use { reads, writes, execs, env, native_fs } from host
+
+fn save(text: string)[writes] {
+    write("notes.txt", text)
+}
+
+fn load()[reads] -> string {
+    let text = read("notes.txt")
+    text
+}
+
+fn backup()[native_fs] {
+    let text = read("notes.txt")
+    write("backup.txt", text)
+}
+
+fn escape()[reads] -> string {
+    let text = read("../secret")
+    text
+}
+
+fn run(program: string, args: stringvec)[execs] -> i32 {
+    let code = exec(program, args)
+    code
+}
+
+fn home()[env] -> string {
+    let home = getenv("HOME")
+    home
+}"#;
+
+    /// A fresh directory for one test, removed when dropped.
+    struct Sandbox(PathBuf);
+
+    impl Sandbox {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("srclang-host-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn dir(&self) -> Dir {
+            Dir::new(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for Sandbox {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn source(db: &Database) -> SourceProgram {
+        SourceProgram::new(db, "inmemory://test".to_string(), PROGRAM.to_string())
+    }
+
+    #[okstd::test]
+    fn test_imports_host_effects() {
+        let db = &Database::default();
+        let src = source(db);
+        let mut diagnostics = resolve::resolve::accumulated::<Diagnostics>(db, src);
+        diagnostics.extend(infer::accumulated::<Diagnostics>(db, src));
+        diagnostics.extend(conformance::accumulated::<Diagnostics>(db, src));
+        assert_eq!(diagnostics, vec![]);
+
+        let res = resolve::resolve(db, src);
+        let (reads, _) = res.iter().find(|(_, def)| def.name == "reads").unwrap();
+        let expanded: Vec<_> = expansions(db, src)[&reads].iter().cloned().collect();
+        assert_eq!(expanded, ["reads", "throws"]);
+
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"use { sockets } from host
+
+fn main()[] {
+    let text = read("x")
+}"#
+            .to_string(),
+        );
+        let messages: Vec<_> = resolve::resolve::accumulated::<Diagnostics>(db, src)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "cannot find `sockets` in module `host`",
+                "cannot find value `read` in this scope"
+            ]
+        );
+    }
+
+    #[okstd::test]
+    fn test_runs_in_a_sandbox() {
+        let db = &Database::default();
+        let sandbox = Sandbox::new("fs");
+        let mut interp = Interpreter::new(db, source(db))
+            .with_handler(READS, Reads::new(sandbox.dir()))
+            .with_handler(WRITES, Writes::new(sandbox.dir()));
+
+        let text = Value::String("remember".to_string());
+        assert_eq!(interp.call("save", vec![text.clone()]), Ok(Value::Unit));
+        assert_eq!(interp.call("load", vec![]), Ok(text));
+        assert!(sandbox.0.join("notes.txt").exists());
+        // the operations of a group go to the handlers of the effects it groups
+        assert_eq!(interp.call("backup", vec![]), Ok(Value::Unit));
+        assert!(sandbox.0.join("backup.txt").exists());
+        assert!(matches!(interp.call("escape", vec![]), Err(Error::Host(_))));
+
+        // only the capabilities handed out are there
+        assert_eq!(
+            interp.call("home", vec![]),
+            Err(Error::Unhandled {
+                effect: ENV.to_string(),
+                op: "getenv".to_string()
+            })
+        );
+    }
+
+    #[okstd::test]
+    fn test_exec_and_env_are_scoped() {
+        let db = &Database::default();
+        let sandbox = Sandbox::new("exec");
+        let mut interp = Interpreter::new(db, source(db))
+            .with_handler(EXECS, Execs::new(sandbox.dir()).allow("true").allow("test"))
+            .with_handler(ENV, Env::new().var("HOME", "/sandbox"));
+
+        let run = |interp: &mut Interpreter, program: &str, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            interp.call(
+                "run",
+                vec![Value::String(program.to_string()), Value::Strings(args)],
+            )
+        };
+        assert_eq!(run(&mut interp, "true", &[]), Ok(Value::Integer(0)));
+        assert_eq!(
+            run(&mut interp, "test", &["a", "=", "a"]),
+            Ok(Value::Integer(0))
+        );
+        assert_eq!(
+            run(&mut interp, "test", &["a b", "=", "a"]),
+            Ok(Value::Integer(1))
+        );
+        assert_eq!(
+            run(&mut interp, "false", &[]),
+            Err(Error::Host("running `false` is not allowed".to_string()))
+        );
+        assert_eq!(
+            interp.call("home", vec![]),
+            Ok(Value::String("/sandbox".to_string()))
+        );
+    }
+
+    #[okstd::test]
+    fn test_handlers_mock_host_effects() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"use { reads } from host
+
+struct Fake {
+}
+
+impl reads for Fake {
+    fn read(self, path: string)[] -> string {
+        resume("fake " + path)
+    }
+    fn exists(self, path: string)[] -> string {
+        resume(path)
+    }
+}
+
+fn load()[reads] -> string {
+    let text = read("notes.txt")
+    text
+}
+
+fn mocked()[throws] -> string {
+    handle {
+        load()
+    } with Fake
+}"#
+            .to_string(),
+        );
+        let mut diagnostics = resolve::resolve::accumulated::<Diagnostics>(db, src);
+        diagnostics.extend(infer::accumulated::<Diagnostics>(db, src));
+        assert_eq!(diagnostics, vec![]);
+        // checked against the prototypes in `host`, related to the `use`
+        let mismatches = conformance::accumulated::<Diagnostics>(db, src);
+        let messages: Vec<&str> = mismatches
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            ["mismatched types: expected `bool`, found `string`"]
+        );
+        let (related, _) = &mismatches[0].related[0];
+        let text = src.text(db);
+        assert_eq!(&text[related.start.offset..related.end.offset], "reads");
+        assert!(related.end.offset < text.find("struct").unwrap());
+
+        // the handler is for the effect of `host`, whatever a program calls its own effects
+        let handlers = compiler::compile(db, src).handlers(db);
+        assert_eq!(handlers[0].effect(db).mangled(db), READS);
+        assert_eq!(
+            handlers[0].ops(db)[0].1.mangled(db),
+            "<Fake as host::reads>::read"
+        );
+
+        let mut interp = Interpreter::new(db, src);
+        assert_eq!(
+            interp.call("mocked", vec![]),
+            Ok(Value::String("fake notes.txt".to_string()))
+        );
+    }
+
+    #[okstd::test]
+    fn test_dir_stays_inside() {
+        let sandbox = Sandbox::new("dir");
+        let dir = sandbox.dir();
+        assert_eq!(dir.path("a/../b.txt"), Ok(dir.root().join("b.txt")));
+        assert!(dir.path("../b.txt").is_err());
+        assert!(dir.path("a/../../b.txt").is_err());
+        assert!(dir.path("/etc/passwd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[okstd::test]
+    fn test_dir_refuses_outward_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let sandbox = Sandbox::new("symlinks");
+        let outside = Sandbox::new("symlinks-outside");
+        std::fs::create_dir(sandbox.0.join("sub")).unwrap();
+        symlink(sandbox.0.join("sub"), sandbox.0.join("inward")).unwrap();
+        symlink(&outside.0, sandbox.0.join("outward")).unwrap();
+        symlink(outside.0.join("missing"), sandbox.0.join("sub/dangling")).unwrap();
+        let dir = sandbox.dir();
+        assert_eq!(
+            dir.path("inward/new.txt"),
+            Ok(dir.root().join("inward/new.txt"))
+        );
+        assert!(dir.path("outward").is_err());
+        assert!(dir.path("outward/new.txt").is_err());
+        // writing through it would create the file outside
+        assert!(dir.path("sub/dangling").is_err());
+        assert!(dir.path("inward/dangling").is_err());
+        assert_eq!(dir.path("missing/../sub"), Ok(dir.root().join("sub")));
+        assert!(dir.path("missing/../outward").is_err());
+    }
+}
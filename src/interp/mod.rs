@@ -0,0 +1,587 @@
+//! A tree-walking interpreter.
+//!
+//! Runs the [`mir::Tree`] of each function, so programs run as soon as they resolve and
+//! type check, without a backend. An operation goes to the innermost `handle` block
+//! handling its effect. When no block in the program does, it goes to the [`EffectHandler`]
+//! registered for the effect from Rust, which is how programs do I/O.
+//!
+//! Handlers in the program are one-shot and tail-resumptive: `resume(v)` ends the method
+//! and makes `v` the result of the operation. A method returning without resuming stops
+//! the program with [`Error::Unresumed`], as it does in the VM and in wasm; the analysis
+//! reports such methods before they run.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+use crate::{
+    compiler::{
+        self,
+        ir::{self, Mangled},
+        mir::{Callee, Const, Expr, ExprKind, Local, Tree},
+        text::SourceProgram,
+    },
+    parser::ast::Operator,
+    Db,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    /// A `stringvec`, only the host makes one.
+    Strings(Vec<String>),
+    /// A struct by name, with its fields.
+    Struct(String, BTreeMap<String, Value>),
+    Function(String),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(fl) => write!(f, "{}", fl),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Strings(strings) => write!(f, "[{}]", strings.join(", ")),
+            Value::Struct(name, fields) => {
+                write!(f, "{} {{", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}: {}", sep, field, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Function(name) => write!(f, "fn {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownFunction(String),
+    /// An operation no `handle` block and no [`EffectHandler`] handles.
+    Unhandled {
+        effect: String,
+        op: String,
+    },
+    /// Arguments a function, operation or operator cannot take.
+    Mismatch(String),
+    NoField {
+        value: Value,
+        field: String,
+    },
+    /// `resume` outside of a method handling an operation.
+    NotResumable,
+    /// A handler method returned without resuming.
+    Unresumed(String),
+    /// Code that did not resolve, see the diagnostics of `src`.
+    Unresolved,
+    /// An operation the host refused or failed to carry out.
+    Host(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownFunction(name) => write!(f, "no function `{}`", name),
+            Error::Unhandled { effect, op } => {
+                write!(
+                    f,
+                    "operation `{}` of effect `{}` is not handled",
+                    op, effect
+                )
+            }
+            Error::Mismatch(message) => write!(f, "{}", message),
+            Error::NoField { value, field } => write!(f, "no field `{}` on `{}`", field, value),
+            Error::NotResumable => write!(f, "`resume` outside of a handler"),
+            Error::Unresumed(method) => write!(f, "handler `{}` returned without resuming", method),
+            Error::Unresolved => write!(f, "cannot run code with errors"),
+            Error::Host(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Handles the operations of one effect from Rust.
+pub trait EffectHandler {
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error>;
+}
+
+impl<F> EffectHandler for F
+where
+    F: FnMut(&str, Vec<Value>) -> Result<Value, Error>,
+{
+    fn perform(&mut self, op: &str, args: Vec<Value>) -> Result<Value, Error> {
+        self(op, args)
+    }
+}
+
+/// Why evaluation stopped before reaching the end of an expression.
+enum Unwind {
+    Error(Error),
+    /// `resume` was called, finishing the method with the result of the operation.
+    Resume(Value),
+}
+
+impl From<Error> for Unwind {
+    fn from(error: Error) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type Eval<T> = Result<T, Unwind>;
+
+pub struct Interpreter<'db> {
+    db: &'db dyn Db,
+    functions: HashMap<String, ir::Function>,
+    handlers: Vec<ir::Handler>,
+    host: HashMap<String, Box<dyn EffectHandler + 'db>>,
+    /// The handlers of the enclosing `handle` blocks, innermost last.
+    handling: Vec<Mangled>,
+}
+
+impl<'db> Interpreter<'db> {
+    pub fn new(db: &'db dyn Db, src: SourceProgram) -> Self {
+        let program = compiler::compile(db, src);
+        Self {
+            db,
+            functions: program
+                .modul(db)
+                .iter()
+                .map(|function| (function.name(db).mangled(db).clone(), *function))
+                .collect(),
+            handlers: program.handlers(db).clone(),
+            host: HashMap::new(),
+            handling: vec![],
+        }
+    }
+
+    /// Handles the operations of `effect` the program leaves unhandled with `handler`.
+    pub fn with_handler(mut self, effect: &str, handler: impl EffectHandler + 'db) -> Self {
+        self.host.insert(effect.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Calls the function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        match self.function(name, args) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            // `resume` only unwinds as far as the method it belongs to
+            Err(Unwind::Resume(_)) => Err(Error::NotResumable),
+        }
+    }
+
+    fn function(&mut self, name: &str, args: Vec<Value>) -> Eval<Value> {
+        let function = *self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::UnknownFunction(name.to_string()))?;
+        let tree = function.tree(self.db);
+        if args.len() != tree.params {
+            return Err(Error::Mismatch(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                tree.params,
+                args.len()
+            ))
+            .into());
+        }
+        let mut frame = Frame {
+            locals: vec![Value::Unit; tree.locals.len()],
+        };
+        for (i, arg) in args.into_iter().enumerate() {
+            frame.locals[i] = arg;
+        }
+        let value = self.block(&mut frame, &tree.body)?;
+        Ok(returned(tree, value))
+    }
+
+    fn block(&mut self, frame: &mut Frame, statements: &[Expr]) -> Eval<Value> {
+        let mut value = Value::Unit;
+        for statement in statements {
+            value = self.expr(frame, statement)?;
+            if statement.kind.is_statement() {
+                value = Value::Unit;
+            }
+        }
+        Ok(value)
+    }
+
+    fn exprs(&mut self, frame: &mut Frame, exprs: &[Expr]) -> Eval<Vec<Value>> {
+        exprs.iter().map(|expr| self.expr(frame, expr)).collect()
+    }
+
+    fn expr(&mut self, frame: &mut Frame, expr: &Expr) -> Eval<Value> {
+        match &expr.kind {
+            ExprKind::Const(value) => Ok(self.constant(value)),
+            ExprKind::Local(local) => Ok(frame.get(*local).clone()),
+            ExprKind::Call(callee, args) => {
+                let args = self.exprs(frame, args)?;
+                let name = match callee {
+                    Callee::Function(name) => name.mangled(self.db).clone(),
+                    Callee::Local(local) => match frame.get(*local) {
+                        Value::Function(name) => name.clone(),
+                        value => {
+                            return Err(
+                                Error::Mismatch(format!("`{}` is not a function", value)).into()
+                            )
+                        }
+                    },
+                };
+                self.function(&name, args)
+            }
+            ExprKind::Perform(effect, op, args) => {
+                let args = self.exprs(frame, args)?;
+                self.perform(*effect, *op, args)
+            }
+            ExprKind::Resume(value) => {
+                let value = self.expr(frame, value)?;
+                Err(Unwind::Resume(value))
+            }
+            ExprKind::Field(base, field) => match self.expr(frame, base)? {
+                Value::Struct(name, mut fields) => match fields.remove(field) {
+                    Some(value) => Ok(value),
+                    None => Err(Error::NoField {
+                        value: Value::Struct(name, fields),
+                        field: field.clone(),
+                    }
+                    .into()),
+                },
+                value => Err(Error::NoField {
+                    value,
+                    field: field.clone(),
+                }
+                .into()),
+            },
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.expr(frame, lhs)?;
+                let rhs = self.expr(frame, rhs)?;
+                Ok(binary(op, lhs, rhs)?)
+            }
+            ExprKind::Let(local, value) => {
+                let value = self.expr(frame, value)?;
+                frame.locals[local.0 as usize] = value;
+                Ok(Value::Unit)
+            }
+            ExprKind::If(cond, then, otherwise) => match self.expr(frame, cond)? {
+                Value::Bool(true) => self.block(frame, then).map(|_| Value::Unit),
+                Value::Bool(false) => self.block(frame, otherwise).map(|_| Value::Unit),
+                value => {
+                    Err(Error::Mismatch(format!("expected a `bool`, found `{}`", value)).into())
+                }
+            },
+            ExprKind::Handle(handler, body) => {
+                let depth = self.handling.len();
+                self.handling.push(*handler);
+                let value = self.block(frame, body);
+                self.handling.truncate(depth);
+                value
+            }
+            ExprKind::Error => Err(Error::Unresolved.into()),
+        }
+    }
+
+    fn constant(&self, value: &Const) -> Value {
+        match value {
+            Const::Unit => Value::Unit,
+            Const::Bool(b) => Value::Bool(*b),
+            Const::Integer(i) => Value::Integer(*i),
+            Const::Float(f) => Value::Float(*f),
+            Const::String(s) => Value::String(s.clone()),
+            Const::Function(name) => Value::Function(name.mangled(self.db).clone()),
+        }
+    }
+
+    fn perform(&mut self, effect: Mangled, op: Mangled, args: Vec<Value>) -> Eval<Value> {
+        let db = self.db;
+        let found = self
+            .handling
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, target)| {
+                let handler = self.handlers.iter().find(|handler| {
+                    handler.target(db) == *target && handler.effect(db) == effect
+                })?;
+                let (_, method) = handler.ops(db).iter().find(|(slot, _)| *slot == op)?;
+                Some((depth, *target, *method))
+            });
+        let Some((depth, target, method)) = found else {
+            let effect = effect.mangled(db);
+            let op = op.mangled(db);
+            return match self.host.get_mut(effect) {
+                Some(handler) => Ok(handler.perform(op, args)?),
+                None => Err(Error::Unhandled {
+                    effect: effect.clone(),
+                    op: op.clone(),
+                }
+                .into()),
+            };
+        };
+        let method = method.mangled(db).clone();
+        let takes_self = self
+            .functions
+            .get(&method)
+            .and_then(|function| function.tree(db).locals.first())
+            .is_some_and(|param| param.name.as_deref() == Some("self"));
+        let mut args = args;
+        if takes_self {
+            // handlers are structs without fields, named by the `with`
+            let receiver = Value::Struct(target.mangled(db).clone(), BTreeMap::new());
+            args.insert(0, receiver);
+        }
+        // the method runs on behalf of the block, outside of it and the handlers within
+        let handling = self.handling.split_off(depth);
+        let result = self.function(&method, args);
+        self.handling.extend(handling);
+        match result {
+            Err(Unwind::Resume(value)) => Ok(value),
+            Ok(_) => Err(Error::Unresumed(method).into()),
+            Err(unwind) => Err(unwind),
+        }
+    }
+}
+
+struct Frame {
+    locals: Vec<Value>,
+}
+
+impl Frame {
+    fn get(&self, local: Local) -> &Value {
+        &self.locals[local.0 as usize]
+    }
+}
+
+/// Without a return type, a function evaluates to `()` whatever its last expression is.
+fn returned(tree: &Tree, value: Value) -> Value {
+    if tree.ret == crate::types::Type::unit() {
+        Value::Unit
+    } else {
+        value
+    }
+}
+
+/// Applies an arithmetic operator, `+` also joins strings.
+pub fn binary(op: &Operator, lhs: Value, rhs: Value) -> Result<Value, Error> {
+    let value = match (op, &lhs, &rhs) {
+        (Operator::Add, Value::Integer(a), Value::Integer(b)) => Value::Integer(a.wrapping_add(*b)),
+        (Operator::Sub, Value::Integer(a), Value::Integer(b)) => Value::Integer(a.wrapping_sub(*b)),
+        (Operator::Mul, Value::Integer(a), Value::Integer(b)) => Value::Integer(a.wrapping_mul(*b)),
+        (Operator::Div, Value::Integer(_), Value::Integer(0)) => {
+            return Err(Error::Mismatch("attempt to divide by zero".to_string()))
+        }
+        (Operator::Div, Value::Integer(a), Value::Integer(b)) => Value::Integer(a.wrapping_div(*b)),
+        (Operator::Add, Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+        (Operator::Sub, Value::Float(a), Value::Float(b)) => Value::Float(a - b),
+        (Operator::Mul, Value::Float(a), Value::Float(b)) => Value::Float(a * b),
+        (Operator::Div, Value::Float(a), Value::Float(b)) => Value::Float(a / b),
+        (Operator::Add, Value::String(a), Value::String(b)) => Value::String(format!("{}{}", a, b)),
+        _ => {
+            return Err(Error::Mismatch(format!(
+                "cannot apply `{}` to `{}` and `{}`",
+                op, lhs, rhs
+            )))
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    fn source(db: &Database, text: &str) -> SourceProgram {
+        SourceProgram::new(db, "inmemory://test".to_string(), text.to_string())
+    }
+
+    /// A `Console` handler writing to a buffer the test can read.
+    fn console(output: Rc<RefCell<Vec<String>>>) -> impl EffectHandler {
+        move |op: &str, args: Vec<Value>| match op {
+            "print" => {
+                let line: Vec<String> = args.iter().map(Value::to_string).collect();
+                output.borrow_mut().push(line.join(" "));
+                Ok(Value::Unit)
+            }
+            op => Err(Error::Unhandled {
+                effect: "Console".to_string(),
+                op: op.to_string(),
+            }),
+        }
+    }
+
+    #[okstd::test]
+    fn test_runs_calls_bindings_and_branches() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"effect Console: async {
+    print(line: string)[] -> unit
+}
+
+fn add(a: i32, b: i32)[] -> i32 {
+    let c = a + b
+    c
+}
+
+fn greet(name: string)[Console] {
+    let line = "hello, " + name
+    print(line)
+}
+
+fn main(loud: bool)[Console] -> i32 {
+    greet("world")
+    let n = add(40, 2)
+    if loud {
+        print("loud")
+    } else {
+        print("quiet")
+    }
+    n
+}"#,
+        );
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut interp = Interpreter::new(db, src).with_handler("Console", console(output.clone()));
+        assert_eq!(
+            interp.call("main", vec![Value::Bool(false)]),
+            Ok(Value::Integer(42))
+        );
+        assert_eq!(*output.borrow(), ["hello, world", "quiet"]);
+    }
+
+    #[okstd::test]
+    fn test_runs_methods_and_fields() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"struct Point {
+    x: i32
+    y: i32
+}
+
+impl Point {
+    fn sum(self, other: Point)[] -> i32 {
+        let x = get_x(other)
+        let y = get_y(other)
+        let s = x + y
+        s
+    }
+}
+
+fn get_x(p: Point)[] -> i32 {
+    p.x
+}
+
+fn get_y(p: Point)[] -> i32 {
+    p.y
+}
+
+fn main(p: Point)[] -> i32 {
+    p.sum(p)
+}"#,
+        );
+        let point = Value::Struct(
+            "Point".to_string(),
+            [
+                ("x".to_string(), Value::Integer(1)),
+                ("y".to_string(), Value::Integer(2)),
+            ]
+            .into(),
+        );
+        let mut interp = Interpreter::new(db, src);
+        assert_eq!(interp.call("main", vec![point]), Ok(Value::Integer(3)));
+        assert_eq!(
+            interp.call("get_x", vec![Value::Integer(1)]),
+            Err(Error::NoField {
+                value: Value::Integer(1),
+                field: "x".to_string()
+            })
+        );
+    }
+
+    #[okstd::test]
+    fn test_handlers_resume_or_stop() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Mock {
+}
+
+struct Missing {
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents of " + path)
+    }
+}
+
+impl Fs for Missing {
+    fn read(self, path: string)[] -> string {
+        path
+    }
+}
+
+fn load()[Fs] -> string {
+    let contents = read("config")
+    contents
+}
+
+fn mocked()[] -> string {
+    handle {
+        load()
+    } with Mock
+}
+
+fn missing()[] -> string {
+    handle {
+        load()
+    } with Missing
+}
+
+fn host()[Fs] -> string {
+    load()
+}"#,
+        );
+        let mut interp = Interpreter::new(db, src).with_handler(
+            "Fs",
+            |op: &str, args: Vec<Value>| -> Result<Value, Error> {
+                assert_eq!(op, "read");
+                Ok(Value::String(format!("host {}", args[0])))
+            },
+        );
+        assert_eq!(
+            interp.call("mocked", vec![]),
+            Ok(Value::String("contents of config".to_string()))
+        );
+        assert_eq!(
+            interp.call("missing", vec![]),
+            Err(Error::Unresumed("<Missing as Fs>::read".to_string()))
+        );
+        assert_eq!(
+            interp.call("host", vec![]),
+            Ok(Value::String("host config".to_string()))
+        );
+        assert_eq!(
+            Interpreter::new(db, src).call("host", vec![]),
+            Err(Error::Unhandled {
+                effect: "Fs".to_string(),
+                op: "read".to_string()
+            })
+        );
+    }
+}
@@ -23,6 +23,21 @@ pub enum LexicalError {
     InvalidStateEmission(State),
 }
 
+impl Display for LexicalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexicalError::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            LexicalError::UnterminatedString => write!(f, "unterminated string"),
+            LexicalError::InvalidNumberFormat => write!(f, "invalid number"),
+            LexicalError::InvalidVariableFormat => write!(f, "invalid variable"),
+            LexicalError::UnexpectedEndOfInput => write!(f, "unexpected end of input"),
+            LexicalError::InvalidStateEmission(state) => {
+                write!(f, "unexpected input in lexer state {:?}", state)
+            }
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, LexicalError>;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -126,6 +141,8 @@ pub enum Word<'input> {
     Self_,
     Pub,
     Priv,
+    Handle,
+    With,
     Ident(&'input str),
     FnIdent(&'input str),
     Any(&'input str),
@@ -165,6 +182,8 @@ impl<'input> Word<'input> {
             Word::Self_ => "self".chars(),
             Word::Pub => "pub".chars(),
             Word::Priv => "priv".chars(),
+            Word::Handle => "handle".chars(),
+            Word::With => "with".chars(),
         }
     }
 }
@@ -324,6 +343,8 @@ pub struct Lexer<'input> {
     buffer: String,
     peekable: Peekable<Chars<'input>>,
     last_char: Option<char>,
+    /// The error that ended the token stream, if one did.
+    error: Option<(LexicalError, Location)>,
 }
 
 impl<'input> Lexer<'input> {
@@ -337,8 +358,14 @@ impl<'input> Lexer<'input> {
             buffer: String::new(),
             peekable: input.chars().peekable(),
             last_char: None,
+            error: None,
         }
     }
+
+    /// The error the lexer stopped at and where, `None` if it read the whole input.
+    pub fn error(&self) -> Option<&(LexicalError, Location)> {
+        self.error.as_ref()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -410,10 +437,11 @@ impl<'input> Lexer<'input> {
     }
 
     fn advance(&mut self, c: char) -> bool {
-        if self.pos + 1 > self.input.len() {
+        // offsets are in bytes, the input is sliced with them
+        if self.pos + c.len_utf8() > self.input.len() {
             return false;
         }
-        self.pos += 1;
+        self.pos += c.len_utf8();
         self.last_char = Some(c);
         if c == '\n' {
             self.line += 1;
@@ -527,6 +555,8 @@ impl<'input> Lexer<'input> {
                     "self" => Word::Self_,
                     "pub" => Word::Pub,
                     "priv" => Word::Priv,
+                    "handle" => Word::Handle,
+                    "with" => Word::With,
                     _ => Word::Ident(word),
                 };
                 Ok(Token::Word(word))
@@ -860,7 +890,10 @@ impl<'input> Iterator for Lexer<'input> {
         }
 
         if self.state == State::Program || self.state == State::Any {
-            self.skip_ws().unwrap();
+            if let Err(error) = self.skip_ws() {
+                self.error = Some((error, (self.pos, self.line, self.col).into()));
+                return None;
+            }
         }
         let res = match self.state {
             State::Op => self.consume_op(),
@@ -889,7 +922,10 @@ impl<'input> Iterator for Lexer<'input> {
                 }
                 Some(token)
             }
-            _ => None,
+            Err(error) => {
+                self.error = Some((error, (self.pos, self.line, self.col).into()));
+                None
+            }
         }
         // Removed the panic! as it's now unreachable.
     }
@@ -951,6 +987,11 @@ impl<'input> TripleIterator<'input> {
     pub fn new(input: &'input str) -> Self {
         TripleIterator(Lexer::new(input, 0))
     }
+
+    /// See [`Lexer::error`].
+    pub fn error(&self) -> Option<&(LexicalError, Location)> {
+        self.0.error()
+    }
 }
 
 impl From<(usize, usize, usize)> for Location {
@@ -10,12 +10,20 @@ pub mod analyzer;
 pub mod ast;
 /// `compiler` contains the compiler for the src-lang.
 pub mod compiler;
+/// `host` is the built-in module giving programs the filesystem, processes and environment.
+pub mod host;
+/// `interp` runs src programs without compiling them, effects can be handled from Rust.
+pub mod interp;
 /// `lexer` contains the intermediate representation for the src-lang.
 pub mod lexer;
 /// `ops` contains the operations tree traversal operations for the src-lang.
 pub mod ops;
 /// `parser` contains the parser for the src-lang, which is written in LALRPOP.
 pub mod parser;
+/// `stdlib` is the standard library, src files loaded as the built-in module `std`.
+pub mod stdlib;
+/// `types` contains the type representation and type inference for the src-lang.
+pub mod types;
 
 use compiler::text;
 
@@ -26,11 +34,18 @@ use crate::compiler::ir;
 /// The default convention is to implement this at the crate level.
 pub struct Jar(
     parser::span::ByteOrLineColOrCoordInterned,
-    parser::span::SourceMap,
+    parser::parse,
     analyzer::SyntaxTree,
     compiler::compile,
     compiler::compile_effect,
     compiler::add_imports,
+    compiler::lower::methods,
+    compiler::lower::functions,
+    compiler::lower::define,
+    compiler::lower::lower_function,
+    compiler::monomorphize::Instance,
+    compiler::monomorphize::instantiate,
+    compiler::monomorphize::monomorphize,
     text::to_spans,
     text::calculate_line_lengths,
     text::Span,
@@ -44,9 +59,23 @@ pub struct Jar(
     ir::InternedEffect,
     ir::Symbol,
     ir::EffectDef,
+    ir::Handler,
     ir::Import,
     ir::Mangled,
     analyzer::get_symbol,
+    analyzer::index::span_index,
+    analyzer::modules::ModuleName,
+    analyzer::modules::builtin,
+    analyzer::resolve::resolve,
+    analyzer::diagnostics::Diagnostics,
+    analyzer::semantic::semantic_tokens,
+    analyzer::navigation::Workspace,
+    analyzer::navigation::module_files,
+    analyzer::symbols::document_symbols,
+    analyzer::symbols::symbol_index,
+    types::effects::expansions,
+    types::infer::infer,
+    types::conformance::conformance,
     analyzer::add_file,
     analyzer::Url,
     analyzer::span_text,
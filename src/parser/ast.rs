@@ -3,7 +3,7 @@ pub const ANON_FN_NAME: &str = "anonymous";
 
 use super::span::*;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Ident(pub String, pub Option<Vec<Spanned<Ident>>>);
 
 impl Display for Ident {
@@ -12,7 +12,7 @@ impl Display for Ident {
     }
 }
 
-#[derive(PartialEq, Debug, Clone, Default)]
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
 pub enum Visibility {
     #[default]
     Private,
@@ -63,6 +63,8 @@ pub enum Keyword {
     From,
     Where,
     Self_,
+    Handle,
+    With,
 }
 
 trait KeywordVisitor {}
@@ -89,6 +91,8 @@ impl Display for Keyword {
             Keyword::Self_ => "Self",
             Keyword::Public => "pub",
             Keyword::Private => "priv",
+            Keyword::Handle => "handle",
+            Keyword::With => "with",
         };
         write!(f, "{}", kw)
     }
@@ -137,6 +141,7 @@ pub enum Node {
     ImplDef(ImplDef),
     Branch(BranchDef),
     FieldAccess(FieldAccess),
+    Handle(HandleDef),
     Visibility(Visibility),
     Error,
 }
@@ -169,6 +174,7 @@ impl Display for Node {
             Node::ImplDef(def) => write!(f, "{}", def.0),
             Node::Branch(branch) => write!(f, "{}", branch.0),
             Node::FieldAccess(access) => write!(f, "{}.{}", access.0, access.1),
+            Node::Handle(handle) => write!(f, "{}", handle),
             Node::Visibility(vis) => write!(f, "{}", vis),
             Node::Error => write!(f, "Error"),
         }
@@ -219,7 +225,7 @@ pub enum Whitespace {
     Newline,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Operator {
     Add,
     Sub,
@@ -334,6 +340,27 @@ pub struct BranchDef(
     pub Vec<(Spanned<Node>, Block<Spanned<Node>>)>,
 );
 
+/// `handle { .. } with Handler` runs the block with the effects `Handler` has impls for
+/// handled by those impls. An operation performed in the block calls the handler's method,
+/// which continues the block by calling `resume` with the operation's result.
+#[derive(PartialEq, Debug, Clone)]
+pub struct HandleDef(
+    pub Spanned<Keyword>,
+    pub Block<Spanned<Node>>,
+    pub Spanned<Keyword>,
+    pub Spanned<Ident>,
+);
+
+impl Display for HandleDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {{", self.0 .1)?;
+        for expr in self.1 .0.iter() {
+            write!(f, "{}", expr)?;
+        }
+        write!(f, "}} {} {}", self.2 .1, self.3)
+    }
+}
+
 // #[visitor]
 #[derive(PartialEq, Debug, Clone)]
 pub struct FnDef(
@@ -372,6 +399,7 @@ impl Arbitrary for Operator {
 }
 
 impl Eq for Node {}
+impl Eq for Module {}
 
 #[cfg(test)]
 mod test {
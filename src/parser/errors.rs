@@ -1,4 +1,77 @@
-use crate::lexer::Location;
+use lalrpop_util::ParseError;
+
+use crate::{
+    analyzer::diagnostics::Diagnostic,
+    lexer::{Location, Token},
+};
+
+/// `one of `a`, `b``, or the only token the parser would have taken.
+fn expected(expected: &[String]) -> String {
+    // the terminals are the quoted strings of the grammar, `"("`
+    let expected: Vec<String> = expected
+        .iter()
+        .map(|terminal| match terminal.trim_matches('"') {
+            "\\n" => "end of line".to_string(),
+            terminal => format!("`{}`", terminal),
+        })
+        .collect();
+    match expected.len() {
+        1 => expected[0].clone(),
+        _ => format!("one of {}", expected.join(", ")),
+    }
+}
+
+/// What the token at `start..end` of `src` reads as in a message.
+fn found(src: &str, token: &Token<'_>, start: Location, end: Location) -> String {
+    match token {
+        Token::NewLine => "end of line".to_string(),
+        _ => match src.get(start.offset..end.offset) {
+            Some(text) if !text.is_empty() => format!("`{}`", text),
+            _ => format!("`{}`", token),
+        },
+    }
+}
+
+/// The diagnostic for an error the parser stopped at or recovered from.
+pub fn diagnostic(src: &str, error: &ParseError<Location, Token<'_>, &str>) -> Diagnostic {
+    match error {
+        ParseError::InvalidToken { location } => {
+            Diagnostic::error(*location..*location, "invalid token")
+        }
+        ParseError::UnrecognizedEof {
+            location,
+            expected: terminals,
+        } => {
+            let mut message = "unexpected end of file".to_string();
+            if !terminals.is_empty() {
+                message = format!("{}, expected {}", message, expected(terminals));
+            }
+            Diagnostic::error(*location..*location, message)
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected: terminals,
+        } => {
+            let mut message = format!("unexpected {}", found(src, token, *start, *end));
+            if !terminals.is_empty() {
+                message = format!("{}, expected {}", message, expected(terminals));
+            }
+            Diagnostic::error(*start..*end, message)
+        }
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => Diagnostic::error(
+            *start..*end,
+            format!(
+                "unexpected {} after the end of the module",
+                found(src, token, *start, *end)
+            ),
+        ),
+        ParseError::User { error } => {
+            Diagnostic::error(Location::default()..Location::default(), *error)
+        }
+    }
+}
 
 #[allow(unused)]
 pub fn pretty_errors(
@@ -7,3 +7,58 @@ pub mod span;
 
 #[allow(clippy::all)]
 pub mod src;
+
+use lalrpop_util::ParseError;
+
+use crate::{
+    analyzer::diagnostics::{Diagnostic, Diagnostics},
+    compiler::text::SourceProgram,
+    lexer::Token,
+    Db,
+};
+
+/// Parses `src` into a module, recovering from errors where the grammar allows it.
+///
+/// Lexer and parser errors are pushed as [`Diagnostics`]. The lexer ends the token stream at
+/// its first error, the parser errors past that point only say the input stopped short and
+/// are left out. Comments are skipped, they document the code for the tools reading the text.
+#[salsa::tracked(return_ref)]
+pub fn parse(db: &dyn Db, src: SourceProgram) -> ast::Module {
+    let text = src.text(db);
+    let mut recovered = vec![];
+    let mut wrapper = crate::lexer::TripleIterator::new(text);
+    let tokens = wrapper
+        .by_ref()
+        .filter(|(_, token, _)| !matches!(token, Token::Comment(_)));
+    let result = src::SourceParser::new().parse(&mut recovered, db, tokens);
+    let mut errors: Vec<_> = recovered.iter().map(|recovery| &recovery.error).collect();
+    if let Err(error) = &result {
+        errors.push(error);
+    }
+    let lexed = wrapper.error();
+    if let Some((_, location)) = lexed {
+        errors.retain(|error| match error {
+            ParseError::UnrecognizedEof { .. } => false,
+            ParseError::InvalidToken { location: start }
+            | ParseError::UnrecognizedToken {
+                token: (start, _, _),
+                ..
+            }
+            | ParseError::ExtraToken {
+                token: (start, _, _),
+            } => start < location,
+            ParseError::User { .. } => true,
+        });
+    }
+    let mut diagnostics: Vec<Diagnostic> = errors
+        .into_iter()
+        .map(|error| errors::diagnostic(text, error))
+        .collect();
+    if let Some((error, location)) = lexed {
+        diagnostics.push(Diagnostic::error(*location..*location, error.to_string()));
+    }
+    for diagnostic in diagnostics {
+        Diagnostics::push(db, diagnostic);
+    }
+    result.unwrap_or_else(|_| ast::Module(vec![]))
+}
@@ -116,3 +116,53 @@ impl Make for Local {
     }
     assert_snapshot!(format!("{:#?}", t.unwrap()));
 }
+
+#[okstd::test]
+fn test_parse_skips_comments() {
+    use crate::{analyzer::diagnostics::Diagnostics, compiler::text::SourceProgram};
+
+    let input = r#"# Says hello.
+fn hello()[] {
+    let a = 1 # one
+    a
+}"#;
+    let db = analyzer::db::Database::default();
+    let src = SourceProgram::new(&db, "inmemory://test".to_string(), input.to_string());
+    let module = crate::parser::parse(&db, src);
+    assert_eq!(module.0.len(), 1);
+    assert!(crate::parser::parse::accumulated::<Diagnostics>(&db, src).is_empty());
+}
+
+#[okstd::test]
+fn test_parse_recovers_statements() {
+    use crate::{
+        analyzer::diagnostics::Diagnostics,
+        compiler::text::SourceProgram,
+        parser::ast::{FnDef, Node},
+    };
+
+    let input = r#"fn hello()[] {
+    let a = 1
+    hello(a,
+    a
+}
+
+fn world()[] {
+}"#;
+    let db = analyzer::db::Database::default();
+    let src = SourceProgram::new(&db, "inmemory://test".to_string(), input.to_string());
+    let module = crate::parser::parse(&db, src);
+    assert_eq!(module.0.len(), 2);
+    let Node::FnDef(FnDef(_, _, body)) = &module.0[0].1 else {
+        panic!("expected a function, got {:?}", module.0[0].1);
+    };
+    let statements: Vec<_> = body.0.iter().map(|statement| &statement.1).collect();
+    assert!(matches!(
+        statements.as_slice(),
+        [Node::Binding(_), Node::Error, Node::Ident(_)]
+    ));
+    assert_eq!(
+        crate::parser::parse::accumulated::<Diagnostics>(&db, src).len(),
+        1
+    );
+}
@@ -1,10 +1,7 @@
 pub use crate::{lexer::Location, Db};
-use hashbrown::HashMap;
 use okstd::prelude::*;
 use std::{fmt::Display, ops::Range};
 
-use super::ast::Node;
-
 #[derive(PartialEq, Debug, Clone, Eq, PartialOrd)]
 pub struct Spanned<T>(pub Location, pub T, pub Location);
 
@@ -75,11 +72,10 @@ pub enum ByteOrLineColOrCoord {
     Coord(Location),
 }
 
-pub trait HasChildSpans
-where
-    Self: Send + Sync + Sized + Spanning,
-{
-    fn children(&self) -> impl IntoIterator<Item = Self>;
+/// Implemented by `#[node]` types, yields the spans of a node's direct children
+/// in field order.
+pub trait HasChildSpans {
+    fn children(&self) -> impl IntoIterator<Item = Range<Location>>;
 }
 
 pub trait Spanning: Send + Sync + Sized {
@@ -125,14 +121,3 @@ macro_rules! span {
         Spanned($start, $expr, $end)
     };
 }
-
-// SourceMap, is the only way to construct a Spanned<T> object
-// this allows us to create a map of T to Range<Coord>,
-// which allows us to find T given some Coord
-// this optimizes the search for the symbol at a given Coord
-// which is useful for hover and go to definition
-#[salsa::tracked]
-pub struct SourceMap {
-    #[return_ref]
-    tokens: HashMap<Range<Location>, Node>,
-}
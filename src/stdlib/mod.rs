@@ -0,0 +1,51 @@
+//! The standard library, written in src.
+//!
+//! The files are embedded in the crate and loaded as [`SourceProgram`]s of the built-in
+//! module `std` by [`modules::builtin`]. Their public items make up the prelude: every
+//! other module can name them without a `use`, and resolves them to their definitions
+//! here like it would items of its own. `sys.src` groups the effects of the built-in
+//! `host` module into the capabilities programs ask for, `fs` and `host`.
+//!
+//! Only the names of the types are here so far. `Option`, `Result`, `vec`, `map` and
+//! `Future` are structs without fields or methods: the language has no enums to build
+//! them from and the backends no intrinsics to store elements, so programs can write the
+//! types in signatures but cannot make or take apart their values yet.
+//!
+//! [`SourceProgram`]: crate::compiler::text::SourceProgram
+//! [`modules::builtin`]: crate::analyzer::modules::builtin
+
+/// The name programs import the module by.
+pub const MODULE: &str = "std";
+
+/// The files of the module, by name.
+pub const FILES: &[(&str, &str)] = &[
+    ("num.src", include_str!("num.src")),
+    ("string.src", include_str!("string.src")),
+    ("option.src", include_str!("option.src")),
+    ("result.src", include_str!("result.src")),
+    ("vec.src", include_str!("vec.src")),
+    ("map.src", include_str!("map.src")),
+    ("future.src", include_str!("future.src")),
+    ("sys.src", include_str!("sys.src")),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analyzer::{db::Database, diagnostics::Diagnostics, modules, resolve::resolve},
+        types::infer::infer,
+    };
+
+    #[okstd::test]
+    fn test_files_are_clean() {
+        let db = &Database::default();
+        let files = modules::prelude(db);
+        assert_eq!(files.len(), super::FILES.len());
+        for &src in files {
+            let mut diagnostics = resolve::accumulated::<Diagnostics>(db, src);
+            diagnostics.extend(infer::accumulated::<Diagnostics>(db, src));
+            assert_eq!(diagnostics, vec![], "{}", src.url(db));
+            assert!(!resolve(db, src).iter().all(|(_, def)| def.span.is_empty()));
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! Checks `impl Effect for Target` blocks against the prototypes of `Effect`.
+//!
+//! Every operation needs a method with the same arguments and return type. A method may
+//! take `self`, the handler, even when the operation does not, but an operation taking
+//! `self` needs it implemented. The effects of a method are its own, they are performed
+//! by the `handle` block using the handler rather than by the operation, but a method may
+//! not perform the effect it implements: the handler would be asked to handle itself.
+//! Handlers are tail-resumptive, so every path through a method must end in `resume`; no
+//! backend can return from the `handle` block on behalf of a method that does not.
+//! Mismatches are reported on the method, pointing at the prototype it is checked
+//! against, or at the `use` of an effect imported from another module such as `host`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use crate::{
+    analyzer::{
+        diagnostics::{Diagnostic, Diagnostics},
+        resolve::{self, DefId, DefKind, Resolution},
+    },
+    compiler::text::SourceProgram,
+    lexer::Location,
+    parser::{
+        self,
+        ast::{self, FnArg, Node},
+        span::Spanned,
+    },
+    types::{effects, infer, Scheme, Type, Unifier},
+    Db,
+};
+
+/// Where the operations of an effect are declared: the module checked, or the one the
+/// effect is imported from.
+struct Declaration<'db> {
+    module: &'db ast::Module,
+    res: &'db Resolution,
+    inference: &'db infer::Inference,
+    effect: DefId,
+    /// The `use` of an imported effect, the spans of its prototypes are in another file.
+    import: Option<Range<Location>>,
+}
+
+impl<'db> Declaration<'db> {
+    /// `span` of the declaring module, or the import when that is another file.
+    fn at(&self, span: Range<Location>) -> Range<Location> {
+        self.import.clone().unwrap_or(span)
+    }
+
+    /// The prototypes of the effect, read back from the module.
+    fn prototypes(&self) -> Vec<&'db Spanned<ast::Prototype>> {
+        let span = &self.res.def(self.effect).span;
+        self.module
+            .0
+            .iter()
+            .find_map(|item| match &item.1 {
+                Node::EffectDef(ast::EffectDef(_, name, _, protos)) if &name.span() == span => {
+                    Some(protos.0.iter().collect())
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+struct Conformance<'db> {
+    db: &'db dyn Db,
+    module: &'db ast::Module,
+    res: &'db Resolution,
+    expansions: &'db BTreeMap<DefId, BTreeSet<String>>,
+    inference: &'db infer::Inference,
+    unifier: Unifier,
+}
+
+impl<'db> Conformance<'db> {
+    fn report(&self, diagnostic: Diagnostic) {
+        Diagnostics::push(self.db, diagnostic);
+    }
+
+    /// The expanded effects of an effect list, unknown names were reported by the resolver.
+    fn declared(&self, effects: &[Spanned<ast::Ident>]) -> BTreeSet<String> {
+        effects
+            .iter()
+            .filter_map(|effect| self.res.def_at(effect.0.offset))
+            .filter_map(|def| self.expansions.get(&def))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// The type `inference` gave `def` with all of its variables made fresh, so the method
+    /// and the prototype can be unified without touching the inference that produced them.
+    fn fresh_type(&mut self, inference: &infer::Inference, def: DefId) -> Option<Type> {
+        let ty = inference.scheme(def)?.ty.clone();
+        let mut vars = BTreeSet::new();
+        ty.free_vars(&mut vars);
+        let mut rows = BTreeSet::new();
+        ty.free_rows(&mut rows);
+        let scheme = Scheme {
+            vars: vars.into_iter().collect(),
+            rows: rows.into_iter().collect(),
+            ty,
+        };
+        Some(self.unifier.instantiate(&scheme))
+    }
+
+    fn self_first(&self, proto: &ast::Prototype) {
+        for arg in proto.args.iter().skip(1) {
+            if arg.1 == FnArg::Reciever {
+                self.report(Diagnostic::error(
+                    arg.span(),
+                    "`self` must be the first argument",
+                ));
+            }
+        }
+    }
+
+    /// Where the effect `def` of this module is declared.
+    fn declaration(&self, def: DefId) -> Declaration<'db> {
+        match self.res.origin(def) {
+            Some((src, effect)) => Declaration {
+                module: parser::parse(self.db, src),
+                res: resolve::resolve(self.db, src),
+                inference: infer::infer(self.db, src),
+                effect,
+                import: Some(self.res.def(def).span.clone()),
+            },
+            None => Declaration {
+                module: self.module,
+                res: self.res,
+                inference: self.inference,
+                effect: def,
+                import: None,
+            },
+        }
+    }
+
+    fn implementation(
+        &mut self,
+        effect: &Spanned<ast::Ident>,
+        effect_def: DefId,
+        methods: &[Spanned<Node>],
+    ) -> Vec<(DefId, DefId)> {
+        let declaration = self.declaration(effect_def);
+        let ops: Vec<(DefId, &Spanned<ast::Prototype>)> = declaration
+            .prototypes()
+            .into_iter()
+            .filter_map(|proto| Some((declaration.res.def_at(proto.1.name.0.offset)?, proto)))
+            .collect();
+        let mut implemented = vec![];
+        for method in methods {
+            let Node::FnDef(ast::FnDef(_, proto, body)) = &method.1 else {
+                continue;
+            };
+            self.self_first(&proto.1);
+            let name = &proto.1.name;
+            let op = ops
+                .iter()
+                .find(|(_, op_proto)| op_proto.1.name.1 .0 == name.1 .0)
+                .copied();
+            let (Some((op, op_proto)), Some(def)) = (op, self.res.def_at(name.0.offset)) else {
+                self.report(
+                    Diagnostic::error(
+                        name.span(),
+                        format!(
+                            "`{}` is not an operation of effect `{}`",
+                            name.1 .0, effect.1 .0
+                        ),
+                    )
+                    .with_related(
+                        self.res.def(effect_def).span.clone(),
+                        format!("`{}` is declared here", effect.1 .0),
+                    ),
+                );
+                continue;
+            };
+            self.method(proto, def, (op_proto, op), &declaration, effect);
+            self.performs_itself(proto, effect, effect_def);
+            if !body.0.iter().any(|node| self.resumes(node)) {
+                self.report(
+                    Diagnostic::error(
+                        name.span(),
+                        format!("`{}` can return without calling `resume`", name.1 .0),
+                    )
+                    .with_related(
+                        declaration.at(op_proto.span()),
+                        "handlers must resume the operation",
+                    ),
+                );
+            }
+            implemented.push((def, op));
+        }
+        for (op, op_proto) in ops {
+            if !implemented
+                .iter()
+                .any(|(_, implemented)| *implemented == op)
+            {
+                let name = &op_proto.1.name.1 .0;
+                self.report(
+                    Diagnostic::error(
+                        effect.span(),
+                        format!(
+                            "not all operations of `{}` are implemented, missing `{}`",
+                            effect.1 .0, name
+                        ),
+                    )
+                    .with_related(
+                        declaration.at(op_proto.span()),
+                        format!("`{}` is declared here", name),
+                    ),
+                );
+            }
+        }
+        implemented
+    }
+
+    /// Checks one method against the prototype of the operation it implements.
+    fn method(
+        &mut self,
+        proto: &Spanned<ast::Prototype>,
+        def: DefId,
+        (op_proto, op): (&Spanned<ast::Prototype>, DefId),
+        declaration: &Declaration,
+        effect: &Spanned<ast::Ident>,
+    ) {
+        let name = &proto.1.name.1 .0;
+        let declared_in = format!("the operation in `{}` is declared here", effect.1 .0);
+
+        let takes_self =
+            |proto: &ast::Prototype| proto.args.iter().any(|arg| arg.1 == FnArg::Reciever);
+        if takes_self(&op_proto.1) && !takes_self(&proto.1) {
+            self.report(
+                Diagnostic::error(
+                    proto.1.name.span(),
+                    format!("`{}` must take `self`, as the operation does", name),
+                )
+                .with_related(declaration.at(op_proto.span()), declared_in.clone()),
+            );
+        }
+
+        let args: Vec<&Spanned<ast::FieldDef>> = fields(&proto.1);
+        let op_args: Vec<&Spanned<ast::FieldDef>> = fields(&op_proto.1);
+        if args.len() != op_args.len() {
+            self.report(
+                Diagnostic::error(
+                    proto.1.name.span(),
+                    format!(
+                        "`{}` takes {} arguments but the operation takes {}",
+                        name,
+                        args.len(),
+                        op_args.len()
+                    ),
+                )
+                .with_related(declaration.at(op_proto.span()), declared_in.clone()),
+            );
+        }
+
+        if let (Some(Type::Fn(params, ret, _)), Some(Type::Fn(op_params, op_ret, _))) = (
+            self.fresh_type(self.inference, def),
+            self.fresh_type(declaration.inference, op),
+        ) {
+            for (((param, op_param), arg), op_arg) in params
+                .iter()
+                .zip(op_params.iter())
+                .zip(args.iter())
+                .zip(op_args.iter())
+            {
+                let op_span = declaration.at(op_arg.1 .2.span());
+                self.same(op_param, param, arg.1 .2.span(), op_span);
+            }
+            let ret_span = |proto: &Spanned<ast::Prototype>| match &proto.1.ret {
+                Some(ret) => ret.span(),
+                None => proto.1.name.span(),
+            };
+            let op_span = declaration.at(ret_span(op_proto));
+            self.same(&op_ret, &ret, ret_span(proto), op_span);
+        }
+    }
+
+    /// Reports a method whose effects include the effect it implements, directly or through
+    /// an effect extending it.
+    fn performs_itself(
+        &self,
+        proto: &Spanned<ast::Prototype>,
+        effect: &Spanned<ast::Ident>,
+        effect_def: DefId,
+    ) {
+        let implemented = &self.res.def(effect_def).name;
+        if !self.declared(&proto.1.effects).contains(implemented) {
+            return;
+        }
+        let span = match (proto.1.effects.first(), proto.1.effects.last()) {
+            (Some(first), Some(last)) => first.0..last.2,
+            _ => proto.1.name.span(),
+        };
+        self.report(
+            Diagnostic::error(
+                span,
+                format!(
+                    "`{}` performs `{}`, the effect it implements",
+                    proto.1.name.1 .0, effect.1 .0
+                ),
+            )
+            .with_related(
+                effect.span(),
+                format!("`{}` is implemented here", effect.1 .0),
+            ),
+        );
+    }
+
+    /// Whether evaluating `node` calls `resume` on every path, which ends the method there.
+    /// A `resume` within a `handle` block is not counted, it may belong to another handler.
+    fn resumes(&self, node: &Spanned<Node>) -> bool {
+        let block = |block: &ast::Block<Spanned<Node>>| block.0.iter().any(|n| self.resumes(n));
+        match &node.1 {
+            Node::FnCall(ast::FnCall(name, args)) => {
+                let def = self.res.def_at(name.0.offset);
+                def.is_some_and(|def| self.res.def(def).kind == DefKind::Resume)
+                    || args.iter().any(|arg| self.resumes(arg))
+            }
+            Node::Binding(ast::Binding(_, value)) => self.resumes(value),
+            Node::BinaryExpression(bin) => self.resumes(&bin.lhs) || self.resumes(&bin.rhs),
+            Node::FieldAccess(ast::FieldAccess(lhs, rhs)) => self.resumes(lhs) || self.resumes(rhs),
+            Node::Branch(ast::BranchDef(cond, arms)) => {
+                let otherwise = arms.iter().any(|(arm, _)| arm.1 == Node::Bool(false));
+                self.resumes(cond) || (otherwise && arms.iter().all(|(_, body)| block(body)))
+            }
+            _ => false,
+        }
+    }
+
+    fn same(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        span: Range<Location>,
+        prototype: Range<Location>,
+    ) {
+        if self.unifier.unify(expected, found).is_err() {
+            let expected = self.unifier.zonk(expected);
+            let found = self.unifier.zonk(found);
+            self.report(
+                Diagnostic::error(
+                    span,
+                    format!(
+                        "mismatched types: expected `{}`, found `{}`",
+                        expected, found
+                    ),
+                )
+                .with_related(prototype, "expected because of the operation's prototype"),
+            );
+        }
+    }
+}
+
+fn fields(proto: &ast::Prototype) -> Vec<&Spanned<ast::FieldDef>> {
+    proto
+        .args
+        .iter()
+        .filter_map(|arg| match &arg.1 {
+            FnArg::Reciever => None,
+            FnArg::Field(field) => Some(field),
+        })
+        .collect()
+}
+
+/// Checks every `impl Effect for Target` in `src` against its effect, returning the
+/// operation each method implements, defined by the module declaring the effect.
+#[salsa::tracked(return_ref)]
+pub fn conformance(db: &dyn Db, src: SourceProgram) -> BTreeMap<DefId, DefId> {
+    let module = parser::parse(db, src);
+    let mut checker = Conformance {
+        db,
+        module,
+        res: resolve::resolve(db, src),
+        expansions: effects::expansions(db, src),
+        inference: infer::infer(db, src),
+        unifier: Unifier::default(),
+    };
+    let mut implemented = BTreeMap::new();
+    for item in module.0.iter() {
+        match &item.1 {
+            Node::EffectDef(ast::EffectDef(_, _, _, protos)) => {
+                protos
+                    .0
+                    .iter()
+                    .for_each(|proto| checker.self_first(&proto.1));
+            }
+            Node::ImplDef(ast::ImplDef(_, effect, Some(_), methods)) => {
+                let effect_def = checker
+                    .res
+                    .def_at(effect.0.offset)
+                    .filter(|def| checker.res.def(*def).kind == DefKind::Effect);
+                if let Some(effect_def) = effect_def {
+                    implemented.extend(checker.implementation(effect, effect_def, &methods.0));
+                }
+            }
+            _ => {}
+        }
+    }
+    implemented
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    #[okstd::test]
+    fn test_checks_impls_against_effects() {
+        let db = &Database::default();
+        let text = r#"effect Make: async + throws {
+    catch() [throws]
+    await<T>(f: Future<T>) [async, throws] -> T
+    exec(arg0: string, args: stringvec) [Make] -> i32
+    stop(self) []
+    spawn(cmd: string, self) []
+}
+
+struct Local {
+}
+
+impl Make for Local {
+    fn catch(self) [throws] {
+        resume(0)
+    }
+    fn await<T>(f: Future<T>) [async, trhows] -> T {
+        if ready {
+            resume(f)
+        }
+    }
+    fn exec(self, arg0: string, args: vec<string>) [Vm] -> i32 {
+        if ready {
+            resume(0)
+        } else {
+            let code = resume(1)
+        }
+    }
+    fn stop() [Make] {
+        resume(0)
+    }
+    fn extra(self) [] {
+    }
+}"#;
+        let src = SourceProgram::new(db, "inmemory://test".to_string(), text.to_string());
+        let implemented = conformance(db, src);
+
+        let mut earlier = resolve::resolve::accumulated::<Diagnostics>(db, src);
+        earlier.extend(infer::infer::accumulated::<Diagnostics>(db, src));
+        let diagnostics: Vec<Diagnostic> = conformance::accumulated::<Diagnostics>(db, src)
+            .into_iter()
+            .filter(|diag| !earlier.contains(diag))
+            .collect();
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .map(|diag| diag.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`self` must be the first argument",
+                "`await` can return without calling `resume`",
+                "mismatched types: expected `stringvec`, found `vec<string>`",
+                "`stop` must take `self`, as the operation does",
+                "`stop` performs `Make`, the effect it implements",
+                "`extra` is not an operation of effect `Make`",
+                "not all operations of `Make` are implemented, missing `spawn`",
+            ]
+        );
+        // the method is reported, the prototype it is checked against is related
+        let (related, _) = &diagnostics[2].related[0];
+        assert_eq!(&text[related.start.offset..related.end.offset], "stringvec");
+        let reported = &diagnostics[2].span;
+        assert_eq!(
+            &text[reported.start.offset..reported.end.offset],
+            "vec<string>"
+        );
+        let reported = &diagnostics[4].span;
+        assert_eq!(&text[reported.start.offset..reported.end.offset], "Make");
+
+        let res = resolve::resolve(db, src);
+        let catch = res.def_at(text.find("catch(self)").unwrap()).unwrap();
+        let op = res.def_at(text.find("catch()").unwrap()).unwrap();
+        assert_eq!(implemented.get(&catch), Some(&op));
+        assert_eq!(implemented.len(), 4);
+    }
+}
@@ -0,0 +1,169 @@
+//! Effect expansion.
+//!
+//! `effect Make: async + throws` lets a function declaring `[Make]` perform `Make`, `async`
+//! and `throws`. Expanding every effect to the full set it stands for ahead of inference
+//! keeps rows flat sets of names.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    analyzer::{
+        diagnostics::{Diagnostic, Diagnostics},
+        resolve::{self, DefId, DefKind, Resolution},
+    },
+    compiler::text::SourceProgram,
+    parser::{
+        self,
+        ast::{self, Node},
+        span::Spanned,
+    },
+    Db,
+};
+
+struct Expander<'db> {
+    db: &'db dyn Db,
+    res: &'db Resolution,
+    /// The name and the effects each effect is declared in terms of.
+    supers: HashMap<DefId, (&'db Spanned<ast::Ident>, Vec<DefId>)>,
+    expanded: BTreeMap<DefId, BTreeSet<String>>,
+    /// Effects whose expansion is in progress, to catch cycles.
+    visiting: Vec<DefId>,
+}
+
+impl<'db> Expander<'db> {
+    fn expand(&mut self, effect: DefId) -> BTreeSet<String> {
+        if let Some(expanded) = self.expanded.get(&effect) {
+            return expanded.clone();
+        }
+        let mut expanded: BTreeSet<String> = [self.res.def(effect).name.clone()].into();
+        if self.visiting.contains(&effect) {
+            if let Some((name, _)) = self.supers.get(&effect) {
+                Diagnostics::push(
+                    self.db,
+                    Diagnostic::error(
+                        name.span(),
+                        format!("effect `{}` is defined in terms of itself", name.1 .0),
+                    ),
+                );
+            }
+            return expanded;
+        }
+        self.visiting.push(effect);
+        let supers = self
+            .supers
+            .get(&effect)
+            .map(|(_, supers)| supers.clone())
+            .unwrap_or_default();
+        for sup in supers {
+            expanded.extend(self.expand(sup));
+        }
+        self.visiting.pop();
+        self.expanded.insert(effect, expanded.clone());
+        expanded
+    }
+}
+
+/// Every effect visible in `src`, builtins included, expanded to the effects it stands for.
+/// Cyclic definitions are reported and cut where they loop back.
+#[salsa::tracked(return_ref)]
+pub fn expansions(db: &dyn Db, src: SourceProgram) -> BTreeMap<DefId, BTreeSet<String>> {
+    let module = parser::parse(db, src);
+    let res = resolve::resolve(db, src);
+    let uses: HashMap<_, _> = res
+        .refs
+        .iter()
+        .map(|reference| (reference.span.start.offset, reference.def))
+        .collect();
+    let mut supers = HashMap::new();
+    for item in module.0.iter() {
+        if let Node::EffectDef(ast::EffectDef(_, name, effects, _)) = &item.1 {
+            let def = res
+                .iter()
+                .find(|(_, def)| def.kind == DefKind::Effect && def.span == name.span())
+                .map(|(id, _)| id);
+            if let Some(def) = def {
+                let effects = effects
+                    .iter()
+                    .filter_map(|effect| uses.get(&effect.0.offset).copied())
+                    .collect();
+                supers.insert(def, (name, effects));
+            }
+        }
+    }
+    // imported effects were expanded by the module defining them
+    let expanded = res
+        .origins
+        .iter()
+        .filter(|(def, _)| res.def(**def).kind == DefKind::Effect)
+        .filter_map(|(def, (module, origin))| {
+            Some((*def, expansions(db, *module).get(origin)?.clone()))
+        })
+        .collect();
+    let mut expander = Expander {
+        db,
+        res,
+        supers,
+        expanded,
+        visiting: vec![],
+    };
+    for (id, def) in res.iter() {
+        if matches!(def.kind, DefKind::Effect | DefKind::BuiltinEffect) {
+            expander.expand(id);
+        }
+    }
+    expander.expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    fn expansion(db: &Database, src: SourceProgram, name: &str) -> Vec<String> {
+        let res = resolve::resolve(db, src);
+        let (id, _) = res.iter().find(|(_, def)| def.name == name).unwrap();
+        expansions(db, src)[&id].iter().cloned().collect()
+    }
+
+    #[okstd::test]
+    fn test_expands_transitively() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"effect Io: async + throws {
+}
+
+effect Make: Io {
+}"#
+            .to_string(),
+        );
+        assert_eq!(
+            expansion(db, src, "Make"),
+            ["Io", "Make", "async", "throws"]
+        );
+        assert_eq!(expansion(db, src, "async"), ["async"]);
+        assert!(expansions::accumulated::<Diagnostics>(db, src).is_empty());
+    }
+
+    #[okstd::test]
+    fn test_reports_cycles() {
+        let db = &Database::default();
+        let src = SourceProgram::new(
+            db,
+            "inmemory://test".to_string(),
+            r#"effect A: B {
+}
+
+effect B: A + async {
+}"#
+            .to_string(),
+        );
+        assert_eq!(expansion(db, src, "A"), ["A", "B", "async"]);
+        let messages: Vec<String> = expansions::accumulated::<Diagnostics>(db, src)
+            .into_iter()
+            .map(|diag| diag.message)
+            .collect();
+        assert_eq!(messages, ["effect `A` is defined in terms of itself"]);
+    }
+}
@@ -0,0 +1,919 @@
+//! Type inference over the resolved AST.
+//!
+//! Signatures come from the annotations on prototypes and fields. Bodies are inferred with
+//! algorithm W: every expression gets a type, `let` bindings are generalized, and calls,
+//! field accesses and return values are unified against the annotations.
+//!
+//! A function type carries the expanded effects of its prototype. Every call performs the
+//! effects of its callee, and a body performing an effect its prototype does not declare
+//! is an error. Inside `handle .. with Handler` the effects `Handler` implements are
+//! discharged, the effects of its methods are performed instead.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Range,
+};
+
+use crate::{
+    analyzer::{
+        diagnostics::{Diagnostic, Diagnostics},
+        resolve::{self, DefId, DefKind, Resolution},
+    },
+    compiler::text::SourceProgram,
+    lexer::Location,
+    parser::{
+        self,
+        ast::{self, FnArg, Node},
+        span::Spanned,
+    },
+    types::{effects, Row, Scheme, Type, Unifier, UnifyError},
+    Db,
+};
+
+/// Byte range of a span, the key used throughout an [`Inference`].
+pub type SpanKey = (usize, usize);
+
+fn key(span: &Range<Location>) -> SpanKey {
+    (span.start.offset, span.end.offset)
+}
+
+/// The types inferred for a module.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Inference {
+    /// The type of every expression, by its span.
+    pub exprs: BTreeMap<SpanKey, Type>,
+    /// The type of every function, field, parameter and local.
+    pub defs: BTreeMap<DefId, Scheme>,
+    /// Fields and methods found through the type of the value they are accessed on.
+    pub members: BTreeMap<SpanKey, DefId>,
+    /// The effects each function body was found to perform.
+    pub performed: BTreeMap<DefId, BTreeSet<String>>,
+}
+
+impl Inference {
+    pub fn type_of(&self, span: &Range<Location>) -> Option<&Type> {
+        self.exprs.get(&key(span))
+    }
+
+    /// The type of the smallest expression containing `offset`.
+    pub fn type_at(&self, offset: usize) -> Option<&Type> {
+        self.exprs
+            .iter()
+            .filter(|((start, end), _)| *start <= offset && offset <= *end)
+            .min_by_key(|((start, end), _)| end - start)
+            .map(|(_, ty)| ty)
+    }
+
+    pub fn scheme(&self, def: DefId) -> Option<&Scheme> {
+        self.defs.get(&def)
+    }
+}
+
+/// A call and the effects it performs.
+struct Call {
+    callee: Spanned<ast::Ident>,
+    row: Row,
+    /// Effects handled by the `handle` blocks around the call.
+    handled: BTreeSet<String>,
+}
+
+/// What a struct does as the handler of a `handle` block.
+#[derive(Debug, Clone, Default)]
+struct Handles {
+    /// The effects it has impls for.
+    effects: BTreeSet<String>,
+    /// The effects its methods declare.
+    performs: BTreeSet<String>,
+}
+
+struct Checker<'db> {
+    db: &'db dyn Db,
+    res: &'db Resolution,
+    unifier: Unifier,
+    /// Identifier uses, by span.
+    uses: HashMap<SpanKey, DefId>,
+    /// Identifier definitions, by span.
+    names: HashMap<SpanKey, DefId>,
+    schemes: BTreeMap<DefId, Scheme>,
+    exprs: Vec<(SpanKey, Type)>,
+    members: BTreeMap<SpanKey, DefId>,
+    /// Parameters and locals of the function being checked, for generalization.
+    env: Vec<DefId>,
+    expansions: &'db BTreeMap<DefId, BTreeSet<String>>,
+    /// The calls of the function being checked.
+    calls: Vec<Call>,
+    performed: BTreeMap<DefId, BTreeSet<String>>,
+    handlers: HashMap<DefId, Handles>,
+    /// The effects handled at the current point of the body, innermost `handle` last.
+    handled: Vec<BTreeSet<String>>,
+}
+
+impl<'db> Checker<'db> {
+    fn new(
+        db: &'db dyn Db,
+        res: &'db Resolution,
+        expansions: &'db BTreeMap<DefId, BTreeSet<String>>,
+    ) -> Self {
+        Self {
+            db,
+            res,
+            unifier: Unifier::default(),
+            uses: res
+                .refs
+                .iter()
+                .map(|reference| (key(&reference.span), reference.def))
+                .collect(),
+            names: res.iter().map(|(id, def)| (key(&def.span), id)).collect(),
+            schemes: BTreeMap::new(),
+            exprs: vec![],
+            members: BTreeMap::new(),
+            env: vec![],
+            expansions,
+            calls: vec![],
+            performed: BTreeMap::new(),
+            handlers: HashMap::new(),
+            handled: vec![],
+        }
+    }
+
+    fn name(&self, ident: &Spanned<ast::Ident>) -> Option<DefId> {
+        self.names.get(&key(&ident.span())).copied()
+    }
+
+    fn error(&self, span: Range<Location>, message: String) {
+        Diagnostics::push(self.db, Diagnostic::error(span, message));
+    }
+
+    /// Unifies, reporting a failure at `span`.
+    fn expect(&mut self, span: Range<Location>, expected: &Type, found: &Type) {
+        match self.unifier.unify(expected, found) {
+            Ok(()) => {}
+            Err(UnifyError::Mismatch(..)) => {
+                let expected = self.unifier.zonk(expected);
+                let found = self.unifier.zonk(found);
+                self.error(
+                    span,
+                    format!(
+                        "mismatched types: expected `{}`, found `{}`",
+                        expected, found
+                    ),
+                );
+            }
+            Err(UnifyError::Infinite(var, ty)) => self.error(
+                span,
+                format!(
+                    "cannot construct the infinite type `{} = {}`",
+                    Type::Var(var),
+                    ty
+                ),
+            ),
+        }
+    }
+
+    /// The type an annotation names, with the generic parameters in scope mapped by `params`.
+    fn annotation(&mut self, ident: &Spanned<ast::Ident>, params: &[(String, Type)]) -> Type {
+        let name = &ident.1 .0;
+        if let Some((_, ty)) = params.iter().find(|(param, _)| param == name) {
+            return ty.clone();
+        }
+        let args = ident
+            .1
+             .1
+            .iter()
+            .flatten()
+            .map(|arg| self.annotation(arg, params))
+            .collect();
+        let def = self.uses.get(&key(&ident.span())).copied();
+        match def {
+            Some(def) if self.res.def(def).kind == DefKind::TypeParam => Type::Param(name.clone()),
+            Some(_) => Type::Con(name.clone(), args),
+            // already reported by the resolver, a variable keeps it from cascading
+            None => self.unifier.fresh(),
+        }
+    }
+
+    /// The expanded effects of an effect list, unknown names were reported by the resolver.
+    fn row(&self, effects: &[Spanned<ast::Ident>]) -> BTreeSet<String> {
+        effects
+            .iter()
+            .filter_map(|effect| self.uses.get(&key(&effect.span())))
+            .filter_map(|def| self.expansions.get(def))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// The scheme of a prototype, quantified over its generic parameters. A receiver is
+    /// not part of the type, it is supplied by the field access the method is called through.
+    /// `implied` are effects performed on top of the declared ones.
+    fn signature(&mut self, proto: &ast::Prototype, implied: &BTreeSet<String>) -> Scheme {
+        let params: Vec<(String, Type)> = proto
+            .name
+            .1
+             .1
+            .iter()
+            .flatten()
+            .map(|generic| (generic.1 .0.clone(), self.unifier.fresh()))
+            .collect();
+        let ty = self.prototype_type(proto, &params, implied);
+        let vars = params
+            .iter()
+            .filter_map(|(_, ty)| match ty {
+                Type::Var(var) => Some(*var),
+                _ => None,
+            })
+            .collect();
+        Scheme {
+            vars,
+            rows: vec![],
+            ty,
+        }
+    }
+
+    fn prototype_type(
+        &mut self,
+        proto: &ast::Prototype,
+        params: &[(String, Type)],
+        implied: &BTreeSet<String>,
+    ) -> Type {
+        let args = proto
+            .args
+            .iter()
+            .filter_map(|arg| match &arg.1 {
+                FnArg::Reciever => None,
+                FnArg::Field(field) => Some(self.annotation(&field.1 .2, params)),
+            })
+            .collect();
+        let ret = match &proto.ret {
+            Some(ret) => self.annotation(ret, params),
+            None => Type::unit(),
+        };
+        let mut effects = self.row(&proto.effects);
+        effects.extend(implied.iter().cloned());
+        Type::Fn(args, Box::new(ret), Box::new(Row::closed(effects)))
+    }
+
+    fn signatures(&mut self, module: &ast::Module) {
+        for item in module.0.iter() {
+            match &item.1 {
+                Node::FnDef(ast::FnDef(_, proto, _)) => self.declare_fn(proto, &BTreeSet::new()),
+                Node::EffectDef(ast::EffectDef(_, name, _, protos)) => {
+                    // performing an operation performs its effect
+                    let effect = self
+                        .name(name)
+                        .and_then(|def| self.expansions.get(&def))
+                        .cloned()
+                        .unwrap_or_default();
+                    for proto in protos.0.iter() {
+                        self.declare_fn(proto, &effect);
+                    }
+                }
+                Node::StructDef(ast::StructDef(_, _, fields)) => {
+                    for field in fields.0.iter() {
+                        let ast::FieldDef(_, name, ty) = &field.1;
+                        let ty = self.annotation(ty, &[]);
+                        if let Some(def) = self.name(name) {
+                            self.schemes.insert(def, Scheme::mono(ty));
+                        }
+                    }
+                }
+                Node::ImplDef(ast::ImplDef(_, name, target, methods)) => {
+                    let mut performs = BTreeSet::new();
+                    for method in methods.0.iter() {
+                        if let Node::FnDef(ast::FnDef(_, proto, _)) = &method.1 {
+                            self.declare_fn(proto, &BTreeSet::new());
+                            performs.extend(self.row(&proto.1.effects));
+                        }
+                    }
+                    // `impl Effect for Target` makes `Target` a handler of `Effect`
+                    let handler = target
+                        .as_ref()
+                        .and_then(|target| self.uses.get(&key(&target.span())));
+                    if let Some(&handler) = handler {
+                        let handles = self.handlers.entry(handler).or_default();
+                        handles.effects.insert(name.1 .0.clone());
+                        handles.performs.extend(performs);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The schemes of imported items, inferred by the module defining them.
+    fn imports(&mut self) {
+        for (def, (module, origin)) in self.res.origins.iter() {
+            if let Some(scheme) = infer(self.db, *module).scheme(*origin) {
+                self.schemes.insert(*def, scheme.clone());
+            }
+        }
+    }
+
+    fn declare_fn(&mut self, proto: &Spanned<ast::Prototype>, implied: &BTreeSet<String>) {
+        let scheme = self.signature(&proto.1, implied);
+        if let Some(def) = self.name(&proto.1.name) {
+            self.schemes.insert(def, scheme);
+        }
+    }
+
+    fn bodies(&mut self, module: &ast::Module) {
+        for item in module.0.iter() {
+            match &item.1 {
+                Node::FnDef(ast::FnDef(_, proto, body)) => self.function(proto, body, None),
+                Node::ImplDef(ast::ImplDef(_, name, target, methods)) => {
+                    let target = target.as_ref().unwrap_or(name);
+                    let receiver = self.annotation(target, &[]);
+                    for method in methods.0.iter() {
+                        if let Node::FnDef(ast::FnDef(_, proto, body)) = &method.1 {
+                            self.function(proto, body, Some(&receiver));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        proto: &Spanned<ast::Prototype>,
+        body: &ast::Block<Spanned<Node>>,
+        receiver: Option<&Type>,
+    ) {
+        // inside its own body a generic parameter is a fixed, unknown type
+        let params: Vec<(String, Type)> = proto
+            .1
+            .name
+            .1
+             .1
+            .iter()
+            .flatten()
+            .map(|generic| (generic.1 .0.clone(), Type::Param(generic.1 .0.clone())))
+            .collect();
+        self.env.clear();
+        self.calls.clear();
+        self.handled.clear();
+        for arg in proto.1.args.iter() {
+            let (def, ty) = match &arg.1 {
+                FnArg::Reciever => {
+                    let def = self.names.get(&key(&arg.span())).copied();
+                    (
+                        def,
+                        receiver.cloned().unwrap_or_else(|| self.unifier.fresh()),
+                    )
+                }
+                FnArg::Field(field) => (
+                    self.name(&field.1 .1),
+                    self.annotation(&field.1 .2, &params),
+                ),
+            };
+            if let Some(def) = def {
+                self.schemes.insert(def, Scheme::mono(ty));
+                self.env.push(def);
+            }
+        }
+        let ret = match &proto.1.ret {
+            Some(ret) => self.annotation(ret, &params),
+            None => Type::unit(),
+        };
+        let method = self.name(&proto.1.name);
+        let resume = self
+            .res
+            .iter()
+            .find(|(_, def)| def.kind == DefKind::Resume && def.parent == method)
+            .map(|(id, _)| id);
+        if let Some(resume) = resume {
+            // resuming hands the operation's result back to the handled block, and evaluates
+            // to whatever the rest of the block does
+            let after = self.unifier.fresh();
+            let ty = Type::Fn(vec![ret.clone()], Box::new(after), Box::default());
+            self.schemes.insert(resume, Scheme::mono(ty));
+            self.env.push(resume);
+        }
+        let mut last = None;
+        for statement in body.0.iter() {
+            last = Some((statement, self.expr(statement)));
+        }
+        // without a `return`, an annotated function evaluates to its last expression
+        if let (Some(_), Some((statement, ty))) = (&proto.1.ret, last) {
+            if !matches!(statement.1, Node::Binding(_) | Node::Branch(_)) {
+                self.expect(statement.span(), &ret, &ty);
+            }
+        }
+        self.check_effects(&proto.1);
+    }
+
+    /// Reports the effects performed by calls in the body that the prototype does not declare.
+    fn check_effects(&mut self, proto: &ast::Prototype) {
+        let declared = self.row(&proto.effects);
+        let effects_span = match (proto.effects.first(), proto.effects.last()) {
+            (Some(first), Some(last)) => first.0..last.2,
+            _ => proto.name.span(),
+        };
+        let mut performed = BTreeSet::new();
+        for Call {
+            callee,
+            row,
+            handled,
+        } in std::mem::take(&mut self.calls)
+        {
+            // an open tail is an effect nothing has pinned down yet, it cannot be missing
+            let row = self.unifier.zonk_row(&row);
+            let effects: BTreeSet<String> = row.effects.difference(&handled).cloned().collect();
+            for effect in effects.iter() {
+                if !declared.contains(effect) {
+                    Diagnostics::push(
+                        self.db,
+                        Diagnostic::error(
+                            callee.span(),
+                            format!(
+                                "`{}` performs effect `{}`, which `{}` does not declare",
+                                callee.1 .0, effect, proto.name.1 .0
+                            ),
+                        )
+                        .with_related(
+                            effects_span.clone(),
+                            format!("add `{}` to the effects of `{}`", effect, proto.name.1 .0),
+                        ),
+                    );
+                }
+            }
+            performed.extend(effects);
+        }
+        if let Some(def) = self.name(&proto.name) {
+            self.performed.insert(def, performed);
+        }
+    }
+
+    fn use_of(&mut self, ident: &Spanned<ast::Ident>) -> Type {
+        let def = self.uses.get(&key(&ident.span())).copied();
+        match def.and_then(|def| self.schemes.get(&def).cloned()) {
+            Some(scheme) => self.unifier.instantiate(&scheme),
+            // imports are typed once their module is, until then they can be anything
+            None => self.unifier.fresh(),
+        }
+    }
+
+    fn expr(&mut self, node: &Spanned<Node>) -> Type {
+        let ty = match &node.1 {
+            Node::Integer(_) => Type::con("i32"),
+            Node::Float(_) => Type::con("f64"),
+            Node::String(_) => Type::con("string"),
+            Node::Bool(_) => Type::bool(),
+            Node::Ident(ident) => self.use_of(ident),
+            Node::FnCall(ast::FnCall(name, args)) => {
+                let callee = self.use_of(name);
+                self.call(name, callee, args)
+            }
+            Node::BinaryExpression(bin) => {
+                let lhs = self.expr(&bin.lhs);
+                let rhs = self.expr(&bin.rhs);
+                self.expect(bin.rhs.span(), &lhs, &rhs);
+                lhs
+            }
+            Node::Binding(ast::Binding(name, value)) => {
+                let ty = self.expr(value);
+                let env: Vec<Scheme> = self
+                    .env
+                    .iter()
+                    .filter_map(|def| self.schemes.get(def).cloned())
+                    .collect();
+                let scheme = self.unifier.generalize(env.iter(), &ty);
+                if let Some(def) = self.name(name) {
+                    self.schemes.insert(def, scheme);
+                    self.env.push(def);
+                }
+                self.exprs.push((key(&name.span()), ty));
+                Type::unit()
+            }
+            Node::FieldAccess(ast::FieldAccess(lhs, rhs)) => {
+                let owner = self.expr(lhs);
+                let ty = self.member(owner, rhs);
+                self.exprs.push((key(&rhs.span()), ty.clone()));
+                ty
+            }
+            Node::Branch(ast::BranchDef(cond, arms)) => {
+                let ty = self.expr(cond);
+                self.expect(cond.span(), &Type::bool(), &ty);
+                for (_, block) in arms {
+                    let scope = self.env.len();
+                    for statement in block.0.iter() {
+                        self.expr(statement);
+                    }
+                    self.env.truncate(scope);
+                }
+                Type::unit()
+            }
+            Node::Handle(ast::HandleDef(_, body, _, handler)) => self.handle(body, handler),
+            _ => self.unifier.fresh(),
+        };
+        self.exprs.push((key(&node.span()), ty.clone()));
+        ty
+    }
+
+    fn call(&mut self, name: &Spanned<ast::Ident>, callee: Type, args: &[Spanned<Node>]) -> Type {
+        let found: Vec<Type> = args.iter().map(|arg| self.expr(arg)).collect();
+        match self.unifier.zonk(&callee) {
+            Type::Fn(params, ret, row) => {
+                if params.len() != found.len() {
+                    self.error(
+                        name.span(),
+                        format!(
+                            "`{}` takes {} arguments but {} were supplied",
+                            name.1 .0,
+                            params.len(),
+                            found.len()
+                        ),
+                    );
+                }
+                for ((param, ty), arg) in params.iter().zip(found.iter()).zip(args.iter()) {
+                    self.expect(arg.span(), param, ty);
+                }
+                self.perform(name, *row);
+                *ret
+            }
+            callee => {
+                let ret = self.unifier.fresh();
+                let row = self.unifier.fresh_row();
+                self.expect(
+                    name.span(),
+                    &callee,
+                    &Type::Fn(found, Box::new(ret.clone()), Box::new(row.clone())),
+                );
+                self.perform(name, row);
+                ret
+            }
+        }
+    }
+
+    fn perform(&mut self, callee: &Spanned<ast::Ident>, row: Row) {
+        self.calls.push(Call {
+            callee: callee.clone(),
+            row,
+            handled: self.handled.last().cloned().unwrap_or_default(),
+        });
+    }
+
+    /// The type of `handle { body } with handler`, the type of the body's last expression.
+    fn handle(&mut self, body: &ast::Block<Spanned<Node>>, handler: &Spanned<ast::Ident>) -> Type {
+        let handles = self
+            .uses
+            .get(&key(&handler.span()))
+            .and_then(|def| self.handlers.get(def))
+            .cloned()
+            .unwrap_or_default();
+        if handles.effects.is_empty() {
+            self.error(
+                handler.span(),
+                format!("`{}` does not implement any effect", handler.1 .0),
+            );
+        }
+        // the handler's methods run on behalf of the block, outside of it
+        self.perform(handler, Row::closed(handles.performs));
+        let mut handled = self.handled.last().cloned().unwrap_or_default();
+        handled.extend(handles.effects);
+        self.handled.push(handled);
+        let scope = self.env.len();
+        let mut last = Type::unit();
+        for statement in body.0.iter() {
+            let ty = self.expr(statement);
+            last = match statement.1 {
+                Node::Binding(_) | Node::Branch(_) => Type::unit(),
+                _ => ty,
+            };
+        }
+        self.env.truncate(scope);
+        self.handled.pop();
+        last
+    }
+
+    /// The type of `owner.rhs`, where `rhs` names a field or calls a method.
+    fn member(&mut self, owner: Type, rhs: &Spanned<Node>) -> Type {
+        let (name, args) = match &rhs.1 {
+            Node::Ident(name) => (name, None),
+            Node::FnCall(ast::FnCall(name, args)) => (name, Some(args)),
+            _ => return self.expr(rhs),
+        };
+        let owner = self.unifier.zonk(&owner);
+        let strukt = match &owner {
+            Type::Con(ty, _) => self
+                .res
+                .iter()
+                .find(|(_, def)| def.kind == DefKind::Struct && &def.name == ty)
+                .map(|(id, _)| id),
+            _ => None,
+        };
+        let member = strukt.and_then(|strukt| {
+            self.res
+                .members(strukt)
+                .find(|(_, def)| def.name == name.1 .0)
+                .map(|(id, _)| id)
+        });
+        let ty = match (strukt, member) {
+            (Some(_), Some(member)) => {
+                self.members.insert(key(&name.span()), member);
+                match self.schemes.get(&member).cloned() {
+                    Some(scheme) => self.unifier.instantiate(&scheme),
+                    None => self.unifier.fresh(),
+                }
+            }
+            (Some(_), None) => {
+                self.error(
+                    name.span(),
+                    format!("no field or method `{}` on type `{}`", name.1 .0, owner),
+                );
+                self.unifier.fresh()
+            }
+            // members of builtins and imports are not known yet
+            (None, _) => self.unifier.fresh(),
+        };
+        match args {
+            Some(args) => self.call(name, ty, args),
+            None => ty,
+        }
+    }
+
+    fn finish(self) -> Inference {
+        let unifier = self.unifier;
+        Inference {
+            exprs: self
+                .exprs
+                .into_iter()
+                .map(|(span, ty)| (span, unifier.zonk(&ty)))
+                .collect(),
+            defs: self
+                .schemes
+                .into_iter()
+                .map(|(def, scheme)| {
+                    let ty = unifier.zonk(&scheme.ty);
+                    (
+                        def,
+                        Scheme {
+                            vars: scheme.vars,
+                            rows: scheme.rows,
+                            ty,
+                        },
+                    )
+                })
+                .collect(),
+            members: self.members,
+            performed: self.performed,
+        }
+    }
+}
+
+/// Infers the type of every expression and definition in `src`.
+#[salsa::tracked(return_ref)]
+pub fn infer(db: &dyn Db, src: SourceProgram) -> Inference {
+    let module = parser::parse(db, src);
+    let res = resolve::resolve(db, src);
+    let mut checker = Checker::new(db, res, effects::expansions(db, src));
+    checker.imports();
+    checker.signatures(module);
+    checker.bodies(module);
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::db::Database;
+
+    fn source(db: &Database, text: &str) -> SourceProgram {
+        SourceProgram::new(db, "inmemory://test".to_string(), text.to_string())
+    }
+
+    /// Type errors only, the resolver's diagnostics are tested with the resolver.
+    fn errors(db: &Database, src: SourceProgram) -> Vec<String> {
+        let mut earlier = resolve::resolve::accumulated::<Diagnostics>(db, src);
+        earlier.extend(effects::expansions::accumulated::<Diagnostics>(db, src));
+        infer::accumulated::<Diagnostics>(db, src)
+            .into_iter()
+            .filter(|diag| !earlier.contains(diag))
+            .map(|diag| diag.message)
+            .collect()
+    }
+
+    fn type_at(db: &Database, src: SourceProgram, needle: &str) -> String {
+        let offset = src.text(db).find(needle).unwrap();
+        infer(db, src).type_at(offset).unwrap().to_string()
+    }
+
+    #[okstd::test]
+    fn test_infers_expressions() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"fn add(a: i32, b: i32)[] -> i32 {
+    let s = "text"
+    let c = a + b
+    c
+}"#,
+        );
+        assert_eq!(type_at(db, src, "a + b"), "i32");
+        assert_eq!(type_at(db, src, "\"text\""), "string");
+        assert!(errors(db, src).is_empty(), "{:?}", errors(db, src));
+    }
+
+    #[okstd::test]
+    fn test_reports_mismatches() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"fn want(a: i32)[] -> string {
+    a
+}
+
+fn main(flag: i32)[] {
+    want("x")
+    want(1, 2)
+    if flag {
+    }
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec![
+                "mismatched types: expected `string`, found `i32`",
+                "mismatched types: expected `i32`, found `string`",
+                "`want` takes 1 arguments but 2 were supplied",
+                "mismatched types: expected `bool`, found `i32`",
+            ]
+        );
+    }
+
+    #[okstd::test]
+    fn test_let_polymorphism() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"fn id<T>(x: T)[] -> T {
+    x
+}
+
+fn main()[] {
+    let f = id
+    let a = f(1)
+    let b = f("s")
+}"#,
+        );
+        assert!(errors(db, src).is_empty(), "{:?}", errors(db, src));
+        assert_eq!(type_at(db, src, "a = "), "i32");
+        assert_eq!(type_at(db, src, "b = "), "string");
+        let res = resolve::resolve(db, src);
+        let f = res.def_at(src.text(db).find("f = ").unwrap()).unwrap();
+        assert_eq!(
+            infer(db, src).scheme(f).unwrap().to_string(),
+            "fn('a) -> 'a"
+        );
+    }
+
+    #[okstd::test]
+    fn test_generic_parameters_are_rigid() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"fn bad<T>(x: T)[] -> i32 {
+    x
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec!["mismatched types: expected `i32`, found `T`"]
+        );
+    }
+
+    #[okstd::test]
+    fn test_struct_members() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"struct Point {
+    x: i32
+}
+
+impl Point {
+    fn get(self) [] -> i32 {
+        self.x
+    }
+}
+
+fn main(p: Point)[] -> string {
+    p.get()
+    p.y
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec!["no field or method `y` on type `Point`"]
+        );
+        assert_eq!(type_at(db, src, "get()"), "i32");
+    }
+
+    #[okstd::test]
+    fn test_checks_effects() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"effect Make: async + throws {
+    exec(arg0: string)[] -> i32
+}
+
+fn fail()[throws] {
+}
+
+fn build()[Make] -> i32 {
+    fail()
+    exec("make")
+}
+
+fn pure()[] {
+    fail()
+}
+
+fn partial()[async] {
+    build()
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec![
+                "`fail` performs effect `throws`, which `pure` does not declare",
+                "`build` performs effect `Make`, which `partial` does not declare",
+                "`build` performs effect `throws`, which `partial` does not declare",
+            ]
+        );
+        let res = resolve::resolve(db, src);
+        let build = res.def_at(src.text(db).find("build()[").unwrap()).unwrap();
+        let performed: Vec<_> = infer(db, src).performed[&build].iter().cloned().collect();
+        assert_eq!(performed, ["Make", "async", "throws"]);
+        assert_eq!(
+            infer(db, src).scheme(build).unwrap().to_string(),
+            "fn()[Make, async, throws] -> i32"
+        );
+    }
+
+    #[okstd::test]
+    fn test_effects_flow_through_let() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"fn fail()[throws] {
+}
+
+fn main()[] {
+    let f = fail
+    f()
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec!["`f` performs effect `throws`, which `main` does not declare"]
+        );
+    }
+
+    #[okstd::test]
+    fn test_handlers_discharge_effects() {
+        let db = &Database::default();
+        let src = source(
+            db,
+            r#"effect Fs: throws {
+    read(path: string)[] -> string
+}
+
+struct Mock {
+}
+
+impl Fs for Mock {
+    fn read(self, path: string)[] -> string {
+        resume("contents")
+    }
+}
+
+fn load()[Fs] -> string {
+    read("config")
+}
+
+fn main()[throws] -> string {
+    handle {
+        load()
+    } with Mock
+}
+
+fn unhandled()[] {
+    handle {
+        load()
+    } with Mock
+}"#,
+        );
+        assert_eq!(
+            errors(db, src),
+            vec!["`load` performs effect `throws`, which `unhandled` does not declare"]
+        );
+        assert_eq!(type_at(db, src, "handle {"), "string");
+        let res = resolve::resolve(db, src);
+        let main = res.def_at(src.text(db).find("main").unwrap()).unwrap();
+        let performed: Vec<_> = infer(db, src).performed[&main].iter().cloned().collect();
+        assert_eq!(performed, ["throws"]);
+    }
+}
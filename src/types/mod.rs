@@ -0,0 +1,474 @@
+//! Types, type schemes and unification for Hindley–Milner inference.
+//!
+//! Function types carry the row of effects calling them performs. Rows are sets of effect
+//! names, left open by a tail variable when more effects may be added by unification.
+
+use std::{collections::BTreeSet, fmt::Display};
+
+pub mod conformance;
+pub mod effects;
+pub mod infer;
+
+/// An inference variable, solved by unification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeVar(pub u32);
+
+/// A row variable, standing for the effects an open row has yet to be given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RowVar(pub u32);
+
+/// The effects performed by calling a function, `[async, throws]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Row {
+    pub effects: BTreeSet<String>,
+    /// The rest of an open row, `None` when the row is closed.
+    pub tail: Option<RowVar>,
+}
+
+impl Row {
+    pub fn closed(effects: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            effects: effects.into_iter().collect(),
+            tail: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty() && self.tail.is_none()
+    }
+}
+
+impl Display for Row {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut effects: Vec<&str> = self.effects.iter().map(String::as_str).collect();
+        if self.tail.is_some() {
+            effects.push("..");
+        }
+        write!(f, "[{}]", effects.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Var(TypeVar),
+    /// A named type applied to its arguments, `i32` or `vec<string>`.
+    Con(String, Vec<Type>),
+    /// A generic parameter of the function being checked, only equal to itself.
+    Param(String),
+    Fn(Vec<Type>, Box<Type>, Box<Row>),
+}
+
+impl Type {
+    pub fn con(name: &str) -> Self {
+        Type::Con(name.to_string(), vec![])
+    }
+
+    pub fn unit() -> Self {
+        Type::con("unit")
+    }
+
+    pub fn bool() -> Self {
+        Type::con("bool")
+    }
+
+    pub fn free_vars(&self, vars: &mut BTreeSet<TypeVar>) {
+        match self {
+            Type::Var(var) => {
+                vars.insert(*var);
+            }
+            Type::Con(_, args) => args.iter().for_each(|arg| arg.free_vars(vars)),
+            Type::Param(_) => {}
+            Type::Fn(args, ret, _) => {
+                args.iter().for_each(|arg| arg.free_vars(vars));
+                ret.free_vars(vars);
+            }
+        }
+    }
+
+    pub fn free_rows(&self, rows: &mut BTreeSet<RowVar>) {
+        match self {
+            Type::Var(_) | Type::Param(_) => {}
+            Type::Con(_, args) => args.iter().for_each(|arg| arg.free_rows(rows)),
+            Type::Fn(args, ret, row) => {
+                args.iter().for_each(|arg| arg.free_rows(rows));
+                ret.free_rows(rows);
+                rows.extend(row.tail);
+            }
+        }
+    }
+
+    fn occurs(&self, var: TypeVar) -> bool {
+        match self {
+            Type::Var(other) => *other == var,
+            Type::Con(_, args) => args.iter().any(|arg| arg.occurs(var)),
+            Type::Param(_) => false,
+            Type::Fn(args, ret, _) => args.iter().any(|arg| arg.occurs(var)) || ret.occurs(var),
+        }
+    }
+
+    /// Replaces the variables in `vars` and the row tails in `rows`, leaving the others alone.
+    pub(crate) fn replace(&self, vars: &[(TypeVar, Type)], rows: &[(RowVar, RowVar)]) -> Type {
+        match self {
+            Type::Var(var) => vars
+                .iter()
+                .find(|(from, _)| from == var)
+                .map(|(_, to)| to.clone())
+                .unwrap_or_else(|| self.clone()),
+            Type::Con(name, args) => Type::Con(
+                name.clone(),
+                args.iter().map(|arg| arg.replace(vars, rows)).collect(),
+            ),
+            Type::Param(_) => self.clone(),
+            Type::Fn(args, ret, row) => Type::Fn(
+                args.iter().map(|arg| arg.replace(vars, rows)).collect(),
+                Box::new(ret.replace(vars, rows)),
+                Box::new(Row {
+                    effects: row.effects.clone(),
+                    tail: row.tail.map(|tail| {
+                        rows.iter()
+                            .find(|(from, _)| *from == tail)
+                            .map_or(tail, |(_, to)| *to)
+                    }),
+                }),
+            ),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Var(var) => write!(f, "?{}", var.0),
+            Type::Con(name, args) if name == "unit" && args.is_empty() => write!(f, "()"),
+            Type::Con(name, args) if args.is_empty() => write!(f, "{}", name),
+            Type::Con(name, args) => {
+                let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            Type::Param(name) => write!(f, "{}", name),
+            Type::Fn(args, ret, row) => {
+                let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+                write!(f, "fn({})", args.join(", "))?;
+                // a pure function reads better without its empty row
+                if !row.is_empty() {
+                    write!(f, "{}", row)?;
+                }
+                write!(f, " -> {}", ret)
+            }
+        }
+    }
+}
+
+/// A type quantified over some of its variables, `forall a. vec<a> -> a`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scheme {
+    pub vars: Vec<TypeVar>,
+    /// Quantified row tails, so each use of a function can perform different effects.
+    pub rows: Vec<RowVar>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    /// A scheme with nothing to instantiate.
+    pub fn mono(ty: Type) -> Self {
+        Self {
+            vars: vec![],
+            rows: vec![],
+            ty,
+        }
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.vars.is_empty() {
+            return write!(f, "{}", self.ty);
+        }
+        // quantified variables read better as letters than as inference numbers
+        let names: Vec<(TypeVar, Type)> = self
+            .vars
+            .iter()
+            .enumerate()
+            .map(|(i, var)| {
+                let name = (b'a' + (i % 26) as u8) as char;
+                (*var, Type::Param(format!("'{}", name)))
+            })
+            .collect();
+        write!(f, "{}", self.ty.replace(&names, &[]))
+    }
+}
+
+/// Why two types could not be unified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnifyError {
+    Mismatch(Type, Type),
+    /// Solving would make a type contain itself.
+    Infinite(TypeVar, Type),
+}
+
+/// The substitution built up by unification.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unifier {
+    solved: Vec<Option<Type>>,
+    rows: Vec<Option<Row>>,
+}
+
+impl Unifier {
+    pub fn fresh(&mut self) -> Type {
+        self.solved.push(None);
+        Type::Var(TypeVar(self.solved.len() as u32 - 1))
+    }
+
+    fn fresh_tail(&mut self) -> RowVar {
+        self.rows.push(None);
+        RowVar(self.rows.len() as u32 - 1)
+    }
+
+    /// A row with no known effects, open to any.
+    pub fn fresh_row(&mut self) -> Row {
+        Row {
+            effects: BTreeSet::new(),
+            tail: Some(self.fresh_tail()),
+        }
+    }
+
+    /// Applies the substitution to the tail of `row`.
+    pub fn zonk_row(&self, row: &Row) -> Row {
+        match row
+            .tail
+            .and_then(|tail| self.rows[tail.0 as usize].as_ref())
+        {
+            Some(rest) => {
+                let rest = self.zonk_row(rest);
+                Row {
+                    effects: row.effects.union(&rest.effects).cloned().collect(),
+                    tail: rest.tail,
+                }
+            }
+            None => row.clone(),
+        }
+    }
+
+    /// Applies the substitution all the way down.
+    pub fn zonk(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.solved[var.0 as usize] {
+                Some(solved) => self.zonk(solved),
+                None => ty.clone(),
+            },
+            Type::Con(name, args) => Type::Con(
+                name.clone(),
+                args.iter().map(|arg| self.zonk(arg)).collect(),
+            ),
+            Type::Param(_) => ty.clone(),
+            Type::Fn(args, ret, row) => Type::Fn(
+                args.iter().map(|arg| self.zonk(arg)).collect(),
+                Box::new(self.zonk(ret)),
+                Box::new(self.zonk_row(row)),
+            ),
+        }
+    }
+
+    /// Makes `expected` and `found` equal, or explains why they cannot be.
+    pub fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), UnifyError> {
+        let expected = self.zonk(expected);
+        let found = self.zonk(found);
+        match (&expected, &found) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), other) | (other, Type::Var(var)) => {
+                if other.occurs(*var) {
+                    return Err(UnifyError::Infinite(*var, other.clone()));
+                }
+                self.solved[var.0 as usize] = Some(other.clone());
+                Ok(())
+            }
+            (Type::Con(a, a_args), Type::Con(b, b_args))
+                if a == b && a_args.len() == b_args.len() =>
+            {
+                for (a, b) in a_args.iter().zip(b_args.iter()) {
+                    self.unify(a, b)
+                        .map_err(|_| UnifyError::Mismatch(expected.clone(), found.clone()))?;
+                }
+                Ok(())
+            }
+            (Type::Param(a), Type::Param(b)) if a == b => Ok(()),
+            (Type::Fn(a_args, a_ret, a_row), Type::Fn(b_args, b_ret, b_row))
+                if a_args.len() == b_args.len() =>
+            {
+                for (a, b) in a_args.iter().zip(b_args.iter()) {
+                    self.unify(a, b)
+                        .map_err(|_| UnifyError::Mismatch(expected.clone(), found.clone()))?;
+                }
+                self.unify(a_ret, b_ret)
+                    .map_err(|_| UnifyError::Mismatch(expected.clone(), found.clone()))?;
+                if self.unify_rows(a_row, b_row) {
+                    Ok(())
+                } else {
+                    Err(UnifyError::Mismatch(expected, found))
+                }
+            }
+            _ => Err(UnifyError::Mismatch(expected, found)),
+        }
+    }
+
+    /// Makes two rows hold the same effects by growing their open tails, returns whether
+    /// that was possible.
+    fn unify_rows(&mut self, expected: &Row, found: &Row) -> bool {
+        let expected = self.zonk_row(expected);
+        let found = self.zonk_row(found);
+        let missing_from_found: BTreeSet<String> = expected
+            .effects
+            .difference(&found.effects)
+            .cloned()
+            .collect();
+        let missing_from_expected: BTreeSet<String> = found
+            .effects
+            .difference(&expected.effects)
+            .cloned()
+            .collect();
+        match (expected.tail, found.tail) {
+            (Some(a), Some(b)) if a == b => {
+                missing_from_found.is_empty() && missing_from_expected.is_empty()
+            }
+            (Some(a), Some(b)) => {
+                let rest = self.fresh_tail();
+                self.rows[a.0 as usize] = Some(Row {
+                    effects: missing_from_expected,
+                    tail: Some(rest),
+                });
+                self.rows[b.0 as usize] = Some(Row {
+                    effects: missing_from_found,
+                    tail: Some(rest),
+                });
+                true
+            }
+            (Some(a), None) if missing_from_found.is_empty() => {
+                self.rows[a.0 as usize] = Some(Row::closed(missing_from_expected));
+                true
+            }
+            (None, Some(b)) if missing_from_expected.is_empty() => {
+                self.rows[b.0 as usize] = Some(Row::closed(missing_from_found));
+                true
+            }
+            (None, None) => missing_from_found.is_empty() && missing_from_expected.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Replaces the quantified variables and rows of `scheme` with fresh ones.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let vars: Vec<_> = scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+        let rows: Vec<_> = scheme
+            .rows
+            .iter()
+            .map(|row| (*row, self.fresh_tail()))
+            .collect();
+        scheme.ty.replace(&vars, &rows)
+    }
+
+    /// Quantifies the variables and rows of `ty` that are not free in `env`.
+    pub fn generalize<'a>(&self, env: impl IntoIterator<Item = &'a Scheme>, ty: &Type) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut vars_in_env = BTreeSet::new();
+        let mut rows_in_env = BTreeSet::new();
+        for scheme in env {
+            let env_ty = self.zonk(&scheme.ty);
+            let mut vars = BTreeSet::new();
+            env_ty.free_vars(&mut vars);
+            vars_in_env.extend(vars.into_iter().filter(|var| !scheme.vars.contains(var)));
+            let mut rows = BTreeSet::new();
+            env_ty.free_rows(&mut rows);
+            rows_in_env.extend(rows.into_iter().filter(|row| !scheme.rows.contains(row)));
+        }
+        let mut vars = BTreeSet::new();
+        ty.free_vars(&mut vars);
+        let mut rows = BTreeSet::new();
+        ty.free_rows(&mut rows);
+        Scheme {
+            vars: vars.difference(&vars_in_env).copied().collect(),
+            rows: rows.difference(&rows_in_env).copied().collect(),
+            ty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[okstd::test]
+    fn test_unify_solves_variables() {
+        let mut unifier = Unifier::default();
+        let a = unifier.fresh();
+        let vec_a = Type::Con("vec".to_string(), vec![a.clone()]);
+        let vec_i32 = Type::Con("vec".to_string(), vec![Type::con("i32")]);
+        assert_eq!(unifier.unify(&vec_a, &vec_i32), Ok(()));
+        assert_eq!(unifier.zonk(&a), Type::con("i32"));
+    }
+
+    #[okstd::test]
+    fn test_unify_reports_mismatch_and_infinite_types() {
+        let mut unifier = Unifier::default();
+        assert_eq!(
+            unifier.unify(&Type::con("i32"), &Type::con("string")),
+            Err(UnifyError::Mismatch(Type::con("i32"), Type::con("string")))
+        );
+        let a = unifier.fresh();
+        let vec_a = Type::Con("vec".to_string(), vec![a.clone()]);
+        assert!(matches!(
+            unifier.unify(&a, &vec_a),
+            Err(UnifyError::Infinite(..))
+        ));
+        assert!(unifier
+            .unify(&Type::Param("T".to_string()), &Type::con("i32"))
+            .is_err());
+    }
+
+    #[okstd::test]
+    fn test_generalize_and_instantiate() {
+        let mut unifier = Unifier::default();
+        let a = unifier.fresh();
+        let id = Type::Fn(vec![a.clone()], Box::new(a), Box::default());
+        let scheme = unifier.generalize([], &id);
+        assert_eq!(scheme.to_string(), "fn('a) -> 'a");
+        let first = unifier.instantiate(&scheme);
+        let second = unifier.instantiate(&scheme);
+        assert_ne!(first, second);
+    }
+
+    #[okstd::test]
+    fn test_unify_rows() {
+        let mut unifier = Unifier::default();
+        let function = |row| Type::Fn(vec![], Box::new(Type::unit()), Box::new(row));
+        let async_ = Row::closed(["async".to_string()]);
+        let async_throws = Row::closed(["async".to_string(), "throws".to_string()]);
+        assert!(unifier
+            .unify(&function(async_.clone()), &function(async_throws.clone()))
+            .is_err());
+
+        // an open row takes on the effects it is missing
+        let open = unifier.fresh_row();
+        let grown = Row {
+            effects: ["async".to_string()].into(),
+            ..open.clone()
+        };
+        assert_eq!(
+            unifier.unify(&function(grown), &function(async_throws.clone())),
+            Ok(())
+        );
+        assert_eq!(unifier.zonk_row(&open), Row::closed(["throws".to_string()]));
+
+        // but cannot lose the ones it has
+        let open = unifier.fresh_row();
+        let grown = Row {
+            effects: ["throws".to_string()].into(),
+            ..open
+        };
+        assert!(unifier.unify(&function(grown), &function(async_)).is_err());
+        assert_eq!(
+            function(async_throws).to_string(),
+            "fn()[async, throws] -> ()"
+        );
+        assert_eq!(function(unifier.fresh_row()).to_string(), "fn()[..] -> ()");
+    }
+}